@@ -1,10 +1,32 @@
-use crate::disk::Disk;
-use crate::notification::Notification;
-use crate::operations::{FilesystemType, HelperConnection, get_smart_data, list_block_devices};
+use crate::audit::AuditEntry;
+use crate::backup::TableBackup;
+use crate::btrfs::{self, Subvolume, SubvolumeList};
+use crate::disk::{Disk, SmartCache};
+use crate::fsck::{CheckReport, Checker};
+use crate::config::MountDefaults;
+use crate::fslabel;
+use crate::fstab::{self, BootTables};
+use crate::layout::{self, Alignment, FreeSpace, LayoutRow, Placement, layout_rows};
+use crate::lvm::{self, LogicalVolume, LvmReport, PhysicalVolume, VolumeGroup};
+use crate::monitor::{Monitor, Thresholds};
+use crate::mount;
+use crate::notification::{Notification, NotificationLevel};
+use crate::operations::{FilesystemType, HelperConnection, scan_block_devices};
+use crate::partition::Partition;
+use crate::parttype::{self, FlagChange, PartitionFlag, PartitionType};
+use crate::protocol::Request;
+use crate::queue::OperationQueue;
+use crate::raid::{self, RaidArray, RaidMember, RaidReport, StoppedArray};
+use crate::runner::SystemRunner;
+use crate::smart::{Level, SmartData};
+use crate::swap::SwapArea;
 use crate::theme::Theme;
-use anyhow::Result;
+use crate::utils::{SizeSpec, SizeUnits, format_bytes};
+use anyhow::{Result, anyhow};
 use ratatui::widgets::{ListState, TableState};
+use std::collections::{HashMap, HashSet};
 use std::sync::{Arc, atomic::AtomicBool};
+use std::time::{Duration, Instant};
 use tui_input::Input;
 
 pub type AppResult<T> = Result<T>;
@@ -15,10 +37,12 @@ pub enum ConfirmationOperation {
     FormatPartition {
         partition: String,
         fs_type: FilesystemType,
+        label: Option<String>,
     },
     FormatDisk {
         disk: String,
         fs_type: FilesystemType,
+        label: Option<String>,
     },
     DeletePartition {
         partition: String,
@@ -29,12 +53,22 @@ pub enum ConfirmationOperation {
     },
     CreatePartition {
         disk: String,
+        start: Option<u64>,
         size: String,
-        fs_type: FilesystemType,
+        fs_type: Option<FilesystemType>,
+        alignment: Alignment,
+        part_type: Option<String>,
     },
     ResizePartition {
         partition: String,
         new_size: String,
+        alignment: Alignment,
+    },
+    ModifyPartition {
+        partition: String,
+        part_type: Option<String>,
+        name: Option<String>,
+        flags: Vec<FlagChange>,
     },
     UnlockLuksDevice {
         device: String,
@@ -47,6 +81,239 @@ pub enum ConfirmationOperation {
         partition: String,
         fs_type: crate::operations::FilesystemType,
     },
+    RestoreTable {
+        disk: String,
+        backup: String,
+    },
+    CreateSwapFile {
+        path: String,
+        size: String,
+    },
+    CheckFilesystem {
+        device: String,
+        repair: bool,
+    },
+    RegenerateUuid {
+        device: String,
+    },
+    CreatePhysicalVolume {
+        device: String,
+    },
+    CreateVolumeGroup {
+        name: String,
+        devices: Vec<String>,
+    },
+    ExtendVolumeGroup {
+        name: String,
+        device: String,
+    },
+    ResizeLogicalVolume {
+        vg: String,
+        name: String,
+        size: String,
+    },
+    RemoveLogicalVolume {
+        vg: String,
+        name: String,
+    },
+    CreateRaid {
+        name: String,
+        level: String,
+        devices: Vec<String>,
+    },
+    AddRaidMember {
+        array: String,
+        device: String,
+    },
+    FailRaidMember {
+        array: String,
+        device: String,
+    },
+    RemoveRaidMember {
+        array: String,
+        device: String,
+    },
+    StopRaid {
+        array: String,
+    },
+    /// Subvolume `id` of the btrfs filesystem on `device`; `path` is only
+    /// shown in the dialog.
+    DeleteSubvolume {
+        device: String,
+        id: u64,
+        path: String,
+    },
+    SetDefaultSubvolume {
+        device: String,
+        id: u64,
+        path: String,
+    },
+    ApplyQueue,
+}
+
+impl ConfirmationOperation {
+    /// Whether confirming this operation adds it to the pending queue (when
+    /// queue mode is on) instead of running it.
+    pub fn is_queueable(&self) -> bool {
+        matches!(
+            self,
+            ConfirmationOperation::FormatPartition { .. }
+                | ConfirmationOperation::FormatDisk { .. }
+                | ConfirmationOperation::DeletePartition { .. }
+                | ConfirmationOperation::CreatePartitionTable { .. }
+                | ConfirmationOperation::CreatePartition { .. }
+                | ConfirmationOperation::ResizePartition { .. }
+                | ConfirmationOperation::ModifyPartition { .. }
+        )
+    }
+
+    /// The helper request this operation runs. LUKS operations take the
+    /// passphrase collected by the passphrase dialog.
+    pub fn request(&self, passphrase: &str) -> Option<Request> {
+        let request = match self.clone() {
+            ConfirmationOperation::FormatPartition {
+                partition,
+                fs_type,
+                label,
+            } => Request::Format {
+                device: partition,
+                fs_type: fs_type.to_string(),
+                label,
+            },
+            ConfirmationOperation::FormatDisk {
+                disk,
+                fs_type,
+                label,
+            } => Request::FormatWholeDisk {
+                disk,
+                fs_type: fs_type.to_string(),
+                label,
+            },
+            ConfirmationOperation::DeletePartition { partition } => {
+                Request::DeletePartition { partition }
+            }
+            ConfirmationOperation::CreatePartitionTable { disk, table_type } => {
+                Request::CreatePartitionTable { disk, table_type }
+            }
+            ConfirmationOperation::CreatePartition {
+                disk,
+                start,
+                size,
+                fs_type,
+                alignment,
+                part_type,
+            } => Request::CreatePartition {
+                disk,
+                start,
+                size,
+                fs_type: fs_type.map(|fs| fs.to_string()),
+                alignment,
+                part_type,
+                name: None,
+            },
+            ConfirmationOperation::ResizePartition {
+                partition,
+                new_size,
+                alignment,
+            } => Request::ResizePartition {
+                partition,
+                new_size,
+                alignment,
+            },
+            ConfirmationOperation::ModifyPartition {
+                partition,
+                part_type,
+                name,
+                flags,
+            } => Request::ModifyPartition {
+                partition,
+                part_type,
+                name,
+                flags,
+            },
+            ConfirmationOperation::LockLuksDevice { mapper_name } => {
+                Request::LockLuks { mapper_name }
+            }
+            ConfirmationOperation::EncryptPartition { partition, fs_type } => {
+                Request::EncryptAndFormat {
+                    partition,
+                    passphrase: passphrase.to_string(),
+                    fs_type: fs_type.to_string(),
+                }
+            }
+            ConfirmationOperation::UnlockLuksDevice {
+                device,
+                mapper_name,
+            } => Request::UnlockLuks {
+                device,
+                passphrase: passphrase.to_string(),
+                mapper_name,
+            },
+            ConfirmationOperation::RestoreTable { disk, backup } => {
+                Request::RestoreTable { disk, backup }
+            }
+            ConfirmationOperation::CreateSwapFile { path, size } => {
+                Request::CreateSwapFile { path, size }
+            }
+            ConfirmationOperation::CheckFilesystem { device, repair } => {
+                Request::CheckFilesystem { device, repair }
+            }
+            ConfirmationOperation::RegenerateUuid { device } => Request::RegenerateUuid { device },
+            ConfirmationOperation::CreatePhysicalVolume { device } => {
+                Request::CreatePhysicalVolume { device }
+            }
+            ConfirmationOperation::CreateVolumeGroup { name, devices } => {
+                Request::CreateVolumeGroup { name, devices }
+            }
+            ConfirmationOperation::ExtendVolumeGroup { name, device } => {
+                Request::ExtendVolumeGroup { name, device }
+            }
+            ConfirmationOperation::ResizeLogicalVolume { vg, name, size } => {
+                Request::ResizeLogicalVolume { vg, name, size }
+            }
+            ConfirmationOperation::RemoveLogicalVolume { vg, name } => {
+                Request::RemoveLogicalVolume { vg, name }
+            }
+            ConfirmationOperation::CreateRaid {
+                name,
+                level,
+                devices,
+            } => Request::CreateRaid {
+                name,
+                level,
+                devices,
+            },
+            ConfirmationOperation::AddRaidMember { array, device } => {
+                Request::AddRaidMember { array, device }
+            }
+            ConfirmationOperation::FailRaidMember { array, device } => {
+                Request::FailRaidMember { array, device }
+            }
+            ConfirmationOperation::RemoveRaidMember { array, device } => {
+                Request::RemoveRaidMember { array, device }
+            }
+            ConfirmationOperation::StopRaid { array } => Request::StopRaid { array },
+            ConfirmationOperation::DeleteSubvolume { device, id, .. } => {
+                Request::DeleteSubvolume { device, id }
+            }
+            ConfirmationOperation::SetDefaultSubvolume { device, id, .. } => {
+                Request::SetDefaultSubvolume { device, id }
+            }
+            ConfirmationOperation::ApplyQueue | ConfirmationOperation::None => return None,
+        };
+        Some(request)
+    }
+}
+
+/// Commands the helper reports it would run for the operation being
+/// confirmed, fetched with a dry run while the dialog is open.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum CommandPreview {
+    #[default]
+    None,
+    Loading,
+    Ready(Vec<String>),
+    Failed(String),
 }
 
 #[derive(Debug)]
@@ -57,6 +324,7 @@ pub struct ConfirmationDialog {
     pub details: Vec<(String, String)>,
     pub selected: usize,
     pub operation: ConfirmationOperation,
+    pub commands: CommandPreview,
 }
 
 impl Default for ConfirmationDialog {
@@ -68,14 +336,540 @@ impl Default for ConfirmationDialog {
             details: Vec::new(),
             selected: 0,
             operation: ConfirmationOperation::None,
+            commands: CommandPreview::None,
+        }
+    }
+}
+
+/// Lines kept in the check log; the oldest are dropped beyond this.
+pub const CHECK_LOG_LINES: usize = 10_000;
+
+/// A filesystem check: first the choice between a read-only check and a
+/// repair, then the checker's output as it runs and the verdict.
+#[derive(Debug, Default)]
+pub struct CheckLogState {
+    pub show_dialog: bool,
+    pub device: String,
+    pub fs_type: String,
+    /// Whether the mode has been chosen and the check started.
+    pub started: bool,
+    pub repair: bool,
+    pub running: bool,
+    pub lines: Vec<String>,
+    /// Lines scrolled up from the end; 0 follows new output.
+    pub scroll: usize,
+    pub report: Option<CheckReport>,
+    pub error: Option<String>,
+}
+
+impl CheckLogState {
+    pub fn open(device: String, fs_type: String) -> Self {
+        Self {
+            show_dialog: true,
+            device,
+            fs_type,
+            ..Default::default()
+        }
+    }
+
+    /// Whether the filesystem has a repair mode; btrfs is only checked.
+    pub fn can_repair(&self) -> bool {
+        Checker::for_filesystem(&self.fs_type)
+            .and_then(|checker| checker.args(true))
+            .is_ok()
+    }
+
+    pub fn push_line(&mut self, line: String) {
+        self.lines.push(line);
+        if self.lines.len() > CHECK_LOG_LINES {
+            self.lines.drain(..self.lines.len() - CHECK_LOG_LINES);
+        }
+        // Keep the view still while scrolled up.
+        if self.scroll > 0 {
+            self.scroll = (self.scroll + 1).min(self.lines.len());
+        }
+    }
+}
+
+/// Past operations on one disk, read from the helper's audit log.
+#[derive(Debug, Default)]
+pub struct AuditLogState {
+    pub show_dialog: bool,
+    pub disk: String,
+    pub loading: bool,
+    pub error: Option<String>,
+    pub entries: Vec<AuditEntry>,
+    pub state: ListState,
+}
+
+/// Saved partition tables of one disk, listed by the helper together with
+/// the current table so the selected backup can be shown as a diff.
+#[derive(Debug, Default)]
+pub struct RestoreDialogState {
+    pub show_dialog: bool,
+    pub disk: String,
+    pub loading: bool,
+    pub error: Option<String>,
+    pub current: Option<String>,
+    pub backups: Vec<TableBackup>,
+    pub state: ListState,
+}
+
+impl RestoreDialogState {
+    pub fn selected_backup(&self) -> Option<&TableBackup> {
+        self.backups.get(self.state.selected()?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum LvmSection {
+    #[default]
+    PhysicalVolumes,
+    VolumeGroups,
+    LogicalVolumes,
+}
+
+impl LvmSection {
+    pub const ALL: [LvmSection; 3] = [
+        LvmSection::PhysicalVolumes,
+        LvmSection::VolumeGroups,
+        LvmSection::LogicalVolumes,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            LvmSection::PhysicalVolumes => "Physical Volumes",
+            LvmSection::VolumeGroups => "Volume Groups",
+            LvmSection::LogicalVolumes => "Logical Volumes",
+        }
+    }
+}
+
+/// What the form in the LVM view asks for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LvmAction {
+    /// A new volume group on an unused physical volume.
+    CreateVolumeGroup { device: String },
+    /// Adding an unused physical volume to a volume group.
+    ExtendVolumeGroup { device: String },
+    CreateLogicalVolume { vg: String },
+    ResizeLogicalVolume { vg: String, name: String },
+}
+
+/// Values entered for an [`LvmAction`], one input per field.
+#[derive(Debug)]
+pub struct LvmForm {
+    pub action: LvmAction,
+    pub fields: Vec<(&'static str, Input)>,
+    pub focus: usize,
+    pub error: Option<String>,
+}
+
+impl LvmForm {
+    pub fn new(action: LvmAction, fields: Vec<(&'static str, Input)>) -> Self {
+        Self {
+            action,
+            fields,
+            focus: 0,
+            error: None,
+        }
+    }
+
+    pub fn title(&self) -> String {
+        match &self.action {
+            LvmAction::CreateVolumeGroup { device } => format!("New volume group on {}", device),
+            LvmAction::ExtendVolumeGroup { device } => format!("Add {} to volume group", device),
+            LvmAction::CreateLogicalVolume { vg } => format!("New logical volume in {}", vg),
+            LvmAction::ResizeLogicalVolume { vg, name } => format!("Resize {}/{}", vg, name),
+        }
+    }
+
+    fn value(&self, index: usize) -> String {
+        self.fields
+            .get(index)
+            .map(|(_, input)| input.value().trim().to_string())
+            .unwrap_or_default()
+    }
+
+    /// What submitting the form does, or why the entered values are
+    /// invalid. Logical volumes are created right away, everything else
+    /// is confirmed first.
+    pub fn operation(&self) -> Result<ConfirmationOperation> {
+        let first = self.value(0);
+        match &self.action {
+            LvmAction::CreateVolumeGroup { device } => {
+                lvm::validate_name(&first)?;
+                Ok(ConfirmationOperation::CreateVolumeGroup {
+                    name: first,
+                    devices: vec![device.clone()],
+                })
+            }
+            LvmAction::ExtendVolumeGroup { device } => {
+                lvm::validate_name(&first)?;
+                Ok(ConfirmationOperation::ExtendVolumeGroup {
+                    name: first,
+                    device: device.clone(),
+                })
+            }
+            LvmAction::CreateLogicalVolume { .. } => Err(anyhow!("Not a confirmed operation")),
+            LvmAction::ResizeLogicalVolume { vg, name } => {
+                first.parse::<SizeSpec>()?;
+                Ok(ConfirmationOperation::ResizeLogicalVolume {
+                    vg: vg.clone(),
+                    name: name.clone(),
+                    size: first,
+                })
+            }
+        }
+    }
+
+    /// The request creating the logical volume of a
+    /// [`LvmAction::CreateLogicalVolume`] form.
+    pub fn create_request(&self) -> Result<Request> {
+        let LvmAction::CreateLogicalVolume { vg } = &self.action else {
+            return Err(anyhow!("Not a logical volume form"));
+        };
+        let name = self.value(0);
+        let size = self.value(1);
+        let fs_type = self.value(2);
+        lvm::validate_name(&name)?;
+        size.parse::<SizeSpec>()?;
+        if !fs_type.is_empty() && !FilesystemType::all().iter().any(|fs| fs.as_str() == fs_type) {
+            return Err(anyhow!("Unknown filesystem: {}", fs_type));
+        }
+        Ok(Request::CreateLogicalVolume {
+            vg: vg.clone(),
+            name,
+            size,
+            fs_type: (!fs_type.is_empty()).then_some(fs_type),
+        })
+    }
+}
+
+/// Physical volumes, volume groups and logical volumes, read through the
+/// helper since the LVM tools need root.
+#[derive(Debug, Default)]
+pub struct LvmViewState {
+    pub show_dialog: bool,
+    pub loading: bool,
+    pub error: Option<String>,
+    pub report: LvmReport,
+    pub section: LvmSection,
+    pub state: TableState,
+    /// The partition selected when the view was opened, offered as a new
+    /// physical volume.
+    pub candidate: Option<String>,
+    pub form: Option<LvmForm>,
+}
+
+impl LvmViewState {
+    pub fn rows(&self) -> usize {
+        match self.section {
+            LvmSection::PhysicalVolumes => self.report.pvs.len(),
+            LvmSection::VolumeGroups => self.report.vgs.len(),
+            LvmSection::LogicalVolumes => self.report.lvs.len(),
+        }
+    }
+
+    pub fn select_section(&mut self, section: LvmSection) {
+        self.section = section;
+        self.state.select((self.rows() > 0).then_some(0));
+    }
+
+    pub fn selected_pv(&self) -> Option<&PhysicalVolume> {
+        match self.section {
+            LvmSection::PhysicalVolumes => self.report.pvs.get(self.state.selected()?),
+            _ => None,
+        }
+    }
+
+    pub fn selected_vg(&self) -> Option<&VolumeGroup> {
+        match self.section {
+            LvmSection::VolumeGroups => self.report.vgs.get(self.state.selected()?),
+            _ => None,
+        }
+    }
+
+    pub fn selected_lv(&self) -> Option<&LogicalVolume> {
+        match self.section {
+            LvmSection::LogicalVolumes => self.report.lvs.get(self.state.selected()?),
+            _ => None,
+        }
+    }
+
+    /// Keeps the selection in range after the report was reloaded.
+    pub fn set_report(&mut self, report: LvmReport) {
+        self.report = report;
+        let rows = self.rows();
+        self.state.select(match self.state.selected() {
+            _ if rows == 0 => None,
+            Some(idx) => Some(idx.min(rows - 1)),
+            None => Some(0),
+        });
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RaidFormField {
+    Name,
+    Level,
+    Devices,
+}
+
+/// The form for a new array, or for picking the device to add to an
+/// existing one.
+#[derive(Debug)]
+pub struct RaidForm {
+    /// The array a device is added to; `None` for a new array.
+    pub array: Option<String>,
+    pub name: Input,
+    /// Index into [`raid::LEVELS`].
+    pub level: usize,
+    /// Unused partitions with their size and whether they are picked.
+    pub devices: Vec<(String, u64, bool)>,
+    pub state: ListState,
+    pub focus: RaidFormField,
+    pub error: Option<String>,
+}
+
+impl RaidForm {
+    pub fn new(array: Option<String>, name: String, devices: Vec<(String, u64)>) -> Self {
+        let mut state = ListState::default();
+        state.select((!devices.is_empty()).then_some(0));
+        Self {
+            focus: match array {
+                Some(_) => RaidFormField::Devices,
+                None => RaidFormField::Name,
+            },
+            array,
+            name: Input::new(name),
+            // RAID 1 is what most people mirroring two disks want.
+            level: 1,
+            devices: devices
+                .into_iter()
+                .map(|(name, size)| (name, size, false))
+                .collect(),
+            state,
+            error: None,
         }
     }
+
+    pub fn title(&self) -> String {
+        match &self.array {
+            Some(array) => format!("Add a device to {}", array),
+            None => "New array".to_string(),
+        }
+    }
+
+    pub fn fields(&self) -> &'static [RaidFormField] {
+        match self.array {
+            Some(_) => &[RaidFormField::Devices],
+            None => &[
+                RaidFormField::Name,
+                RaidFormField::Level,
+                RaidFormField::Devices,
+            ],
+        }
+    }
+
+    /// The array to create with the picked devices, or the device under
+    /// the cursor to add.
+    pub fn operation(&self) -> Result<ConfirmationOperation> {
+        if let Some(array) = &self.array {
+            let (device, _, _) = self
+                .state
+                .selected()
+                .and_then(|idx| self.devices.get(idx))
+                .ok_or_else(|| anyhow!("No unused partition to add"))?;
+            return Ok(ConfirmationOperation::AddRaidMember {
+                array: array.clone(),
+                device: device.clone(),
+            });
+        }
+
+        let name = self.name.value().trim().to_string();
+        raid::validate_array_name(&name)?;
+        let (level, min) = raid::LEVELS[self.level];
+        let devices: Vec<String> = self
+            .devices
+            .iter()
+            .filter(|(_, _, picked)| *picked)
+            .map(|(device, _, _)| device.clone())
+            .collect();
+        if devices.len() < min {
+            return Err(anyhow!(
+                "Pick at least {} devices for {} with Space",
+                min,
+                level
+            ));
+        }
+        Ok(ConfirmationOperation::CreateRaid {
+            name,
+            level: level.to_string(),
+            devices,
+        })
+    }
+}
+
+/// md arrays and their members, read through the helper since
+/// `mdadm --detail` needs root. Running syncs are followed in between
+/// from /proc/mdstat.
+#[derive(Debug, Default)]
+pub struct RaidViewState {
+    pub show_dialog: bool,
+    pub loading: bool,
+    pub error: Option<String>,
+    pub report: RaidReport,
+    /// Running arrays followed by stopped ones.
+    pub state: TableState,
+    pub member_state: TableState,
+    pub members_focused: bool,
+    pub form: Option<RaidForm>,
+    /// Array whose resync the progress dialog follows once the next report
+    /// shows it, set when devices were given to it.
+    pub follow: Option<String>,
+    pub polled: Option<Instant>,
+}
+
+impl RaidViewState {
+    pub fn rows(&self) -> usize {
+        self.report.arrays.len() + self.report.stopped.len()
+    }
+
+    pub fn selected_array(&self) -> Option<&RaidArray> {
+        self.report.arrays.get(self.state.selected()?)
+    }
+
+    pub fn selected_stopped(&self) -> Option<&StoppedArray> {
+        let idx = self.state.selected()?.checked_sub(self.report.arrays.len())?;
+        self.report.stopped.get(idx)
+    }
+
+    pub fn selected_member(&self) -> Option<&RaidMember> {
+        self.selected_array()?
+            .members
+            .get(self.member_state.selected()?)
+    }
+
+    /// Moves the selection of the focused table. Another array starts at
+    /// its first member.
+    pub fn move_selection(&mut self, down: bool) {
+        let members = |view: &Self| view.selected_array().map_or(0, |array| array.members.len());
+        let rows = match self.members_focused {
+            true => members(self),
+            false => self.rows(),
+        };
+        let state = match self.members_focused {
+            true => &mut self.member_state,
+            false => &mut self.state,
+        };
+        state.select(state.selected().map(|idx| match down {
+            true => (idx + 1).min(rows.saturating_sub(1)),
+            false => idx.saturating_sub(1),
+        }));
+        if !self.members_focused {
+            self.member_state.select((members(self) > 0).then_some(0));
+        }
+    }
+
+    /// Keeps the selections in range after the report was reloaded.
+    pub fn set_report(&mut self, report: RaidReport) {
+        self.report = report;
+        let clamp = |selected: Option<usize>, rows: usize| match selected {
+            _ if rows == 0 => None,
+            Some(idx) => Some(idx.min(rows - 1)),
+            None => Some(0),
+        };
+        self.state.select(clamp(self.state.selected(), self.rows()));
+        let members = self.selected_array().map_or(0, |array| array.members.len());
+        self.member_state
+            .select(clamp(self.member_state.selected(), members));
+    }
+}
+
+/// What the form in the subvolume view creates.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubvolumeAction {
+    Create,
+    /// A read-only snapshot of subvolume `id`.
+    Snapshot { id: u64, path: String },
+}
+
+/// The path of a new subvolume or snapshot, relative to the mount point.
+#[derive(Debug)]
+pub struct SubvolumeForm {
+    pub action: SubvolumeAction,
+    pub input: Input,
+    pub error: Option<String>,
+}
+
+impl SubvolumeForm {
+    pub fn new(action: SubvolumeAction, path: String) -> Self {
+        Self {
+            action,
+            input: Input::new(path),
+            error: None,
+        }
+    }
+
+    pub fn title(&self) -> String {
+        match &self.action {
+            SubvolumeAction::Create => "New subvolume".to_string(),
+            SubvolumeAction::Snapshot { path, .. } => format!("Read-only snapshot of {}", path),
+        }
+    }
+
+    /// The request creating the subvolume or snapshot, or why the path is
+    /// invalid. Neither touches existing data, so both run unconfirmed.
+    pub fn request(&self, device: &str) -> Result<Request> {
+        let path = self.input.value().trim().to_string();
+        btrfs::validate_path(&path)?;
+        let device = device.to_string();
+        Ok(match self.action {
+            SubvolumeAction::Create => Request::CreateSubvolume { device, path },
+            SubvolumeAction::Snapshot { id, .. } => {
+                Request::SnapshotSubvolume { device, id, path }
+            }
+        })
+    }
+}
+
+/// The subvolumes of the mounted btrfs filesystem on `device`, read
+/// through the helper since `btrfs subvolume list` needs root.
+#[derive(Debug, Default)]
+pub struct SubvolumeViewState {
+    pub show_dialog: bool,
+    pub loading: bool,
+    pub error: Option<String>,
+    /// The partition, or the mapper device of an unlocked one.
+    pub device: String,
+    pub list: SubvolumeList,
+    pub state: TableState,
+    pub form: Option<SubvolumeForm>,
+}
+
+impl SubvolumeViewState {
+    pub fn selected(&self) -> Option<&Subvolume> {
+        self.list.subvolumes.get(self.state.selected()?)
+    }
+
+    /// Keeps the selection in range after the list was reloaded.
+    pub fn set_list(&mut self, list: SubvolumeList) {
+        self.list = list;
+        let rows = self.list.subvolumes.len();
+        self.state.select(match self.state.selected() {
+            _ if rows == 0 => None,
+            Some(idx) => Some(idx.min(rows - 1)),
+            None => Some(0),
+        });
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum FocusedBlock {
     Disks,
     Partitions,
+    Queue,
     DiskInfo,
 }
 
@@ -88,6 +882,7 @@ pub enum PartitionDialogMode {
 #[derive(Debug, Clone, PartialEq)]
 pub enum CreatePartitionStep {
     EnterSize,
+    SelectType,
     SelectFilesystem,
 }
 
@@ -98,6 +893,10 @@ pub struct ProgressState {
     pub disk_name: String,
     pub disk_model: String,
     pub spinner_index: usize,
+    pub percent: Option<f64>,
+    /// The md array whose resync the dialog shows. Unlike an operation it
+    /// can be hidden, since the array keeps syncing.
+    pub raid: Option<String>,
 }
 
 #[derive(Debug)]
@@ -105,6 +904,33 @@ pub struct FormatDialogState {
     pub show_dialog: bool,
     pub type_state: ListState,
     pub encrypt_mode: bool,
+    /// Label for the new filesystem; not offered in encrypt mode.
+    pub label_input: Input,
+    pub label_focused: bool,
+}
+
+impl FormatDialogState {
+    pub fn open(encrypt_mode: bool) -> Self {
+        Self {
+            show_dialog: true,
+            encrypt_mode,
+            ..Self::default()
+        }
+    }
+
+    /// The entered label, or `None` to leave it unset.
+    pub fn label(&self) -> Option<String> {
+        let label = self.label_input.value();
+        (!label.is_empty()).then(|| label.to_string())
+    }
+
+    /// Why the entered label does not fit the selected filesystem.
+    pub fn label_error(&self, fs_type: &FilesystemType) -> Option<String> {
+        let label = self.label()?;
+        fslabel::validate_label(fs_type.as_str(), &label)
+            .err()
+            .map(|e| e.to_string())
+    }
 }
 
 impl Default for FormatDialogState {
@@ -115,10 +941,42 @@ impl Default for FormatDialogState {
             show_dialog: false,
             encrypt_mode: false,
             type_state,
+            label_input: Input::default(),
+            label_focused: false,
         }
     }
 }
 
+/// Relabelling of an existing filesystem. The label is checked against the
+/// filesystem's limits as it is typed.
+#[derive(Debug, Default)]
+pub struct LabelDialogState {
+    pub show_dialog: bool,
+    pub device: String,
+    pub fs_type: String,
+    pub input: Input,
+}
+
+impl LabelDialogState {
+    pub fn open(partition: &Partition) -> Self {
+        Self {
+            show_dialog: true,
+            device: partition
+                .mapper_device
+                .clone()
+                .unwrap_or(partition.name.clone()),
+            fs_type: partition.filesystem.clone().unwrap_or_default(),
+            input: Input::new(partition.label.clone().unwrap_or_default()),
+        }
+    }
+
+    pub fn error(&self) -> Option<String> {
+        fslabel::validate_label(&self.fs_type, self.input.value())
+            .err()
+            .map(|e| e.to_string())
+    }
+}
+
 #[derive(Debug)]
 pub struct PartitionDialogState {
     pub show_dialog: bool,
@@ -128,6 +986,17 @@ pub struct PartitionDialogState {
     pub table_types: Vec<String>,
     pub size_input: Input,
     pub new_partition_fs_state: ListState,
+    /// Types offered for the new partition, for the disk's table type.
+    pub part_types: Vec<&'static PartitionType>,
+    pub part_type_state: ListState,
+    /// The free space the new partition goes into.
+    pub gap: Option<FreeSpace>,
+}
+
+impl PartitionDialogState {
+    pub fn selected_part_type(&self) -> Option<&'static PartitionType> {
+        self.part_types.get(self.part_type_state.selected()?).copied()
+    }
 }
 
 impl Default for PartitionDialogState {
@@ -145,6 +1014,9 @@ impl Default for PartitionDialogState {
             table_types: vec!["gpt".to_string(), "msdos".to_string()],
             size_input: Input::default(),
             new_partition_fs_state,
+            part_types: Vec::new(),
+            part_type_state: ListState::default(),
+            gap: None,
         }
     }
 }
@@ -156,6 +1028,486 @@ pub struct ResizeDialogState {
 }
 
 
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum SwapFileField {
+    #[default]
+    Path,
+    Size,
+}
+
+/// Swap file creation on a mounted filesystem. The swap files already
+/// active there are listed, and entering one of their paths disables it.
+#[derive(Debug, Default)]
+pub struct SwapFileDialogState {
+    pub show_dialog: bool,
+    pub mount_point: String,
+    pub active: Vec<SwapArea>,
+    pub path_input: Input,
+    pub size_input: Input,
+    pub focus: SwapFileField,
+}
+
+impl SwapFileDialogState {
+    pub fn open(partition: &Partition) -> Self {
+        let mount_point = partition.mount_point.clone().unwrap_or_default();
+        let path = match partition.swap_files.first() {
+            Some(swap) => swap.path.clone(),
+            None => format!("{}/swapfile", mount_point.trim_end_matches('/')),
+        };
+        Self {
+            show_dialog: true,
+            mount_point,
+            active: partition.swap_files.clone(),
+            path_input: Input::new(path),
+            size_input: Input::default(),
+            focus: SwapFileField::Path,
+        }
+    }
+
+    /// The active swap file at the entered path, if there is one.
+    pub fn selected_active(&self) -> Option<&SwapArea> {
+        let path = self.path_input.value().trim();
+        self.active.iter().find(|swap| swap.path == path)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum MountField {
+    #[default]
+    Target,
+    Options,
+    Compress,
+    Subvol,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MountToggle {
+    ReadOnly,
+    Noatime,
+    Owner,
+    Remember,
+}
+
+impl MountToggle {
+    pub fn label(&self) -> &'static str {
+        match self {
+            MountToggle::ReadOnly => "Read-only",
+            MountToggle::Noatime => "Do not update access times (noatime)",
+            MountToggle::Owner => "Owned by you (FAT, exFAT, NTFS)",
+            MountToggle::Remember => "Remember for this filesystem",
+        }
+    }
+}
+
+/// Mount point and options for mounting a filesystem, filled in from the
+/// settings remembered for its UUID. The filesystem inside an unlocked LUKS
+/// device is not known here, so every option is offered for it.
+#[derive(Debug, Default)]
+pub struct MountDialogState {
+    pub show_dialog: bool,
+    pub device: String,
+    pub fs_type: Option<String>,
+    pub uuid: Option<String>,
+    pub target_input: Input,
+    pub toggles: Vec<(MountToggle, bool)>,
+    pub toggle_state: ListState,
+    pub compress_input: Input,
+    pub subvol_input: Input,
+    pub focus: MountField,
+}
+
+impl MountDialogState {
+    pub fn open(partition: &Partition, remembered: Option<&MountDefaults>) -> Self {
+        let device = partition
+            .mapper_device
+            .clone()
+            .unwrap_or(partition.name.clone());
+        let fs_type = partition
+            .filesystem
+            .clone()
+            .filter(|_| partition.mapper_device.is_none());
+        let defaults = remembered.cloned().unwrap_or_else(|| MountDefaults {
+            owner: fs_type.as_deref().is_some_and(mount::supports_owner),
+            ..MountDefaults::default()
+        });
+
+        let mut dialog = Self {
+            show_dialog: true,
+            target_input: Input::new(
+                defaults
+                    .target
+                    .clone()
+                    .unwrap_or_else(|| format!("/mnt/{}", device)),
+            ),
+            device,
+            fs_type,
+            uuid: partition.uuid.clone(),
+            toggles: vec![
+                (MountToggle::ReadOnly, defaults.read_only),
+                (MountToggle::Noatime, defaults.noatime),
+            ],
+            toggle_state: ListState::default(),
+            compress_input: Input::new(defaults.compress.clone().unwrap_or_default()),
+            subvol_input: Input::new(defaults.subvol.clone().unwrap_or_default()),
+            focus: MountField::Target,
+        };
+        if dialog.offers(mount::supports_owner) {
+            dialog.toggles.push((MountToggle::Owner, defaults.owner));
+        }
+        if dialog.uuid.is_some() {
+            dialog
+                .toggles
+                .push((MountToggle::Remember, remembered.is_some()));
+        }
+        dialog.toggle_state.select(Some(0));
+        dialog
+    }
+
+    /// Whether an option that only some filesystems take is offered.
+    pub fn offers(&self, supports: fn(&str) -> bool) -> bool {
+        self.fs_type.as_deref().is_none_or(supports)
+    }
+
+    pub fn fields(&self) -> Vec<MountField> {
+        let mut fields = vec![MountField::Target, MountField::Options];
+        if self.offers(mount::supports_compress) {
+            fields.push(MountField::Compress);
+            fields.push(MountField::Subvol);
+        }
+        fields
+    }
+
+    pub fn toggled(&self, toggle: MountToggle) -> bool {
+        self.toggles.iter().any(|(t, on)| *t == toggle && *on)
+    }
+
+    /// The settings as entered, for saving under the filesystem's UUID.
+    pub fn defaults(&self) -> MountDefaults {
+        let compress = self.compress_input.value().trim();
+        let subvol = self.subvol_input.value().trim();
+        MountDefaults {
+            target: Some(self.target_input.value().trim().to_string()),
+            read_only: self.toggled(MountToggle::ReadOnly),
+            noatime: self.toggled(MountToggle::Noatime),
+            owner: self.toggled(MountToggle::Owner),
+            compress: (!compress.is_empty() && self.offers(mount::supports_compress))
+                .then(|| compress.to_string()),
+            subvol: (!subvol.is_empty() && self.offers(mount::supports_compress))
+                .then(|| subvol.to_string()),
+        }
+    }
+
+    /// The mount request, or why the entered settings are invalid.
+    pub fn request(&self) -> Result<Request> {
+        let defaults = self.defaults();
+        if let Some(compress) = &defaults.compress {
+            mount::validate_compress(compress)?;
+        }
+        if let Some(subvol) = &defaults.subvol {
+            mount::validate_subvol(subvol)?;
+        }
+        let options = defaults.options();
+        let target = defaults.target.filter(|target| {
+            !target.is_empty() && *target != format!("/mnt/{}", self.device)
+        });
+        Ok(Request::Mount {
+            device: self.device.clone(),
+            target,
+            options,
+        })
+    }
+}
+
+/// The table an entry read at boot goes into.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BootTable {
+    #[default]
+    Fstab,
+    Crypttab,
+}
+
+impl BootTable {
+    /// The tables a partition can have entries in: fstab for filesystems,
+    /// crypttab for LUKS, and both for unlocked LUKS.
+    pub fn for_partition(partition: &Partition) -> Vec<BootTable> {
+        let mut tables = Vec::new();
+        if !partition.is_encrypted || partition.mapper_device.is_some() {
+            tables.push(BootTable::Fstab);
+        }
+        if partition.is_encrypted {
+            tables.push(BootTable::Crypttab);
+        }
+        tables
+    }
+
+    pub fn path(&self) -> &'static str {
+        match self {
+            BootTable::Fstab => fstab::FSTAB,
+            BootTable::Crypttab => fstab::CRYPTTAB,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum BootTableField {
+    #[default]
+    Target,
+    Options,
+    Pass,
+}
+
+/// An fstab or crypttab entry being added or edited. For crypttab the
+/// target is the mapper name and there is no pass number.
+#[derive(Debug, Default)]
+pub struct BootTableDialogState {
+    pub show_dialog: bool,
+    pub table: BootTable,
+    /// Both tables apply to the partition.
+    pub switchable: bool,
+    /// The partition, or the mapper of an unlocked one for fstab.
+    pub device: String,
+    pub swap: bool,
+    pub target_input: Input,
+    pub options_input: Input,
+    pub pass_input: Input,
+    pub focus: BootTableField,
+    /// Source (fstab) or name (crypttab) of the entry that is replaced.
+    pub existing: Option<String>,
+    /// Whether that entry names a device that is gone.
+    pub stale: bool,
+}
+
+impl BootTableDialogState {
+    /// Starts from the partition's current entry, or else from its
+    /// remembered mount settings. An entry whose device is gone and that
+    /// has the same mount point or mapper name is replaced.
+    pub fn open(
+        partition: &Partition,
+        tables: &BootTables,
+        table: BootTable,
+        remembered: Option<&MountDefaults>,
+    ) -> Self {
+        let switchable = BootTable::for_partition(partition).len() > 1;
+        match table {
+            BootTable::Fstab => {
+                let device = partition
+                    .mapper_device
+                    .clone()
+                    .unwrap_or(partition.name.clone());
+                let path = match &partition.mapper_device {
+                    Some(mapper) => format!("/dev/mapper/{}", mapper),
+                    None => format!("/dev/{}", partition.name),
+                };
+                let entry = tables.fstab_entry(&fstab::canonical_path(&path).unwrap_or(path));
+                let fs_type = partition.filesystem.clone().unwrap_or_default();
+                let swap = partition.swap.is_some() || fs_type == "swap";
+                let target = match entry {
+                    _ if swap => "none".to_string(),
+                    Some(entry) => entry.target.clone(),
+                    None => remembered
+                        .and_then(|defaults| defaults.target.clone())
+                        .or(partition.mount_point.clone())
+                        .unwrap_or_else(|| format!("/mnt/{}", device)),
+                };
+                let stale = match entry {
+                    Some(_) => None,
+                    None => tables
+                        .missing_fstab()
+                        .find(|stale| !swap && stale.target == target),
+                };
+                let options = match entry {
+                    Some(entry) => entry.options.clone(),
+                    None => remembered
+                        .map(|defaults| defaults.options().fstab_field())
+                        .unwrap_or_else(|| "defaults".to_string()),
+                };
+                let pass = entry.map_or_else(|| fstab::default_pass(&fs_type), |entry| entry.pass);
+                Self {
+                    show_dialog: true,
+                    table,
+                    switchable,
+                    device,
+                    swap,
+                    target_input: Input::new(target),
+                    options_input: Input::new(options),
+                    pass_input: Input::new(pass.to_string()),
+                    focus: if swap { BootTableField::Options } else { BootTableField::Target },
+                    existing: entry.or(stale).map(|entry| entry.source.clone()),
+                    stale: stale.is_some(),
+                }
+            }
+            BootTable::Crypttab => {
+                let entry = tables.crypttab_entry(&format!("/dev/{}", partition.name));
+                let name = match entry {
+                    Some(entry) => entry.name.clone(),
+                    None => partition
+                        .mapper_device
+                        .clone()
+                        .unwrap_or_else(|| format!("luks-{}", partition.name)),
+                };
+                let stale = match entry {
+                    Some(_) => None,
+                    None => tables.missing_crypttab().find(|stale| stale.name == name),
+                };
+                Self {
+                    show_dialog: true,
+                    table,
+                    switchable,
+                    device: partition.name.clone(),
+                    swap: false,
+                    target_input: Input::new(name),
+                    options_input: Input::new(
+                        entry.map_or_else(|| "luks".to_string(), |entry| entry.options.clone()),
+                    ),
+                    pass_input: Input::default(),
+                    focus: BootTableField::Target,
+                    existing: entry.or(stale).map(|entry| entry.name.clone()),
+                    stale: stale.is_some(),
+                }
+            }
+        }
+    }
+
+    pub fn fields(&self) -> Vec<BootTableField> {
+        match self.table {
+            BootTable::Fstab if self.swap => vec![BootTableField::Options],
+            BootTable::Fstab => vec![
+                BootTableField::Target,
+                BootTableField::Options,
+                BootTableField::Pass,
+            ],
+            BootTable::Crypttab => vec![BootTableField::Target, BootTableField::Options],
+        }
+    }
+
+    /// The request that adds or replaces the entry, or why the entered
+    /// values are invalid.
+    pub fn request(&self) -> Result<Request> {
+        let target = self.target_input.value().trim().to_string();
+        let options = self.options_input.value().trim().to_string();
+        match self.table {
+            BootTable::Fstab => {
+                fstab::validate_options(&options)?;
+                let pass = match self.pass_input.value().trim().parse() {
+                    Ok(pass @ 0..=2) => pass,
+                    _ => return Err(anyhow!("The pass number is 0, 1 or 2")),
+                };
+                Ok(Request::SetFstabEntry {
+                    device: self.device.clone(),
+                    target,
+                    options,
+                    pass: (!self.swap).then_some(pass),
+                })
+            }
+            BootTable::Crypttab => {
+                if target.is_empty() {
+                    return Err(anyhow!("Enter a name for the unlocked device"));
+                }
+                if !options.is_empty() {
+                    fstab::validate_options(&options)?;
+                }
+                Ok(Request::SetCrypttabEntry {
+                    device: self.device.clone(),
+                    name: target,
+                    options,
+                })
+            }
+        }
+    }
+
+    /// The request that removes the entry being edited, if there is one.
+    pub fn removal(&self) -> Option<Request> {
+        let existing = self.existing.clone()?;
+        Some(match self.table {
+            BootTable::Fstab => Request::RemoveFstabEntry { source: existing },
+            BootTable::Crypttab => Request::RemoveCrypttabEntry { name: existing },
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TypeDialogField {
+    #[default]
+    Type,
+    Name,
+    Flags,
+}
+
+/// Type, GPT name and flags of an existing partition being edited. The
+/// original values are kept so that only changes are sent.
+#[derive(Debug, Default)]
+pub struct PartitionTypeDialogState {
+    pub show_dialog: bool,
+    pub partition: String,
+    pub gpt: bool,
+    pub types: Vec<&'static PartitionType>,
+    pub type_state: ListState,
+    pub original_type: Option<&'static str>,
+    pub name_input: Input,
+    pub original_name: String,
+    pub flags: Vec<(PartitionFlag, bool)>,
+    pub flag_state: ListState,
+    pub original_flags: Vec<PartitionFlag>,
+    pub focus: TypeDialogField,
+}
+
+impl PartitionTypeDialogState {
+    pub fn open(partition: &Partition, table_type: Option<&str>) -> Self {
+        let gpt = table_type == Some("gpt");
+        let types = parttype::types_for(table_type);
+        let current = partition.part_type.as_deref().and_then(parttype::lookup);
+        let mut type_state = ListState::default();
+        type_state.select(current.and_then(|current| {
+            types.iter().position(|t| t.alias == current.alias)
+        }));
+        // legacy_boot only exists on GPT.
+        let flags = PartitionFlag::ALL
+            .into_iter()
+            .filter(|flag| gpt || *flag != PartitionFlag::LegacyBoot)
+            .map(|flag| (flag, partition.flags.contains(&flag)))
+            .collect();
+        let mut flag_state = ListState::default();
+        flag_state.select(Some(0));
+        let name = partition.part_label.clone().unwrap_or_default();
+
+        Self {
+            show_dialog: true,
+            partition: partition.name.clone(),
+            gpt,
+            types,
+            type_state,
+            original_type: current.map(|t| t.alias),
+            name_input: Input::new(name.clone()),
+            original_name: name,
+            flags,
+            flag_state,
+            original_flags: partition.flags.clone(),
+            focus: TypeDialogField::Type,
+        }
+    }
+
+    /// The type alias, name and flag changes that differ from the
+    /// partition's current values.
+    pub fn changes(&self) -> (Option<String>, Option<String>, Vec<FlagChange>) {
+        let part_type = self
+            .type_state
+            .selected()
+            .and_then(|i| self.types.get(i))
+            .map(|t| t.alias)
+            .filter(|alias| Some(*alias) != self.original_type)
+            .map(str::to_string);
+        let name = Some(self.name_input.value().to_string())
+            .filter(|name| self.gpt && *name != self.original_name);
+        let flags = self
+            .flags
+            .iter()
+            .filter(|(flag, enabled)| self.original_flags.contains(flag) != *enabled)
+            .map(|&(flag, enabled)| FlagChange { flag, enabled })
+            .collect();
+        (part_type, name, flags)
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum PassphraseOperation {
     Unlock,
@@ -192,6 +1544,16 @@ pub struct App {
     pub running: bool,
     pub focused_block: FocusedBlock,
     pub disks: Vec<Disk>,
+    pub smart_cache: SmartCache,
+    /// Disks whose SMART data is being read in the background.
+    pub smart_probes: HashSet<String>,
+    pub monitor: Monitor,
+    /// Time between background SMART readings, from the config file. Zero
+    /// turns them off.
+    pub smart_interval: Duration,
+    pub smart_polled: Instant,
+    /// Lines the disk information view is scrolled down by.
+    pub disk_info_scroll: usize,
     pub disks_state: TableState,
     pub partitions_state: TableState,
     pub notifications: Vec<Notification>,
@@ -202,21 +1564,36 @@ pub struct App {
     pub format_dialog: FormatDialogState,
     pub partition_dialog: PartitionDialogState,
     pub resize_dialog: ResizeDialogState,
+    pub part_type_dialog: PartitionTypeDialogState,
+    pub swap_file_dialog: SwapFileDialogState,
+    pub label_dialog: LabelDialogState,
+    pub mount_dialog: MountDialogState,
+    pub boot_table_dialog: BootTableDialogState,
     pub passphrase_dialog: PassphraseDialogState,
     pub confirmation_dialog: ConfirmationDialog,
+    pub queue: OperationQueue,
+    pub audit_log: AuditLogState,
+    pub check_log: CheckLogState,
+    pub restore_dialog: RestoreDialogState,
+    pub lvm: LvmViewState,
+    pub raid: RaidViewState,
+    pub subvolumes: SubvolumeViewState,
     pub theme: Theme,
     pub helper: Option<Arc<HelperConnection>>,
+    /// Alignment for new and resized partitions, from the config file.
+    pub alignment: Alignment,
+    /// Units sizes are shown in, from the config file.
+    pub size_units: SizeUnits,
+    /// Mount settings remembered per filesystem UUID, from the config file.
+    pub mount_defaults: HashMap<String, MountDefaults>,
+    pub boot_tables: BootTables,
 }
 
 impl App {
     pub async fn new(helper: Option<Arc<HelperConnection>>) -> AppResult<Self> {
-        let devices = list_block_devices().await?;
-        let mut disks = Vec::new();
-
-        for device in devices {
-            let smart_data = get_smart_data(&device.name).await.ok();
-            disks.push(Disk::new(device, smart_data));
-        }
+        let mut smart_cache = SmartCache::default();
+        let devices = scan_block_devices(&SystemRunner).await?;
+        let disks = smart_cache.load(&SystemRunner, devices).await;
 
         let mut disks_state = TableState::default();
         if !disks.is_empty() {
@@ -224,16 +1601,22 @@ impl App {
         }
 
         let mut partitions_state = TableState::default();
-        if !disks.is_empty() && !disks[0].device.partitions.is_empty() {
+        if !disks.is_empty() && !layout_rows(&disks[0].device).is_empty() {
             partitions_state.select(Some(0));
         }
 
         let filesystem_types = FilesystemType::all();
 
-        Ok(Self {
+        let mut app = Self {
             running: true,
             focused_block: FocusedBlock::Disks,
             disks,
+            smart_cache,
+            smart_probes: HashSet::new(),
+            monitor: Monitor::default(),
+            smart_interval: Duration::ZERO,
+            smart_polled: Instant::now(),
+            disk_info_scroll: 0,
             disks_state,
             partitions_state,
             notifications: Vec::new(),
@@ -244,53 +1627,162 @@ impl App {
             format_dialog: FormatDialogState::default(),
             partition_dialog: PartitionDialogState::default(),
             resize_dialog: ResizeDialogState::default(),
+            part_type_dialog: PartitionTypeDialogState::default(),
+            swap_file_dialog: SwapFileDialogState::default(),
+            label_dialog: LabelDialogState::default(),
+            mount_dialog: MountDialogState::default(),
+            boot_table_dialog: BootTableDialogState::default(),
             passphrase_dialog: PassphraseDialogState::default(),
             confirmation_dialog: ConfirmationDialog::default(),
+            queue: OperationQueue::default(),
+            audit_log: AuditLogState::default(),
+            check_log: CheckLogState::default(),
+            restore_dialog: RestoreDialogState::default(),
+            lvm: LvmViewState::default(),
+            raid: RaidViewState::default(),
+            subvolumes: SubvolumeViewState::default(),
             theme: Theme::new(),
             helper,
-        })
+            alignment: Alignment::default(),
+            size_units: SizeUnits::default(),
+            mount_defaults: HashMap::new(),
+            boot_tables: BootTables::default(),
+        };
+        app.load_boot_tables();
+        Ok(app)
     }
 
     pub async fn refresh(&mut self) -> AppResult<()> {
-        let devices = list_block_devices().await?;
+        let devices = scan_block_devices(&SystemRunner).await?;
         let selected_disk_index = self.disks_state.selected();
         let selected_partition_index = self.partitions_state.selected();
+        let selected_disk_name = self.selected_disk().map(|d| d.device.name.clone());
+        let selected_partition_name = self.selected_partition().map(|p| p.name.clone());
+        let selected_gap = self.selected_free_space();
 
-        let mut disks = Vec::new();
-        for device in devices {
-            let smart_data = get_smart_data(&device.name).await.ok();
-            disks.push(Disk::new(device, smart_data));
-        }
+        let disks = self.smart_cache.load(&SystemRunner, devices).await;
 
+        self.notify_hotplug(&disks);
         self.disks = disks;
+        self.load_boot_tables();
+        self.monitor
+            .retain(|name| self.disks.iter().any(|disk| disk.device.name == name));
+        for name in self.smart_cache.probed().to_vec() {
+            self.record_smart_data(&name);
+        }
+
+        // Keep the same disk and partition selected when devices come and go
+        // around them, falling back to the old position.
+        let disk_idx = selected_disk_name
+            .and_then(|name| self.disks.iter().position(|d| d.device.name == name))
+            .or(selected_disk_index.filter(|&idx| idx < self.disks.len()))
+            .or((!self.disks.is_empty()).then_some(0));
+        self.disks_state.select(disk_idx);
+
+        if let Some(disk) = disk_idx.and_then(|idx| self.disks.get(idx)) {
+            let rows = layout_rows(&disk.device);
+            let part_idx = selected_partition_name
+                .and_then(|name| {
+                    rows.iter().position(
+                        |row| matches!(row, LayoutRow::Partition(p) if p.name == name),
+                    )
+                })
+                .or_else(|| {
+                    let gap = selected_gap?;
+                    rows.iter().position(
+                        |row| matches!(row, LayoutRow::Free(free) if free.contains(gap.start)),
+                    )
+                })
+                .or(selected_partition_index.filter(|&idx| idx < rows.len()))
+                .or((!rows.is_empty()).then_some(0));
+            self.partitions_state.select(part_idx);
+        } else {
+            self.partitions_state.select(None);
+        }
 
-        if let Some(idx) = selected_disk_index {
-            if idx < self.disks.len() {
-                self.disks_state.select(Some(idx));
-            } else if !self.disks.is_empty() {
-                self.disks_state.select(Some(0));
-            } else {
-                self.disks_state.select(None);
+        Ok(())
+    }
+
+    /// Notifies about disks that appeared or disappeared since the last refresh.
+    fn notify_hotplug(&mut self, disks: &[Disk]) {
+        for disk in disks {
+            if self.disks.iter().any(|d| d.device.name == disk.device.name) {
+                continue;
             }
+            let message = match &disk.device.model {
+                Some(model) => format!("{} attached: {} {}", disk.device.name, model, disk.size_str(self.size_units)),
+                None => format!("{} attached: {}", disk.device.name, disk.size_str(self.size_units)),
+            };
+            self.notifications
+                .push(Notification::new(message, NotificationLevel::Info));
         }
+        for disk in &self.disks {
+            if !disks.iter().any(|d| d.device.name == disk.device.name) {
+                self.notifications.push(Notification::new(
+                    format!("{} removed", disk.device.name),
+                    NotificationLevel::Info,
+                ));
+            }
+        }
+    }
 
-        if let Some(disk_idx) = self.disks_state.selected()
-            && disk_idx < self.disks.len() {
-                let partitions_len = self.disks[disk_idx].device.partitions.len();
-                if let Some(part_idx) = selected_partition_index {
-                    if part_idx < partitions_len {
-                        self.partitions_state.select(Some(part_idx));
-                    } else if partitions_len > 0 {
-                        self.partitions_state.select(Some(0));
-                    } else {
-                        self.partitions_state.select(None);
-                    }
-                } else if partitions_len > 0 {
-                    self.partitions_state.select(Some(0));
-                }
+    /// Rereads fstab and crypttab, warning about entries whose device has
+    /// gone since the last look.
+    fn load_boot_tables(&mut self) {
+        let tables = BootTables::load();
+        let known = self.boot_tables.warnings();
+        for warning in tables.warnings() {
+            if !known.contains(&warning) {
+                self.notifications
+                    .push(Notification::new(warning, NotificationLevel::Warning));
             }
+        }
+        self.boot_tables = tables;
+    }
 
-        Ok(())
+    /// Stores SMART data read outside of a refresh.
+    pub fn update_smart_data(&mut self, name: &str, smart_data: Option<SmartData>) {
+        if !smart_data.as_ref().is_some_and(SmartData::test_in_progress) {
+            self.smart_probes.remove(name);
+        }
+        if let Some(disk) = self.disks.iter_mut().find(|disk| disk.device.name == name) {
+            self.smart_cache.store(&disk.device, smart_data.clone());
+            disk.smart_data = smart_data;
+        }
+        self.record_smart_data(name);
+    }
+
+    /// Sets up background SMART monitoring and records the readings taken
+    /// at startup, so that disks already past a threshold are reported.
+    pub fn start_monitoring(&mut self, thresholds: Thresholds, interval: Duration) {
+        self.monitor = Monitor::new(thresholds);
+        self.smart_interval = interval;
+        self.smart_polled = Instant::now();
+        let names: Vec<String> = self.disks.iter().map(|disk| disk.device.name.clone()).collect();
+        for name in names {
+            self.record_smart_data(&name);
+        }
+    }
+
+    /// Adds the current SMART data of a disk to its history and notifies
+    /// about the alerts it raises.
+    pub fn record_smart_data(&mut self, name: &str) {
+        let Some(smart_data) = self
+            .disks
+            .iter()
+            .find(|disk| disk.device.name == name)
+            .and_then(|disk| disk.smart_data.as_ref())
+        else {
+            return;
+        };
+        for alert in self.monitor.record(name, smart_data) {
+            let level = match alert.level {
+                Level::Ok => NotificationLevel::Info,
+                Level::Warning => NotificationLevel::Warning,
+                Level::Failing => NotificationLevel::Error,
+            };
+            self.notifications.push(Notification::new(alert.message, level));
+        }
     }
 
     pub async fn tick(&mut self) -> AppResult<()> {
@@ -308,13 +1800,153 @@ impl App {
         self.disks_state.selected().and_then(|i| self.disks.get(i))
     }
 
+    /// The selected row of the partitions table, which lists free space
+    /// between the partitions.
+    pub fn selected_row(&self) -> Option<LayoutRow<'_>> {
+        let disk = self.selected_disk()?;
+        let row_idx = self.partitions_state.selected()?;
+        layout_rows(&disk.device).get(row_idx).copied()
+    }
+
     pub fn selected_partition(&self) -> Option<&crate::partition::Partition> {
-        if let Some(disk_idx) = self.disks_state.selected()
-            && let Some(disk) = self.disks.get(disk_idx)
-                && let Some(part_idx) = self.partitions_state.selected() {
-                    return disk.device.partitions.get(part_idx);
+        match self.selected_row()? {
+            LayoutRow::Partition(part) => Some(part),
+            LayoutRow::Free(_) => None,
+        }
+    }
+
+    pub fn selected_free_space(&self) -> Option<FreeSpace> {
+        match self.selected_row()? {
+            LayoutRow::Free(gap) => Some(gap),
+            LayoutRow::Partition(_) => None,
+        }
+    }
+
+    /// Partitions that can go into an array: not mounted, not swap and not
+    /// a member of an array, volume group or btrfs filesystem.
+    pub fn raid_candidates(&self) -> Vec<(String, u64)> {
+        self.disks
+            .iter()
+            .flat_map(|disk| &disk.device.partitions)
+            .filter(|p| !p.is_mounted && p.swap.is_none() && p.mapper_device.is_none())
+            .filter(|p| p.holder.is_none())
+            .filter(|p| self.raid.report.array_of(&p.name).is_none())
+            .map(|p| (p.name.clone(), p.size))
+            .collect()
+    }
+
+    /// Stores a RAID report, following the resync of an array that was
+    /// just given new devices.
+    pub fn set_raid_report(&mut self, report: RaidReport) {
+        self.raid.set_report(report);
+        if let Some(name) = self.raid.follow.take()
+            && let Some(progress) = self
+                .raid
+                .report
+                .array(&name)
+                .and_then(|array| array.progress.clone())
+        {
+            self.progress = ProgressState {
+                show_dialog: true,
+                message: format!("{}: {}", name, progress.summary()),
+                percent: progress.percent,
+                raid: Some(name),
+                ..ProgressState::default()
+            };
+        }
+    }
+
+    /// Follows the resync of `array` in the progress dialog.
+    pub fn watch_raid(&mut self, array: &RaidArray) {
+        if let Some(progress) = &array.progress {
+            self.progress = ProgressState {
+                show_dialog: true,
+                message: format!("{}: {}", array.name, progress.summary()),
+                percent: progress.percent,
+                raid: Some(array.name.clone()),
+                ..ProgressState::default()
+            };
+        }
+    }
+
+    pub fn hide_raid_progress(&mut self) {
+        self.progress = ProgressState::default();
+    }
+
+    /// Updates syncs from a fresh read of /proc/mdstat. Returns whether a
+    /// sync shown in the RAID view finished, after which the view should be
+    /// reloaded for the new array state.
+    pub fn update_raid_progress(&mut self, arrays: &[RaidArray]) -> bool {
+        let progress_of = |name: &str| {
+            arrays
+                .iter()
+                .find(|array| array.name == name)
+                .and_then(|array| array.progress.clone())
+        };
+
+        if let Some(name) = self.progress.raid.clone() {
+            match progress_of(&name) {
+                Some(progress) => {
+                    self.progress.message = format!("{}: {}", name, progress.summary());
+                    self.progress.percent = progress.percent;
                 }
-        None
+                None => {
+                    self.hide_raid_progress();
+                    self.notifications.push(Notification::new(
+                        format!("{} is in sync", name),
+                        NotificationLevel::Info,
+                    ));
+                }
+            }
+        }
+
+        let mut finished = false;
+        for array in &mut self.raid.report.arrays {
+            let progress = progress_of(&array.name);
+            finished |= array.progress.is_some() && progress.is_none();
+            array.progress = progress;
+        }
+        finished
+    }
+
+    /// Where the partition typed into the create dialog would go, or why
+    /// its size is not valid.
+    pub fn create_preview(&self) -> Option<anyhow::Result<Placement>> {
+        let disk = self.selected_disk()?;
+        let gap = self.partition_dialog.gap?;
+        Some(layout::place_partition(
+            &[gap],
+            Some(gap.start),
+            self.partition_dialog.size_input.value(),
+            &disk.device.geometry,
+            self.alignment,
+        ))
+    }
+
+    /// The size in bytes the selected partition would be resized to.
+    pub fn resize_preview(&self) -> Option<anyhow::Result<u64>> {
+        let disk = self.selected_disk()?;
+        let partition = self.selected_partition()?;
+        let input = self.resize_dialog.size_input.value();
+        if input.trim().is_empty() {
+            return None;
+        }
+        let geometry = &disk.device.geometry;
+        Some(input.parse::<SizeSpec>().and_then(|spec| {
+            let available = layout::max_partition_size(&disk.device, partition);
+            let size = spec.resolve(available, geometry.logical_sector_size)?;
+            if size == 0 {
+                return Err(anyhow::anyhow!("New size must be greater than zero"));
+            }
+            let size = layout::aligned_resize(partition.size, size, self.alignment.bytes(geometry));
+            if size > available {
+                return Err(anyhow::anyhow!(
+                    "Not enough free space: {} available",
+                    format_bytes(available)
+                ));
+            }
+            Ok(size)
+        }))
     }
 
     pub fn quit(&mut self) {
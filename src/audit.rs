@@ -0,0 +1,214 @@
+use crate::protocol::Request;
+use crate::runner::CommandRunner;
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::process::Output;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_AUDIT_LOG: &str = "/var/log/disktui/audit.jsonl";
+
+/// Command output beyond this many bytes is cut off in the log.
+const MAX_OUTPUT: usize = 4096;
+
+/// An external command run while handling a request.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CommandRecord {
+	pub command: String,
+	pub exit_code: Option<i32>,
+	pub stdout: String,
+	pub stderr: String,
+}
+
+/// One line of the audit log: a request, who sent it and how it ended.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+	pub timestamp: u64,
+	pub uid: Option<u32>,
+	pub request: Request,
+	pub success: bool,
+	pub error: Option<String>,
+	pub commands: Vec<CommandRecord>,
+}
+
+impl AuditEntry {
+	pub fn new(request: &Request, result: &Result<Option<String>>, commands: Vec<CommandRecord>) -> Self {
+		Self {
+			timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+			// pkexec exports the uid of the user that started the helper.
+			uid: std::env::var("PKEXEC_UID").ok().and_then(|uid| uid.parse().ok()),
+			request: redact(request),
+			success: result.is_ok(),
+			error: result.as_ref().err().map(|e| e.to_string()),
+			commands,
+		}
+	}
+}
+
+/// Copy of `request` with passphrases blanked out.
+pub fn redact(request: &Request) -> Request {
+	let mut request = request.clone();
+	match &mut request {
+		Request::CreateEncryptedPartition { passphrase, .. }
+		| Request::UnlockLuks { passphrase, .. }
+		| Request::EncryptPartition { passphrase, .. }
+		| Request::EncryptAndFormat { passphrase, .. } => *passphrase = "[redacted]".to_string(),
+		Request::DryRun { request } => **request = redact(request),
+		_ => {}
+	}
+	request
+}
+
+fn truncate(bytes: &[u8]) -> String {
+	let text = String::from_utf8_lossy(bytes);
+	if text.len() <= MAX_OUTPUT {
+		return text.to_string();
+	}
+	let mut end = MAX_OUTPUT;
+	while !text.is_char_boundary(end) {
+		end -= 1;
+	}
+	format!("{}... (truncated)", &text[..end])
+}
+
+/// Wraps a runner and keeps the command line, exit code and output of every
+/// command it runs. Stdin is never recorded since it carries passphrases.
+pub struct AuditRunner<'a> {
+	inner: &'a dyn CommandRunner,
+	commands: Mutex<Vec<CommandRecord>>,
+}
+
+impl<'a> AuditRunner<'a> {
+	pub fn new(inner: &'a dyn CommandRunner) -> Self {
+		Self {
+			inner,
+			commands: Mutex::new(Vec::new()),
+		}
+	}
+
+	pub fn into_commands(self) -> Vec<CommandRecord> {
+		self.commands.into_inner().unwrap_or_default()
+	}
+
+	fn record(&self, program: &str, args: &[&str], result: &io::Result<Output>) {
+		let command = std::iter::once(program).chain(args.iter().copied()).collect::<Vec<_>>().join(" ");
+		let record = match result {
+			Ok(output) => CommandRecord {
+				command,
+				exit_code: output.status.code(),
+				stdout: truncate(&output.stdout),
+				stderr: truncate(&output.stderr),
+			},
+			Err(e) => CommandRecord {
+				command,
+				exit_code: None,
+				stdout: String::new(),
+				stderr: e.to_string(),
+			},
+		};
+		self.commands.lock().unwrap().push(record);
+	}
+}
+
+impl CommandRunner for AuditRunner<'_> {
+	fn output<'a>(&'a self, program: &'a str, args: &'a [&'a str]) -> BoxFuture<'a, io::Result<Output>> {
+		Box::pin(async move {
+			let result = self.inner.output(program, args).await;
+			self.record(program, args, &result);
+			result
+		})
+	}
+
+	fn output_with_stdin<'a>(
+		&'a self,
+		program: &'a str,
+		args: &'a [&'a str],
+		stdin: &'a [u8],
+	) -> BoxFuture<'a, io::Result<Output>> {
+		Box::pin(async move {
+			let result = self.inner.output_with_stdin(program, args, stdin).await;
+			self.record(program, args, &result);
+			result
+		})
+	}
+
+	fn output_streaming<'a>(
+		&'a self,
+		program: &'a str,
+		args: &'a [&'a str],
+		on_line: &'a mut (dyn FnMut(&str) + Send),
+	) -> BoxFuture<'a, io::Result<Output>> {
+		Box::pin(async move {
+			let result = self.inner.output_streaming(program, args, on_line).await;
+			self.record(program, args, &result);
+			result
+		})
+	}
+
+	fn path_exists(&self, path: &str) -> bool {
+		self.inner.path_exists(path)
+	}
+
+	fn read_dir_names(&self, path: &str) -> io::Result<Vec<String>> {
+		self.inner.read_dir_names(path)
+	}
+
+	fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()> {
+		self.inner.sleep(duration)
+	}
+
+	fn is_dry_run(&self) -> bool {
+		self.inner.is_dry_run()
+	}
+}
+
+/// Append-only JSON-lines log of every request the helper handles.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+	path: PathBuf,
+}
+
+impl AuditLog {
+	pub fn new(path: impl Into<PathBuf>) -> Self {
+		Self { path: path.into() }
+	}
+
+	pub fn append(&self, entry: &AuditEntry) -> Result<()> {
+		if let Some(dir) = self.path.parent() {
+			std::fs::create_dir_all(dir)?;
+		}
+		let mut line = serde_json::to_string(entry)?;
+		line.push('\n');
+		let mut file = OpenOptions::new()
+			.create(true)
+			.append(true)
+			.mode(0o600)
+			.open(&self.path)
+			.with_context(|| format!("Failed to open {}", self.path.display()))?;
+		file.write_all(line.as_bytes())?;
+		Ok(())
+	}
+
+	/// The most recent `limit` entries, oldest first, optionally only those
+	/// acting on `disk`. Lines that fail to parse are skipped.
+	pub fn read(&self, disk: Option<&str>, limit: usize) -> Result<Vec<AuditEntry>> {
+		let contents = match std::fs::read_to_string(&self.path) {
+			Ok(contents) => contents,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+			Err(e) => return Err(e).with_context(|| format!("Failed to read {}", self.path.display())),
+		};
+		let mut entries: Vec<AuditEntry> = contents
+			.lines()
+			.filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok())
+			.filter(|entry| disk.is_none_or(|disk| entry.request.disk() == disk))
+			.collect();
+		let skip = entries.len().saturating_sub(limit);
+		entries.drain(..skip);
+		Ok(entries)
+	}
+}
@@ -0,0 +1,266 @@
+use crate::helper::ResponseWriter;
+use crate::runner::CommandRunner;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const DEFAULT_BACKUP_DIR: &str = "/var/lib/disktui/backups";
+
+/// Backups kept per disk; older ones are removed when a new one is saved.
+const KEEP_BACKUPS: usize = 20;
+
+/// A saved `sfdisk -d` dump. The id is the file name, `<serial>-<timestamp>.sfdisk`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TableBackup {
+	pub id: String,
+	pub timestamp: u64,
+	pub table: String,
+}
+
+/// Reply to `Request::ListBackups`: the disk's current table, if it has one,
+/// and its backups, newest first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupList {
+	pub current: Option<String>,
+	pub backups: Vec<TableBackup>,
+}
+
+/// One line of a table diff.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffLine {
+	Same(String),
+	Removed(String),
+	Added(String),
+}
+
+/// Partition table dumps stored on disk, keyed by disk serial so that a
+/// backup follows the disk even if its device name changes.
+#[derive(Debug, Clone)]
+pub struct BackupStore {
+	dir: PathBuf,
+}
+
+impl BackupStore {
+	pub fn new(dir: impl Into<PathBuf>) -> Self {
+		Self { dir: dir.into() }
+	}
+
+	/// Saves the current table of `disk`. Disks without a partition table
+	/// have nothing to back up. During a dry run the table is read but not
+	/// written.
+	pub async fn backup(&self, runner: &dyn CommandRunner, disk: &str, writer: &mut ResponseWriter) -> Result<()> {
+		let Some(table) = dump_table(runner, disk).await? else {
+			return Ok(());
+		};
+		if runner.is_dry_run() {
+			return Ok(());
+		}
+
+		let serial = disk_serial(runner, disk).await;
+		let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+		let id = format!("{}-{}.sfdisk", serial, timestamp);
+
+		std::fs::create_dir_all(&self.dir).with_context(|| format!("Failed to create {}", self.dir.display()))?;
+		std::fs::write(self.dir.join(&id), table).context("Failed to write partition table backup")?;
+		self.prune(&serial)?;
+
+		writer.notify("info", format!("Saved partition table of {} as {}", disk, id))?;
+		Ok(())
+	}
+
+	/// Backups of `disk`, newest first, together with its current table.
+	pub async fn list(&self, runner: &dyn CommandRunner, disk: &str) -> Result<BackupList> {
+		let serial = disk_serial(runner, disk).await;
+		Ok(BackupList {
+			current: dump_table(runner, disk).await?,
+			backups: self.backups_for(&serial)?,
+		})
+	}
+
+	/// Writes a saved table back to `disk`. The backup must belong to the
+	/// same disk and none of its partitions may be mounted.
+	pub async fn restore(&self, runner: &dyn CommandRunner, disk: &str, id: &str, writer: &mut ResponseWriter) -> Result<()> {
+		if id.contains('/') || id.contains("..") {
+			return Err(anyhow!("Invalid backup name: {}", id));
+		}
+		let serial = disk_serial(runner, disk).await;
+		if !id.starts_with(&format!("{}-", serial)) {
+			return Err(anyhow!("Backup {} does not belong to {}", id, disk));
+		}
+
+		let mounted = runner.output("lsblk", &["-n", "-o", "MOUNTPOINT", &format!("/dev/{}", disk)]).await?;
+		if String::from_utf8_lossy(&mounted.stdout).lines().any(|line| !line.trim().is_empty()) {
+			return Err(anyhow!("{} has mounted partitions. Unmount them first", disk));
+		}
+
+		let table = std::fs::read_to_string(self.dir.join(id)).with_context(|| format!("Failed to read backup {}", id))?;
+		// The dump names the device it came from, which may have changed.
+		let table: String = table
+			.lines()
+			.filter(|line| !line.starts_with("device:"))
+			.map(|line| format!("{}\n", line))
+			.collect();
+
+		writer.progress_start(format!("Restoring partition table of {}...", disk))?;
+		let output = runner
+			.output_with_stdin("sfdisk", &["--force", "--no-reread", &format!("/dev/{}", disk)], table.as_bytes())
+			.await
+			.context("Failed to spawn sfdisk")?;
+		writer.progress_end()?;
+
+		if !output.status.success() {
+			let err = String::from_utf8_lossy(&output.stderr);
+			return Err(anyhow!("Failed to restore partition table: {}", err));
+		}
+
+		let _ = runner.output("partprobe", &[&format!("/dev/{}", disk)]).await;
+		runner.sleep(Duration::from_secs(1)).await;
+
+		writer.notify("info", format!("Restored partition table of {} from {}", disk, id))?;
+		Ok(())
+	}
+
+	fn backups_for(&self, serial: &str) -> Result<Vec<TableBackup>> {
+		let prefix = format!("{}-", serial);
+		let entries = match std::fs::read_dir(&self.dir) {
+			Ok(entries) => entries,
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+			Err(e) => return Err(e).with_context(|| format!("Failed to read {}", self.dir.display())),
+		};
+
+		let mut backups: Vec<TableBackup> = entries
+			.flatten()
+			.filter_map(|entry| {
+				let id = entry.file_name().to_string_lossy().to_string();
+				let timestamp = id.strip_prefix(&prefix)?.strip_suffix(".sfdisk")?.parse().ok()?;
+				let table = std::fs::read_to_string(entry.path()).ok()?;
+				Some(TableBackup { id, timestamp, table })
+			})
+			.collect();
+		backups.sort_by_key(|backup| std::cmp::Reverse(backup.timestamp));
+		Ok(backups)
+	}
+
+	fn prune(&self, serial: &str) -> Result<()> {
+		for old in self.backups_for(serial)?.iter().skip(KEEP_BACKUPS) {
+			let _ = std::fs::remove_file(self.dir.join(&old.id));
+		}
+		Ok(())
+	}
+}
+
+/// The `sfdisk -d` dump of `disk`, or `None` if it has no partition table.
+async fn dump_table(runner: &dyn CommandRunner, disk: &str) -> Result<Option<String>> {
+	let output = runner
+		.output("sfdisk", &["-d", &format!("/dev/{}", disk)])
+		.await
+		.context("Failed to dump partition table")?;
+	if !output.status.success() {
+		return Ok(None);
+	}
+	let table = String::from_utf8_lossy(&output.stdout).to_string();
+	Ok((!table.trim().is_empty()).then_some(table))
+}
+
+/// The disk serial with anything unsafe for a file name replaced. Disks
+/// without a serial (loop devices, some VMs) fall back to their name.
+async fn disk_serial(runner: &dyn CommandRunner, disk: &str) -> String {
+	let serial = runner
+		.output("lsblk", &["-d", "-n", "-o", "SERIAL", &format!("/dev/{}", disk)])
+		.await
+		.ok()
+		.filter(|output| output.status.success())
+		.map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+		.filter(|serial| !serial.is_empty())
+		.unwrap_or_else(|| disk.to_string());
+	serial.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect()
+}
+
+/// Line diff between two table dumps, from `old` to `new`.
+pub fn diff_tables(old: &str, new: &str) -> Vec<DiffLine> {
+	let old: Vec<&str> = old.lines().collect();
+	let new: Vec<&str> = new.lines().collect();
+
+	// Longest common subsequence table, filled from the end.
+	let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+	for i in (0..old.len()).rev() {
+		for j in (0..new.len()).rev() {
+			lcs[i][j] = if old[i] == new[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+		}
+	}
+
+	let (mut i, mut j) = (0, 0);
+	let mut diff = Vec::new();
+	while i < old.len() || j < new.len() {
+		if i < old.len() && j < new.len() && old[i] == new[j] {
+			diff.push(DiffLine::Same(old[i].to_string()));
+			i += 1;
+			j += 1;
+		} else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+			diff.push(DiffLine::Removed(old[i].to_string()));
+			i += 1;
+		} else {
+			diff.push(DiffLine::Added(new[j].to_string()));
+			j += 1;
+		}
+	}
+	diff
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::runner::{FakeResponse, FakeRunner};
+
+	const TABLE: &str = "label: gpt\ndevice: /dev/sdb\n\n/dev/sdb1 : start=2048, size=1000\n";
+
+	fn runner() -> FakeRunner {
+		let runner = FakeRunner::new();
+		runner
+			.on("lsblk -d -n -o SERIAL /dev/sdb", FakeResponse::ok("WD-1234 \n"))
+			.on("sfdisk -d /dev/sdb", FakeResponse::ok(TABLE));
+		runner
+	}
+
+	#[tokio::test]
+	async fn backups_are_keyed_by_serial_and_restored_without_device_line() {
+		let dir = std::env::temp_dir().join(format!("disktui-backups-{}", std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		let store = BackupStore::new(&dir);
+		let runner = runner();
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		store.backup(&runner, "sdb", &mut writer).await.unwrap();
+		let list = store.list(&runner, "sdb").await.unwrap();
+		assert_eq!(list.current.as_deref(), Some(TABLE));
+		assert_eq!(list.backups.len(), 1);
+		assert!(list.backups[0].id.starts_with("WD_1234-"));
+
+		store.restore(&runner, "sdb", &list.backups[0].id, &mut writer).await.unwrap();
+		let sfdisk = runner.position("sfdisk --force --no-reread /dev/sdb").unwrap();
+		let stdin = String::from_utf8(runner.calls()[sfdisk].stdin.clone().unwrap()).unwrap();
+		assert_eq!(stdin, "label: gpt\n\n/dev/sdb1 : start=2048, size=1000\n");
+
+		assert!(store.restore(&runner, "sdb", "OTHER-1.sfdisk", &mut writer).await.is_err());
+		assert!(store.restore(&runner, "sdb", "../WD_1234-1.sfdisk", &mut writer).await.is_err());
+
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn diff_tables_marks_changed_partitions() {
+		let old = "label: gpt\n/dev/sdb1 : start=2048, size=1000\n/dev/sdb2 : start=4096, size=500";
+		let new = "label: gpt\n/dev/sdb1 : start=2048, size=3000";
+
+		assert_eq!(
+			diff_tables(old, new),
+			vec![
+				DiffLine::Same("label: gpt".into()),
+				DiffLine::Removed("/dev/sdb1 : start=2048, size=1000".into()),
+				DiffLine::Removed("/dev/sdb2 : start=4096, size=500".into()),
+				DiffLine::Added("/dev/sdb1 : start=2048, size=3000".into()),
+			]
+		);
+	}
+}
@@ -0,0 +1,274 @@
+use crate::runner::CommandRunner;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+/// The subvolume every btrfs filesystem has at its root.
+pub const TOP_LEVEL_ID: u64 = 5;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subvolume {
+    pub id: u64,
+    pub generation: u64,
+    /// ID of the subvolume this one sits in.
+    pub top_level: u64,
+    /// Path from the top level, such as "@home"; empty for the top level
+    /// itself.
+    pub path: String,
+    pub read_only: bool,
+}
+
+impl Subvolume {
+    pub fn display_path(&self) -> &str {
+        match self.path.as_str() {
+            "" => "<top level>",
+            path => path,
+        }
+    }
+}
+
+/// The subvolumes of a mounted btrfs filesystem.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SubvolumeList {
+    pub mount_point: String,
+    /// The subvolume mounted there, as a path from the top level; empty
+    /// when the top level is mounted.
+    pub mounted: String,
+    pub default_id: u64,
+    pub subvolumes: Vec<Subvolume>,
+}
+
+impl SubvolumeList {
+    /// Lists the subvolumes of the filesystem mounted at `mount_point`,
+    /// where `fs_root` is findmnt's FSROOT, such as "/@".
+    pub async fn load(
+        runner: &dyn CommandRunner,
+        mount_point: &str,
+        fs_root: &str,
+    ) -> Result<Self> {
+        let list = btrfs(runner, &["subvolume", "list", mount_point]).await?;
+        let read_only = btrfs(runner, &["subvolume", "list", "-r", mount_point]).await?;
+        let default = btrfs(runner, &["subvolume", "get-default", mount_point]).await?;
+
+        let read_only: Vec<u64> = parse_list(&read_only).iter().map(|s| s.id).collect();
+        let mut subvolumes = vec![Subvolume {
+            id: TOP_LEVEL_ID,
+            generation: 0,
+            top_level: 0,
+            path: String::new(),
+            read_only: false,
+        }];
+        subvolumes.extend(parse_list(&list).into_iter().map(|mut subvolume| {
+            subvolume.read_only = read_only.contains(&subvolume.id);
+            subvolume
+        }));
+        Ok(Self {
+            mount_point: mount_point.to_string(),
+            mounted: fs_root.trim_matches('/').to_string(),
+            default_id: parse_default(&default).unwrap_or(TOP_LEVEL_ID),
+            subvolumes,
+        })
+    }
+
+    pub fn subvolume(&self, id: u64) -> Option<&Subvolume> {
+        self.subvolumes.iter().find(|subvolume| subvolume.id == id)
+    }
+
+    /// Where the subvolume is found below the mount point: empty for the
+    /// mounted subvolume itself, `None` outside of it.
+    pub fn local_path(&self, subvolume: &Subvolume) -> Option<String> {
+        if subvolume.path == self.mounted {
+            return Some(String::new());
+        }
+        if self.mounted.is_empty() {
+            return Some(subvolume.path.clone());
+        }
+        subvolume
+            .path
+            .strip_prefix(&format!("{}/", self.mounted))
+            .map(str::to_string)
+    }
+
+    /// The subvolume's path on the mounted filesystem.
+    pub fn absolute_path(&self, subvolume: &Subvolume) -> Option<String> {
+        let local = self.local_path(subvolume)?;
+        Some(join(&self.mount_point, &local))
+    }
+}
+
+/// `path` below `mount_point`.
+pub fn join(mount_point: &str, path: &str) -> String {
+    match path {
+        "" => mount_point.to_string(),
+        path => format!("{}/{}", mount_point.trim_end_matches('/'), path),
+    }
+}
+
+async fn btrfs(runner: &dyn CommandRunner, args: &[&str]) -> Result<String> {
+    let output = runner
+        .output("btrfs", args)
+        .await
+        .context("Failed to execute btrfs. Install the btrfs-progs package.")?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "btrfs {} failed: {}",
+            args[..2].join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Lines such as "ID 257 gen 12 top level 5 path @home". The path comes
+/// last and may contain spaces.
+pub fn parse_list(text: &str) -> Vec<Subvolume> {
+    text.lines()
+        .filter_map(|line| {
+            let (fields, path) = line.split_once(" path ")?;
+            let fields: Vec<&str> = fields.split_whitespace().collect();
+            let value = |key: &str| {
+                let idx = fields.iter().position(|field| *field == key)?;
+                fields.get(idx + 1)?.parse().ok()
+            };
+            Some(Subvolume {
+                id: value("ID")?,
+                generation: value("gen").unwrap_or(0),
+                top_level: value("level").unwrap_or(TOP_LEVEL_ID),
+                path: path.to_string(),
+                read_only: false,
+            })
+        })
+        .collect()
+}
+
+/// "ID 5 (FS_TREE)" or a subvolume line as `btrfs subvolume list` prints it.
+pub fn parse_default(text: &str) -> Option<u64> {
+    text.split_whitespace().nth(1)?.parse().ok()
+}
+
+/// Used and free bytes from `btrfs filesystem usage -b`. Used is what the
+/// data, metadata and system chunks hold, counted once however many copies
+/// their profile keeps; free is btrfs' own estimate. `df` gets both wrong
+/// for filesystems with mirrored metadata or several devices.
+pub fn parse_usage(text: &str) -> Option<(u64, u64)> {
+    let mut used = None;
+    let mut free = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("Free (estimated):") {
+            free = value.split_whitespace().next()?.parse::<u64>().ok();
+        } else if ["Data,", "Metadata,", "System,"]
+            .iter()
+            .any(|kind| line.starts_with(kind))
+            && let Some((_, value)) = line.split_once("Used:")
+        {
+            let value = value.split([',', ' ']).next()?.parse::<u64>().ok()?;
+            used = Some(used.unwrap_or(0) + value);
+        }
+    }
+    Some((used?, free?))
+}
+
+/// The "Total devices" count from `btrfs filesystem show`.
+pub fn parse_device_count(text: &str) -> Option<usize> {
+    text.lines().find_map(|line| {
+        line.trim()
+            .strip_prefix("Total devices ")?
+            .split_whitespace()
+            .next()?
+            .parse()
+            .ok()
+    })
+}
+
+/// Checks a path for a new subvolume or snapshot, relative to where the
+/// filesystem is mounted.
+pub fn validate_path(path: &str) -> Result<()> {
+    if path.is_empty() {
+        return Err(anyhow!("Path cannot be empty"));
+    }
+    if path.starts_with('/') {
+        return Err(anyhow!("Give the path relative to the mount point"));
+    }
+    if path
+        .split('/')
+        .any(|part| part.is_empty() || part == "." || part == "..")
+    {
+        return Err(anyhow!("Invalid path: {}", path));
+    }
+    if path.chars().any(char::is_control) {
+        return Err(anyhow!("Path cannot contain control characters"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subvolumes_are_listed_relative_to_the_mounted_one() {
+        let subvolumes = parse_list(
+            "ID 256 gen 30 top level 5 path @\n\
+             ID 257 gen 31 top level 5 path @home\n\
+             ID 260 gen 28 top level 256 path @/var/lib/my data\n",
+        );
+        assert_eq!(subvolumes.len(), 3);
+        assert_eq!(subvolumes[2].top_level, 256);
+        assert_eq!(subvolumes[2].path, "@/var/lib/my data");
+        assert_eq!(parse_default("ID 5 (FS_TREE)\n"), Some(5));
+        assert_eq!(
+            parse_default("ID 256 gen 30 top level 5 path @\n"),
+            Some(256)
+        );
+
+        let list = SubvolumeList {
+            mount_point: "/mnt/data".into(),
+            mounted: "@".into(),
+            default_id: 256,
+            subvolumes,
+        };
+        let local = |idx: usize| list.local_path(&list.subvolumes[idx]);
+        assert_eq!(local(0).as_deref(), Some(""));
+        assert_eq!(local(1), None);
+        assert_eq!(
+            list.absolute_path(&list.subvolumes[2]).as_deref(),
+            Some("/mnt/data/var/lib/my data")
+        );
+
+        assert!(validate_path("snapshots/home-1").is_ok());
+        assert!(validate_path("/abs").is_err());
+        assert!(validate_path("a/../b").is_err());
+    }
+
+    #[test]
+    fn usage_counts_each_chunk_once() {
+        let usage = "\
+Overall:
+    Device size:                 10737418240
+    Device allocated:             1124073472
+    Used:                          537133056
+    Free (estimated):             9597616128      (min: 4798808064)
+    Data ratio:                         1.00
+    Metadata ratio:                     2.00
+
+Data,single: Size:1073741824, Used:268435456 (3.20%)
+   /dev/sdb1     8388608
+
+Metadata,DUP: Size:536870912, Used:131072 (0.02%)
+   /dev/sdb1  1073741824
+
+System,DUP: Size:8388608, Used:16384 (0.20%)
+   /dev/sdb1    16777216
+";
+        assert_eq!(
+            parse_usage(usage),
+            Some((268435456 + 131072 + 16384, 9597616128))
+        );
+        assert_eq!(parse_usage("ERROR: not a btrfs filesystem"), None);
+
+        let show = "Label: none  uuid: 4b0c6a2e-8f5a-4d3c-9a8e-1f2b3c4d5e6f\n\
+                    \tTotal devices 2 FS bytes used 144.00KiB\n\
+                    \tdevid    1 size 1.00GiB used 126.38MiB path /dev/sdb1\n";
+        assert_eq!(parse_device_count(show), Some(2));
+    }
+}
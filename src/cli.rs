@@ -0,0 +1,1417 @@
+use crate::audit::AuditEntry;
+use crate::backup::BackupList;
+use crate::btrfs::{self, SubvolumeList};
+use crate::fsck::{CheckReport, CheckStatus};
+use crate::fstab::BootTables;
+use crate::layout::{Alignment, LayoutRow, layout_rows};
+use crate::lvm::LvmReport;
+use crate::mount::{self, MountOptions, Owner};
+use crate::operations::{
+    BlockDevice, FilesystemType, HelperConnection, get_smart_data, scan_block_devices,
+};
+use crate::parttype::{FlagChange, type_name};
+use crate::protocol::{Request, Response};
+use crate::raid::RaidReport;
+use crate::runner::SystemRunner;
+use crate::smart::{Level, SelfTestKind, SmartData};
+use crate::utils::{Amount, SizeUnits, format_bytes, format_timestamp};
+use anyhow::{Result, anyhow};
+use std::io::BufRead;
+
+pub const USAGE: &str = "\
+Usage: disktui [COMMAND]
+
+Without a command the interactive TUI is started.
+
+Commands:
+  list [--json]                               List disks and partitions
+  mount <device> [--target <dir>] [--ro] [--noatime] [--owner] [--compress <alg>]
+        [--subvol <path>]                     Mount a partition, under /mnt unless --target
+                                              is given; --owner makes FAT, exFAT and NTFS
+                                              files yours; --subvol picks a btrfs subvolume
+  unmount <device>                            Unmount a partition
+  format <device> --fs <type> [--label <l>]  Format a partition
+  check <device> [--repair]                   Check an unmounted filesystem, read-only unless
+                                              --repair is given
+  format-disk <disk> --fs <type> [--label <l>]
+                                              Wipe a disk and create one formatted partition
+  label <device> --set <label> | --clear      Change or clear a filesystem label
+  newuuid <device>                            Give an unmounted filesystem a new random UUID
+  smart <disk> [--json]                       Show SMART health, attributes and self-tests
+  selftest <disk> [--long]                    Start a short or long SMART self-test
+  fstab <device> --target <dir> [--options <opts>] [--pass <n>]
+                                              Add or replace the /etc/fstab entry of a
+                                              filesystem, by UUID
+  fstab-rm <source>                           Remove /etc/fstab entries, such as UUID=...
+  crypttab <device> [--name <mapper>] [--options <opts>]
+                                              Add or replace the /etc/crypttab entry of a
+                                              LUKS device, by LUKS UUID
+  crypttab-rm <name>                          Remove an /etc/crypttab entry
+  mklabel <disk> [--type gpt|msdos]           Create a new partition table
+  mkpart <disk> [--start <offset>] [--size <size>] [--fs <type>] [--encrypt] [--align <a>]
+         [--type <type>] [--name <name>]      Create a partition in the free space containing
+                                              --start, or the last free space (empty size fills it)
+  rm <partition>                              Delete a partition
+  resize <partition> --size <size> [--align <a>]
+                                              Resize a partition and its filesystem
+  modify <partition> [--type <type>] [--name <name>] [--set <flag>]... [--clear <flag>]...
+                                              Change the type, GPT name or flags of a partition
+  encrypt <partition> [--fs <type>]           Encrypt a partition with LUKS2 and format it
+  unlock <device> [--name <mapper>]           Unlock a LUKS device
+  lock <mapper>                               Lock an unlocked LUKS device
+  swapon <device|file>                        Enable a swap partition or swap file
+  swapoff <device|file>                       Disable a swap partition or swap file
+  mkswapfile <path> --size <size>             Create and enable a swap file on a mounted
+                                              filesystem
+  lvm [--json]                                List LVM physical volumes, volume groups and
+                                              logical volumes
+  pvcreate <device>                           Make a device an LVM physical volume
+  vgcreate <name> <device>...                 Create a volume group
+  vgextend <name> <device>                    Add a device to a volume group
+  lvcreate <vg>/<lv> [--size <size>] [--fs <type>]
+                                              Create a logical volume in the free space of a
+                                              volume group (empty size fills it)
+  lvresize <vg>/<lv> --size <size>            Resize an unmounted logical volume and its
+                                              filesystem
+  lvremove <vg>/<lv>                          Remove an unmounted logical volume
+  raid [--json]                               List md arrays, their members and running syncs
+  mdcreate <name> --level <level> <device>... Create an md array, such as md0
+  mdadd <array> <device>                      Add a device to an array
+  mdfail <array> <device>                     Mark an array member as failed
+  mdremove <array> <device>                   Remove a failed or spare member from an array
+  mdstop <array>                              Stop an unmounted array
+  mdassemble <uuid>                           Assemble a stopped array by its UUID
+  subvol <device> [--json]                    List the subvolumes of a mounted btrfs filesystem
+  subvol-create <device> <path>               Create a subvolume below the mount point
+  subvol-delete <device> --id <id>            Delete a subvolume
+  snapshot <device> --id <id> <path>          Take a read-only snapshot of a subvolume
+  subvol-default <device> --id <id>           Mount a subvolume when no subvol= is given
+  history [<disk>] [--limit <n>] [--json]     Show recorded operations from the audit log
+  backups <disk> [--json]                     List saved partition tables of a disk
+  restore <disk> --backup <id>                Restore a saved partition table
+  help                                        Show this message
+
+Options:
+  --dry-run                                   Print the commands a command would run
+                                              without changing anything
+
+Filesystem types: ext4, fat32, ntfs, exfat, btrfs, xfs, swap
+Sizes: 512M or 512MiB (powers of 1024), 512MB (powers of 1000), 2048s (sectors),
+       50% of the free space, -1G (all but 1G), rest
+Alignments: 1MiB (default), optimal (the disk's optimal I/O size)
+Partition types: linux, esp, swap, lvm, raid, msdata, bios-boot, a GPT type GUID
+                 or an MBR id such as 0x83
+Flags: esp, legacy_boot (GPT only), hidden
+RAID levels: 0, 1, 4, 5, 6, 10 (or raid0, raid1, ...)
+Compression (btrfs): zstd, zstd:1 to zstd:15, zlib, zlib:1 to zlib:9, lzo
+Passphrases for encrypt, unlock and mkpart --encrypt are read from stdin
+(not in --dry-run mode).
+Progress and notifications are written to stderr, the output of check to stdout.
+list warns about /etc/fstab and /etc/crypttab entries whose device is gone.
+check exits with 1 when errors are left or the checker fails.";
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+
+#[derive(Debug)]
+pub enum CliCommand {
+    Help,
+    List { json: bool },
+    Smart { disk: String, json: bool },
+    Run(Request),
+    Check(Request),
+    History { request: Request, json: bool },
+    Backups { request: Request, json: bool },
+    Lvm { json: bool },
+    Raid { json: bool },
+    Subvolumes { request: Request, json: bool },
+}
+
+struct Args {
+    raw: Vec<String>,
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    fn new(args: &[String]) -> Self {
+        Self {
+            raw: args.to_vec(),
+            positional: Vec::new(),
+            options: Vec::new(),
+        }
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(key, _)| key == name)
+    }
+
+    /// Removes a flag that applies to every command, so that per-command
+    /// option checks do not see it.
+    fn take_flag(&mut self, name: &str) -> bool {
+        let flag = format!("--{}", name);
+        let found = self.raw.contains(&flag);
+        self.raw.retain(|arg| *arg != flag);
+        found
+    }
+
+    /// Splits the arguments into positionals and options. Which options take
+    /// a value and which are plain flags depends on the command, so that
+    /// `label --clear` is a flag while `modify --clear esp` takes a value.
+    fn check_options(&mut self, values: &[&str], flags: &[&str]) -> Result<()> {
+        let mut iter = std::mem::take(&mut self.raw).into_iter();
+        while let Some(arg) = iter.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                self.positional.push(arg);
+                continue;
+            };
+            let (key, value) = match name.split_once('=') {
+                Some((key, value)) => (key, Some(value.to_string())),
+                None => (name, None),
+            };
+            let value = if flags.contains(&key) {
+                if value.is_some() {
+                    return Err(anyhow!("--{} does not take a value", key));
+                }
+                None
+            } else if values.contains(&key) {
+                match value {
+                    Some(value) => Some(value),
+                    None => Some(
+                        iter.next()
+                            .ok_or_else(|| anyhow!("Missing value for --{}", key))?,
+                    ),
+                }
+            } else {
+                return Err(anyhow!("Unknown option: --{}", key));
+            };
+            self.options.push((key.to_string(), value));
+        }
+        Ok(())
+    }
+
+    fn value(&self, name: &str) -> Option<String> {
+        self.options
+            .iter()
+            .find(|(key, _)| key == name)
+            .and_then(|(_, value)| value.clone())
+    }
+
+    fn device(&self, what: &str) -> Result<String> {
+        let device = self
+            .positional
+            .first()
+            .ok_or_else(|| anyhow!("Missing {} argument", what))?;
+        if self.positional.len() > 1 {
+            return Err(anyhow!("Unexpected argument: {}", self.positional[1]));
+        }
+        Ok(strip_device_prefix(device))
+    }
+
+    fn values(&self, name: &str) -> impl Iterator<Item = &str> {
+        self.options
+            .iter()
+            .filter(move |(key, _)| key == name)
+            .filter_map(|(_, value)| value.as_deref())
+    }
+
+    fn fs_type(&self) -> Result<Option<String>> {
+        self.value("fs")
+            .map(|fs| fs.parse::<FilesystemType>().map(|fs| fs.to_string()))
+            .transpose()
+    }
+
+    /// A logical volume given as `<vg>/<lv>`.
+    fn logical_volume(&self) -> Result<(String, String)> {
+        let volume = self.device("logical volume")?;
+        let volume = volume.strip_prefix("mapper/").unwrap_or(&volume);
+        volume
+            .split_once('/')
+            .map(|(vg, lv)| (vg.to_string(), lv.to_string()))
+            .ok_or_else(|| anyhow!("Give the logical volume as <vg>/<lv>"))
+    }
+
+    /// An array and one of its members, such as `md0 sdb1`.
+    fn array_member(&self) -> Result<(String, String)> {
+        let [array, device] = self.positional.as_slice() else {
+            return Err(anyhow!("Give the array and a device"));
+        };
+        Ok((strip_device_prefix(array), strip_device_prefix(device)))
+    }
+
+    /// A device and a path below where it is mounted.
+    fn device_path(&self) -> Result<(String, String)> {
+        let [device, path] = self.positional.as_slice() else {
+            return Err(anyhow!("Give the device and a path below its mount point"));
+        };
+        Ok((strip_device_prefix(device), path.clone()))
+    }
+
+    /// The subvolume given with `--id`.
+    fn subvolume_id(&self) -> Result<u64> {
+        let id = self.value("id").ok_or_else(|| anyhow!("Missing --id"))?;
+        id.parse().map_err(|_| anyhow!("Invalid --id: {}", id))
+    }
+
+    fn alignment(&self) -> Result<Alignment> {
+        self.value("align")
+            .map(|align| align.parse())
+            .transpose()
+            .map(Option::unwrap_or_default)
+    }
+}
+
+fn strip_device_prefix(device: &str) -> String {
+    device
+        .strip_prefix("/dev/mapper/")
+        .or_else(|| device.strip_prefix("/dev/"))
+        .unwrap_or(device)
+        .to_string()
+}
+
+/// Parses command line arguments (without the program name) into a command.
+/// Passphrases are requested through `read_passphrase` so that parsing stays
+/// independent of stdin.
+pub fn parse_args<F>(args: &[String], mut read_passphrase: F) -> Result<CliCommand>
+where
+    F: FnMut() -> Result<String>,
+{
+    let Some((command, rest)) = args.split_first() else {
+        return Ok(CliCommand::Help);
+    };
+    let mut args = Args::new(rest);
+    let dry_run = args.take_flag("dry-run");
+    // A dry run never uses the passphrase, so do not ask for one.
+    let mut read_passphrase = || {
+        if dry_run {
+            Ok(String::new())
+        } else {
+            read_passphrase()
+        }
+    };
+
+    let command = match command.as_str() {
+        "help" | "-h" | "--help" => CliCommand::Help,
+        "list" | "ls" => {
+            args.check_options(&[], &["json"])?;
+            if let Some(extra) = args.positional.first() {
+                return Err(anyhow!("Unexpected argument: {}", extra));
+            }
+            CliCommand::List {
+                json: args.flag("json"),
+            }
+        }
+        "mount" => {
+            args.check_options(
+                &["target", "compress", "subvol"],
+                &["ro", "noatime", "owner"],
+            )?;
+            if let Some(compress) = args.value("compress") {
+                mount::validate_compress(&compress)?;
+            }
+            if let Some(subvol) = args.value("subvol") {
+                mount::validate_subvol(&subvol)?;
+            }
+            CliCommand::Run(Request::Mount {
+                device: args.device("device")?,
+                target: args.value("target"),
+                options: MountOptions {
+                    read_only: args.flag("ro"),
+                    noatime: args.flag("noatime"),
+                    owner: args.flag("owner").then(Owner::current),
+                    compress: args.value("compress"),
+                    subvol: args.value("subvol"),
+                },
+            })
+        }
+        "unmount" | "umount" => {
+            args.check_options(&[], &[])?;
+            CliCommand::Run(Request::Unmount {
+                device: args.device("device")?,
+            })
+        }
+        "format" => {
+            args.check_options(&["fs", "label"], &[])?;
+            CliCommand::Run(Request::Format {
+                device: args.device("device")?,
+                fs_type: args.fs_type()?.ok_or_else(|| anyhow!("Missing --fs"))?,
+                label: args.value("label"),
+            })
+        }
+        "check" | "fsck" => {
+            args.check_options(&[], &["repair"])?;
+            CliCommand::Check(Request::CheckFilesystem {
+                device: args.device("device")?,
+                repair: args.flag("repair"),
+            })
+        }
+        "format-disk" => {
+            args.check_options(&["fs", "label"], &[])?;
+            CliCommand::Run(Request::FormatWholeDisk {
+                disk: args.device("disk")?,
+                fs_type: args.fs_type()?.ok_or_else(|| anyhow!("Missing --fs"))?,
+                label: args.value("label"),
+            })
+        }
+        "label" => {
+            args.check_options(&["set"], &["clear"])?;
+            let label = match (args.value("set"), args.flag("clear")) {
+                (Some(label), false) => label,
+                (None, true) => String::new(),
+                _ => return Err(anyhow!("Give either --set <label> or --clear")),
+            };
+            CliCommand::Run(Request::SetLabel {
+                device: args.device("device")?,
+                label,
+            })
+        }
+        "newuuid" => {
+            args.check_options(&[], &[])?;
+            CliCommand::Run(Request::RegenerateUuid {
+                device: args.device("device")?,
+            })
+        }
+        "smart" => {
+            args.check_options(&[], &["json"])?;
+            CliCommand::Smart {
+                disk: args.device("disk")?,
+                json: args.flag("json"),
+            }
+        }
+        "selftest" => {
+            args.check_options(&[], &["long"])?;
+            CliCommand::Run(Request::StartSelfTest {
+                disk: args.device("disk")?,
+                test: if args.flag("long") {
+                    SelfTestKind::Long
+                } else {
+                    SelfTestKind::Short
+                },
+            })
+        }
+        "fstab" => {
+            args.check_options(&["target", "options", "pass"], &[])?;
+            let pass = args
+                .value("pass")
+                .map(|pass| match pass.parse() {
+                    Ok(pass @ 0..=2) => Ok(pass),
+                    _ => Err(anyhow!("Invalid --pass: {} (use 0, 1 or 2)", pass)),
+                })
+                .transpose()?;
+            CliCommand::Run(Request::SetFstabEntry {
+                device: args.device("device")?,
+                target: args
+                    .value("target")
+                    .ok_or_else(|| anyhow!("Missing --target"))?,
+                options: args
+                    .value("options")
+                    .unwrap_or_else(|| "defaults".to_string()),
+                pass,
+            })
+        }
+        "fstab-rm" => {
+            args.check_options(&[], &[])?;
+            // Sources are matched as written, so /dev/ is kept.
+            let source = match args.positional.as_slice() {
+                [source] => source.clone(),
+                [] => return Err(anyhow!("Missing source argument")),
+                [_, extra, ..] => return Err(anyhow!("Unexpected argument: {}", extra)),
+            };
+            CliCommand::Run(Request::RemoveFstabEntry { source })
+        }
+        "crypttab" => {
+            args.check_options(&["name", "options"], &[])?;
+            let device = args.device("device")?;
+            CliCommand::Run(Request::SetCrypttabEntry {
+                name: args
+                    .value("name")
+                    .unwrap_or_else(|| format!("luks-{}", device)),
+                device,
+                options: args.value("options").unwrap_or_else(|| "luks".to_string()),
+            })
+        }
+        "crypttab-rm" => {
+            args.check_options(&[], &[])?;
+            CliCommand::Run(Request::RemoveCrypttabEntry {
+                name: args.device("name")?,
+            })
+        }
+        "mklabel" => {
+            args.check_options(&["type"], &[])?;
+            let table_type = args.value("type").unwrap_or_else(|| "gpt".to_string());
+            if !matches!(table_type.as_str(), "gpt" | "msdos" | "mbr") {
+                return Err(anyhow!("Unsupported partition table type: {}", table_type));
+            }
+            CliCommand::Run(Request::CreatePartitionTable {
+                disk: args.device("disk")?,
+                table_type,
+            })
+        }
+        "mkpart" => {
+            args.check_options(
+                &["start", "size", "fs", "align", "type", "name"],
+                &["encrypt"],
+            )?;
+            let disk = args.device("disk")?;
+            let alignment = args.alignment()?;
+            let start = args
+                .value("start")
+                .map(|start| match start.parse()? {
+                    Amount::Bytes(bytes) => Ok(bytes),
+                    Amount::Sectors(_) => Err(anyhow!("--start takes a byte offset")),
+                })
+                .transpose()?;
+            let size = args.value("size").unwrap_or_default();
+            if args.flag("encrypt") {
+                if args.flag("type") || args.flag("name") {
+                    return Err(anyhow!("--type and --name cannot be used with --encrypt"));
+                }
+                let fs_type = args
+                    .fs_type()?
+                    .unwrap_or_else(|| FilesystemType::Ext4.to_string());
+                CliCommand::Run(Request::CreateEncryptedPartition {
+                    disk,
+                    start,
+                    size,
+                    passphrase: read_passphrase()?,
+                    fs_type,
+                    alignment,
+                })
+            } else {
+                CliCommand::Run(Request::CreatePartition {
+                    disk,
+                    start,
+                    size,
+                    fs_type: args.fs_type()?,
+                    alignment,
+                    part_type: args.value("type"),
+                    name: args.value("name"),
+                })
+            }
+        }
+        "rm" | "delete" => {
+            args.check_options(&[], &[])?;
+            CliCommand::Run(Request::DeletePartition {
+                partition: args.device("partition")?,
+            })
+        }
+        "resize" => {
+            args.check_options(&["size", "align"], &[])?;
+            CliCommand::Run(Request::ResizePartition {
+                partition: args.device("partition")?,
+                new_size: args
+                    .value("size")
+                    .ok_or_else(|| anyhow!("Missing --size"))?,
+                alignment: args.alignment()?,
+            })
+        }
+        "modify" => {
+            args.check_options(&["type", "name", "set", "clear"], &[])?;
+            let partition = args.device("partition")?;
+            let mut flags = Vec::new();
+            for (option, enabled) in [("set", true), ("clear", false)] {
+                for flag in args.values(option) {
+                    flags.push(FlagChange {
+                        flag: flag.parse()?,
+                        enabled,
+                    });
+                }
+            }
+            let part_type = args.value("type");
+            let name = args.value("name");
+            if part_type.is_none() && name.is_none() && flags.is_empty() {
+                return Err(anyhow!(
+                    "Nothing to change: use --type, --name, --set or --clear"
+                ));
+            }
+            CliCommand::Run(Request::ModifyPartition {
+                partition,
+                part_type,
+                name,
+                flags,
+            })
+        }
+        "encrypt" => {
+            args.check_options(&["fs"], &[])?;
+            let partition = args.device("partition")?;
+            let fs_type = args
+                .fs_type()?
+                .unwrap_or_else(|| FilesystemType::Ext4.to_string());
+            CliCommand::Run(Request::EncryptAndFormat {
+                partition,
+                passphrase: read_passphrase()?,
+                fs_type,
+            })
+        }
+        "unlock" => {
+            args.check_options(&["name"], &[])?;
+            let device = args.device("device")?;
+            let mapper_name = args
+                .value("name")
+                .unwrap_or_else(|| format!("luks-{}", device));
+            CliCommand::Run(Request::UnlockLuks {
+                device,
+                passphrase: read_passphrase()?,
+                mapper_name,
+            })
+        }
+        "history" => {
+            args.check_options(&["limit"], &["json"])?;
+            let disk = match args.positional.as_slice() {
+                [] => None,
+                [disk] => Some(strip_device_prefix(disk)),
+                [_, extra, ..] => return Err(anyhow!("Unexpected argument: {}", extra)),
+            };
+            let limit = match args.value("limit") {
+                Some(limit) => limit
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid --limit: {}", limit))?,
+                None => 50,
+            };
+            CliCommand::History {
+                request: Request::AuditLog { disk, limit },
+                json: args.flag("json"),
+            }
+        }
+        "backups" => {
+            args.check_options(&[], &["json"])?;
+            CliCommand::Backups {
+                request: Request::ListBackups {
+                    disk: args.device("disk")?,
+                },
+                json: args.flag("json"),
+            }
+        }
+        "restore" => {
+            args.check_options(&["backup"], &[])?;
+            CliCommand::Run(Request::RestoreTable {
+                disk: args.device("disk")?,
+                backup: args
+                    .value("backup")
+                    .ok_or_else(|| anyhow!("Missing --backup"))?,
+            })
+        }
+        "lock" => {
+            args.check_options(&[], &[])?;
+            CliCommand::Run(Request::LockLuks {
+                mapper_name: args.device("mapper name")?,
+            })
+        }
+        "swapon" => {
+            args.check_options(&[], &[])?;
+            CliCommand::Run(Request::SwapOn {
+                target: args.device("device or file")?,
+            })
+        }
+        "swapoff" => {
+            args.check_options(&[], &[])?;
+            CliCommand::Run(Request::SwapOff {
+                target: args.device("device or file")?,
+            })
+        }
+        "lvm" => {
+            args.check_options(&[], &["json"])?;
+            if let Some(extra) = args.positional.first() {
+                return Err(anyhow!("Unexpected argument: {}", extra));
+            }
+            CliCommand::Lvm {
+                json: args.flag("json"),
+            }
+        }
+        "pvcreate" => {
+            args.check_options(&[], &[])?;
+            CliCommand::Run(Request::CreatePhysicalVolume {
+                device: args.device("device")?,
+            })
+        }
+        "vgcreate" => {
+            args.check_options(&[], &[])?;
+            let (name, devices) = match args.positional.split_first() {
+                Some((name, devices)) if !devices.is_empty() => (name, devices),
+                _ => return Err(anyhow!("Give a name and at least one device")),
+            };
+            CliCommand::Run(Request::CreateVolumeGroup {
+                name: name.clone(),
+                devices: devices
+                    .iter()
+                    .map(|device| strip_device_prefix(device))
+                    .collect(),
+            })
+        }
+        "vgextend" => {
+            args.check_options(&[], &[])?;
+            let [name, device] = args.positional.as_slice() else {
+                return Err(anyhow!("Give the volume group name and a device"));
+            };
+            CliCommand::Run(Request::ExtendVolumeGroup {
+                name: name.clone(),
+                device: strip_device_prefix(device),
+            })
+        }
+        "lvcreate" => {
+            args.check_options(&["size", "fs"], &[])?;
+            let (vg, name) = args.logical_volume()?;
+            CliCommand::Run(Request::CreateLogicalVolume {
+                vg,
+                name,
+                size: args.value("size").unwrap_or_default(),
+                fs_type: args.fs_type()?,
+            })
+        }
+        "lvresize" => {
+            args.check_options(&["size"], &[])?;
+            let (vg, name) = args.logical_volume()?;
+            CliCommand::Run(Request::ResizeLogicalVolume {
+                vg,
+                name,
+                size: args
+                    .value("size")
+                    .ok_or_else(|| anyhow!("Missing --size"))?,
+            })
+        }
+        "lvremove" => {
+            args.check_options(&[], &[])?;
+            let (vg, name) = args.logical_volume()?;
+            CliCommand::Run(Request::RemoveLogicalVolume { vg, name })
+        }
+        "raid" => {
+            args.check_options(&[], &["json"])?;
+            if let Some(extra) = args.positional.first() {
+                return Err(anyhow!("Unexpected argument: {}", extra));
+            }
+            CliCommand::Raid {
+                json: args.flag("json"),
+            }
+        }
+        "mdcreate" => {
+            args.check_options(&["level"], &[])?;
+            let level = args
+                .value("level")
+                .ok_or_else(|| anyhow!("Missing --level"))?;
+            let (name, devices) = match args.positional.split_first() {
+                Some((name, devices)) if !devices.is_empty() => (name, devices),
+                _ => return Err(anyhow!("Give a name and the devices")),
+            };
+            CliCommand::Run(Request::CreateRaid {
+                name: strip_device_prefix(name),
+                level: match level.parse::<u32>() {
+                    Ok(number) => format!("raid{}", number),
+                    Err(_) => level,
+                },
+                devices: devices
+                    .iter()
+                    .map(|device| strip_device_prefix(device))
+                    .collect(),
+            })
+        }
+        "mdadd" => {
+            args.check_options(&[], &[])?;
+            let (array, device) = args.array_member()?;
+            CliCommand::Run(Request::AddRaidMember { array, device })
+        }
+        "mdfail" => {
+            args.check_options(&[], &[])?;
+            let (array, device) = args.array_member()?;
+            CliCommand::Run(Request::FailRaidMember { array, device })
+        }
+        "mdremove" => {
+            args.check_options(&[], &[])?;
+            let (array, device) = args.array_member()?;
+            CliCommand::Run(Request::RemoveRaidMember { array, device })
+        }
+        "mdstop" => {
+            args.check_options(&[], &[])?;
+            CliCommand::Run(Request::StopRaid {
+                array: args.device("array")?,
+            })
+        }
+        "mdassemble" => {
+            args.check_options(&[], &[])?;
+            CliCommand::Run(Request::AssembleRaid {
+                uuid: args.device("UUID")?,
+            })
+        }
+        "subvol" => {
+            args.check_options(&[], &["json"])?;
+            CliCommand::Subvolumes {
+                request: Request::ListSubvolumes {
+                    device: args.device("device")?,
+                },
+                json: args.flag("json"),
+            }
+        }
+        "subvol-create" => {
+            args.check_options(&[], &[])?;
+            let (device, path) = args.device_path()?;
+            btrfs::validate_path(&path)?;
+            CliCommand::Run(Request::CreateSubvolume { device, path })
+        }
+        "subvol-delete" => {
+            args.check_options(&["id"], &[])?;
+            CliCommand::Run(Request::DeleteSubvolume {
+                device: args.device("device")?,
+                id: args.subvolume_id()?,
+            })
+        }
+        "snapshot" => {
+            args.check_options(&["id"], &[])?;
+            let (device, path) = args.device_path()?;
+            btrfs::validate_path(&path)?;
+            CliCommand::Run(Request::SnapshotSubvolume {
+                device,
+                id: args.subvolume_id()?,
+                path,
+            })
+        }
+        "subvol-default" => {
+            args.check_options(&["id"], &[])?;
+            CliCommand::Run(Request::SetDefaultSubvolume {
+                device: args.device("device")?,
+                id: args.subvolume_id()?,
+            })
+        }
+        "mkswapfile" => {
+            args.check_options(&["size"], &[])?;
+            CliCommand::Run(Request::CreateSwapFile {
+                path: args.device("path")?,
+                size: args
+                    .value("size")
+                    .ok_or_else(|| anyhow!("Missing --size"))?,
+            })
+        }
+        other => return Err(anyhow!("Unknown command: {}", other)),
+    };
+
+    if !dry_run {
+        return Ok(command);
+    }
+    match command {
+        CliCommand::Run(request) | CliCommand::Check(request) => {
+            Ok(CliCommand::Run(Request::DryRun {
+                request: Box::new(request),
+            }))
+        }
+        _ => Err(anyhow!(
+            "--dry-run only applies to commands that change disks"
+        )),
+    }
+}
+
+fn read_passphrase_from_stdin() -> Result<String> {
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line)?;
+    let passphrase = line.trim_end_matches(['\n', '\r']).to_string();
+    if passphrase.is_empty() {
+        return Err(anyhow!("Passphrase cannot be empty"));
+    }
+    Ok(passphrase)
+}
+
+fn print_devices(devices: &[BlockDevice]) {
+    println!(
+        "NAME                   SIZE  FSTYPE       TYPE                 MOUNTPOINT               LABEL"
+    );
+    for device in devices {
+        println!(
+            "{:<16} {:>10}  {:<12} {:<20} {:<24} {}",
+            device.name,
+            format_bytes(device.size),
+            "",
+            device.partition_table.as_deref().unwrap_or(""),
+            "",
+            device.model.as_deref().unwrap_or("")
+        );
+        for row in layout_rows(device) {
+            let part = match row {
+                LayoutRow::Partition(part) => part,
+                LayoutRow::Free(gap) => {
+                    // The offset is what `mkpart --start` expects.
+                    println!(
+                        "  {:<14} {:>10}  {:<12} {:<20} {:<24} at {}",
+                        "free",
+                        format_bytes(gap.size()),
+                        "",
+                        "",
+                        "",
+                        gap.start
+                    );
+                    continue;
+                }
+            };
+            if part.name == device.name {
+                continue;
+            }
+            let filesystem = if part.is_encrypted && part.mapper_device.is_none() {
+                part.encryption_type.as_deref().unwrap_or("LUKS")
+            } else {
+                part.filesystem.as_deref().unwrap_or("")
+            };
+            println!(
+                "  {:<14} {:>10}  {:<12} {:<20} {:<24} {}",
+                part.name,
+                part.size_str(SizeUnits::Si),
+                filesystem,
+                part.part_type.as_deref().map(type_name).unwrap_or_default(),
+                match part.swap {
+                    Some(_) => "[SWAP]",
+                    None => part.mount_point.as_deref().unwrap_or(""),
+                },
+                part.label.as_deref().unwrap_or("")
+            );
+            for swap in &part.swap_files {
+                println!(
+                    "    {:<12} {:>10}  {:<12} {:<20} {:<24} used {}, priority {}",
+                    "swap file",
+                    format_bytes(swap.size),
+                    "swap",
+                    "",
+                    swap.path,
+                    format_bytes(swap.used),
+                    swap.priority
+                );
+            }
+        }
+    }
+}
+
+fn print_smart(data: &SmartData) {
+    println!("Health: {}", data.health());
+    if let Some(temperature) = data.temperature {
+        println!("Temperature: {}°C", temperature);
+    }
+    if let Some(hours) = data.power_on_hours {
+        println!("Power on hours: {}", hours);
+    }
+    if let Some(test) = &data.self_test {
+        println!("Self-test: {}", test.description);
+    }
+    let marker = |level| match level {
+        Level::Ok => "",
+        Level::Warning => "  <- warning",
+        Level::Failing => "  <- FAILING",
+    };
+    if let Some(nvme) = &data.nvme {
+        println!();
+        for reading in nvme.readings() {
+            println!(
+                "{:<20} {}{}",
+                reading.name,
+                reading.value,
+                marker(reading.level)
+            );
+        }
+    }
+    if !data.attributes.is_empty() {
+        println!();
+        println!(" ID  ATTRIBUTE                  VALUE WORST THRESH RAW");
+        for attribute in &data.attributes {
+            println!(
+                "{:>3}  {:<26} {:>5} {:>5} {:>6} {}{}",
+                attribute.id,
+                attribute.name,
+                attribute.value,
+                attribute.worst,
+                attribute.thresh,
+                attribute.raw,
+                marker(attribute.level())
+            );
+        }
+    }
+    if !data.self_test_log.is_empty() {
+        println!();
+        for entry in &data.self_test_log {
+            println!(
+                "{:<20} {:<40} {}",
+                entry.kind,
+                entry.status,
+                entry
+                    .power_on_hours
+                    .map(|hours| format!("{} h", hours))
+                    .unwrap_or_default()
+            );
+        }
+    }
+}
+
+fn print_response(response: &Response) {
+    match response {
+        Response::Progress {
+            action,
+            message: Some(message),
+        } if action == "start" => {
+            eprintln!("{}", message);
+        }
+        Response::Notification { level, message } => {
+            eprintln!("[{}] {}", level, message);
+        }
+        Response::Error { message } => {
+            eprintln!("error: {}", message);
+        }
+        Response::Output { line } => {
+            println!("{}", line);
+        }
+        _ => {}
+    }
+}
+
+fn print_history(entries: &[AuditEntry]) {
+    for entry in entries {
+        println!(
+            "{}  {:<4} {}{}",
+            format_timestamp(entry.timestamp),
+            if entry.success { "ok" } else { "FAIL" },
+            entry.request.description(),
+            entry
+                .error
+                .as_ref()
+                .map(|e| format!(": {}", e))
+                .unwrap_or_default()
+        );
+        for command in &entry.commands {
+            match command.exit_code {
+                Some(code) => println!("    $ {}  (exit {})", command.command, code),
+                None => println!("    $ {}  (not run)", command.command),
+            }
+        }
+    }
+}
+
+fn print_backups(list: &BackupList) {
+    if list.backups.is_empty() {
+        println!("No saved partition tables");
+        return;
+    }
+    for backup in &list.backups {
+        let changed = list.current.as_deref() != Some(backup.table.as_str());
+        println!(
+            "{}  {}{}",
+            format_timestamp(backup.timestamp),
+            backup.id,
+            if changed {
+                ""
+            } else {
+                "  (matches current table)"
+            }
+        );
+    }
+}
+
+fn print_lvm(report: &LvmReport) {
+    println!("{:<22} {:<18} {:>10}  {:>10}", "PV", "VG", "SIZE", "FREE");
+    for pv in &report.pvs {
+        println!(
+            "{:<22} {:<18} {:>10}  {:>10}",
+            pv.name,
+            pv.vg_name.as_deref().unwrap_or("-"),
+            format_bytes(pv.size),
+            format_bytes(pv.free)
+        );
+    }
+    println!();
+    println!(
+        "{:<18} {:>10}  {:>10}  {:>8}  {:>3}  {:>3}",
+        "VG", "SIZE", "FREE", "FREE PE", "PVS", "LVS"
+    );
+    for vg in &report.vgs {
+        println!(
+            "{:<18} {:>10}  {:>10}  {:>8}  {:>3}  {:>3}",
+            vg.name,
+            format_bytes(vg.size),
+            format_bytes(vg.free),
+            vg.free_extents,
+            vg.pv_count,
+            vg.lv_count
+        );
+    }
+    println!();
+    println!("{:<24} {:>10}  PATH", "LV", "SIZE");
+    for lv in &report.lvs {
+        println!(
+            "{:<24} {:>10}  {}{}",
+            format!("{}/{}", lv.vg_name, lv.name),
+            format_bytes(lv.size),
+            lv.path,
+            if lv.active { "" } else { "  (inactive)" }
+        );
+    }
+}
+
+fn print_raid(report: &RaidReport) {
+    println!(
+        "{:<8} {:<7} {:>10}  {:<7} {:<28} SYNC",
+        "ARRAY", "LEVEL", "SIZE", "DEVICES", "STATE"
+    );
+    for array in &report.arrays {
+        println!(
+            "{:<8} {:<7} {:>10}  {:<7} {:<28} {}",
+            array.name,
+            array.level.as_deref().unwrap_or("-"),
+            format_bytes(array.size),
+            array
+                .raid_devices
+                .map(|(devices, working)| format!("{}/{}", working, devices))
+                .unwrap_or_default(),
+            array.state(),
+            array
+                .progress
+                .as_ref()
+                .map(|progress| progress.summary())
+                .unwrap_or_default()
+        );
+        for member in &array.members {
+            println!("  {:<14} {}", member.name, member.state());
+        }
+    }
+    for stopped in &report.stopped {
+        println!("{:<8} stopped  UUID {}", stopped.name, stopped.uuid);
+    }
+}
+
+fn print_subvolumes(list: &SubvolumeList) {
+    println!("{:>6}  {:<40} FLAGS", "ID", "SUBVOLUME");
+    for subvolume in &list.subvolumes {
+        let mut flags = Vec::new();
+        if subvolume.id == list.default_id {
+            flags.push("default".to_string());
+        }
+        match list.absolute_path(subvolume) {
+            Some(path) if path == list.mount_point => flags.push(format!("mounted at {}", path)),
+            Some(path) => flags.push(path),
+            None => {}
+        }
+        if subvolume.read_only {
+            flags.push("read-only".to_string());
+        }
+        println!(
+            "{:>6}  {:<40} {}",
+            subvolume.id,
+            subvolume.display_path(),
+            flags.join(", ")
+        );
+    }
+}
+
+async fn run_request(request: Request) -> i32 {
+    run_request_with(request, |data| {
+        println!("{}", data);
+        Ok(())
+    })
+    .await
+}
+
+/// Sends a request to a freshly started helper and passes the data of the
+/// final response, if any, to `on_data`.
+async fn run_request_with<F>(request: Request, on_data: F) -> i32
+where
+    F: FnOnce(String) -> Result<()>,
+{
+    let result = tokio::task::spawn_blocking(move || {
+        let helper = HelperConnection::spawn()?;
+        let mut reported = false;
+        let result = helper.request_with(request, |response| {
+            reported |= matches!(response, Response::Error { .. });
+            print_response(&response);
+            Ok(())
+        });
+        Ok::<_, anyhow::Error>((result, reported))
+    })
+    .await
+    .map_err(anyhow::Error::from)
+    .and_then(|result| result);
+
+    match result {
+        Ok((Ok(data), _)) => match data.map(on_data).transpose() {
+            Ok(_) => EXIT_OK,
+            Err(e) => {
+                eprintln!("error: {}", e);
+                EXIT_FAILURE
+            }
+        },
+        Ok((Err(e), reported)) => {
+            if !reported {
+                eprintln!("error: {}", e);
+            }
+            EXIT_FAILURE
+        }
+        Err(e) => {
+            eprintln!("error: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+/// Runs a non-interactive command and returns the process exit code.
+pub async fn run(args: &[String]) -> i32 {
+    let command = match parse_args(args, read_passphrase_from_stdin) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    match command {
+        CliCommand::Help => {
+            println!("{}", USAGE);
+            EXIT_OK
+        }
+        CliCommand::List { json } => match scan_block_devices(&SystemRunner).await {
+            Ok(devices) => {
+                if json {
+                    match serde_json::to_string_pretty(&devices) {
+                        Ok(json) => println!("{}", json),
+                        Err(e) => {
+                            eprintln!("error: {}", e);
+                            return EXIT_FAILURE;
+                        }
+                    }
+                } else {
+                    print_devices(&devices);
+                    for warning in BootTables::load().warnings() {
+                        eprintln!("[warning] {}", warning);
+                    }
+                }
+                EXIT_OK
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                EXIT_FAILURE
+            }
+        },
+        CliCommand::Smart { disk, json } => match get_smart_data(&SystemRunner, &disk).await {
+            Ok(data) => {
+                if json {
+                    match serde_json::to_string_pretty(&data) {
+                        Ok(json) => println!("{}", json),
+                        Err(e) => {
+                            eprintln!("error: {}", e);
+                            return EXIT_FAILURE;
+                        }
+                    }
+                } else {
+                    print_smart(&data);
+                }
+                EXIT_OK
+            }
+            Err(e) => {
+                eprintln!("error: {}", e);
+                EXIT_FAILURE
+            }
+        },
+        CliCommand::Run(request) => run_request(request).await,
+        CliCommand::Check(request) => {
+            // The meaning of the exit code was already printed as a
+            // notification; only the exit status is left to set.
+            let mut clean = false;
+            let code = run_request_with(request, |data| {
+                let report: CheckReport = serde_json::from_str(&data)?;
+                clean = matches!(report.status, CheckStatus::Clean | CheckStatus::Corrected);
+                Ok(())
+            })
+            .await;
+            if code == EXIT_OK && !clean {
+                EXIT_FAILURE
+            } else {
+                code
+            }
+        }
+        CliCommand::History { request, json } => {
+            if json {
+                return run_request(request).await;
+            }
+            run_request_with(request, |data| {
+                let entries: Vec<AuditEntry> = serde_json::from_str(&data)?;
+                print_history(&entries);
+                Ok(())
+            })
+            .await
+        }
+        CliCommand::Lvm { json } => {
+            if json {
+                return run_request(Request::ListLvm).await;
+            }
+            run_request_with(Request::ListLvm, |data| {
+                let report: LvmReport = serde_json::from_str(&data)?;
+                print_lvm(&report);
+                Ok(())
+            })
+            .await
+        }
+        CliCommand::Raid { json } => {
+            if json {
+                return run_request(Request::ListRaid).await;
+            }
+            run_request_with(Request::ListRaid, |data| {
+                let report: RaidReport = serde_json::from_str(&data)?;
+                print_raid(&report);
+                Ok(())
+            })
+            .await
+        }
+        CliCommand::Subvolumes { request, json } => {
+            if json {
+                return run_request(request).await;
+            }
+            run_request_with(request, |data| {
+                let list: SubvolumeList = serde_json::from_str(&data)?;
+                print_subvolumes(&list);
+                Ok(())
+            })
+            .await
+        }
+        CliCommand::Backups { request, json } => {
+            if json {
+                return run_request(request).await;
+            }
+            run_request_with(request, |data| {
+                let list: BackupList = serde_json::from_str(&data)?;
+                print_backups(&list);
+                Ok(())
+            })
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parttype::PartitionFlag;
+
+    fn parse(args: &[&str]) -> Result<CliCommand> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_args(&args, || Ok("secret".to_string()))
+    }
+
+    #[test]
+    fn clear_is_a_flag_for_label_but_takes_a_value_for_modify() {
+        let CliCommand::Run(Request::SetLabel { device, label }) =
+            parse(&["label", "sdb1", "--clear"]).unwrap()
+        else {
+            panic!("label --clear should set an empty label");
+        };
+        assert_eq!((device.as_str(), label.as_str()), ("sdb1", ""));
+
+        let CliCommand::Run(Request::ModifyPartition {
+            partition, flags, ..
+        }) = parse(&["modify", "sda1", "--clear", "esp"]).unwrap()
+        else {
+            panic!("modify --clear should take a flag name");
+        };
+        assert_eq!(partition, "sda1");
+        assert_eq!(
+            flags,
+            [FlagChange {
+                flag: PartitionFlag::Esp,
+                enabled: false,
+            }]
+        );
+    }
+
+    #[test]
+    fn options_take_values_inline_or_from_the_next_argument() {
+        let CliCommand::Run(Request::Mount {
+            device,
+            target,
+            options,
+        }) = parse(&[
+            "mount",
+            "/dev/sdb1",
+            "--ro",
+            "--target=/mnt/data",
+            "--subvol",
+            "@home",
+        ])
+        .unwrap()
+        else {
+            panic!("expected a mount request");
+        };
+        assert_eq!(device, "sdb1");
+        assert_eq!(target.as_deref(), Some("/mnt/data"));
+        assert!(options.read_only && !options.noatime);
+        assert_eq!(options.subvol.as_deref(), Some("@home"));
+
+        assert!(parse(&["list", "--json=yes"]).is_err());
+        assert!(parse(&["resize", "sdb1", "--size"]).is_err());
+    }
+
+    #[test]
+    fn unknown_options_are_refused() {
+        let error = parse(&["rm", "sdb1", "--force"]).unwrap_err();
+        assert_eq!(error.to_string(), "Unknown option: --force");
+        // A flag of another command is not accepted either.
+        assert!(parse(&["format", "sdb1", "--fs", "ext4", "--json"]).is_err());
+        assert!(parse(&["label", "sdb1", "--set", "data", "--clear"]).is_err());
+    }
+
+    #[test]
+    fn dry_runs_do_not_ask_for_a_passphrase() {
+        let args: Vec<String> = ["encrypt", "sdb1", "--dry-run"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+        let command = parse_args(&args, || panic!("passphrase requested")).unwrap();
+        let CliCommand::Run(Request::DryRun { request }) = command else {
+            panic!("expected a dry run");
+        };
+        let Request::EncryptAndFormat {
+            partition,
+            passphrase,
+            fs_type,
+        } = *request
+        else {
+            panic!("expected an encrypt request");
+        };
+        assert_eq!((partition.as_str(), fs_type.as_str()), ("sdb1", "ext4"));
+        assert!(passphrase.is_empty());
+
+        let CliCommand::Run(Request::UnlockLuks { passphrase, .. }) =
+            parse(&["unlock", "sdb1"]).unwrap()
+        else {
+            panic!("expected an unlock request");
+        };
+        assert_eq!(passphrase, "secret");
+
+        assert!(parse(&["list", "--dry-run"]).is_err());
+    }
+
+    #[test]
+    fn group_and_array_commands_take_a_name_and_devices() {
+        let CliCommand::Run(Request::CreateVolumeGroup { name, devices }) =
+            parse(&["vgcreate", "data", "/dev/sdb1", "sdc1"]).unwrap()
+        else {
+            panic!("expected a vgcreate request");
+        };
+        assert_eq!(name, "data");
+        assert_eq!(devices, ["sdb1", "sdc1"]);
+        assert!(parse(&["vgcreate", "data"]).is_err());
+
+        let CliCommand::Run(Request::CreateRaid {
+            name,
+            level,
+            devices,
+        }) = parse(&["mdcreate", "/dev/md0", "--level", "1", "sdb1", "sdc1"]).unwrap()
+        else {
+            panic!("expected an mdcreate request");
+        };
+        assert_eq!((name.as_str(), level.as_str()), ("md0", "raid1"));
+        assert_eq!(devices, ["sdb1", "sdc1"]);
+        assert!(parse(&["mdcreate", "md0", "sdb1", "sdc1"]).is_err());
+        assert!(parse(&["mdcreate", "md0", "--level", "1"]).is_err());
+    }
+
+    #[test]
+    fn fstab_pass_is_limited_to_0_1_and_2() {
+        let CliCommand::Run(Request::SetFstabEntry { pass, options, .. }) =
+            parse(&["fstab", "sdb1", "--target", "/data", "--pass", "2"]).unwrap()
+        else {
+            panic!("expected an fstab request");
+        };
+        assert_eq!(pass, Some(2));
+        assert_eq!(options, "defaults");
+        for pass in ["3", "-1", "one"] {
+            assert!(parse(&["fstab", "sdb1", "--target", "/data", "--pass", pass]).is_err());
+        }
+    }
+}
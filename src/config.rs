@@ -1,4 +1,12 @@
-use serde::Deserialize;
+use crate::layout::Alignment;
+use crate::monitor::Thresholds;
+use crate::mount::{MountOptions, Owner};
+use crate::utils::SizeUnits;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use toml_edit::{DocumentMut, Item, Table};
 
 #[derive(Deserialize, Debug, Default)]
 pub struct Config {
@@ -7,6 +15,88 @@ pub struct Config {
 
     #[serde(default)]
     pub disk: DiskKeys,
+
+    #[serde(default)]
+    pub partitioning: Partitioning,
+
+    #[serde(default)]
+    pub display: Display,
+
+    #[serde(default)]
+    pub monitoring: Monitoring,
+
+    /// Mount settings remembered per filesystem UUID.
+    #[serde(default)]
+    pub mounts: HashMap<String, MountDefaults>,
+}
+
+/// How a filesystem was last mounted from the mount dialog, saved as a
+/// `[mounts."<uuid>"]` table.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct MountDefaults {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub noatime: bool,
+    /// Mount FAT, exFAT and NTFS filesystems for the user running disktui.
+    #[serde(default)]
+    pub owner: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compress: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subvol: Option<String>,
+}
+
+impl MountDefaults {
+    pub fn options(&self) -> MountOptions {
+        MountOptions {
+            read_only: self.read_only,
+            noatime: self.noatime,
+            owner: self.owner.then(Owner::current),
+            compress: self.compress.clone(),
+            subvol: self.subvol.clone(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Display {
+    /// "si" for KB/MB/GB or "iec" for KiB/MiB/GiB.
+    #[serde(default)]
+    pub units: SizeUnits,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Monitoring {
+    /// Seconds between background SMART readings; 0 turns them off.
+    #[serde(default = "default_smart_interval")]
+    pub smart_interval: u64,
+
+    #[serde(default, flatten)]
+    pub thresholds: Thresholds,
+}
+
+impl Default for Monitoring {
+    fn default() -> Self {
+        Self {
+            smart_interval: default_smart_interval(),
+            thresholds: Thresholds::default(),
+        }
+    }
+}
+
+fn default_smart_interval() -> u64 {
+    600
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Partitioning {
+    /// Boundary new and resized partitions are aligned to: "1MiB" or
+    /// "optimal" for the disk's optimal I/O size.
+    #[serde(default)]
+    pub alignment: Alignment,
 }
 
 #[derive(Deserialize, Debug)]
@@ -60,6 +150,42 @@ pub struct DiskKeys {
 
     #[serde(default = "default_encrypt")]
     pub encrypt: char,
+
+    #[serde(default = "default_queue")]
+    pub queue: char,
+
+    #[serde(default = "default_history")]
+    pub history: char,
+
+    #[serde(default = "default_restore")]
+    pub restore: char,
+
+    #[serde(default = "default_part_type")]
+    pub part_type: char,
+
+    #[serde(default = "default_swap_file")]
+    pub swap_file: char,
+
+    #[serde(default = "default_check")]
+    pub check: char,
+
+    #[serde(default = "default_label")]
+    pub label: char,
+
+    #[serde(default = "default_uuid")]
+    pub uuid: char,
+
+    #[serde(default = "default_fstab")]
+    pub fstab: char,
+
+    #[serde(default = "default_lvm")]
+    pub lvm: char,
+
+    #[serde(default = "default_raid")]
+    pub raid: char,
+
+    #[serde(default = "default_subvolumes")]
+    pub subvolumes: char,
 }
 
 impl Default for DiskKeys {
@@ -73,6 +199,18 @@ impl Default for DiskKeys {
             resize: 'r',
             lock: 'l',
             encrypt: 'e',
+            queue: 'o',
+            history: 'h',
+            restore: 'b',
+            part_type: 't',
+            swap_file: 'w',
+            check: 'c',
+            label: 'L',
+            uuid: 'U',
+            fstab: 'F',
+            lvm: 'V',
+            raid: 'R',
+            subvolumes: 'S',
         }
     }
 }
@@ -109,12 +247,64 @@ fn default_encrypt() -> char {
     'e'
 }
 
+fn default_queue() -> char {
+    'o'
+}
+
+fn default_history() -> char {
+    'h'
+}
+
+fn default_restore() -> char {
+    'b'
+}
+
+fn default_part_type() -> char {
+    't'
+}
+
+fn default_swap_file() -> char {
+    'w'
+}
+
+fn default_check() -> char {
+    'c'
+}
+
+fn default_label() -> char {
+    'L'
+}
+
+fn default_uuid() -> char {
+    'U'
+}
+
+fn default_fstab() -> char {
+    'F'
+}
+
+fn default_lvm() -> char {
+    'V'
+}
+
+fn default_raid() -> char {
+    'R'
+}
+
+fn default_subvolumes() -> char {
+    'S'
+}
+
 impl Config {
-    pub fn new() -> Self {
-        let conf_path = dirs::config_dir()
-            .unwrap_or_else(|| std::path::PathBuf::from("."))
+    pub fn path() -> PathBuf {
+        dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
             .join("disktui")
-            .join("config.toml");
+            .join("config.toml")
+    }
+
+    pub fn new() -> Self {
+        let conf_path = Self::path();
 
         match std::fs::read_to_string(&conf_path) {
             Ok(config_str) => {
@@ -128,3 +318,107 @@ impl Config {
         }
     }
 }
+
+/// Saves the mount settings of a filesystem to the config file, or forgets
+/// them with `None`. Only that filesystem's table is rewritten, so comments
+/// and other settings stay.
+pub fn save_mount_defaults(uuid: &str, defaults: Option<&MountDefaults>) -> Result<()> {
+    let path = Config::path();
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let table = defaults
+        .map(toml::to_string)
+        .transpose()
+        .context("Failed to encode mount settings")?;
+    let contents = replace_mount_table(&contents, uuid, table.as_deref())?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// Replaces the mount table of `uuid` in `contents` with `table`, appending
+/// it when there is none and removing it for `None`. The file is edited as
+/// a TOML document, so however the table was written it is found, and
+/// comments and layout elsewhere are kept.
+fn replace_mount_table(contents: &str, uuid: &str, table: Option<&str>) -> Result<String> {
+    let mut doc: DocumentMut = contents.parse().context("Failed to parse config file")?;
+    let Some(table) = table else {
+        if let Some(mounts) = doc.get_mut("mounts").and_then(Item::as_table_like_mut) {
+            mounts.remove(uuid);
+            if mounts.is_empty() {
+                doc.remove("mounts");
+            }
+        }
+        return Ok(doc.to_string());
+    };
+
+    let table: DocumentMut = table.parse().context("Failed to encode mount settings")?;
+    let mounts = doc
+        .entry("mounts")
+        .or_insert_with(|| {
+            let mut mounts = Table::new();
+            mounts.set_implicit(true);
+            Item::Table(mounts)
+        })
+        .as_table_like_mut()
+        .context("mounts in the config file is not a table")?;
+    match mounts.get_mut(uuid).and_then(Item::as_table_like_mut) {
+        Some(existing) => {
+            existing.clear();
+            for (key, value) in table.iter() {
+                existing.insert(key, value.clone());
+            }
+        }
+        None => {
+            let mut new = table.as_table().clone();
+            if !contents.trim().is_empty() {
+                new.decor_mut().set_prefix("\n");
+            }
+            mounts.insert(uuid, Item::Table(new));
+        }
+    }
+    Ok(doc.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mount_tables_are_replaced_in_place() {
+        let contents = "# keys\n[disk]\nformat = 'F'\n\n[mounts.\"1234-ABCD\"]\nread_only = true\n\n[display]\nunits = \"iec\"\n";
+        let replaced = replace_mount_table(contents, "1234-ABCD", Some("noatime = true\n")).unwrap();
+        assert_eq!(
+            replaced,
+            "# keys\n[disk]\nformat = 'F'\n\n[mounts.\"1234-ABCD\"]\nnoatime = true\n\n[display]\nunits = \"iec\"\n"
+        );
+        let removed = replace_mount_table(contents, "1234-ABCD", None).unwrap();
+        assert_eq!(removed, "# keys\n[disk]\nformat = 'F'\n\n[display]\nunits = \"iec\"\n");
+
+        let appended = replace_mount_table("[disk]\nformat = 'F'\n", "5678", Some("owner = true\n")).unwrap();
+        assert_eq!(appended, "[disk]\nformat = 'F'\n\n[mounts.5678]\nowner = true\n");
+        let config: Config = toml::from_str(&appended).unwrap();
+        assert!(config.mounts["5678"].owner);
+
+        // A table written by hand with a bare key is the same table.
+        let bare = "[mounts.1234-ABCD]\nread_only = true\n";
+        let replaced = replace_mount_table(bare, "1234-ABCD", Some("owner = true\n")).unwrap();
+        assert_eq!(replaced, "[mounts.1234-ABCD]\nowner = true\n");
+        let config: Config = toml::from_str(&replaced).unwrap();
+        assert!(config.mounts["1234-ABCD"].owner);
+    }
+
+    #[test]
+    fn monitoring_thresholds_default_individually() {
+        let config: Config =
+            toml::from_str("[monitoring]\nsmart_interval = 60\ntemperature_warning = 50\n").unwrap();
+        assert_eq!(config.monitoring.smart_interval, 60);
+        assert_eq!(config.monitoring.thresholds.temperature_warning, 50);
+        assert_eq!(config.monitoring.thresholds.temperature_critical, 65);
+    }
+}
@@ -1,5 +1,12 @@
-use crate::operations::{BlockDevice, SmartData};
-use crate::utils::format_bytes;
+use crate::operations::{BlockDevice, get_smart_data};
+use crate::runner::CommandRunner;
+use crate::smart::SmartData;
+use crate::utils::{SizeUnits, format_size};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long SMART data is reused before smartctl runs again for a disk.
+const SMART_TTL: Duration = Duration::from_secs(300);
 
 #[derive(Debug, Clone)]
 pub struct Disk {
@@ -12,8 +19,8 @@ impl Disk {
         Self { device, smart_data }
     }
 
-    pub fn size_str(&self) -> String {
-        format_bytes(self.device.size)
+    pub fn size_str(&self, units: SizeUnits) -> String {
+        format_size(self.device.size, units)
     }
 
     pub fn device_type(&self) -> &str {
@@ -31,3 +38,75 @@ impl Disk {
         }
     }
 }
+
+/// SMART results per disk. smartctl is slow and may wake a sleeping disk, so
+/// refreshes reuse recent results and only probe new or stale disks, all at
+/// once.
+#[derive(Debug, Default)]
+pub struct SmartCache {
+    entries: HashMap<String, (Instant, Option<SmartData>)>,
+    /// Disks the last `load` read SMART data from.
+    probed: Vec<String>,
+}
+
+impl SmartCache {
+    /// Pairs each device with its SMART data, probing where needed.
+    pub async fn load(
+        &mut self,
+        runner: &dyn CommandRunner,
+        devices: Vec<BlockDevice>,
+    ) -> Vec<Disk> {
+        let now = Instant::now();
+        let probes = devices
+            .iter()
+            .filter(|device| {
+                self.entries
+                    .get(&cache_key(device))
+                    .is_none_or(|(at, _)| now.duration_since(*at) > SMART_TTL)
+            })
+            .map(|device| async move {
+                (
+                    device.name.clone(),
+                    cache_key(device),
+                    get_smart_data(runner, &device.name).await.ok(),
+                )
+            });
+        self.probed.clear();
+        for (name, key, smart_data) in futures::future::join_all(probes).await {
+            if smart_data.is_some() {
+                self.probed.push(name);
+            }
+            self.entries.insert(key, (now, smart_data));
+        }
+
+        self.entries
+            .retain(|key, _| devices.iter().any(|device| &cache_key(device) == key));
+        devices
+            .into_iter()
+            .map(|device| {
+                let smart_data = self
+                    .entries
+                    .get(&cache_key(&device))
+                    .and_then(|(_, smart_data)| smart_data.clone());
+                Disk::new(device, smart_data)
+            })
+            .collect()
+    }
+
+    pub fn probed(&self) -> &[String] {
+        &self.probed
+    }
+
+    /// Replaces the cached data of `device` with data read outside of
+    /// `load`.
+    pub fn store(&mut self, device: &BlockDevice, smart_data: Option<SmartData>) {
+        self.entries
+            .insert(cache_key(device), (Instant::now(), smart_data));
+    }
+}
+
+/// Keyed by serial as well as name, so a different disk showing up under a
+/// reused name is probed again.
+fn cache_key(device: &BlockDevice) -> String {
+    format!("{}/{}", device.name, device.serial.as_deref().unwrap_or(""))
+}
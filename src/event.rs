@@ -1,10 +1,23 @@
+use std::collections::HashMap;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
 use std::time::Duration;
 
 use crossterm::event::{Event as CrosstermEvent, KeyEvent};
 use futures::{FutureExt, StreamExt};
 use tokio::sync::mpsc;
 
-use crate::{app::AppResult, notification::Notification};
+use crate::{
+    app::{AppResult, CommandPreview},
+    audit::AuditEntry,
+    backup::BackupList,
+    btrfs::SubvolumeList,
+    fsck::CheckReport,
+    lvm::LvmReport,
+    notification::Notification,
+    raid::RaidReport,
+    smart::SmartData,
+};
 
 #[derive(Clone, Debug)]
 pub enum Event {
@@ -14,6 +27,148 @@ pub enum Event {
     Refresh,
     StartProgress(String),
     EndProgress,
+    QueueFinished(usize),
+    /// Dry-run result, keyed by the description of the previewed request.
+    CommandPreview(String, CommandPreview),
+    AuditLog(Result<Vec<AuditEntry>, String>),
+    Backups(Result<BackupList, String>),
+    Lvm(Result<LvmReport, String>),
+    Raid(Result<RaidReport, String>),
+    Subvolumes(Result<SubvolumeList, String>),
+    /// A line printed by the running filesystem checker.
+    CheckOutput(String),
+    CheckFinished(Result<CheckReport, String>),
+    /// SMART data read in the background, keyed by disk name.
+    SmartData(String, Option<Box<SmartData>>),
+}
+
+/// Hotplug usually comes as a burst of uevents (the disk, then each of its
+/// partitions), so refreshes wait this long for the burst to end.
+const HOTPLUG_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// A kernel uevent, as broadcast on the `NETLINK_KOBJECT_UEVENT` socket.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Uevent {
+    pub action: String,
+    pub properties: HashMap<String, String>,
+}
+
+impl Uevent {
+    /// Parses a message of the form `action@devpath\0KEY=value\0...`.
+    /// Messages from udev itself start with `libudev` and are ignored.
+    pub fn parse(message: &[u8]) -> Option<Self> {
+        let mut fields = message.split(|&b| b == 0).map(String::from_utf8_lossy);
+        let header = fields.next()?;
+        let (action, _) = header.split_once('@')?;
+        let properties = fields
+            .filter_map(|field| {
+                let (key, value) = field.split_once('=')?;
+                Some((key.to_string(), value.to_string()))
+            })
+            .collect();
+        Some(Self {
+            action: action.to_string(),
+            properties,
+        })
+    }
+
+    fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(|value| value.as_str())
+    }
+
+    /// Whether the event means a disk appeared or went away: a whole disk
+    /// being added or removed, or a card reader reporting new media.
+    pub fn is_disk_hotplug(&self) -> bool {
+        if self.property("SUBSYSTEM") != Some("block") || self.property("DEVTYPE") != Some("disk") {
+            return false;
+        }
+        match self.action.as_str() {
+            "add" | "remove" => true,
+            "change" => self.property("DISK_MEDIA_CHANGE") == Some("1"),
+            _ => false,
+        }
+    }
+}
+
+fn open_uevent_socket() -> io::Result<OwnedFd> {
+    // SAFETY: plain socket(2) call; the returned descriptor is owned below.
+    let fd = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_DGRAM | libc::SOCK_CLOEXEC,
+            libc::NETLINK_KOBJECT_UEVENT,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: fd is a freshly created descriptor that nothing else owns.
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    // SAFETY: sockaddr_nl is plain data, all zeroes is a valid value.
+    let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    // Group 1 carries the kernel's own uevents.
+    addr.nl_groups = 1;
+    // SAFETY: addr is a valid sockaddr_nl and the length matches it.
+    let ret = unsafe {
+        libc::bind(
+            socket.as_raw_fd(),
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(socket)
+}
+
+/// Listens for block device uevents and sends a debounced `Event::Refresh`
+/// when a disk is plugged in or removed. Without netlink access (some
+/// containers) hotplug detection is silently unavailable.
+fn spawn_hotplug_monitor(sender: mpsc::UnboundedSender<Event>) {
+    let Ok(socket) = open_uevent_socket() else {
+        return;
+    };
+    let (hotplug_sender, mut hotplug) = mpsc::unbounded_channel();
+
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        loop {
+            // SAFETY: buf is valid for writes of buf.len() bytes.
+            let len = unsafe {
+                libc::recv(
+                    socket.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                )
+            };
+            if len < 0 {
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                break;
+            }
+            let Some(uevent) = Uevent::parse(&buf[..len as usize]) else {
+                continue;
+            };
+            if uevent.is_disk_hotplug() && hotplug_sender.send(()).is_err() {
+                break;
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        while hotplug.recv().await.is_some() {
+            tokio::time::sleep(HOTPLUG_DEBOUNCE).await;
+            while hotplug.try_recv().is_ok() {}
+            if sender.send(Event::Refresh).is_err() {
+                break;
+            }
+        }
+    });
 }
 
 #[derive(Debug)]
@@ -27,6 +182,7 @@ impl EventHandler {
     pub fn new(tick_rate: u64) -> Self {
         let tick_rate = Duration::from_millis(tick_rate);
         let (sender, receiver) = mpsc::unbounded_channel();
+        spawn_hotplug_monitor(sender.clone());
         let sender_cloned = sender.clone();
         let handler = tokio::spawn(async move {
             let mut reader = crossterm::event::EventStream::new();
@@ -67,3 +223,29 @@ impl EventHandler {
             .ok_or_else(|| anyhow::anyhow!("Event channel closed"))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uevent_parse_detects_disk_hotplug() {
+        let add = b"add@/devices/pci0000:00/usb1/1-1/host6/target6:0:0/6:0:0:0/block/sdc\0\
+ACTION=add\0DEVPATH=/devices/pci0000:00/usb1/1-1/host6/target6:0:0/6:0:0:0/block/sdc\0\
+SUBSYSTEM=block\0DEVNAME=sdc\0DEVTYPE=disk\0SEQNUM=4242\0";
+        let uevent = Uevent::parse(add).unwrap();
+        assert_eq!(uevent.action, "add");
+        assert_eq!(uevent.property("DEVNAME"), Some("sdc"));
+        assert!(uevent.is_disk_hotplug());
+
+        let partition = b"add@/block/sdc/sdc1\0SUBSYSTEM=block\0DEVTYPE=partition\0";
+        assert!(!Uevent::parse(partition).unwrap().is_disk_hotplug());
+
+        let media = b"change@/block/sdd\0SUBSYSTEM=block\0DEVTYPE=disk\0DISK_MEDIA_CHANGE=1\0";
+        assert!(Uevent::parse(media).unwrap().is_disk_hotplug());
+        let change = b"change@/block/sdd\0SUBSYSTEM=block\0DEVTYPE=disk\0";
+        assert!(!Uevent::parse(change).unwrap().is_disk_hotplug());
+
+        assert_eq!(Uevent::parse(b"libudev\0\xfe\xed"), None);
+    }
+}
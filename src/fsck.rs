@@ -0,0 +1,213 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+/// Overall result of a filesystem check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    /// No errors were found.
+    Clean,
+    /// Errors were found and repaired.
+    Corrected,
+    /// Errors were found and are still there.
+    Errors,
+    /// The checker could not do its job.
+    Failed,
+}
+
+impl CheckStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckStatus::Clean => "clean",
+            CheckStatus::Corrected => "corrected",
+            CheckStatus::Errors => "errors",
+            CheckStatus::Failed => "failed",
+        }
+    }
+}
+
+/// What the helper reports once a checker has run. The checker's output
+/// has been streamed before this.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckReport {
+    pub device: String,
+    pub fs_type: String,
+    pub repair: bool,
+    /// `None` when the checker was killed by a signal.
+    pub exit_code: Option<i32>,
+    pub status: CheckStatus,
+    pub meaning: String,
+}
+
+/// The checking tool of a filesystem. Each has its own flags and exit
+/// codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checker {
+    E2fsck,
+    FsckFat,
+    FsckExfat,
+    XfsRepair,
+    BtrfsCheck,
+    Ntfsfix,
+}
+
+impl Checker {
+    /// The checker for a filesystem type as blkid or lsblk report it.
+    pub fn for_filesystem(fs_type: &str) -> Result<Checker> {
+        match fs_type {
+            "ext2" | "ext3" | "ext4" => Ok(Checker::E2fsck),
+            "vfat" | "fat32" | "fat16" | "fat12" => Ok(Checker::FsckFat),
+            "exfat" => Ok(Checker::FsckExfat),
+            "xfs" => Ok(Checker::XfsRepair),
+            "btrfs" => Ok(Checker::BtrfsCheck),
+            "ntfs" | "ntfs3" => Ok(Checker::Ntfsfix),
+            "crypto_LUKS" => Err(anyhow!(
+                "The device is encrypted. Unlock it and check the unlocked device"
+            )),
+            "" => Err(anyhow!("No filesystem detected")),
+            other => Err(anyhow!("Checking {} filesystems is not supported", other)),
+        }
+    }
+
+    /// The program to run, for error messages and `which`.
+    pub fn program(&self) -> &'static str {
+        match self {
+            Checker::E2fsck => "e2fsck",
+            Checker::FsckFat => "fsck.fat",
+            Checker::FsckExfat => "fsck.exfat",
+            Checker::XfsRepair => "xfs_repair",
+            Checker::BtrfsCheck => "btrfs",
+            Checker::Ntfsfix => "ntfsfix",
+        }
+    }
+
+    /// Arguments before the device path. Read-only checks never write to
+    /// the device; repairs answer yes to every fix so that nothing waits
+    /// for input.
+    pub fn args(&self, repair: bool) -> Result<Vec<&'static str>> {
+        let args = match (self, repair) {
+            (Checker::E2fsck, false) => vec!["-f", "-n"],
+            (Checker::E2fsck, true) => vec!["-f", "-y"],
+            (Checker::FsckFat, false) => vec!["-n"],
+            (Checker::FsckFat, true) => vec!["-a"],
+            (Checker::FsckExfat, false) => vec!["-n"],
+            (Checker::FsckExfat, true) => vec!["-y"],
+            (Checker::XfsRepair, false) => vec!["-n"],
+            (Checker::XfsRepair, true) => vec![],
+            (Checker::BtrfsCheck, false) => vec!["check", "--readonly"],
+            // The btrfs documentation warns that --repair can make damage
+            // worse; it is not something to run from a menu.
+            (Checker::BtrfsCheck, true) => {
+                return Err(anyhow!(
+                    "btrfs check --repair is not offered since it can cause further damage. Use a read-only check, then btrfs scrub or btrfs rescue"
+                ));
+            }
+            (Checker::Ntfsfix, false) => vec!["-n"],
+            (Checker::Ntfsfix, true) => vec![],
+        };
+        Ok(args)
+    }
+
+    /// What an exit code means for this checker.
+    pub fn interpret(&self, repair: bool, exit_code: Option<i32>) -> (CheckStatus, String) {
+        let Some(code) = exit_code else {
+            return (CheckStatus::Failed, "Killed by a signal".to_string());
+        };
+        let (status, meaning) = match self {
+            // Both follow the fsck(8) convention of summing these bits.
+            Checker::E2fsck | Checker::FsckExfat => match code {
+                0 => (CheckStatus::Clean, "No errors found"),
+                _ if code & 8 != 0 => (CheckStatus::Failed, "Operational error"),
+                _ if code & 16 != 0 => (CheckStatus::Failed, "Usage or syntax error"),
+                _ if code & 32 != 0 => (CheckStatus::Failed, "Checking was cancelled"),
+                _ if code & 128 != 0 => (CheckStatus::Failed, "Shared library error"),
+                _ if code & 4 != 0 => (CheckStatus::Errors, "Errors left uncorrected"),
+                _ if code & 2 != 0 => (
+                    CheckStatus::Corrected,
+                    "Errors corrected, the system should be rebooted",
+                ),
+                _ => (CheckStatus::Corrected, "Errors corrected"),
+            },
+            Checker::FsckFat => match (code, repair) {
+                (0, _) => (CheckStatus::Clean, "No errors found"),
+                (1, false) => (CheckStatus::Errors, "Errors found"),
+                (1, true) => (CheckStatus::Corrected, "Errors found and corrected"),
+                (2, _) => (
+                    CheckStatus::Failed,
+                    "Usage error, the filesystem was not accessed",
+                ),
+                _ => (CheckStatus::Failed, "Unexpected exit code"),
+            },
+            Checker::XfsRepair => match (code, repair) {
+                (0, false) => (CheckStatus::Clean, "No corruption found"),
+                (1, false) => (CheckStatus::Errors, "Corruption found"),
+                (0, true) => (CheckStatus::Clean, "Repair completed"),
+                (1, true) => (
+                    CheckStatus::Failed,
+                    "Repair failed with a runtime error, run it again",
+                ),
+                (2, _) => (
+                    CheckStatus::Failed,
+                    "The log is dirty, mount and unmount the filesystem to replay it first",
+                ),
+                _ => (CheckStatus::Failed, "Unexpected exit code"),
+            },
+            Checker::BtrfsCheck => match code {
+                0 => (CheckStatus::Clean, "No errors found"),
+                _ => (CheckStatus::Errors, "Errors found"),
+            },
+            // ntfsfix only fixes common problems and schedules a full check
+            // for the next Windows boot.
+            Checker::Ntfsfix => match (code, repair) {
+                (0, false) => (CheckStatus::Clean, "No common problems found"),
+                (0, true) => (
+                    CheckStatus::Corrected,
+                    "Common problems fixed, run chkdsk on Windows for a full check",
+                ),
+                (_, false) => (CheckStatus::Errors, "Problems found"),
+                (_, true) => (CheckStatus::Failed, "The volume could not be fixed"),
+            },
+        };
+        (status, format!("{} (exit code {})", meaning, code))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes_follow_each_checker() {
+        let (status, meaning) = Checker::E2fsck.interpret(true, Some(1));
+        assert_eq!(status, CheckStatus::Corrected);
+        assert_eq!(meaning, "Errors corrected (exit code 1)");
+        assert_eq!(
+            Checker::E2fsck.interpret(false, Some(4)).0,
+            CheckStatus::Errors
+        );
+        assert_eq!(
+            Checker::E2fsck.interpret(true, Some(12)).0,
+            CheckStatus::Failed
+        );
+        assert_eq!(
+            Checker::FsckFat.interpret(false, Some(1)).0,
+            CheckStatus::Errors
+        );
+        assert_eq!(
+            Checker::XfsRepair.interpret(true, Some(2)).0,
+            CheckStatus::Failed
+        );
+        assert_eq!(
+            Checker::Ntfsfix.interpret(true, None).0,
+            CheckStatus::Failed
+        );
+    }
+
+    #[test]
+    fn btrfs_is_only_checked_read_only() {
+        let checker = Checker::for_filesystem("btrfs").unwrap();
+        assert_eq!(checker.args(false).unwrap(), vec!["check", "--readonly"]);
+        assert!(checker.args(true).is_err());
+        assert!(Checker::for_filesystem("swap").is_err());
+    }
+}
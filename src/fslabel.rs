@@ -0,0 +1,160 @@
+use anyhow::{Result, anyhow};
+
+/// How long a label may be and which characters it may contain.
+struct LabelRules {
+    /// Maximum length in bytes, or in UTF-16 code units when `utf16` is set.
+    max: usize,
+    utf16: bool,
+    /// FAT labels are stored in the OEM code page, so only plain ASCII is
+    /// safe.
+    ascii_only: bool,
+    forbidden: &'static str,
+}
+
+/// Filesystem names as blkid reports them, plus the names the format
+/// dialog uses.
+fn rules(fs_type: &str) -> Result<LabelRules> {
+    let (max, utf16, ascii_only, forbidden) = match fs_type {
+        "ext2" | "ext3" | "ext4" => (16, false, false, ""),
+        "vfat" | "fat32" | "fat16" | "fat12" => (11, false, true, "*?.,;:/\\|+=<>[]\""),
+        "exfat" => (11, true, false, ""),
+        "ntfs" | "ntfs3" => (128, true, false, ""),
+        "btrfs" => (255, false, false, ""),
+        "xfs" => (12, false, false, ""),
+        "swap" => (16, false, false, ""),
+        "" => return Err(anyhow!("No filesystem detected")),
+        other => return Err(anyhow!("Labels of {} filesystems are not supported", other)),
+    };
+    Ok(LabelRules {
+        max,
+        utf16,
+        ascii_only,
+        forbidden,
+    })
+}
+
+/// Checks a label against the limits of the filesystem. An empty label
+/// clears it.
+pub fn validate_label(fs_type: &str, label: &str) -> Result<()> {
+    let rules = rules(fs_type)?;
+    if label.chars().any(char::is_control) {
+        return Err(anyhow!("Labels cannot contain control characters"));
+    }
+    if rules.ascii_only && !label.is_ascii() {
+        return Err(anyhow!(
+            "{} labels can only contain ASCII characters",
+            fs_type
+        ));
+    }
+    if let Some(c) = label.chars().find(|c| rules.forbidden.contains(*c)) {
+        return Err(anyhow!("{} labels cannot contain '{}'", fs_type, c));
+    }
+    let (length, unit) = if rules.utf16 {
+        (label.encode_utf16().count(), "characters")
+    } else {
+        (label.len(), "bytes")
+    };
+    if length > rules.max {
+        return Err(anyhow!(
+            "{} labels are at most {} {}",
+            fs_type,
+            rules.max,
+            unit
+        ));
+    }
+    Ok(())
+}
+
+/// The mkfs option that sets the label, if a label was given.
+pub fn mkfs_label_args<'a>(fs_type: &str, label: Option<&'a str>) -> Vec<&'a str> {
+    match (fs_type, label) {
+        (_, None) | (_, Some("")) => Vec::new(),
+        ("fat32" | "vfat", Some(label)) => vec!["-n", label],
+        (_, Some(label)) => vec!["-L", label],
+    }
+}
+
+/// Whether the label tool works while the filesystem is mounted. btrfs is
+/// then labelled through its mount point.
+pub fn can_relabel_mounted(fs_type: &str) -> bool {
+    matches!(fs_type, "ext2" | "ext3" | "ext4" | "btrfs")
+}
+
+/// The command that sets the label of the filesystem at `target`, a device
+/// or, for mounted btrfs, the mount point.
+pub fn label_command<'a>(
+    fs_type: &str,
+    target: &'a str,
+    label: &'a str,
+) -> Result<(&'static str, Vec<&'a str>)> {
+    validate_label(fs_type, label)?;
+    let command = match fs_type {
+        "ext2" | "ext3" | "ext4" => ("e2label", vec![target, label]),
+        "vfat" | "fat32" | "fat16" | "fat12" if label.is_empty() => {
+            ("fatlabel", vec!["-r", target])
+        }
+        "vfat" | "fat32" | "fat16" | "fat12" => ("fatlabel", vec![target, label]),
+        "exfat" => ("exfatlabel", vec![target, label]),
+        "ntfs" | "ntfs3" => ("ntfslabel", vec![target, label]),
+        "btrfs" => ("btrfs", vec!["filesystem", "label", target, label]),
+        // "--" is how xfs_admin clears a label.
+        "xfs" if label.is_empty() => ("xfs_admin", vec!["-L", "--", target]),
+        "xfs" => ("xfs_admin", vec!["-L", label, target]),
+        "swap" => ("swaplabel", vec!["-L", label, target]),
+        _ => unreachable!("validate_label accepted {}", fs_type),
+    };
+    Ok(command)
+}
+
+/// The command that gives the filesystem on `device` a new random UUID.
+pub fn uuid_command<'a>(fs_type: &str, device: &'a str) -> Result<(&'static str, Vec<&'a str>)> {
+    match fs_type {
+        "ext2" | "ext3" | "ext4" => Ok(("tune2fs", vec!["-U", "random", device])),
+        "xfs" => Ok(("xfs_admin", vec!["-U", "generate", device])),
+        // -f skips the confirmation prompt; all metadata is rewritten.
+        "btrfs" => Ok(("btrfstune", vec!["-f", "-u", device])),
+        "" => Err(anyhow!("No filesystem detected")),
+        other => Err(anyhow!(
+            "Changing the UUID of {} filesystems is not supported",
+            other
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_follow_filesystem_limits() {
+        assert!(validate_label("ext4", "sixteen-bytes-ok").is_ok());
+        assert_eq!(
+            validate_label("ext4", "seventeen-bytes!!")
+                .unwrap_err()
+                .to_string(),
+            "ext4 labels are at most 16 bytes"
+        );
+        assert!(validate_label("vfat", "USB STICK").is_ok());
+        assert!(validate_label("vfat", "a.b").is_err());
+        assert!(validate_label("vfat", "ÜSB").is_err());
+        // Eleven characters, but more than eleven bytes.
+        assert!(validate_label("exfat", "ÄÖÜäöüßéèêë").is_ok());
+        assert!(validate_label("xfs", "tab\there").is_err());
+        assert!(validate_label("iso9660", "x").is_err());
+    }
+
+    #[test]
+    fn commands_dispatch_per_filesystem() {
+        assert_eq!(
+            label_command("xfs", "/dev/sdb1", "").unwrap(),
+            ("xfs_admin", vec!["-L", "--", "/dev/sdb1"])
+        );
+        assert_eq!(
+            label_command("vfat", "/dev/sdb1", "BOOT").unwrap(),
+            ("fatlabel", vec!["/dev/sdb1", "BOOT"])
+        );
+        assert_eq!(mkfs_label_args("fat32", Some("BOOT")), vec!["-n", "BOOT"]);
+        assert!(mkfs_label_args("ext4", Some("")).is_empty());
+        assert!(uuid_command("vfat", "/dev/sdb1").is_err());
+    }
+}
@@ -0,0 +1,428 @@
+use crate::sysfs::unescape_octal;
+use anyhow::{Result, anyhow};
+
+pub const FSTAB: &str = "/etc/fstab";
+pub const CRYPTTAB: &str = "/etc/crypttab";
+
+/// A line of /etc/fstab.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FstabEntry {
+    /// What to mount: "UUID=…", "LABEL=…", a device or file path, or a
+    /// network share.
+    pub source: String,
+    /// The mount point, or "none" for swap.
+    pub target: String,
+    pub fs_type: String,
+    pub options: String,
+    pub dump: u32,
+    /// Order of the checks at boot: 0 for none, 1 for the root filesystem
+    /// and 2 for the others.
+    pub pass: u32,
+}
+
+impl FstabEntry {
+    /// Options, dump and pass may be left out and then take their defaults.
+    fn from_fields(fields: &[String]) -> Option<Self> {
+        let [source, target, fs_type, rest @ ..] = fields else {
+            return None;
+        };
+        Some(FstabEntry {
+            source: source.clone(),
+            target: target.clone(),
+            fs_type: fs_type.clone(),
+            options: rest
+                .first()
+                .cloned()
+                .unwrap_or_else(|| "defaults".to_string()),
+            dump: rest.get(1).and_then(|v| v.parse().ok()).unwrap_or(0),
+            pass: rest.get(2).and_then(|v| v.parse().ok()).unwrap_or(0),
+        })
+    }
+
+    pub fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            escape(&self.source),
+            escape(&self.target),
+            escape(&self.fs_type),
+            escape(&self.options),
+            self.dump,
+            self.pass
+        )
+    }
+}
+
+/// A line of /etc/crypttab.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrypttabEntry {
+    /// Name of the unlocked device under /dev/mapper.
+    pub name: String,
+    pub device: String,
+    /// "none" asks for the passphrase at boot.
+    pub key_file: String,
+    pub options: String,
+}
+
+impl CrypttabEntry {
+    fn from_fields(fields: &[String]) -> Option<Self> {
+        let [name, device, rest @ ..] = fields else {
+            return None;
+        };
+        Some(CrypttabEntry {
+            name: name.clone(),
+            device: device.clone(),
+            key_file: rest.first().cloned().unwrap_or_else(|| "none".to_string()),
+            options: rest.get(1).cloned().unwrap_or_default(),
+        })
+    }
+
+    pub fn to_line(&self) -> String {
+        let mut line = format!(
+            "{}\t{}\t{}",
+            escape(&self.name),
+            escape(&self.device),
+            escape(&self.key_file)
+        );
+        if !self.options.is_empty() {
+            line.push('\t');
+            line.push_str(&escape(&self.options));
+        }
+        line
+    }
+}
+
+/// The unescaped fields of an entry; `None` for comments and blank lines.
+fn fields(line: &str) -> Option<Vec<String>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    Some(line.split_whitespace().map(unescape_octal).collect())
+}
+
+/// Escapes whitespace and backslashes as octal, the way fstab(5) reads
+/// them.
+fn escape(field: &str) -> String {
+    let mut escaped = String::with_capacity(field.len());
+    for c in field.chars() {
+        match c {
+            ' ' | '\t' | '\n' | '\\' => escaped.push_str(&format!("\\{:03o}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Checks an options field: a comma separated list without whitespace,
+/// which would start the next field.
+pub fn validate_options(options: &str) -> Result<()> {
+    if options.is_empty() {
+        return Err(anyhow!("Options cannot be empty, use \"defaults\""));
+    }
+    if options.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err(anyhow!("Options cannot contain spaces"));
+    }
+    if options.split(',').any(str::is_empty) {
+        return Err(anyhow!("Options cannot contain empty items"));
+    }
+    if options.len() > 4096 {
+        return Err(anyhow!("Options are too long"));
+    }
+    Ok(())
+}
+
+/// The usual pass number for a filesystem other than the root: 2 where
+/// fsck checks it at boot, 0 where that is not done.
+pub fn default_pass(fs_type: &str) -> u32 {
+    match fs_type {
+        "ext2" | "ext3" | "ext4" | "vfat" | "fat32" | "fat16" | "fat12" => 2,
+        // xfs and btrfs check themselves when mounted.
+        _ => 0,
+    }
+}
+
+pub fn parse_fstab(contents: &str) -> Vec<FstabEntry> {
+    contents
+        .lines()
+        .filter_map(fields)
+        .filter_map(|fields| FstabEntry::from_fields(&fields))
+        .collect()
+}
+
+pub fn parse_crypttab(contents: &str) -> Vec<CrypttabEntry> {
+    contents
+        .lines()
+        .filter_map(fields)
+        .filter_map(|fields| CrypttabEntry::from_fields(&fields))
+        .collect()
+}
+
+/// Puts `line` in place of the first entry `is_same` matches and drops the
+/// other matches, or appends it when nothing matches. Without a line the
+/// matches are only dropped. Comments and other entries stay as they are.
+fn replace_entries(
+    contents: &str,
+    is_same: impl Fn(&[String]) -> bool,
+    line: Option<&str>,
+) -> String {
+    let mut lines = Vec::new();
+    let mut placed = false;
+    for existing in contents.lines() {
+        if fields(existing).is_some_and(|fields| is_same(&fields)) {
+            if !placed {
+                lines.extend(line);
+                placed = true;
+            }
+            continue;
+        }
+        lines.push(existing);
+    }
+    if !placed {
+        lines.extend(line);
+    }
+    if lines.is_empty() {
+        return String::new();
+    }
+    let mut updated = lines.join("\n");
+    updated.push('\n');
+    updated
+}
+
+/// Sets, or with `None` removes, the fstab entries `is_same` matches.
+pub fn update_fstab(
+    contents: &str,
+    is_same: impl Fn(&FstabEntry) -> bool,
+    entry: Option<&FstabEntry>,
+) -> String {
+    let line = entry.map(FstabEntry::to_line);
+    replace_entries(
+        contents,
+        |fields| FstabEntry::from_fields(fields).is_some_and(|existing| is_same(&existing)),
+        line.as_deref(),
+    )
+}
+
+/// Sets, or with `None` removes, the crypttab entries `is_same` matches.
+pub fn update_crypttab(
+    contents: &str,
+    is_same: impl Fn(&CrypttabEntry) -> bool,
+    entry: Option<&CrypttabEntry>,
+) -> String {
+    let line = entry.map(CrypttabEntry::to_line);
+    replace_entries(
+        contents,
+        |fields| CrypttabEntry::from_fields(fields).is_some_and(|existing| is_same(&existing)),
+        line.as_deref(),
+    )
+}
+
+/// The device path a source such as "UUID=…" names. `None` for sources
+/// that are no block devices, and for labels, which need not be unique.
+pub fn source_path(source: &str) -> Option<String> {
+    let unquoted = |value: &str| value.trim_matches('"').to_string();
+    if let Some(uuid) = source.strip_prefix("UUID=") {
+        Some(format!("/dev/disk/by-uuid/{}", unquoted(uuid)))
+    } else if let Some(uuid) = source.strip_prefix("PARTUUID=") {
+        Some(format!("/dev/disk/by-partuuid/{}", unquoted(uuid)))
+    } else if source.starts_with("/dev/") {
+        Some(source.to_string())
+    } else {
+        None
+    }
+}
+
+/// Whether the device an entry names is there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// The canonical device node, such as /dev/dm-0.
+    Device(String),
+    Missing,
+    /// Network shares, tmpfs, swap files and labels.
+    Other,
+}
+
+/// The fstab and crypttab entries of the running system, with the devices
+/// they name.
+#[derive(Debug, Clone, Default)]
+pub struct BootTables {
+    pub fstab: Vec<(FstabEntry, Source)>,
+    pub crypttab: Vec<(CrypttabEntry, Source)>,
+}
+
+impl BootTables {
+    /// Reads the tables of the running system. crypttab is often only
+    /// readable by root; tables that cannot be read are left empty.
+    pub fn load() -> Self {
+        let read = |path| std::fs::read_to_string(path).unwrap_or_default();
+        Self::parse(&read(FSTAB), &read(CRYPTTAB), canonical_path)
+    }
+
+    /// `resolve` gives the canonical device node for a path, or `None` when
+    /// nothing is there.
+    pub fn parse(fstab: &str, crypttab: &str, resolve: impl Fn(&str) -> Option<String>) -> Self {
+        let source = |name: &str| match source_path(name) {
+            Some(path) => resolve(&path).map_or(Source::Missing, Source::Device),
+            None => Source::Other,
+        };
+        BootTables {
+            fstab: parse_fstab(fstab)
+                .into_iter()
+                .map(|entry| {
+                    let source = source(&entry.source);
+                    (entry, source)
+                })
+                .collect(),
+            crypttab: parse_crypttab(crypttab)
+                .into_iter()
+                .map(|entry| {
+                    let source = source(&entry.device);
+                    (entry, source)
+                })
+                .collect(),
+        }
+    }
+
+    /// The fstab entry for the device node `node`.
+    pub fn fstab_entry(&self, node: &str) -> Option<&FstabEntry> {
+        self.fstab
+            .iter()
+            .find(|(_, source)| *source == Source::Device(node.to_string()))
+            .map(|(entry, _)| entry)
+    }
+
+    pub fn crypttab_entry(&self, node: &str) -> Option<&CrypttabEntry> {
+        self.crypttab
+            .iter()
+            .find(|(_, source)| *source == Source::Device(node.to_string()))
+            .map(|(entry, _)| entry)
+    }
+
+    pub fn missing_fstab(&self) -> impl Iterator<Item = &FstabEntry> {
+        self.fstab
+            .iter()
+            .filter(|(_, source)| *source == Source::Missing)
+            .map(|(entry, _)| entry)
+    }
+
+    pub fn missing_crypttab(&self) -> impl Iterator<Item = &CrypttabEntry> {
+        self.crypttab
+            .iter()
+            .filter(|(_, source)| *source == Source::Missing)
+            .map(|(entry, _)| entry)
+    }
+
+    /// One line per entry whose device is gone.
+    pub fn warnings(&self) -> Vec<String> {
+        let fstab = self.missing_fstab().map(|entry| {
+            format!(
+                "{}: {} points at {}, which no longer exists",
+                FSTAB, entry.target, entry.source
+            )
+        });
+        let crypttab = self.missing_crypttab().map(|entry| {
+            format!(
+                "{}: {} points at {}, which no longer exists",
+                CRYPTTAB, entry.name, entry.device
+            )
+        });
+        fstab.chain(crypttab).collect()
+    }
+
+    /// The stale entry that was meant for a partition: an fstab entry for
+    /// its mount point or a crypttab entry for its mapper name, whose
+    /// device no longer exists, as happens after reformatting.
+    pub fn stale_entry_for(
+        &self,
+        mount_point: Option<&str>,
+        mapper: Option<&str>,
+    ) -> Option<String> {
+        let fstab = self
+            .missing_fstab()
+            .find(|entry| Some(entry.target.as_str()) == mount_point)
+            .map(|entry| format!("{} in {}", entry.source, FSTAB));
+        let crypttab = || {
+            self.missing_crypttab()
+                .find(|entry| Some(entry.name.as_str()) == mapper)
+                .map(|entry| format!("{} in {}", entry.device, CRYPTTAB))
+        };
+        fstab.or_else(crypttab)
+    }
+}
+
+/// The device node behind a path such as /dev/mapper/name, or `None` when
+/// it does not exist.
+pub fn canonical_path(path: &str) -> Option<String> {
+    std::fs::canonicalize(path)
+        .ok()
+        .map(|path| path.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FSTAB_CONTENTS: &str = "\
+# /etc/fstab: static file system information.
+UUID=1111\t/\text4\terrors=remount-ro\t0\t1
+UUID=\"2222\" /mnt/my\\040data ext4 defaults 0 2
+tmpfs /tmp tmpfs defaults
+";
+
+    #[test]
+    fn entries_are_replaced_in_place_and_comments_kept() {
+        let entry = FstabEntry {
+            source: "UUID=3333".into(),
+            target: "/mnt/my data".into(),
+            fs_type: "xfs".into(),
+            options: "defaults,nofail".into(),
+            dump: 0,
+            pass: 2,
+        };
+        let updated = update_fstab(
+            FSTAB_CONTENTS,
+            |existing| existing.target == "/mnt/my data",
+            Some(&entry),
+        );
+        let lines: Vec<&str> = updated.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("# /etc/fstab"));
+        assert_eq!(
+            lines[2],
+            "UUID=3333\t/mnt/my\\040data\txfs\tdefaults,nofail\t0\t2"
+        );
+        assert_eq!(parse_fstab(&updated)[1], entry);
+
+        let removed = update_fstab(&updated, |existing| existing.fs_type == "tmpfs", None);
+        assert_eq!(parse_fstab(&removed).len(), 2);
+        let appended = update_crypttab(
+            "",
+            |_| false,
+            Some(&CrypttabEntry {
+                name: "luks-sdb1".into(),
+                device: "UUID=4444".into(),
+                key_file: "none".into(),
+                options: "luks".into(),
+            }),
+        );
+        assert_eq!(appended, "luks-sdb1\tUUID=4444\tnone\tluks\n");
+    }
+
+    #[test]
+    fn entries_for_missing_devices_are_flagged() {
+        let tables = BootTables::parse(FSTAB_CONTENTS, "luks-sdb1 UUID=4444 none luks\n", |path| {
+            (path == "/dev/disk/by-uuid/1111").then(|| "/dev/sda2".to_string())
+        });
+        assert_eq!(tables.fstab_entry("/dev/sda2").unwrap().target, "/");
+        assert_eq!(tables.fstab[2].1, Source::Other);
+        assert_eq!(tables.warnings().len(), 2);
+        assert_eq!(
+            tables.stale_entry_for(Some("/mnt/my data"), None).unwrap(),
+            "UUID=\"2222\" in /etc/fstab"
+        );
+        assert_eq!(
+            tables.stale_entry_for(None, Some("luks-sdb1")).unwrap(),
+            "UUID=4444 in /etc/crypttab"
+        );
+        assert!(tables.stale_entry_for(Some("/"), None).is_none());
+    }
+}
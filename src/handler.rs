@@ -1,15 +1,25 @@
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use std::sync::Arc;
 use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc::UnboundedSender;
 use tui_input::backend::crossterm::EventHandler;
 
-use crate::app::{App, AppResult, FocusedBlock, PartitionDialogMode};
+use crate::app::{
+    App, AppResult, AuditLogState, CommandPreview, FocusedBlock, LvmViewState,
+    PartitionDialogMode, RaidViewState, RestoreDialogState, SubvolumeViewState,
+};
 use crate::config::Config;
 use crate::event::Event;
+use crate::layout::{device_gaps, is_whole_disk, layout_rows};
 use crate::notification::{Notification, NotificationLevel};
-use crate::operations::HelperConnection;
-use crate::protocol::Request;
+use crate::operations::{FilesystemType, HelperConnection, get_smart_data, get_smart_data_if_awake};
+use crate::parttype;
+use crate::protocol::{Request, Response};
+use crate::queue::OperationQueue;
+use crate::raid;
+use crate::runner::SystemRunner;
+use crate::smart::{SelfTestKind, SmartData};
 
 type SharedHelper = Arc<HelperConnection>;
 
@@ -70,6 +80,352 @@ fn spawn_helper_operation(
 	true
 }
 
+/// Queues the request when queue mode is on and the request can be queued,
+/// otherwise runs it right away.
+fn submit_request(app: &mut App, sender: &UnboundedSender<Event>, request: Request) {
+	if app.queue.enabled && OperationQueue::is_queueable(&request) {
+		let _ = Notification::send(
+			format!("Queued: {}", request.description()),
+			NotificationLevel::Info,
+			sender,
+		);
+		app.queue.push(request);
+	} else {
+		spawn_helper_operation(app, sender, request);
+	}
+}
+
+/// Sends every queued request to the helper in order, stopping at the first
+/// failure. Operations that did not complete stay in the queue.
+fn spawn_queue_apply(app: &mut App, sender: &UnboundedSender<Event>) -> bool {
+	if app.queue.is_empty() || check_operation_in_progress(app, sender) {
+		return false;
+	}
+	let Some(helper) = ensure_helper(app, sender) else {
+		return false;
+	};
+	app.operation_in_progress.store(true, Ordering::Release);
+	let requests = app.queue.operations.clone();
+	let sender_clone = sender.clone();
+	let operation_flag = app.operation_in_progress.clone();
+	tokio::task::spawn_blocking(move || {
+		let total = requests.len();
+		let mut completed = 0;
+		for request in requests {
+			let description = request.description();
+			if helper.request(request, &sender_clone).is_err() {
+				let _ = Notification::send(
+					format!(
+						"Stopped at step {} of {} ({}). Remaining operations are still queued.",
+						completed + 1,
+						total,
+						description
+					),
+					NotificationLevel::Error,
+					&sender_clone,
+				);
+				break;
+			}
+			completed += 1;
+		}
+		if completed == total {
+			let _ = Notification::send(
+				format!("Applied {} pending operations", total),
+				NotificationLevel::Info,
+				&sender_clone,
+			);
+		}
+		let _ = sender_clone.send(Event::QueueFinished(completed));
+		let _ = sender_clone.send(Event::Refresh);
+		operation_flag.store(false, Ordering::Release);
+	});
+	true
+}
+
+/// Loads the audit log entries for `disk` from the helper. Reading the log
+/// needs root, so this starts the helper if it is not running yet.
+fn spawn_audit_log_fetch(app: &mut App, sender: &UnboundedSender<Event>, disk: String) {
+	let Some(helper) = ensure_helper(app, sender) else {
+		return;
+	};
+	app.audit_log = AuditLogState {
+		show_dialog: true,
+		disk: disk.clone(),
+		loading: true,
+		..Default::default()
+	};
+
+	let sender = sender.clone();
+	tokio::task::spawn_blocking(move || {
+		let request = Request::AuditLog {
+			disk: Some(disk),
+			limit: 200,
+		};
+		let result = helper
+			.request_with(request, |_| Ok(()))
+			.and_then(|data| Ok(serde_json::from_str(&data.unwrap_or_default())?))
+			.map_err(|e| e.to_string());
+		let _ = sender.send(Event::AuditLog(result));
+	});
+}
+
+/// Loads the saved partition tables of `disk` and its current table from the
+/// helper, which keeps the backups in a root-only directory.
+fn spawn_backups_fetch(app: &mut App, sender: &UnboundedSender<Event>, disk: String) {
+	let Some(helper) = ensure_helper(app, sender) else {
+		return;
+	};
+	app.restore_dialog = RestoreDialogState {
+		show_dialog: true,
+		disk: disk.clone(),
+		loading: true,
+		..Default::default()
+	};
+
+	let sender = sender.clone();
+	tokio::task::spawn_blocking(move || {
+		let result = helper
+			.request_with(Request::ListBackups { disk }, |_| Ok(()))
+			.and_then(|data| Ok(serde_json::from_str(&data.unwrap_or_default())?))
+			.map_err(|e| e.to_string());
+		let _ = sender.send(Event::Backups(result));
+	});
+}
+
+/// Loads the LVM report from the helper, since the LVM tools need root.
+fn spawn_lvm_fetch(app: &mut App, sender: &UnboundedSender<Event>) {
+	let Some(helper) = ensure_helper(app, sender) else {
+		return;
+	};
+	app.lvm.loading = true;
+	app.lvm.error = None;
+
+	let sender = sender.clone();
+	tokio::task::spawn_blocking(move || {
+		let result = helper
+			.request_with(Request::ListLvm, |_| Ok(()))
+			.and_then(|data| Ok(serde_json::from_str(&data.unwrap_or_default())?))
+			.map_err(|e| e.to_string());
+		let _ = sender.send(Event::Lvm(result));
+	});
+}
+
+/// Rereads the LVM report after a refresh while the LVM view is open.
+pub fn reload_lvm(app: &mut App, sender: &UnboundedSender<Event>) {
+	if app.lvm.show_dialog && !app.lvm.loading {
+		spawn_lvm_fetch(app, sender);
+	}
+}
+
+/// Loads the md arrays from the helper, since `mdadm --detail` needs root.
+fn spawn_raid_fetch(app: &mut App, sender: &UnboundedSender<Event>) {
+	let Some(helper) = ensure_helper(app, sender) else {
+		return;
+	};
+	app.raid.loading = true;
+	app.raid.error = None;
+
+	let sender = sender.clone();
+	tokio::task::spawn_blocking(move || {
+		let result = helper
+			.request_with(Request::ListRaid, |_| Ok(()))
+			.and_then(|data| Ok(serde_json::from_str(&data.unwrap_or_default())?))
+			.map_err(|e| e.to_string());
+		let _ = sender.send(Event::Raid(result));
+	});
+}
+
+/// Rereads the arrays after a refresh while the RAID view is open or an
+/// array is about to be followed.
+pub fn reload_raid(app: &mut App, sender: &UnboundedSender<Event>) {
+	if (app.raid.show_dialog || app.raid.follow.is_some()) && !app.raid.loading {
+		spawn_raid_fetch(app, sender);
+	}
+}
+
+/// Loads the subvolumes of the filesystem shown in the subvolume view from
+/// the helper, since `btrfs subvolume list` needs root.
+fn spawn_subvolume_fetch(app: &mut App, sender: &UnboundedSender<Event>) {
+	let Some(helper) = ensure_helper(app, sender) else {
+		return;
+	};
+	app.subvolumes.loading = true;
+	app.subvolumes.error = None;
+
+	let request = Request::ListSubvolumes { device: app.subvolumes.device.clone() };
+	let sender = sender.clone();
+	tokio::task::spawn_blocking(move || {
+		let result = helper
+			.request_with(request, |_| Ok(()))
+			.and_then(|data| Ok(serde_json::from_str(&data.unwrap_or_default())?))
+			.map_err(|e| e.to_string());
+		let _ = sender.send(Event::Subvolumes(result));
+	});
+}
+
+/// Rereads the subvolumes after a refresh while the subvolume view is open.
+pub fn reload_subvolumes(app: &mut App, sender: &UnboundedSender<Event>) {
+	if app.subvolumes.show_dialog && !app.subvolumes.loading {
+		spawn_subvolume_fetch(app, sender);
+	}
+}
+
+/// Follows running syncs in /proc/mdstat, which needs no root, once a
+/// second while the RAID view or a resync in the progress dialog is shown.
+pub fn poll_raid(app: &mut App, sender: &UnboundedSender<Event>) {
+	if !app.raid.show_dialog && app.progress.raid.is_none() {
+		return;
+	}
+	if app.raid.polled.is_some_and(|polled| polled.elapsed() < Duration::from_secs(1)) {
+		return;
+	}
+	app.raid.polled = Some(Instant::now());
+	let arrays = match std::fs::read_to_string(raid::MDSTAT) {
+		Ok(text) => raid::parse_mdstat(&text),
+		Err(_) => Vec::new(),
+	};
+	if app.update_raid_progress(&arrays) && app.raid.show_dialog {
+		reload_raid(app, sender);
+	}
+}
+
+/// Runs a filesystem check, feeding the checker's output into the check log
+/// as it arrives. Progress responses are skipped since the log shows the
+/// check running.
+fn spawn_filesystem_check(app: &mut App, sender: &UnboundedSender<Event>, device: String, repair: bool) {
+	if check_operation_in_progress(app, sender) {
+		return;
+	}
+	let Some(helper) = ensure_helper(app, sender) else {
+		return;
+	};
+	app.check_log.started = true;
+	app.check_log.repair = repair;
+	app.check_log.running = true;
+	app.check_log.show_dialog = true;
+	app.operation_in_progress.store(true, Ordering::Release);
+
+	let sender = sender.clone();
+	let operation_flag = app.operation_in_progress.clone();
+	tokio::task::spawn_blocking(move || {
+		let result = helper
+			.request_with(Request::CheckFilesystem { device, repair }, |response| {
+				match response {
+					Response::Output { line } => sender.send(Event::CheckOutput(line))?,
+					Response::Notification { level, message } => {
+						let level = match level.as_str() {
+							"error" => NotificationLevel::Error,
+							"warning" => NotificationLevel::Warning,
+							_ => NotificationLevel::Info,
+						};
+						Notification::send(message, level, &sender)?;
+					}
+					_ => {}
+				}
+				Ok(())
+			})
+			.and_then(|data| Ok(serde_json::from_str(&data.unwrap_or_default())?))
+			.map_err(|e| e.to_string());
+		let _ = sender.send(Event::CheckFinished(result));
+		let _ = sender.send(Event::Refresh);
+		operation_flag.store(false, Ordering::Release);
+	});
+}
+
+/// How often SMART data is read again while a self-test is running.
+const SELF_TEST_POLL: Duration = Duration::from_secs(30);
+
+/// Reads the SMART data of `disk` in the background, and keeps reading it
+/// while a self-test is running so the info view can show its progress.
+async fn watch_smart_data(sender: UnboundedSender<Event>, disk: String) {
+	loop {
+		let smart_data = get_smart_data(&SystemRunner, &disk).await.ok();
+		let running = smart_data.as_ref().is_some_and(SmartData::test_in_progress);
+		if sender.send(Event::SmartData(disk.clone(), smart_data.map(Box::new))).is_err() || !running {
+			break;
+		}
+		tokio::time::sleep(SELF_TEST_POLL).await;
+	}
+}
+
+fn spawn_smart_probe(app: &mut App, sender: &UnboundedSender<Event>, disk: String) {
+	if app.smart_probes.insert(disk.clone()) {
+		tokio::spawn(watch_smart_data(sender.clone(), disk));
+	}
+}
+
+/// Reads the SMART data of every disk that has any once the configured
+/// interval has passed. Disks in standby are left asleep.
+pub fn poll_smart_data(app: &mut App, sender: &UnboundedSender<Event>) {
+	if app.smart_interval.is_zero() || app.smart_polled.elapsed() < app.smart_interval {
+		return;
+	}
+	app.smart_polled = Instant::now();
+	for disk in &app.disks {
+		let name = disk.device.name.clone();
+		if disk.smart_data.is_none() || app.smart_probes.contains(&name) {
+			continue;
+		}
+		let sender = sender.clone();
+		tokio::spawn(async move {
+			if let Ok(Some(smart_data)) = get_smart_data_if_awake(&SystemRunner, &name).await {
+				let _ = sender.send(Event::SmartData(name, Some(Box::new(smart_data))));
+			}
+		});
+	}
+}
+
+fn spawn_self_test(app: &mut App, sender: &UnboundedSender<Event>, disk: String, test: SelfTestKind) {
+	if check_operation_in_progress(app, sender) {
+		return;
+	}
+	let Some(helper) = ensure_helper(app, sender) else {
+		return;
+	};
+	app.operation_in_progress.store(true, Ordering::Release);
+	// A probe that is already running picks the test up on its next read.
+	let watch = app.smart_probes.insert(disk.clone());
+	let sender = sender.clone();
+	let operation_flag = app.operation_in_progress.clone();
+	let runtime = tokio::runtime::Handle::current();
+	tokio::task::spawn_blocking(move || {
+		let _ = helper.request(Request::StartSelfTest { disk: disk.clone(), test }, &sender);
+		operation_flag.store(false, Ordering::Release);
+		if watch {
+			runtime.spawn(watch_smart_data(sender, disk));
+		}
+	});
+}
+
+/// Asks the helper for the commands the operation in the confirmation dialog
+/// would run. Only done when the helper is already running, so opening a
+/// dialog never triggers an authentication prompt on its own.
+pub fn preview_commands(app: &mut App, sender: &UnboundedSender<Event>) {
+	let dialog = &mut app.confirmation_dialog;
+	if !dialog.show_dialog || dialog.commands != CommandPreview::None {
+		return;
+	}
+	let (Some(helper), Some(request)) = (app.helper.clone(), dialog.operation.request("")) else {
+		return;
+	};
+	dialog.commands = CommandPreview::Loading;
+
+	let sender = sender.clone();
+	tokio::task::spawn_blocking(move || {
+		let description = request.description();
+		let request = Request::DryRun {
+			request: Box::new(request),
+		};
+		let preview = match helper.request_with(request, |_| Ok(())) {
+			Ok(data) => CommandPreview::Ready(
+				data.unwrap_or_default().lines().map(|line| line.to_string()).collect(),
+			),
+			Err(e) => CommandPreview::Failed(e.to_string()),
+		};
+		let _ = sender.send(Event::CommandPreview(description, preview));
+	});
+}
+
 pub async fn handle_key_events(
     key_event: KeyEvent,
     app: &mut App,
@@ -81,6 +437,15 @@ pub async fn handle_key_events(
         return Ok(());
     }
 
+    // A resync can take hours, so its progress can be put away; other
+    // progress only shows while an operation blocks everything else.
+    if app.progress.raid.is_some() {
+        if matches!(key_event.code, KeyCode::Esc | KeyCode::Char('q')) {
+            app.hide_raid_progress();
+        }
+        return Ok(());
+    }
+
     if app.passphrase_dialog.show_dialog {
         return handle_passphrase_dialog(key_event, app, sender).await;
     }
@@ -101,6 +466,71 @@ pub async fn handle_key_events(
         return handle_resize_dialog(key_event, app, sender).await;
     }
 
+    if app.part_type_dialog.show_dialog {
+        handle_part_type_dialog(key_event, app, &sender);
+        return Ok(());
+    }
+
+    if app.mount_dialog.show_dialog {
+        handle_mount_dialog(key_event, app, &sender);
+        return Ok(());
+    }
+
+    if app.label_dialog.show_dialog {
+        handle_label_dialog(key_event, app, &sender);
+        return Ok(());
+    }
+
+    if app.boot_table_dialog.show_dialog {
+        handle_boot_table_dialog(key_event, app, &sender);
+        return Ok(());
+    }
+
+    if app.swap_file_dialog.show_dialog {
+        handle_swap_file_dialog(key_event, app, &sender);
+        return Ok(());
+    }
+
+    if app.check_log.show_dialog {
+        handle_check_log(key_event, app, &sender, &config);
+        return Ok(());
+    }
+
+    if app.audit_log.show_dialog {
+        handle_audit_log_dialog(key_event, app, &config);
+        return Ok(());
+    }
+
+    if app.restore_dialog.show_dialog {
+        handle_restore_dialog(key_event, app, &sender, &config);
+        return Ok(());
+    }
+
+    if app.lvm.show_dialog {
+        handle_lvm_view(key_event, app, &sender, &config);
+        return Ok(());
+    }
+
+    if app.raid.show_dialog {
+        handle_raid_view(key_event, app, &sender, &config);
+        return Ok(());
+    }
+
+    if app.subvolumes.show_dialog {
+        handle_subvolume_view(key_event, app, &sender, &config);
+        return Ok(());
+    }
+
+    if app.focused_block == FocusedBlock::Queue && handle_queue_keys(key_event, app, &config) {
+        return Ok(());
+    }
+
+    if app.focused_block == FocusedBlock::DiskInfo
+        && handle_disk_info_keys(key_event, app, &sender, &config)
+    {
+        return Ok(());
+    }
+
     match key_event.code {
         KeyCode::Char('q') | KeyCode::Char('Q') => {
             if app.focused_block == FocusedBlock::DiskInfo {
@@ -112,10 +542,8 @@ pub async fn handle_key_events(
         KeyCode::Char('c') | KeyCode::Char('C') if key_event.modifiers == KeyModifiers::CONTROL => {
             app.quit();
         }
-        KeyCode::Esc => {
-            if app.focused_block == FocusedBlock::DiskInfo {
-                app.focused_block = FocusedBlock::Disks;
-            }
+        KeyCode::Esc if app.focused_block == FocusedBlock::DiskInfo => {
+            app.focused_block = FocusedBlock::Disks;
         }
         KeyCode::Char('?') => {
             app.show_help = true;
@@ -125,17 +553,137 @@ pub async fn handle_key_events(
                 || app.focused_block == FocusedBlock::Partitions
             {
                 app.focused_block = FocusedBlock::DiskInfo;
+                app.disk_info_scroll = 0;
+                // Follow a self-test that is already running.
+                if let Some(disk) = app.selected_disk()
+                    && disk.smart_data.as_ref().is_some_and(SmartData::test_in_progress)
+                {
+                    let name = disk.device.name.clone();
+                    spawn_smart_probe(app, &sender, name);
+                }
             } else if app.focused_block == FocusedBlock::DiskInfo {
                 app.focused_block = FocusedBlock::Disks;
             }
         }
-        KeyCode::Tab | KeyCode::BackTab => {
+        KeyCode::Tab => {
             app.focused_block = match app.focused_block {
                 FocusedBlock::Disks => FocusedBlock::Partitions,
-                FocusedBlock::Partitions => FocusedBlock::Disks,
+                FocusedBlock::Partitions if app.queue.enabled => FocusedBlock::Queue,
                 _ => FocusedBlock::Disks,
             };
         }
+        KeyCode::BackTab => {
+            app.focused_block = match app.focused_block {
+                FocusedBlock::Disks if app.queue.enabled => FocusedBlock::Queue,
+                FocusedBlock::Queue => FocusedBlock::Partitions,
+                FocusedBlock::Partitions => FocusedBlock::Disks,
+                _ => FocusedBlock::Partitions,
+            };
+        }
+        KeyCode::Char(c)
+            if c == config.disk.history
+                && matches!(app.focused_block, FocusedBlock::Disks | FocusedBlock::Partitions) =>
+        {
+            if let Some(disk) = app.selected_disk() {
+                let disk = disk.device.name.clone();
+                spawn_audit_log_fetch(app, &sender, disk);
+            }
+        }
+        KeyCode::Char(c) if c == config.disk.restore && app.focused_block == FocusedBlock::Disks => {
+            if check_operation_in_progress(app, &sender) {
+                return Ok(());
+            }
+            if let Some(disk) = app.selected_disk() {
+                let disk = disk.device.name.clone();
+                spawn_backups_fetch(app, &sender, disk);
+            }
+        }
+        KeyCode::Char(c)
+            if c == config.disk.lvm
+                && matches!(app.focused_block, FocusedBlock::Disks | FocusedBlock::Partitions) =>
+        {
+            // The selected partition, or a disk without partitions, is
+            // offered as a new physical volume.
+            let candidate = match app.focused_block {
+                FocusedBlock::Partitions => app.selected_partition().map(|p| p.name.clone()),
+                _ => app
+                    .selected_disk()
+                    .filter(|disk| disk.device.partitions.is_empty())
+                    .map(|disk| disk.device.name.clone()),
+            };
+            if candidate.is_some()
+                && let Some(blocker) = holder_blocker(app) {
+                    let _ = Notification::send(blocker, NotificationLevel::Error, &sender);
+                    return Ok(());
+                }
+            app.lvm = LvmViewState {
+                show_dialog: true,
+                candidate,
+                ..Default::default()
+            };
+            spawn_lvm_fetch(app, &sender);
+        }
+        KeyCode::Char(c)
+            if c == config.disk.raid
+                && matches!(app.focused_block, FocusedBlock::Disks | FocusedBlock::Partitions) =>
+        {
+            app.raid = RaidViewState {
+                show_dialog: true,
+                ..Default::default()
+            };
+            spawn_raid_fetch(app, &sender);
+        }
+        KeyCode::Char(c)
+            if c == config.disk.subvolumes && app.focused_block == FocusedBlock::Partitions =>
+        {
+            let Some(partition) = app.selected_partition() else {
+                return Ok(());
+            };
+            // The filesystem inside an unlocked LUKS device is not known
+            // here; the helper checks it.
+            let warning = match partition.filesystem.as_deref() {
+                _ if !partition.is_mounted => {
+                    Some(format!("Mount {} to manage its subvolumes", partition.name))
+                }
+                Some("btrfs") => None,
+                _ if partition.mapper_device.is_some() => None,
+                _ => Some(format!("{} is not a btrfs filesystem", partition.name)),
+            };
+            if let Some(warning) = warning {
+                let _ = Notification::send(warning, NotificationLevel::Warning, &sender);
+                return Ok(());
+            }
+            app.subvolumes = SubvolumeViewState {
+                show_dialog: true,
+                device: partition
+                    .mapper_device
+                    .clone()
+                    .unwrap_or(partition.name.clone()),
+                ..Default::default()
+            };
+            spawn_subvolume_fetch(app, &sender);
+        }
+        KeyCode::Char(c) if c == config.disk.queue => {
+            if !app.queue.enabled {
+                app.queue.enabled = true;
+                let _ = Notification::send(
+                    "Queue mode on: changes are collected until applied".to_string(),
+                    NotificationLevel::Info,
+                    &sender,
+                );
+            } else if !app.queue.is_empty() {
+                let _ = Notification::send(
+                    "Apply or clear the pending operations before leaving queue mode".to_string(),
+                    NotificationLevel::Warning,
+                    &sender,
+                );
+            } else {
+                app.queue.enabled = false;
+                if app.focused_block == FocusedBlock::Queue {
+                    app.focused_block = FocusedBlock::Disks;
+                }
+            }
+        }
         KeyCode::Char(c) if c == config.navigation.scroll_down => {
             handle_scroll_down(app);
         }
@@ -148,69 +696,105 @@ pub async fn handle_key_events(
         KeyCode::Up => {
             handle_scroll_up(app);
         }
-        KeyCode::Char(c) if c == config.disk.format => {
-            if (app.focused_block == FocusedBlock::Partitions && app.selected_partition().is_some())
-                || (app.focused_block == FocusedBlock::Disks && app.selected_disk().is_some())
-            {
-                app.format_dialog.show_dialog = true;
-                app.format_dialog.type_state.select(Some(0));
+        KeyCode::Char(c)
+            if c == config.disk.format
+                && ((app.focused_block == FocusedBlock::Partitions
+                    && app.selected_partition().is_some())
+                    || (app.focused_block == FocusedBlock::Disks && app.selected_disk().is_some())) =>
+        {
+            if let Some(blocker) = holder_blocker(app) {
+                let _ = Notification::send(blocker, NotificationLevel::Error, &sender);
+                return Ok(());
             }
+            app.format_dialog = crate::app::FormatDialogState::open(false);
         }
         KeyCode::Char('n') | KeyCode::Char('N') => {
-            if app.focused_block == FocusedBlock::Disks
-                && let Some(disk) = app.selected_disk() {
-                    if disk.device.partitions.len() == 1
-                        && disk.device.partitions[0].name == disk.device.name
-                    {
-                        use crate::notification::{Notification, NotificationLevel};
-                        let _ = Notification::send(
-                            format!(
-                                "No partition table on {}. Press 'p' to create one first.",
-                                disk.device.name
-                            ),
-                            NotificationLevel::Error,
-                            &sender,
-                        );
-                    } else {
-                        let used_space: u64 = disk.device.partitions.iter().map(|p| p.size).sum();
-                        let free_space = disk.device.size.saturating_sub(used_space);
-                        if free_space > 0 {
-                            app.partition_dialog.show_dialog = true;
-                            app.partition_dialog.mode = PartitionDialogMode::CreatePartition;
-                            app.partition_dialog.create_step =
-                                crate::app::CreatePartitionStep::EnterSize;
-                            app.partition_dialog.size_input = tui_input::Input::default();
-                            app.partition_dialog.new_partition_fs_state.select(Some(0));
-                        }
-                    }
+            // On a free space row the partition goes there; from the disk
+            // list it goes into the largest free space.
+            let target = match app.focused_block {
+                FocusedBlock::Partitions => app
+                    .selected_free_space()
+                    .and_then(|gap| app.selected_disk().map(|disk| (disk, Some(gap)))),
+                FocusedBlock::Disks => app.selected_disk().map(|disk| {
+                    let gap = device_gaps(&disk.device).into_iter().max_by_key(|gap| gap.size());
+                    (disk, gap)
+                }),
+                _ => None,
+            };
+            if let Some((disk, gap)) = target {
+                if disk.device.partition_table.is_none() || is_whole_disk(&disk.device) {
+                    let _ = Notification::send(
+                        format!(
+                            "No partition table on {}. Press 'p' to create one first.",
+                            disk.device.name
+                        ),
+                        NotificationLevel::Error,
+                        &sender,
+                    );
+                } else if gap.is_none() {
+                    let _ = Notification::send(
+                        format!("No free space on {}", disk.device.name),
+                        NotificationLevel::Error,
+                        &sender,
+                    );
+                } else {
+                    app.partition_dialog.show_dialog = true;
+                    app.partition_dialog.mode = PartitionDialogMode::CreatePartition;
+                    app.partition_dialog.create_step =
+                        crate::app::CreatePartitionStep::EnterSize;
+                    app.partition_dialog.size_input = tui_input::Input::default();
+                    app.partition_dialog.new_partition_fs_state.select(Some(0));
+                    app.partition_dialog.gap = gap;
                 }
+            }
         }
-        KeyCode::Char(c) if c == config.disk.partition => {
-            if app.focused_block == FocusedBlock::Disks && app.selected_disk().is_some() {
-                app.partition_dialog.show_dialog = true;
-                app.partition_dialog.mode = PartitionDialogMode::SelectTableType;
+        KeyCode::Char(c)
+            if c == config.disk.partition
+                && app.focused_block == FocusedBlock::Disks
+                && app.selected_disk().is_some() =>
+        {
+            if let Some(blocker) = holder_blocker(app) {
+                let _ = Notification::send(blocker, NotificationLevel::Error, &sender);
+                return Ok(());
             }
+            app.partition_dialog.show_dialog = true;
+            app.partition_dialog.mode = PartitionDialogMode::SelectTableType;
         }
         KeyCode::Char(c) if c == config.disk.mount => {
             if app.focused_block == FocusedBlock::Partitions
                 && let Some(partition) = app.selected_partition() {
                     let device_name = partition.mapper_device.clone().unwrap_or(partition.name.clone());
-                    let request = if partition.is_mounted {
+                    let request = if partition.filesystem.as_deref() == Some("swap") {
+                        if partition.swap.is_some() {
+                            Request::SwapOff { target: device_name }
+                        } else {
+                            Request::SwapOn { target: device_name }
+                        }
+                    } else if partition.is_mounted {
                         Request::Unmount { device: device_name }
+                    } else if let Some(blocker) = partition.holder.as_ref().and_then(|h| h.mount_blocker(&partition.name)) {
+                        let _ = Notification::send(blocker, NotificationLevel::Warning, &sender);
+                        return Ok(());
                     } else {
-                        Request::Mount { device: device_name }
+                        let remembered = partition.uuid.as_ref().and_then(|uuid| app.mount_defaults.get(uuid));
+                        app.mount_dialog = crate::app::MountDialogState::open(partition, remembered);
+                        return Ok(());
                     };
                     spawn_helper_operation(app, &sender, request);
                 }
         }
         KeyCode::Char(c) if c == config.disk.delete => {
             use crate::app::ConfirmationOperation;
-            use crate::utils::format_bytes;
+            use crate::utils::format_size;
 
             if app.focused_block == FocusedBlock::Partitions
                 && let Some(partition) = app.selected_partition() {
+                    if let Some(blocker) = holder_blocker(app) {
+                        let _ = Notification::send(blocker, NotificationLevel::Error, &sender);
+                        return Ok(());
+                    }
                     let part_name = partition.name.clone();
-                    let part_size = format_bytes(partition.size);
+                    let part_size = format_size(partition.size, app.size_units);
                     let filesystem = partition
                         .filesystem
                         .clone()
@@ -228,13 +812,17 @@ pub async fn handle_key_events(
                         show_dialog: true,
                         title: "Confirm Delete Partition".to_string(),
                         message: "Are you sure you want to delete this partition?".to_string(),
-                        details: vec![
+                        details: [
                             ("Partition".to_string(), part_name.clone()),
                             ("Size".to_string(), part_size),
                             ("Filesystem".to_string(), filesystem),
                             ("Mounted".to_string(), mount_status),
-                        ],
+                        ]
+                        .into_iter()
+                        .chain(holder_warning(partition))
+                        .collect(),
                         selected: 0,
+                        commands: CommandPreview::None,
                         operation: ConfirmationOperation::DeletePartition {
                             partition: part_name,
                         },
@@ -250,6 +838,14 @@ pub async fn handle_key_events(
                             NotificationLevel::Warning,
                             &sender,
                         );
+                    } else if partition.swap.is_some() {
+                        let _ = Notification::send(
+                            format!("{} is in use as swap. Disable it first (press 'm')", partition.name),
+                            NotificationLevel::Warning,
+                            &sender,
+                        );
+                    } else if let Some(blocker) = holder_blocker(app) {
+                        let _ = Notification::send(blocker, NotificationLevel::Error, &sender);
                     } else if partition.is_encrypted {
                         let _ = Notification::send(
                             format!("{} is encrypted. Resizing encrypted partitions is not supported due to data corruption risks.", partition.name),
@@ -262,48 +858,181 @@ pub async fn handle_key_events(
                     }
                 }
         }
-        KeyCode::Char(c) if c == config.disk.lock => {
+        KeyCode::Char(c) if c == config.disk.part_type => {
             if app.focused_block == FocusedBlock::Partitions
+                && let Some(disk) = app.selected_disk()
                 && let Some(partition) = app.selected_partition()
-                    && partition.is_encrypted {
-                        if let Some(mapper_name) = &partition.mapper_device {
-                            use crate::app::ConfirmationOperation;
-                            let part_name = partition.name.clone();
-                            let mapper = mapper_name.clone();
-
-                            app.confirmation_dialog = crate::app::ConfirmationDialog {
-                                show_dialog: true,
-                                title: "Confirm Lock".to_string(),
-                                message: format!("Lock encrypted device {}?", part_name),
-                                details: vec![
-                                    ("Device".to_string(), part_name),
-                                    ("Mapper".to_string(), mapper.clone()),
-                                ],
-                                selected: 0,
-                                operation: ConfirmationOperation::LockLuksDevice {
-                                    mapper_name: mapper,
-                                },
-                            };
-                        } else {
-                            use crate::app::PassphraseOperation;
-                            let part_name = partition.name.clone();
-
-                            app.passphrase_dialog.show_dialog = true;
-                            app.passphrase_dialog.operation = PassphraseOperation::Unlock;
-                            app.passphrase_dialog.target_device = part_name;
-                            app.passphrase_dialog.input = tui_input::Input::default();
-                            app.passphrase_dialog.confirm_mode = false;
-                            app.passphrase_dialog.first_passphrase.clear();
-                        }
-                    }
+            {
+                if disk.device.partition_table.is_none() || is_whole_disk(&disk.device) {
+                    let _ = Notification::send(
+                        format!("{} is not in a partition table", partition.name),
+                        NotificationLevel::Error,
+                        &sender,
+                    );
+                } else {
+                    app.part_type_dialog = crate::app::PartitionTypeDialogState::open(
+                        partition,
+                        disk.device.partition_table.as_deref(),
+                    );
+                }
+            }
+        }
+        KeyCode::Char(c) if c == config.disk.check => {
+            if app.focused_block == FocusedBlock::Partitions
+                && let Some(partition) = app.selected_partition() {
+                    let problem = if partition.is_mounted {
+                        Some(format!("{} is mounted. Unmount it first (press 'm')", partition.name))
+                    } else if partition.is_encrypted && partition.mapper_device.is_none() {
+                        Some(format!("{} is encrypted. Unlock it first (press 'l')", partition.name))
+                    } else if partition.swap.is_some() || partition.filesystem.as_deref() == Some("swap") {
+                        Some(format!("{} is swap, there is no filesystem to check", partition.name))
+                    } else if partition.filesystem.is_none() {
+                        Some(format!("No filesystem on {}", partition.name))
+                    } else {
+                        None
+                    };
+                    match problem {
+                        Some(message) => {
+                            let _ = Notification::send(message, NotificationLevel::Warning, &sender);
+                        }
+                        None => {
+                            let device = partition.mapper_device.clone().unwrap_or(partition.name.clone());
+                            let fs_type = partition.filesystem.clone().unwrap_or_default();
+                            app.check_log = crate::app::CheckLogState::open(device, fs_type);
+                        }
+                    }
+                }
+        }
+        KeyCode::Char(c) if c == config.disk.label => {
+            if app.focused_block == FocusedBlock::Partitions
+                && let Some(partition) = app.selected_partition() {
+                    let fs_type = partition.filesystem.clone().unwrap_or_default();
+                    let problem = if partition.is_encrypted && partition.mapper_device.is_none() {
+                        Some(format!("{} is encrypted. Unlock it first (press 'l')", partition.name))
+                    } else if let Err(e) = crate::fslabel::validate_label(&fs_type, "") {
+                        Some(e.to_string())
+                    } else if partition.is_mounted && !crate::fslabel::can_relabel_mounted(&fs_type) {
+                        Some(format!("{} is mounted. Unmount it before changing the {} label", partition.name, fs_type))
+                    } else {
+                        None
+                    };
+                    match problem {
+                        Some(message) => {
+                            let _ = Notification::send(message, NotificationLevel::Warning, &sender);
+                        }
+                        None => app.label_dialog = crate::app::LabelDialogState::open(partition),
+                    }
+                }
+        }
+        KeyCode::Char(c) if c == config.disk.fstab => {
+            if app.focused_block == FocusedBlock::Partitions
+                && let Some(partition) = app.selected_partition() {
+                    let table = crate::app::BootTable::for_partition(partition)[0];
+                    let remembered = partition.uuid.as_ref().and_then(|uuid| app.mount_defaults.get(uuid));
+                    app.boot_table_dialog =
+                        crate::app::BootTableDialogState::open(partition, &app.boot_tables, table, remembered);
+                }
+        }
+        KeyCode::Char(c) if c == config.disk.uuid => {
+            if app.focused_block == FocusedBlock::Partitions
+                && let Some(partition) = app.selected_partition() {
+                    let fs_type = partition.filesystem.clone().unwrap_or_default();
+                    let device = partition.mapper_device.clone().unwrap_or(partition.name.clone());
+                    let problem = if partition.is_mounted {
+                        Some(format!("{} is mounted. Unmount it first (press 'm')", partition.name))
+                    } else if partition.is_encrypted && partition.mapper_device.is_none() {
+                        Some(format!("{} is encrypted. Unlock it first (press 'l')", partition.name))
+                    } else {
+                        crate::fslabel::uuid_command(&fs_type, &device).err().map(|e| e.to_string())
+                    };
+                    match problem {
+                        Some(message) => {
+                            let _ = Notification::send(message, NotificationLevel::Warning, &sender);
+                        }
+                        None => {
+                            app.confirmation_dialog = crate::app::ConfirmationDialog {
+                                show_dialog: true,
+                                title: "Confirm Regenerate UUID".to_string(),
+                                message: "Give this filesystem a new random UUID?".to_string(),
+                                details: vec![
+                                    ("Device".to_string(), device.clone()),
+                                    ("Filesystem".to_string(), fs_type),
+                                    (
+                                        "Label".to_string(),
+                                        partition.label.clone().unwrap_or_else(|| "none".to_string()),
+                                    ),
+                                    (
+                                        "Note".to_string(),
+                                        "fstab and crypttab entries using the old UUID must be updated".to_string(),
+                                    ),
+                                ],
+                                selected: 0,
+                                commands: CommandPreview::None,
+                                operation: crate::app::ConfirmationOperation::RegenerateUuid { device },
+                            };
+                        }
+                    }
+                }
+        }
+        KeyCode::Char(c) if c == config.disk.swap_file => {
+            if app.focused_block == FocusedBlock::Partitions
+                && let Some(partition) = app.selected_partition() {
+                    if partition.is_mounted && partition.mount_point.is_some() {
+                        app.swap_file_dialog = crate::app::SwapFileDialogState::open(partition);
+                    } else {
+                        let _ = Notification::send(
+                            format!("{} is not mounted. Mount it first (press 'm')", partition.name),
+                            NotificationLevel::Warning,
+                            &sender,
+                        );
+                    }
+                }
+        }
+        KeyCode::Char(c) if c == config.disk.lock => {
+            if app.focused_block == FocusedBlock::Partitions
+                && let Some(partition) = app.selected_partition()
+                    && partition.is_encrypted {
+                        if let Some(mapper_name) = &partition.mapper_device {
+                            use crate::app::ConfirmationOperation;
+                            let part_name = partition.name.clone();
+                            let mapper = mapper_name.clone();
+
+                            app.confirmation_dialog = crate::app::ConfirmationDialog {
+                                show_dialog: true,
+                                title: "Confirm Lock".to_string(),
+                                message: format!("Lock encrypted device {}?", part_name),
+                                details: vec![
+                                    ("Device".to_string(), part_name),
+                                    ("Mapper".to_string(), mapper.clone()),
+                                ],
+                                selected: 0,
+                                commands: CommandPreview::None,
+                                operation: ConfirmationOperation::LockLuksDevice {
+                                    mapper_name: mapper,
+                                },
+                            };
+                        } else {
+                            use crate::app::PassphraseOperation;
+                            let part_name = partition.name.clone();
+
+                            app.passphrase_dialog.show_dialog = true;
+                            app.passphrase_dialog.operation = PassphraseOperation::Unlock;
+                            app.passphrase_dialog.target_device = part_name;
+                            app.passphrase_dialog.input = tui_input::Input::default();
+                            app.passphrase_dialog.confirm_mode = false;
+                            app.passphrase_dialog.first_passphrase.clear();
+                        }
+                    }
         }
         KeyCode::Char(c) if c == config.disk.encrypt => {
             if app.focused_block == FocusedBlock::Partitions
                 && let Some(partition) = app.selected_partition()
                     && !partition.is_encrypted {
-                        app.format_dialog.show_dialog = true;
-                        app.format_dialog.type_state.select(Some(0));
-                        app.format_dialog.encrypt_mode = true;
+                        if let Some(blocker) = holder_blocker(app) {
+                            let _ = Notification::send(blocker, NotificationLevel::Error, &sender);
+                            return Ok(());
+                        }
+                        app.format_dialog = crate::app::FormatDialogState::open(true);
                     }
         }
         _ => {}
@@ -315,12 +1044,28 @@ pub async fn handle_key_events(
 async fn handle_format_dialog(
     key_event: KeyEvent,
     app: &mut App,
-    _sender: UnboundedSender<Event>,
+    sender: UnboundedSender<Event>,
 ) -> AppResult<()> {
     match key_event.code {
         KeyCode::Esc => {
             app.format_dialog.show_dialog = false;
         }
+        KeyCode::Tab | KeyCode::BackTab if !app.format_dialog.encrypt_mode => {
+            app.format_dialog.label_focused = !app.format_dialog.label_focused;
+        }
+        KeyCode::Char(_)
+        | KeyCode::Backspace
+        | KeyCode::Delete
+        | KeyCode::Left
+        | KeyCode::Right
+        | KeyCode::Home
+        | KeyCode::End
+            if app.format_dialog.label_focused =>
+        {
+            app.format_dialog
+                .label_input
+                .handle_event(&crossterm::event::Event::Key(key_event));
+        }
         KeyCode::Char('j') | KeyCode::Down => {
             if let Some(i) = app.format_dialog.type_state.selected()
                 && i < app.filesystem_types.len() - 1 {
@@ -335,7 +1080,7 @@ async fn handle_format_dialog(
         }
         KeyCode::Enter => {
             use crate::app::ConfirmationOperation;
-            use crate::utils::format_bytes;
+            use crate::utils::format_size;
 
             if let Some(fs_idx) = app.format_dialog.type_state.selected() {
                 let fs_type = app.filesystem_types[fs_idx].clone();
@@ -359,13 +1104,19 @@ async fn handle_format_dialog(
                     return Ok(());
                 }
 
+                if let Some(error) = app.format_dialog.label_error(&fs_type) {
+                    let _ = Notification::send(error, NotificationLevel::Warning, &sender);
+                    return Ok(());
+                }
+                let label = app.format_dialog.label();
+                let label_detail = ("Label".to_string(), label.clone().unwrap_or_else(|| "none".to_string()));
                 app.format_dialog.show_dialog = false;
 
                 if app.focused_block == FocusedBlock::Partitions {
                     if let Some(partition) = app.selected_partition() {
                         let part_name = partition.name.clone();
                         let device_name = partition.mapper_device.clone().unwrap_or(partition.name.clone());
-                        let part_size = format_bytes(partition.size);
+                        let part_size = format_size(partition.size, app.size_units);
                         let current_fs = partition
                             .filesystem
                             .clone()
@@ -375,23 +1126,29 @@ async fn handle_format_dialog(
                             show_dialog: true,
                             title: "Confirm Format Partition".to_string(),
                             message: "Are you sure you want to format this partition?".to_string(),
-                            details: vec![
+                            details: [
                                 ("Partition".to_string(), part_name.clone()),
                                 ("Size".to_string(), part_size),
                                 ("Current Filesystem".to_string(), current_fs),
                                 ("New Filesystem".to_string(), fs_type.to_string()),
-                            ],
+                                label_detail,
+                            ]
+                            .into_iter()
+                            .chain(holder_warning(partition))
+                            .collect(),
                             selected: 0,
+                            commands: CommandPreview::None,
                             operation: ConfirmationOperation::FormatPartition {
                                 partition: device_name,
                                 fs_type,
+                                label,
                             },
                         };
                     }
                 } else if app.focused_block == FocusedBlock::Disks
                     && let Some(disk) = app.selected_disk() {
                         let disk_name = disk.device.name.clone();
-                        let disk_size = format_bytes(disk.device.size);
+                        let disk_size = format_size(disk.device.size, app.size_units);
                         let disk_model = disk
                             .device
                             .model
@@ -408,11 +1165,14 @@ async fn handle_format_dialog(
                                 ("Size".to_string(), disk_size),
                                 ("Model".to_string(), disk_model),
                                 ("New Filesystem".to_string(), fs_type.to_string()),
+                                label_detail,
                             ],
                             selected: 0,
+                            commands: CommandPreview::None,
                             operation: ConfirmationOperation::FormatDisk {
                                 disk: disk_name,
                                 fs_type,
+                                label,
                             },
                         };
                     }
@@ -438,13 +1198,24 @@ async fn handle_partition_dialog(
             if app.partition_dialog.mode == PartitionDialogMode::SelectTableType {
                 app.partition_dialog.mode = PartitionDialogMode::CreatePartition;
                 app.partition_dialog.create_step = CreatePartitionStep::EnterSize;
+                app.partition_dialog.gap = app.selected_disk().and_then(|disk| {
+                    device_gaps(&disk.device).into_iter().max_by_key(|gap| gap.size())
+                });
             }
         }
-        KeyCode::Backspace => {
-            if app.partition_dialog.mode == PartitionDialogMode::CreatePartition
-                && app.partition_dialog.create_step == CreatePartitionStep::SelectFilesystem
-            {
-                app.partition_dialog.create_step = CreatePartitionStep::EnterSize;
+        KeyCode::Backspace if app.partition_dialog.mode == PartitionDialogMode::CreatePartition => {
+            match app.partition_dialog.create_step {
+                CreatePartitionStep::SelectFilesystem => {
+                    app.partition_dialog.create_step = CreatePartitionStep::SelectType;
+                }
+                CreatePartitionStep::SelectType => {
+                    app.partition_dialog.create_step = CreatePartitionStep::EnterSize;
+                }
+                CreatePartitionStep::EnterSize => {
+                    app.partition_dialog
+                        .size_input
+                        .handle_event(&crossterm::event::Event::Key(key_event));
+                }
             }
         }
         KeyCode::Char('j') | KeyCode::Down => {
@@ -453,6 +1224,10 @@ async fn handle_partition_dialog(
                     && i < app.partition_dialog.table_types.len() - 1 {
                         app.partition_dialog.table_type_state.select(Some(i + 1));
                     }
+            } else if app.partition_dialog.create_step == CreatePartitionStep::SelectType
+                && let Some(i) = app.partition_dialog.part_type_state.selected()
+                    && i + 1 < app.partition_dialog.part_types.len() {
+                        app.partition_dialog.part_type_state.select(Some(i + 1));
             } else if app.partition_dialog.create_step == CreatePartitionStep::SelectFilesystem
                 && let Some(i) = app.partition_dialog.new_partition_fs_state.selected()
                     && i < app.filesystem_types.len() - 1 {
@@ -467,6 +1242,10 @@ async fn handle_partition_dialog(
                     && i > 0 {
                         app.partition_dialog.table_type_state.select(Some(i - 1));
                     }
+            } else if app.partition_dialog.create_step == CreatePartitionStep::SelectType
+                && let Some(i) = app.partition_dialog.part_type_state.selected()
+                    && i > 0 {
+                        app.partition_dialog.part_type_state.select(Some(i - 1));
             } else if app.partition_dialog.create_step == CreatePartitionStep::SelectFilesystem
                 && let Some(i) = app.partition_dialog.new_partition_fs_state.selected()
                     && i > 0 {
@@ -477,13 +1256,13 @@ async fn handle_partition_dialog(
         }
         KeyCode::Enter => {
             use crate::app::ConfirmationOperation;
-            use crate::utils::format_bytes;
+            use crate::utils::format_size;
 
             if app.partition_dialog.mode == PartitionDialogMode::SelectTableType {
                 if let Some(disk) = app.selected_disk()
                     && let Some(table_idx) = app.partition_dialog.table_type_state.selected() {
                         let disk_name = disk.device.name.clone();
-                        let disk_size = format_bytes(disk.device.size);
+                        let disk_size = format_size(disk.device.size, app.size_units);
                         let disk_model = disk
                             .device
                             .model
@@ -510,6 +1289,7 @@ async fn handle_partition_dialog(
                                 ),
                             ],
                             selected: 0,
+                            commands: CommandPreview::None,
                             operation: ConfirmationOperation::CreatePartitionTable {
                                 disk: disk_name,
                                 table_type,
@@ -517,47 +1297,37 @@ async fn handle_partition_dialog(
                         };
                     }
             } else if app.partition_dialog.mode == PartitionDialogMode::CreatePartition {
-                if app.partition_dialog.create_step == CreatePartitionStep::EnterSize {
-                    app.partition_dialog.create_step = CreatePartitionStep::SelectFilesystem;
-                } else if let (Some(disk), Some(fs_idx)) = (
-                    app.selected_disk(),
-                    app.partition_dialog.new_partition_fs_state.selected(),
-                ) {
-                    let disk_name = disk.device.name.clone();
-                    let disk_size = format_bytes(disk.device.size);
-                    let size_str = app.partition_dialog.size_input.value().to_string();
-                    let fs_type = app.filesystem_types[fs_idx].clone();
-
-                    let used_space: u64 = disk.device.partitions.iter().map(|p| p.size).sum();
-                    let free_space = disk.device.size.saturating_sub(used_space);
-                    let free_space_str = format_bytes(free_space);
-
-                    let display_size = if size_str.trim().is_empty() {
-                        format!("{} (all available)", free_space_str)
-                    } else {
-                        size_str.clone()
-                    };
-
-                    app.partition_dialog.show_dialog = false;
-
-                    app.confirmation_dialog = crate::app::ConfirmationDialog {
-                        show_dialog: true,
-                        title: "Confirm Create Partition".to_string(),
-                        message: "Create new partition with the following settings?".to_string(),
-                        details: vec![
-                            ("Disk".to_string(), disk_name.clone()),
-                            ("Disk Size".to_string(), disk_size),
-                            ("Available Space".to_string(), free_space_str),
-                            ("New Partition Size".to_string(), display_size),
-                            ("Filesystem".to_string(), fs_type.to_string()),
-                        ],
-                        selected: 0,
-                        operation: ConfirmationOperation::CreatePartition {
-                            disk: disk_name,
-                            size: size_str,
-                            fs_type,
-                        },
-                    };
+                match app.partition_dialog.create_step {
+                    CreatePartitionStep::EnterSize => {
+                        // The dialog shows why the size is invalid.
+                        if let Some(Ok(_)) = app.create_preview()
+                            && let Some(disk) = app.selected_disk()
+                        {
+                            app.partition_dialog.part_types =
+                                parttype::types_for(disk.device.partition_table.as_deref());
+                            app.partition_dialog.part_type_state.select(Some(0));
+                            app.partition_dialog.create_step = CreatePartitionStep::SelectType;
+                        }
+                    }
+                    CreatePartitionStep::SelectType => {
+                        match app.partition_dialog.selected_part_type() {
+                            Some(part_type) if part_type.has_filesystem => {
+                                app.partition_dialog.create_step =
+                                    CreatePartitionStep::SelectFilesystem;
+                            }
+                            Some(part_type) if part_type.alias == "swap" => {
+                                confirm_create_partition(app, Some(FilesystemType::Swap))
+                            }
+                            Some(_) => confirm_create_partition(app, None),
+                            None => {}
+                        }
+                    }
+                    CreatePartitionStep::SelectFilesystem => {
+                        if let Some(fs_idx) = app.partition_dialog.new_partition_fs_state.selected() {
+                            let fs_type = app.filesystem_types[fs_idx].clone();
+                            confirm_create_partition(app, Some(fs_type));
+                        }
+                    }
                 }
             }
         }
@@ -574,13 +1344,132 @@ async fn handle_partition_dialog(
     Ok(())
 }
 
+/// Shows the confirmation for the partition set up in the create dialog.
+/// `fs_type` is `None` for types that hold no filesystem.
+fn confirm_create_partition(app: &mut App, fs_type: Option<FilesystemType>) {
+    use crate::app::ConfirmationOperation;
+    use crate::utils::format_size;
+
+    let Some(disk) = app.selected_disk() else {
+        return;
+    };
+    let part_type = app.partition_dialog.selected_part_type();
+    let disk_name = disk.device.name.clone();
+    let disk_size = format_size(disk.device.size, app.size_units);
+    let size_str = app.partition_dialog.size_input.value().to_string();
+    let gap = app.partition_dialog.gap;
+
+    let free_space = gap.map(|gap| gap.size()).unwrap_or(0);
+    let free_space_str = format_size(free_space, app.size_units);
+    let placement = app.create_preview().and_then(Result::ok);
+    let gap_start = placement
+        .map(|p| format_size(p.range.start, app.size_units))
+        .unwrap_or_default();
+    let new_size = placement
+        .map(|p| format_size(p.range.size(), app.size_units))
+        .unwrap_or_default();
+
+    let display_size = if size_str.trim().is_empty() {
+        format!("{} (all available)", new_size)
+    } else {
+        format!("{} ({})", new_size, size_str)
+    };
+
+    app.partition_dialog.show_dialog = false;
+
+    app.confirmation_dialog = crate::app::ConfirmationDialog {
+        show_dialog: true,
+        title: "Confirm Create Partition".to_string(),
+        message: "Create new partition with the following settings?".to_string(),
+        details: vec![
+            ("Disk".to_string(), disk_name.clone()),
+            ("Disk Size".to_string(), disk_size),
+            ("Available Space".to_string(), free_space_str),
+            ("Starts At".to_string(), gap_start),
+            ("New Partition Size".to_string(), display_size),
+            (
+                "Type".to_string(),
+                part_type.map(|t| t.name.to_string()).unwrap_or_default(),
+            ),
+            (
+                "Filesystem".to_string(),
+                fs_type
+                    .as_ref()
+                    .map(|fs| fs.to_string())
+                    .unwrap_or_else(|| "none".to_string()),
+            ),
+            ("Alignment".to_string(), app.alignment.to_string()),
+        ],
+        selected: 0,
+        commands: CommandPreview::None,
+        operation: ConfirmationOperation::CreatePartition {
+            disk: disk_name,
+            start: gap.map(|gap| gap.start),
+            size: size_str,
+            fs_type,
+            alignment: app.alignment,
+            // The table's default type needs no extra command.
+            part_type: part_type
+                .map(|t| t.alias)
+                .filter(|alias| *alias != parttype::LINUX_FILESYSTEM)
+                .map(str::to_string),
+        },
+    };
+}
+
+/// Why the selected partition, or a partition of the selected disk, cannot
+/// be overwritten while it belongs to a volume group, array or btrfs
+/// filesystem.
+fn holder_blocker(app: &App) -> Option<String> {
+    let partitions: Vec<&crate::partition::Partition> = match app.focused_block {
+        FocusedBlock::Partitions => app.selected_partition().into_iter().collect(),
+        FocusedBlock::Disks => app
+            .selected_disk()
+            .map(|disk| disk.device.partitions.iter().collect())
+            .unwrap_or_default(),
+        _ => Vec::new(),
+    };
+    partitions
+        .into_iter()
+        .find_map(|partition| partition.holder.as_ref()?.blocker(&partition.name))
+}
+
+/// Confirmation detail for a member that is detached but still carries the
+/// signature of its volume group, array or btrfs filesystem.
+fn holder_warning(partition: &crate::partition::Partition) -> Option<(String, String)> {
+    let warning = partition.holder.as_ref()?.warning(&partition.name);
+    Some(("Warning".to_string(), warning))
+}
+
 fn handle_scroll_down(app: &mut App) {
     match app.focused_block {
-        FocusedBlock::Disks => {
-            if !app.disks.is_empty() {
-                let i = match app.disks_state.selected() {
+        FocusedBlock::Disks if !app.disks.is_empty() => {
+            let i = match app.disks_state.selected() {
+                Some(i) => {
+                    if i < app.disks.len() - 1 {
+                        i + 1
+                    } else {
+                        i
+                    }
+                }
+                None => 0,
+            };
+            app.disks_state.select(Some(i));
+            if !layout_rows(&app.disks[i].device).is_empty() {
+                app.partitions_state.select(Some(0));
+            } else {
+                app.partitions_state.select(None);
+            }
+        }
+        FocusedBlock::Partitions => {
+            let row_count = app
+                .selected_disk()
+                .map(|disk| layout_rows(&disk.device).len())
+                .unwrap_or(0);
+            if row_count > 0 {
+                let i = match app.partitions_state.selected() {
                     Some(i) => {
-                        if i < app.disks.len() - 1 {
+                        if i < row_count - 1 {
                             i + 1
                         } else {
                             i
@@ -588,209 +1477,1315 @@ fn handle_scroll_down(app: &mut App) {
                     }
                     None => 0,
                 };
-                app.disks_state.select(Some(i));
-                if !app.disks[i].device.partitions.is_empty() {
-                    app.partitions_state.select(Some(0));
-                } else {
-                    app.partitions_state.select(None);
-                }
+                app.partitions_state.select(Some(i));
             }
         }
-        FocusedBlock::Partitions => {
-            if let Some(disk) = app.selected_disk()
-                && !disk.device.partitions.is_empty() {
-                    let i = match app.partitions_state.selected() {
-                        Some(i) => {
-                            if i < disk.device.partitions.len() - 1 {
-                                i + 1
-                            } else {
-                                i
-                            }
-                        }
-                        None => 0,
-                    };
-                    app.partitions_state.select(Some(i));
-                }
-        }
         _ => {}
     }
 }
 
 fn handle_scroll_up(app: &mut App) {
     match app.focused_block {
-        FocusedBlock::Disks => {
-            if !app.disks.is_empty() {
-                let i = match app.disks_state.selected() {
-                    Some(i) => i.saturating_sub(1),
-                    None => 0,
-                };
-                app.disks_state.select(Some(i));
-                if !app.disks[i].device.partitions.is_empty() {
-                    app.partitions_state.select(Some(0));
-                } else {
-                    app.partitions_state.select(None);
-                }
+        FocusedBlock::Disks if !app.disks.is_empty() => {
+            let i = match app.disks_state.selected() {
+                Some(i) => i.saturating_sub(1),
+                None => 0,
+            };
+            app.disks_state.select(Some(i));
+            if !layout_rows(&app.disks[i].device).is_empty() {
+                app.partitions_state.select(Some(0));
+            } else {
+                app.partitions_state.select(None);
             }
         }
-        FocusedBlock::Partitions => {
-            if let Some(disk) = app.selected_disk()
-                && !disk.device.partitions.is_empty() {
-                    let i = match app.partitions_state.selected() {
-                        Some(i) => i.saturating_sub(1),
-                        None => 0,
-                    };
-                    app.partitions_state.select(Some(i));
-                }
+        FocusedBlock::Partitions
+            if app
+                .selected_disk()
+                .is_some_and(|disk| !layout_rows(&disk.device).is_empty()) =>
+        {
+            let i = match app.partitions_state.selected() {
+                Some(i) => i.saturating_sub(1),
+                None => 0,
+            };
+            app.partitions_state.select(Some(i));
         }
         _ => {}
     }
 }
 
-async fn handle_confirmation_dialog(
+/// Keys specific to the pending operations panel. Returns false for keys the
+/// panel does not use so that the global bindings still apply.
+fn handle_disk_info_keys(
     key_event: KeyEvent,
     app: &mut App,
-    sender: UnboundedSender<Event>,
-) -> AppResult<()> {
+    sender: &UnboundedSender<Event>,
+    config: &Config,
+) -> bool {
+    let Some(disk) = app.selected_disk().map(|disk| disk.device.name.clone()) else {
+        return false;
+    };
+    match key_event.code {
+        KeyCode::Char(c) if c == config.navigation.scroll_down => app.disk_info_scroll += 1,
+        KeyCode::Down => app.disk_info_scroll += 1,
+        KeyCode::Char(c) if c == config.navigation.scroll_up => {
+            app.disk_info_scroll = app.disk_info_scroll.saturating_sub(1)
+        }
+        KeyCode::Up => app.disk_info_scroll = app.disk_info_scroll.saturating_sub(1),
+        KeyCode::PageDown => app.disk_info_scroll += 10,
+        KeyCode::PageUp => app.disk_info_scroll = app.disk_info_scroll.saturating_sub(10),
+        KeyCode::Char('g') | KeyCode::Home => app.disk_info_scroll = 0,
+        // Clamped to the end of the view when drawn.
+        KeyCode::Char('G') | KeyCode::End => app.disk_info_scroll = usize::MAX,
+        KeyCode::Char('s') => spawn_self_test(app, sender, disk, SelfTestKind::Short),
+        KeyCode::Char('l') => spawn_self_test(app, sender, disk, SelfTestKind::Long),
+        KeyCode::Char('r') => spawn_smart_probe(app, sender, disk),
+        _ => return false,
+    }
+    true
+}
+
+fn handle_queue_keys(key_event: KeyEvent, app: &mut App, config: &Config) -> bool {
     use crate::app::ConfirmationOperation;
 
     match key_event.code {
-        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('Q') => {
-            app.confirmation_dialog.show_dialog = false;
-            app.confirmation_dialog.operation = ConfirmationOperation::None;
-            app.confirmation_dialog.selected = 0;
+        KeyCode::Char(c) if c == config.navigation.scroll_down => app.queue.select_next(),
+        KeyCode::Down => app.queue.select_next(),
+        KeyCode::Char(c) if c == config.navigation.scroll_up => app.queue.select_previous(),
+        KeyCode::Up => app.queue.select_previous(),
+        KeyCode::Char('J') => app.queue.move_selected_down(),
+        KeyCode::Char('K') => app.queue.move_selected_up(),
+        KeyCode::Char('u') => {
+            app.queue.undo();
         }
-        KeyCode::Left | KeyCode::Right | KeyCode::Char('h') | KeyCode::Char('l') => {
-            app.confirmation_dialog.selected = 1 - app.confirmation_dialog.selected;
+        KeyCode::Char('c') => app.queue.clear(),
+        KeyCode::Char(c) if c == config.disk.delete => {
+            app.queue.remove_selected();
         }
-        KeyCode::Enter => {
-            if app.confirmation_dialog.selected == 1 {
-                let operation = app.confirmation_dialog.operation.clone();
-                app.confirmation_dialog.show_dialog = false;
-                app.confirmation_dialog.operation = ConfirmationOperation::None;
-                app.confirmation_dialog.selected = 0;
+        KeyCode::Delete | KeyCode::Backspace => {
+            app.queue.remove_selected();
+        }
+        KeyCode::Char('a') if !app.queue.is_empty() => {
+            app.confirmation_dialog = crate::app::ConfirmationDialog {
+                show_dialog: true,
+                title: "Confirm Apply Pending Operations".to_string(),
+                message: "Run all pending operations in this order?".to_string(),
+                details: app
+                    .queue
+                    .operations
+                    .iter()
+                    .enumerate()
+                    .map(|(i, request)| (format!("{}", i + 1), request.description()))
+                    .collect(),
+                selected: 0,
+                commands: CommandPreview::None,
+                operation: ConfirmationOperation::ApplyQueue,
+            };
+        }
+        _ => return false,
+    }
+    true
+}
 
-                let request = match operation {
-                    ConfirmationOperation::FormatPartition { partition, fs_type } => {
-                        Some(Request::Format {
-                            device: partition,
-                            fs_type: fs_type.to_string(),
-                        })
-                    }
-                    ConfirmationOperation::FormatDisk { disk, fs_type } => {
-                        Some(Request::FormatWholeDisk {
-                            disk,
-                            fs_type: fs_type.to_string(),
-                        })
-                    }
-                    ConfirmationOperation::DeletePartition { partition } => {
-                        Some(Request::DeletePartition { partition })
-                    }
-                    ConfirmationOperation::CreatePartitionTable { disk, table_type } => {
-                        Some(Request::CreatePartitionTable { disk, table_type })
-                    }
-                    ConfirmationOperation::CreatePartition { disk, size, fs_type } => {
-                        Some(Request::CreatePartition {
-                            disk,
-                            size,
-                            fs_type: Some(fs_type.to_string()),
-                        })
-                    }
-                    ConfirmationOperation::ResizePartition { partition, new_size } => {
-                        Some(Request::ResizePartition { partition, new_size })
-                    }
-                    ConfirmationOperation::LockLuksDevice { mapper_name } => {
-                        Some(Request::LockLuks { mapper_name })
-                    }
-                    ConfirmationOperation::EncryptPartition { partition, fs_type } => {
-                        let passphrase = app.passphrase_dialog.first_passphrase.clone();
-                        app.passphrase_dialog.first_passphrase.clear();
-                        app.passphrase_dialog.filesystem_type = None;
-                        Some(Request::EncryptAndFormat {
-                            partition,
-                            passphrase,
-                            fs_type: fs_type.to_string(),
-                        })
-                    }
-                    ConfirmationOperation::UnlockLuksDevice { device, mapper_name } => {
-                        let passphrase = app.passphrase_dialog.first_passphrase.clone();
-                        app.passphrase_dialog.first_passphrase.clear();
-                        Some(Request::UnlockLuks {
-                            device,
-                            passphrase,
-                            mapper_name,
-                        })
-                    }
-                    ConfirmationOperation::None => None,
-                };
-                if let Some(req) = request {
-                    spawn_helper_operation(app, &sender, req);
-                }
-            } else {
-                app.confirmation_dialog.show_dialog = false;
-                app.confirmation_dialog.operation = ConfirmationOperation::None;
-                app.confirmation_dialog.selected = 0;
+fn handle_audit_log_dialog(key_event: KeyEvent, app: &mut App, config: &Config) {
+    let log = &mut app.audit_log;
+    let last = log.entries.len().saturating_sub(1);
+    match key_event.code {
+        KeyCode::Esc | KeyCode::Char('q') => log.show_dialog = false,
+        KeyCode::Char(c) if c == config.disk.history => log.show_dialog = false,
+        KeyCode::Char(c) if c == config.navigation.scroll_down => {
+            if let Some(idx) = log.state.selected() {
+                log.state.select(Some((idx + 1).min(last)));
+            }
+        }
+        KeyCode::Down => {
+            if let Some(idx) = log.state.selected() {
+                log.state.select(Some((idx + 1).min(last)));
+            }
+        }
+        KeyCode::Char(c) if c == config.navigation.scroll_up => {
+            if let Some(idx) = log.state.selected() {
+                log.state.select(Some(idx.saturating_sub(1)));
+            }
+        }
+        KeyCode::Up => {
+            if let Some(idx) = log.state.selected() {
+                log.state.select(Some(idx.saturating_sub(1)));
             }
         }
         _ => {}
     }
-    Ok(())
 }
 
-async fn handle_resize_dialog(
+fn handle_check_log(
     key_event: KeyEvent,
     app: &mut App,
-    _sender: UnboundedSender<Event>,
-) -> AppResult<()> {
+    sender: &UnboundedSender<Event>,
+    config: &Config,
+) {
     use crate::app::ConfirmationOperation;
-    use crate::utils::format_bytes;
-
-    match key_event.code {
-        KeyCode::Esc => {
-            app.resize_dialog.show_dialog = false;
-        }
-        KeyCode::Enter => {
-            if let Some(partition) = app.selected_partition() {
-                let part_name = partition.name.clone();
-                let current_size = partition.size;
-                let current_size_str = format_bytes(current_size);
-                let new_size_str = app.resize_dialog.size_input.value().to_string();
-
-                if new_size_str.trim().is_empty() {
-                    return Ok(());
-                }
-
-                let filesystem = partition
-                    .filesystem
-                    .clone()
-                    .unwrap_or_else(|| "none".to_string());
-
-                app.resize_dialog.show_dialog = false;
 
+    let log = &mut app.check_log;
+    if !log.started {
+        match key_event.code {
+            KeyCode::Esc | KeyCode::Char('q') => log.show_dialog = false,
+            KeyCode::Char(c) if c == config.navigation.scroll_down => log.repair = log.can_repair(),
+            KeyCode::Char(c) if c == config.navigation.scroll_up => log.repair = false,
+            KeyCode::Down => log.repair = log.can_repair(),
+            KeyCode::Up => log.repair = false,
+            KeyCode::Enter if log.repair => {
+                // Repairs write to the filesystem, so they are confirmed
+                // like any other change.
+                let device = log.device.clone();
+                let fs_type = log.fs_type.clone();
+                log.show_dialog = false;
                 app.confirmation_dialog = crate::app::ConfirmationDialog {
                     show_dialog: true,
-                    title: "Confirm Resize Partition".to_string(),
-                    message: "Resize partition and filesystem to new size?".to_string(),
+                    title: "Confirm Filesystem Repair".to_string(),
+                    message: "Check the filesystem and repair every error found?".to_string(),
                     details: vec![
-                        ("Partition".to_string(), part_name.clone()),
-                        ("Current Size".to_string(), current_size_str),
-                        ("New Size".to_string(), new_size_str.clone()),
-                        ("Filesystem".to_string(), filesystem),
+                        ("Device".to_string(), device.clone()),
+                        ("Filesystem".to_string(), fs_type),
                     ],
                     selected: 0,
-                    operation: ConfirmationOperation::ResizePartition {
-                        partition: part_name,
-                        new_size: new_size_str,
-                    },
+                    commands: CommandPreview::None,
+                    operation: ConfirmationOperation::CheckFilesystem { device, repair: true },
                 };
             }
+            KeyCode::Enter => {
+                let device = log.device.clone();
+                spawn_filesystem_check(app, sender, device, false);
+            }
+            _ => {}
+        }
+        return;
+    }
+
+    let len = log.lines.len();
+    match key_event.code {
+        KeyCode::Esc | KeyCode::Char('q') => log.show_dialog = false,
+        KeyCode::Char(c) if c == config.navigation.scroll_up => log.scroll = (log.scroll + 1).min(len),
+        KeyCode::Char(c) if c == config.navigation.scroll_down => log.scroll = log.scroll.saturating_sub(1),
+        KeyCode::Up => log.scroll = (log.scroll + 1).min(len),
+        KeyCode::Down => log.scroll = log.scroll.saturating_sub(1),
+        KeyCode::PageUp => log.scroll = (log.scroll + 10).min(len),
+        KeyCode::PageDown => log.scroll = log.scroll.saturating_sub(10),
+        KeyCode::Char('g') | KeyCode::Home => log.scroll = len,
+        KeyCode::Char('G') | KeyCode::End => log.scroll = 0,
+        _ => {}
+    }
+}
+
+fn handle_restore_dialog(
+    key_event: KeyEvent,
+    app: &mut App,
+    sender: &UnboundedSender<Event>,
+    config: &Config,
+) {
+    use crate::app::{ConfirmationDialog, ConfirmationOperation};
+    use crate::utils::format_timestamp;
+
+    let dialog = &mut app.restore_dialog;
+    let last = dialog.backups.len().saturating_sub(1);
+    match key_event.code {
+        KeyCode::Esc | KeyCode::Char('q') => dialog.show_dialog = false,
+        KeyCode::Char(c) if c == config.navigation.scroll_down => {
+            if let Some(idx) = dialog.state.selected() {
+                dialog.state.select(Some((idx + 1).min(last)));
+            }
+        }
+        KeyCode::Down => {
+            if let Some(idx) = dialog.state.selected() {
+                dialog.state.select(Some((idx + 1).min(last)));
+            }
+        }
+        KeyCode::Char(c) if c == config.navigation.scroll_up => {
+            if let Some(idx) = dialog.state.selected() {
+                dialog.state.select(Some(idx.saturating_sub(1)));
+            }
+        }
+        KeyCode::Up => {
+            if let Some(idx) = dialog.state.selected() {
+                dialog.state.select(Some(idx.saturating_sub(1)));
+            }
+        }
+        KeyCode::Enter => {
+            let Some(backup) = dialog.selected_backup() else {
+                return;
+            };
+            let disk = dialog.disk.clone();
+            let backup_id = backup.id.clone();
+            let saved = format_timestamp(backup.timestamp);
+            if check_operation_in_progress(app, sender) {
+                return;
+            }
+            app.restore_dialog.show_dialog = false;
+
+            app.confirmation_dialog = ConfirmationDialog {
+                show_dialog: true,
+                title: "Confirm Restore Partition Table".to_string(),
+                message: "Replace the partition table with this backup?".to_string(),
+                details: vec![
+                    ("Disk".to_string(), disk.clone()),
+                    ("Backup".to_string(), backup_id.clone()),
+                    ("Saved".to_string(), saved),
+                ],
+                selected: 0,
+                commands: CommandPreview::None,
+                operation: ConfirmationOperation::RestoreTable {
+                    disk,
+                    backup: backup_id,
+                },
+            };
+        }
+        _ => {}
+    }
+}
+
+fn handle_lvm_view(
+    key_event: KeyEvent,
+    app: &mut App,
+    sender: &UnboundedSender<Event>,
+    config: &Config,
+) {
+    use crate::app::{ConfirmationOperation, LvmAction, LvmForm, LvmSection};
+    use tui_input::Input;
+
+    if app.lvm.form.is_some() {
+        handle_lvm_form(key_event, app, sender);
+        return;
+    }
+
+    let view = &mut app.lvm;
+    let section = LvmSection::ALL
+        .iter()
+        .position(|section| *section == view.section)
+        .unwrap_or(0);
+    let last = view.rows().saturating_sub(1);
+    let unused_pv = view
+        .selected_pv()
+        .filter(|pv| pv.vg_name.is_none())
+        .map(|pv| pv.name.trim_start_matches("/dev/").to_string());
+
+    let operation = match key_event.code {
+        KeyCode::Esc | KeyCode::Char('q') => {
+            view.show_dialog = false;
+            return;
+        }
+        KeyCode::Tab | KeyCode::Right => {
+            view.select_section(LvmSection::ALL[(section + 1) % LvmSection::ALL.len()]);
+            return;
+        }
+        KeyCode::BackTab | KeyCode::Left => {
+            let len = LvmSection::ALL.len();
+            view.select_section(LvmSection::ALL[(section + len - 1) % len]);
+            return;
+        }
+        KeyCode::Char(c) if c == config.navigation.scroll_down => {
+            view.state.select(view.state.selected().map(|idx| (idx + 1).min(last)));
+            return;
+        }
+        KeyCode::Down => {
+            view.state.select(view.state.selected().map(|idx| (idx + 1).min(last)));
+            return;
+        }
+        KeyCode::Char(c) if c == config.navigation.scroll_up => {
+            view.state.select(view.state.selected().map(|idx| idx.saturating_sub(1)));
+            return;
+        }
+        KeyCode::Up => {
+            view.state.select(view.state.selected().map(|idx| idx.saturating_sub(1)));
+            return;
+        }
+        KeyCode::Char('n') if view.section == LvmSection::PhysicalVolumes => {
+            let Some(device) = view.candidate.clone() else {
+                let _ = Notification::send(
+                    "Select a partition before opening this view to make it a physical volume"
+                        .to_string(),
+                    NotificationLevel::Warning,
+                    sender,
+                );
+                return;
+            };
+            ConfirmationOperation::CreatePhysicalVolume { device }
+        }
+        KeyCode::Char('n') => {
+            let vg = match view.section {
+                LvmSection::VolumeGroups => view.selected_vg().map(|vg| vg.name.clone()),
+                _ => view.selected_lv().map(|lv| lv.vg_name.clone()),
+            };
+            if let Some(vg) = vg {
+                view.form = Some(LvmForm::new(
+                    LvmAction::CreateLogicalVolume { vg },
+                    vec![
+                        ("Name", Input::default()),
+                        ("Size (empty for all free space)", Input::default()),
+                        ("Filesystem (empty for none)", Input::new("ext4".to_string())),
+                    ],
+                ));
+            }
+            return;
+        }
+        KeyCode::Char('v') => {
+            if let Some(device) = unused_pv {
+                view.form = Some(LvmForm::new(
+                    LvmAction::CreateVolumeGroup { device },
+                    vec![("Name", Input::default())],
+                ));
+            }
+            return;
+        }
+        KeyCode::Char('a') => {
+            if let Some(device) = unused_pv {
+                let vg = view.report.vgs.first().map(|vg| vg.name.clone()).unwrap_or_default();
+                view.form = Some(LvmForm::new(
+                    LvmAction::ExtendVolumeGroup { device },
+                    vec![("Volume group", Input::new(vg))],
+                ));
+            }
+            return;
+        }
+        KeyCode::Char(c) if c == config.disk.resize => {
+            if let Some(lv) = view.selected_lv() {
+                let action = LvmAction::ResizeLogicalVolume {
+                    vg: lv.vg_name.clone(),
+                    name: lv.name.clone(),
+                };
+                view.form = Some(LvmForm::new(action, vec![("New size", Input::default())]));
+            }
+            return;
+        }
+        KeyCode::Char(c) if c == config.disk.delete => {
+            let Some(lv) = view.selected_lv() else {
+                return;
+            };
+            ConfirmationOperation::RemoveLogicalVolume {
+                vg: lv.vg_name.clone(),
+                name: lv.name.clone(),
+            }
+        }
+        _ => return,
+    };
+    confirm_lvm_operation(app, sender, operation);
+}
+
+fn handle_lvm_form(key_event: KeyEvent, app: &mut App, sender: &UnboundedSender<Event>) {
+    use crate::app::LvmAction;
+
+    let Some(form) = app.lvm.form.as_mut() else {
+        return;
+    };
+    let len = form.fields.len();
+    match key_event.code {
+        KeyCode::Esc => app.lvm.form = None,
+        KeyCode::Tab | KeyCode::Down => form.focus = (form.focus + 1) % len,
+        KeyCode::BackTab | KeyCode::Up => form.focus = (form.focus + len - 1) % len,
+        KeyCode::Enter if matches!(form.action, LvmAction::CreateLogicalVolume { .. }) => {
+            match form.create_request() {
+                Ok(request) => {
+                    app.lvm.form = None;
+                    spawn_helper_operation(app, sender, request);
+                }
+                Err(e) => form.error = Some(e.to_string()),
+            }
+        }
+        KeyCode::Enter => match form.operation() {
+            Ok(operation) => {
+                app.lvm.form = None;
+                confirm_lvm_operation(app, sender, operation);
+            }
+            Err(e) => form.error = Some(e.to_string()),
+        },
+        _ => {
+            if let Some((_, input)) = form.fields.get_mut(form.focus) {
+                input.handle_event(&crossterm::event::Event::Key(key_event));
+                form.error = None;
+            }
+        }
+    }
+}
+
+/// Asks for confirmation of an LVM change. The LVM view stays open behind
+/// the dialog and is reloaded once the change is done.
+fn confirm_lvm_operation(
+    app: &mut App,
+    sender: &UnboundedSender<Event>,
+    operation: crate::app::ConfirmationOperation,
+) {
+    use crate::app::ConfirmationOperation;
+    use crate::utils::format_size;
+
+    if check_operation_in_progress(app, sender) {
+        return;
+    }
+    let report = &app.lvm.report;
+    let (title, message, details) = match &operation {
+        ConfirmationOperation::CreatePhysicalVolume { device } => (
+            "Confirm Create Physical Volume",
+            "Erase the signatures on this device and make it an LVM physical volume?",
+            vec![("Device".to_string(), device.clone())],
+        ),
+        ConfirmationOperation::CreateVolumeGroup { name, devices } => (
+            "Confirm Create Volume Group",
+            "Create a volume group on these physical volumes?",
+            vec![
+                ("Volume Group".to_string(), name.clone()),
+                ("Devices".to_string(), devices.join(", ")),
+            ],
+        ),
+        ConfirmationOperation::ExtendVolumeGroup { name, device } => (
+            "Confirm Extend Volume Group",
+            "Add this physical volume to the volume group?",
+            vec![
+                ("Volume Group".to_string(), name.clone()),
+                ("Device".to_string(), device.clone()),
+            ],
+        ),
+        ConfirmationOperation::ResizeLogicalVolume { vg, name, size } => {
+            let current = report
+                .lv(vg, name)
+                .map(|lv| format_size(lv.size, app.size_units))
+                .unwrap_or_default();
+            (
+                "Confirm Resize Logical Volume",
+                "Resize the logical volume and its filesystem?",
+                vec![
+                    ("Logical Volume".to_string(), format!("{}/{}", vg, name)),
+                    ("Current Size".to_string(), current),
+                    ("New Size".to_string(), size.clone()),
+                ],
+            )
+        }
+        ConfirmationOperation::RemoveLogicalVolume { vg, name } => (
+            "Confirm Remove Logical Volume",
+            "Remove the logical volume and all data on it?",
+            vec![("Logical Volume".to_string(), format!("{}/{}", vg, name))],
+        ),
+        _ => return,
+    };
+    app.confirmation_dialog = crate::app::ConfirmationDialog {
+        show_dialog: true,
+        title: title.to_string(),
+        message: message.to_string(),
+        details,
+        selected: 0,
+        commands: CommandPreview::None,
+        operation,
+    };
+}
+
+fn handle_subvolume_view(
+    key_event: KeyEvent,
+    app: &mut App,
+    sender: &UnboundedSender<Event>,
+    config: &Config,
+) {
+    use crate::app::{ConfirmationOperation, SubvolumeAction, SubvolumeForm};
+
+    if app.subvolumes.form.is_some() {
+        handle_subvolume_form(key_event, app, sender);
+        return;
+    }
+
+    let view = &mut app.subvolumes;
+    let last = view.list.subvolumes.len().saturating_sub(1);
+    let selected = view.selected().cloned();
+    let device = view.device.clone();
+
+    let operation = match key_event.code {
+        KeyCode::Esc | KeyCode::Char('q') => {
+            view.show_dialog = false;
+            return;
+        }
+        KeyCode::Char(c) if c == config.navigation.scroll_down => {
+            view.state.select(view.state.selected().map(|idx| (idx + 1).min(last)));
+            return;
+        }
+        KeyCode::Down => {
+            view.state.select(view.state.selected().map(|idx| (idx + 1).min(last)));
+            return;
+        }
+        KeyCode::Char(c) if c == config.navigation.scroll_up => {
+            view.state.select(view.state.selected().map(|idx| idx.saturating_sub(1)));
+            return;
+        }
+        KeyCode::Up => {
+            view.state.select(view.state.selected().map(|idx| idx.saturating_sub(1)));
+            return;
+        }
+        KeyCode::Char('n') => {
+            view.form = Some(SubvolumeForm::new(SubvolumeAction::Create, String::new()));
+            return;
+        }
+        KeyCode::Char('s') => {
+            let Some(subvolume) = selected else {
+                return;
+            };
+            // Next to the subvolume, where it is mounted.
+            let path = match view.list.local_path(&subvolume).as_deref() {
+                Some("") | None => "snapshot".to_string(),
+                Some(local) => format!("{}-snapshot", local),
+            };
+            view.form = Some(SubvolumeForm::new(
+                SubvolumeAction::Snapshot {
+                    id: subvolume.id,
+                    path: subvolume.display_path().to_string(),
+                },
+                path,
+            ));
+            return;
+        }
+        KeyCode::Char(c) if c == config.disk.delete => {
+            let Some(subvolume) = selected else {
+                return;
+            };
+            ConfirmationOperation::DeleteSubvolume {
+                device,
+                id: subvolume.id,
+                path: subvolume.display_path().to_string(),
+            }
+        }
+        KeyCode::Char('*') => {
+            let Some(subvolume) = selected else {
+                return;
+            };
+            ConfirmationOperation::SetDefaultSubvolume {
+                device,
+                id: subvolume.id,
+                path: subvolume.display_path().to_string(),
+            }
+        }
+        _ => return,
+    };
+    confirm_subvolume_operation(app, sender, operation);
+}
+
+fn handle_subvolume_form(key_event: KeyEvent, app: &mut App, sender: &UnboundedSender<Event>) {
+    let view = &mut app.subvolumes;
+    let Some(form) = view.form.as_mut() else {
+        return;
+    };
+    match key_event.code {
+        KeyCode::Esc => view.form = None,
+        KeyCode::Enter => match form.request(&view.device) {
+            Ok(request) => {
+                view.form = None;
+                spawn_helper_operation(app, sender, request);
+            }
+            Err(e) => form.error = Some(e.to_string()),
+        },
+        _ => {
+            form.input.handle_event(&crossterm::event::Event::Key(key_event));
+            form.error = None;
+        }
+    }
+}
+
+/// Asks for confirmation of deleting a subvolume or changing the default
+/// one. The subvolume view stays open behind the dialog.
+fn confirm_subvolume_operation(
+    app: &mut App,
+    sender: &UnboundedSender<Event>,
+    operation: crate::app::ConfirmationOperation,
+) {
+    use crate::app::ConfirmationOperation;
+
+    if check_operation_in_progress(app, sender) {
+        return;
+    }
+    let (title, message, details) = match &operation {
+        ConfirmationOperation::DeleteSubvolume { device, id, path } => (
+            "Confirm Delete Subvolume",
+            "Delete the subvolume and all files in it?",
+            vec![
+                ("Device".to_string(), device.clone()),
+                ("Subvolume".to_string(), format!("{} (ID {})", path, id)),
+            ],
+        ),
+        ConfirmationOperation::SetDefaultSubvolume { device, id, path } => (
+            "Confirm Set Default Subvolume",
+            "Mount this subvolume when no subvolume is given, including at the next boot?",
+            vec![
+                ("Device".to_string(), device.clone()),
+                ("Subvolume".to_string(), format!("{} (ID {})", path, id)),
+            ],
+        ),
+        _ => return,
+    };
+    app.confirmation_dialog = crate::app::ConfirmationDialog {
+        show_dialog: true,
+        title: title.to_string(),
+        message: message.to_string(),
+        details,
+        selected: 0,
+        commands: CommandPreview::None,
+        operation,
+    };
+}
+
+fn handle_raid_view(
+    key_event: KeyEvent,
+    app: &mut App,
+    sender: &UnboundedSender<Event>,
+    config: &Config,
+) {
+    use crate::app::{ConfirmationOperation, RaidForm};
+
+    if app.raid.form.is_some() {
+        handle_raid_form(key_event, app, sender, config);
+        return;
+    }
+
+    let view = &mut app.raid;
+    let operation = match key_event.code {
+        KeyCode::Esc | KeyCode::Char('q') => {
+            view.show_dialog = false;
+            return;
+        }
+        KeyCode::Tab | KeyCode::BackTab => {
+            view.members_focused = !view.members_focused && view.selected_array().is_some();
+            return;
+        }
+        KeyCode::Char(c) if c == config.navigation.scroll_down => {
+            view.move_selection(true);
+            return;
+        }
+        KeyCode::Down => {
+            view.move_selection(true);
+            return;
+        }
+        KeyCode::Char(c) if c == config.navigation.scroll_up => {
+            view.move_selection(false);
+            return;
+        }
+        KeyCode::Up => {
+            view.move_selection(false);
+            return;
+        }
+        KeyCode::Char('n') => {
+            let name = view.report.next_name();
+            let devices = app.raid_candidates();
+            app.raid.form = Some(RaidForm::new(None, name, devices));
+            return;
+        }
+        KeyCode::Char('a') => {
+            if let Some(stopped) = view.selected_stopped() {
+                let uuid = stopped.uuid.clone();
+                spawn_helper_operation(app, sender, Request::AssembleRaid { uuid });
+            } else if let Some(array) = view.selected_array() {
+                let array = array.name.clone();
+                let devices = app.raid_candidates();
+                app.raid.form = Some(RaidForm::new(Some(array), String::new(), devices));
+            }
+            return;
+        }
+        KeyCode::Char('s') => {
+            let Some(array) = view.selected_array() else {
+                return;
+            };
+            ConfirmationOperation::StopRaid {
+                array: array.name.clone(),
+            }
+        }
+        KeyCode::Char('f') if view.members_focused => {
+            let (Some(array), Some(member)) = (view.selected_array(), view.selected_member()) else {
+                return;
+            };
+            ConfirmationOperation::FailRaidMember {
+                array: array.name.clone(),
+                device: member.name.clone(),
+            }
+        }
+        KeyCode::Char(c) if c == config.disk.delete && view.members_focused => {
+            let (Some(array), Some(member)) = (view.selected_array(), view.selected_member()) else {
+                return;
+            };
+            ConfirmationOperation::RemoveRaidMember {
+                array: array.name.clone(),
+                device: member.name.clone(),
+            }
+        }
+        KeyCode::Enter => {
+            if let Some(array) = view.selected_array().cloned() {
+                app.watch_raid(&array);
+            }
+            return;
+        }
+        _ => return,
+    };
+    confirm_raid_operation(app, sender, operation);
+}
+
+fn handle_raid_form(
+    key_event: KeyEvent,
+    app: &mut App,
+    sender: &UnboundedSender<Event>,
+    config: &Config,
+) {
+    use crate::app::RaidFormField;
+
+    let Some(form) = app.raid.form.as_mut() else {
+        return;
+    };
+    let fields = form.fields();
+    let field = fields.iter().position(|field| *field == form.focus).unwrap_or(0);
+    let last = form.devices.len().saturating_sub(1);
+    match key_event.code {
+        KeyCode::Esc => app.raid.form = None,
+        KeyCode::Tab => form.focus = fields[(field + 1) % fields.len()],
+        KeyCode::BackTab => form.focus = fields[(field + fields.len() - 1) % fields.len()],
+        KeyCode::Enter => match form.operation() {
+            Ok(operation) => {
+                app.raid.form = None;
+                confirm_raid_operation(app, sender, operation);
+            }
+            Err(e) => form.error = Some(e.to_string()),
+        },
+        KeyCode::Left if form.focus == RaidFormField::Level => {
+            form.level = form.level.saturating_sub(1);
+        }
+        KeyCode::Right if form.focus == RaidFormField::Level => {
+            form.level = (form.level + 1).min(raid::LEVELS.len() - 1);
+        }
+        KeyCode::Char(c)
+            if form.focus == RaidFormField::Devices && c == config.navigation.scroll_down =>
+        {
+            form.state.select(form.state.selected().map(|idx| (idx + 1).min(last)));
+        }
+        KeyCode::Down if form.focus == RaidFormField::Devices => {
+            form.state.select(form.state.selected().map(|idx| (idx + 1).min(last)));
+        }
+        KeyCode::Char(c)
+            if form.focus == RaidFormField::Devices && c == config.navigation.scroll_up =>
+        {
+            form.state.select(form.state.selected().map(|idx| idx.saturating_sub(1)));
+        }
+        KeyCode::Up if form.focus == RaidFormField::Devices => {
+            form.state.select(form.state.selected().map(|idx| idx.saturating_sub(1)));
+        }
+        KeyCode::Char(' ') if form.focus == RaidFormField::Devices => {
+            if let Some((_, _, picked)) = form.state.selected().and_then(|idx| form.devices.get_mut(idx)) {
+                *picked = !*picked;
+                form.error = None;
+            }
+        }
+        _ if form.focus == RaidFormField::Name => {
+            form.name.handle_event(&crossterm::event::Event::Key(key_event));
+            form.error = None;
+        }
+        _ => {}
+    }
+}
+
+/// Asks for confirmation of an array change. The RAID view stays open
+/// behind the dialog and is reloaded once the change is done.
+fn confirm_raid_operation(
+    app: &mut App,
+    sender: &UnboundedSender<Event>,
+    operation: crate::app::ConfirmationOperation,
+) {
+    use crate::app::ConfirmationOperation;
+
+    if check_operation_in_progress(app, sender) {
+        return;
+    }
+    let (title, message, details) = match &operation {
+        ConfirmationOperation::CreateRaid {
+            name,
+            level,
+            devices,
+        } => (
+            "Confirm Create Array",
+            "Create the array? All data on these devices will be lost.",
+            vec![
+                ("Array".to_string(), name.clone()),
+                ("Level".to_string(), level.clone()),
+                ("Devices".to_string(), devices.join(", ")),
+            ],
+        ),
+        ConfirmationOperation::AddRaidMember { array, device } => (
+            "Confirm Add Array Member",
+            "Add the device to the array? All data on it will be lost.",
+            vec![
+                ("Array".to_string(), array.clone()),
+                ("Device".to_string(), device.clone()),
+            ],
+        ),
+        ConfirmationOperation::FailRaidMember { array, device } => (
+            "Confirm Fail Array Member",
+            "Mark the device as failed? The array runs without it until it is replaced.",
+            vec![
+                ("Array".to_string(), array.clone()),
+                ("Device".to_string(), device.clone()),
+            ],
+        ),
+        ConfirmationOperation::RemoveRaidMember { array, device } => (
+            "Confirm Remove Array Member",
+            "Remove the device from the array and erase its RAID superblock?",
+            vec![
+                ("Array".to_string(), array.clone()),
+                ("Device".to_string(), device.clone()),
+            ],
+        ),
+        ConfirmationOperation::StopRaid { array } => (
+            "Confirm Stop Array",
+            "Stop the array? Its devices stay intact and it can be assembled again.",
+            vec![("Array".to_string(), array.clone())],
+        ),
+        _ => return,
+    };
+    app.confirmation_dialog = crate::app::ConfirmationDialog {
+        show_dialog: true,
+        title: title.to_string(),
+        message: message.to_string(),
+        details,
+        selected: 0,
+        commands: CommandPreview::None,
+        operation,
+    };
+}
+
+async fn handle_confirmation_dialog(
+    key_event: KeyEvent,
+    app: &mut App,
+    sender: UnboundedSender<Event>,
+) -> AppResult<()> {
+    use crate::app::ConfirmationOperation;
+
+    match key_event.code {
+        KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('Q') => {
+            app.confirmation_dialog.show_dialog = false;
+            app.confirmation_dialog.operation = ConfirmationOperation::None;
+            app.confirmation_dialog.selected = 0;
+        }
+        KeyCode::Left | KeyCode::Right | KeyCode::Char('h') | KeyCode::Char('l') => {
+            app.confirmation_dialog.selected = 1 - app.confirmation_dialog.selected;
+        }
+        KeyCode::Enter => {
+            if app.confirmation_dialog.selected == 1 {
+                let operation = app.confirmation_dialog.operation.clone();
+                app.confirmation_dialog.show_dialog = false;
+                app.confirmation_dialog.operation = ConfirmationOperation::None;
+                app.confirmation_dialog.selected = 0;
+
+                let request = operation.request(&app.passphrase_dialog.first_passphrase);
+                match operation {
+                    ConfirmationOperation::EncryptPartition { .. } => {
+                        app.passphrase_dialog.first_passphrase.clear();
+                        app.passphrase_dialog.filesystem_type = None;
+                    }
+                    ConfirmationOperation::UnlockLuksDevice { .. } => {
+                        app.passphrase_dialog.first_passphrase.clear();
+                    }
+                    ConfirmationOperation::ApplyQueue => {
+                        spawn_queue_apply(app, &sender);
+                    }
+                    ConfirmationOperation::CheckFilesystem { device, repair } => {
+                        spawn_filesystem_check(app, &sender, device, repair);
+                        return Ok(());
+                    }
+                    // New members start a resync worth following.
+                    ConfirmationOperation::CreateRaid { name: array, .. }
+                    | ConfirmationOperation::AddRaidMember { array, .. } => {
+                        app.raid.follow = Some(array);
+                    }
+                    _ => {}
+                }
+                if let Some(req) = request {
+                    submit_request(app, &sender, req);
+                }
+            } else {
+                app.confirmation_dialog.show_dialog = false;
+                app.confirmation_dialog.operation = ConfirmationOperation::None;
+                app.confirmation_dialog.selected = 0;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+async fn handle_resize_dialog(
+    key_event: KeyEvent,
+    app: &mut App,
+    _sender: UnboundedSender<Event>,
+) -> AppResult<()> {
+    use crate::app::ConfirmationOperation;
+    use crate::utils::format_size;
+
+    match key_event.code {
+        KeyCode::Esc => {
+            app.resize_dialog.show_dialog = false;
+        }
+        KeyCode::Enter => {
+            if let Some(partition) = app.selected_partition() {
+                let part_name = partition.name.clone();
+                let current_size = partition.size;
+                let current_size_str = format_size(current_size, app.size_units);
+                let new_size_str = app.resize_dialog.size_input.value().to_string();
+
+                let Some(Ok(new_size)) = app.resize_preview() else {
+                    return Ok(());
+                };
+
+                let filesystem = partition
+                    .filesystem
+                    .clone()
+                    .unwrap_or_else(|| "none".to_string());
+
+                app.resize_dialog.show_dialog = false;
+
+                app.confirmation_dialog = crate::app::ConfirmationDialog {
+                    show_dialog: true,
+                    title: "Confirm Resize Partition".to_string(),
+                    message: "Resize partition and filesystem to new size?".to_string(),
+                    details: vec![
+                        ("Partition".to_string(), part_name.clone()),
+                        ("Current Size".to_string(), current_size_str),
+                        (
+                            "New Size".to_string(),
+                            format!("{} ({})", format_size(new_size, app.size_units), new_size_str),
+                        ),
+                        ("Filesystem".to_string(), filesystem),
+                        ("Alignment".to_string(), app.alignment.to_string()),
+                    ],
+                    selected: 0,
+                    commands: CommandPreview::None,
+                    operation: ConfirmationOperation::ResizePartition {
+                        partition: part_name,
+                        new_size: new_size_str,
+                        alignment: app.alignment,
+                    },
+                };
+            }
+        }
+        _ => {
+            app.resize_dialog
+                .size_input
+                .handle_event(&crossterm::event::Event::Key(key_event));
+        }
+    }
+    Ok(())
+}
+
+fn handle_part_type_dialog(key_event: KeyEvent, app: &mut App, sender: &UnboundedSender<Event>) {
+    use crate::app::{ConfirmationOperation, TypeDialogField};
+
+    let dialog = &mut app.part_type_dialog;
+    match key_event.code {
+        KeyCode::Esc => {
+            dialog.show_dialog = false;
+        }
+        KeyCode::Tab | KeyCode::BackTab => {
+            let fields = if dialog.gpt {
+                vec![TypeDialogField::Type, TypeDialogField::Name, TypeDialogField::Flags]
+            } else {
+                vec![TypeDialogField::Type, TypeDialogField::Flags]
+            };
+            let i = fields.iter().position(|f| *f == dialog.focus).unwrap_or(0);
+            let next = if key_event.code == KeyCode::Tab {
+                (i + 1) % fields.len()
+            } else {
+                (i + fields.len() - 1) % fields.len()
+            };
+            dialog.focus = fields[next];
+        }
+        KeyCode::Char('j') | KeyCode::Down if dialog.focus != TypeDialogField::Name => {
+            let (state, len) = match dialog.focus {
+                TypeDialogField::Type => (&mut dialog.type_state, dialog.types.len()),
+                _ => (&mut dialog.flag_state, dialog.flags.len()),
+            };
+            let next = state.selected().map(|i| (i + 1).min(len.saturating_sub(1))).unwrap_or(0);
+            state.select(Some(next));
+        }
+        KeyCode::Char('k') | KeyCode::Up if dialog.focus != TypeDialogField::Name => {
+            let state = match dialog.focus {
+                TypeDialogField::Type => &mut dialog.type_state,
+                _ => &mut dialog.flag_state,
+            };
+            let previous = state.selected().map(|i| i.saturating_sub(1)).unwrap_or(0);
+            state.select(Some(previous));
+        }
+        KeyCode::Char(' ') if dialog.focus == TypeDialogField::Flags => {
+            if let Some(i) = dialog.flag_state.selected()
+                && let Some((_, enabled)) = dialog.flags.get_mut(i)
+            {
+                *enabled = !*enabled;
+            }
+        }
+        KeyCode::Enter => {
+            let (part_type, name, flags) = dialog.changes();
+            let partition = dialog.partition.clone();
+            dialog.show_dialog = false;
+
+            if part_type.is_none() && name.is_none() && flags.is_empty() {
+                let _ = Notification::send(
+                    format!("No changes to {}", partition),
+                    NotificationLevel::Info,
+                    sender,
+                );
+                return;
+            }
+
+            let mut details = vec![("Partition".to_string(), partition.clone())];
+            if let Some(part_type) = &part_type {
+                details.push(("New Type".to_string(), parttype::type_name(part_type)));
+            }
+            if let Some(name) = &name {
+                let name = if name.is_empty() { "(none)".to_string() } else { name.clone() };
+                details.push(("New Name".to_string(), name));
+            }
+            for change in &flags {
+                let state = if change.enabled { "set" } else { "clear" };
+                details.push((format!("Flag {}", change.flag), state.to_string()));
+            }
+
+            app.confirmation_dialog = crate::app::ConfirmationDialog {
+                show_dialog: true,
+                title: "Confirm Partition Change".to_string(),
+                message: "Change the partition entry? The data is not touched.".to_string(),
+                details,
+                selected: 0,
+                commands: CommandPreview::None,
+                operation: ConfirmationOperation::ModifyPartition {
+                    partition,
+                    part_type,
+                    name,
+                    flags,
+                },
+            };
+        }
+        _ => {
+            if dialog.focus == TypeDialogField::Name {
+                dialog
+                    .name_input
+                    .handle_event(&crossterm::event::Event::Key(key_event));
+            }
+        }
+    }
+}
+
+fn handle_swap_file_dialog(key_event: KeyEvent, app: &mut App, sender: &UnboundedSender<Event>) {
+    use crate::app::{ConfirmationOperation, SwapFileField};
+    use crate::utils::{Amount, format_size};
+
+    let dialog = &mut app.swap_file_dialog;
+    match key_event.code {
+        KeyCode::Esc => {
+            dialog.show_dialog = false;
+        }
+        KeyCode::Tab | KeyCode::BackTab => {
+            dialog.focus = match dialog.focus {
+                SwapFileField::Path => SwapFileField::Size,
+                SwapFileField::Size => SwapFileField::Path,
+            };
+        }
+        KeyCode::Enter => {
+            if let Some(swap) = dialog.selected_active() {
+                let request = Request::SwapOff { target: swap.path.clone() };
+                dialog.show_dialog = false;
+                spawn_helper_operation(app, sender, request);
+                return;
+            }
+
+            let path = dialog.path_input.value().trim().to_string();
+            let size = dialog.size_input.value().trim().to_string();
+            let bytes = match size.parse::<Amount>() {
+                Ok(Amount::Bytes(bytes)) if bytes > 0 => bytes,
+                _ => {
+                    let _ = Notification::send(
+                        "Enter a swap file size such as 2G".to_string(),
+                        NotificationLevel::Warning,
+                        sender,
+                    );
+                    return;
+                }
+            };
+            let mount_point = dialog.mount_point.clone();
+            dialog.show_dialog = false;
+
+            app.confirmation_dialog = crate::app::ConfirmationDialog {
+                show_dialog: true,
+                title: "Confirm Create Swap File".to_string(),
+                message: "Create and enable a swap file?".to_string(),
+                details: vec![
+                    ("Path".to_string(), path.clone()),
+                    ("Size".to_string(), format_size(bytes, app.size_units)),
+                    ("Filesystem".to_string(), mount_point),
+                ],
+                selected: 0,
+                commands: CommandPreview::None,
+                operation: ConfirmationOperation::CreateSwapFile { path, size },
+            };
+        }
+        _ => {
+            let input = match dialog.focus {
+                SwapFileField::Path => &mut dialog.path_input,
+                SwapFileField::Size => &mut dialog.size_input,
+            };
+            input.handle_event(&crossterm::event::Event::Key(key_event));
+        }
+    }
+}
+
+fn handle_mount_dialog(key_event: KeyEvent, app: &mut App, sender: &UnboundedSender<Event>) {
+    use crate::app::{MountField, MountToggle};
+
+    let dialog = &mut app.mount_dialog;
+    match key_event.code {
+        KeyCode::Esc => {
+            dialog.show_dialog = false;
+        }
+        KeyCode::Tab | KeyCode::BackTab => {
+            let fields = dialog.fields();
+            let i = fields.iter().position(|f| *f == dialog.focus).unwrap_or(0);
+            let next = if key_event.code == KeyCode::Tab {
+                (i + 1) % fields.len()
+            } else {
+                (i + fields.len() - 1) % fields.len()
+            };
+            dialog.focus = fields[next];
+        }
+        KeyCode::Char('j') | KeyCode::Down if dialog.focus == MountField::Options => {
+            let last = dialog.toggles.len().saturating_sub(1);
+            let next = dialog.toggle_state.selected().map(|i| (i + 1).min(last)).unwrap_or(0);
+            dialog.toggle_state.select(Some(next));
+        }
+        KeyCode::Char('k') | KeyCode::Up if dialog.focus == MountField::Options => {
+            let previous = dialog.toggle_state.selected().map(|i| i.saturating_sub(1)).unwrap_or(0);
+            dialog.toggle_state.select(Some(previous));
+        }
+        KeyCode::Char(' ') if dialog.focus == MountField::Options => {
+            if let Some(i) = dialog.toggle_state.selected()
+                && let Some((_, enabled)) = dialog.toggles.get_mut(i)
+            {
+                *enabled = !*enabled;
+            }
+        }
+        KeyCode::Enter => {
+            let request = match dialog.request() {
+                Ok(request) => request,
+                Err(e) => {
+                    let _ = Notification::send(e.to_string(), NotificationLevel::Warning, sender);
+                    return;
+                }
+            };
+            dialog.show_dialog = false;
+
+            // Remembered settings are saved, or forgotten, even if the
+            // mount then fails.
+            if let Some(uuid) = dialog.uuid.clone() {
+                let defaults = dialog.toggled(MountToggle::Remember).then(|| dialog.defaults());
+                if defaults.as_ref() != app.mount_defaults.get(&uuid) {
+                    match crate::config::save_mount_defaults(&uuid, defaults.as_ref()) {
+                        Ok(()) => match defaults {
+                            Some(defaults) => {
+                                app.mount_defaults.insert(uuid, defaults);
+                            }
+                            None => {
+                                app.mount_defaults.remove(&uuid);
+                            }
+                        },
+                        Err(e) => {
+                            let _ = Notification::send(
+                                format!("Could not save mount settings: {:#}", e),
+                                NotificationLevel::Warning,
+                                sender,
+                            );
+                        }
+                    }
+                }
+            }
+            spawn_helper_operation(app, sender, request);
+        }
+        _ => {
+            let input = match dialog.focus {
+                MountField::Target => &mut dialog.target_input,
+                MountField::Compress => &mut dialog.compress_input,
+                MountField::Subvol => &mut dialog.subvol_input,
+                MountField::Options => return,
+            };
+            input.handle_event(&crossterm::event::Event::Key(key_event));
+        }
+    }
+}
+
+fn handle_boot_table_dialog(key_event: KeyEvent, app: &mut App, sender: &UnboundedSender<Event>) {
+    use crate::app::{BootTable, BootTableDialogState, BootTableField};
+
+    let dialog = &mut app.boot_table_dialog;
+    let ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+    match key_event.code {
+        KeyCode::Esc => {
+            dialog.show_dialog = false;
+        }
+        KeyCode::Tab | KeyCode::BackTab => {
+            let fields = dialog.fields();
+            let i = fields.iter().position(|f| *f == dialog.focus).unwrap_or(0);
+            let next = if key_event.code == KeyCode::Tab {
+                (i + 1) % fields.len()
+            } else {
+                (i + fields.len() - 1) % fields.len()
+            };
+            dialog.focus = fields[next];
+        }
+        KeyCode::Char('t') if ctrl && dialog.switchable => {
+            let table = match dialog.table {
+                BootTable::Fstab => BootTable::Crypttab,
+                BootTable::Crypttab => BootTable::Fstab,
+            };
+            if let Some(partition) = app.selected_partition() {
+                let remembered = partition.uuid.as_ref().and_then(|uuid| app.mount_defaults.get(uuid));
+                app.boot_table_dialog = BootTableDialogState::open(partition, &app.boot_tables, table, remembered);
+            }
+        }
+        KeyCode::Char('d') if ctrl => {
+            if let Some(request) = dialog.removal() {
+                dialog.show_dialog = false;
+                spawn_helper_operation(app, sender, request);
+            }
+        }
+        KeyCode::Enter => match dialog.request() {
+            Ok(request) => {
+                dialog.show_dialog = false;
+                spawn_helper_operation(app, sender, request);
+            }
+            Err(e) => {
+                let _ = Notification::send(e.to_string(), NotificationLevel::Warning, sender);
+            }
+        },
+        _ => {
+            let input = match dialog.focus {
+                BootTableField::Target => &mut dialog.target_input,
+                BootTableField::Options => &mut dialog.options_input,
+                BootTableField::Pass => &mut dialog.pass_input,
+            };
+            input.handle_event(&crossterm::event::Event::Key(key_event));
+        }
+    }
+}
+
+fn handle_label_dialog(key_event: KeyEvent, app: &mut App, sender: &UnboundedSender<Event>) {
+    let dialog = &mut app.label_dialog;
+    match key_event.code {
+        KeyCode::Esc => {
+            dialog.show_dialog = false;
+        }
+        KeyCode::Enter => {
+            if let Some(error) = dialog.error() {
+                let _ = Notification::send(error, NotificationLevel::Warning, sender);
+                return;
+            }
+            let request = Request::SetLabel {
+                device: dialog.device.clone(),
+                label: dialog.input.value().to_string(),
+            };
+            dialog.show_dialog = false;
+            spawn_helper_operation(app, sender, request);
+        }
+        _ => {
+            dialog.input.handle_event(&crossterm::event::Event::Key(key_event));
         }
-        _ => {
-            app.resize_dialog
-                .size_input
-                .handle_event(&crossterm::event::Event::Key(key_event));
-        }
     }
-    Ok(())
 }
 
 async fn handle_passphrase_dialog(
@@ -877,6 +2872,7 @@ async fn handle_passphrase_dialog(
                                 ("Filesystem".to_string(), fs_type.to_string()),
                             ],
                             selected: 0,
+                            commands: CommandPreview::None,
                             operation: ConfirmationOperation::EncryptPartition {
                                 partition: device,
                                 fs_type,
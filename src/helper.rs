@@ -0,0 +1,3116 @@
+use crate::btrfs::{self, SubvolumeList};
+use crate::fsck::{CheckReport, CheckStatus, Checker};
+use crate::fslabel;
+use crate::fstab::{self, CrypttabEntry, FstabEntry};
+use crate::layout::{self, Alignment, DiskGeometry};
+use crate::lvm::{self, LvmReport};
+use crate::mount::MountOptions;
+use crate::parttype::{self, FlagChange, PartitionFlag};
+use crate::protocol::{self, Request, Response, SysfsBlock};
+use crate::raid::{self, RaidArray, RaidReport};
+use crate::runner::CommandRunner;
+use crate::smart::SelfTestKind;
+use crate::sysfs::unescape_udev;
+use crate::utils::{Amount, SizeSpec, format_bytes};
+use anyhow::{Context, Result, anyhow};
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Writes JSON-line responses back to the TUI.
+pub struct ResponseWriter {
+	out: Box<dyn Write + Send>,
+}
+
+impl ResponseWriter {
+	pub fn new(out: Box<dyn Write + Send>) -> Self {
+		Self { out }
+	}
+
+	pub fn stdout() -> Self {
+		Self::new(Box::new(std::io::stdout()))
+	}
+
+	pub fn send(&mut self, response: Response) -> Result<()> {
+		let json = serde_json::to_string(&response)?;
+		writeln!(self.out, "{}", json)?;
+		self.out.flush()?;
+		Ok(())
+	}
+
+	pub fn notify(&mut self, level: &str, message: impl Into<String>) -> Result<()> {
+		self.send(Response::notification(level, message))
+	}
+
+	pub fn progress_start(&mut self, message: impl Into<String>) -> Result<()> {
+		self.send(Response::progress_start(message))
+	}
+
+	pub fn progress_end(&mut self) -> Result<()> {
+		self.send(Response::progress_end())
+	}
+}
+
+pub(crate) fn validate_device_name(name: &str) -> Result<()> {
+	if name.is_empty() {
+		return Err(anyhow!("Invalid device name: empty"));
+	}
+	if name.contains("..") || name.contains('/') {
+		return Err(anyhow!("Invalid device name: contains path traversal characters"));
+	}
+	if !name.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_') {
+		return Err(anyhow!("Invalid device name: contains illegal characters"));
+	}
+	if name.len() > 32 {
+		return Err(anyhow!("Invalid device name: too long"));
+	}
+	Ok(())
+}
+
+fn get_device_path(runner: &dyn CommandRunner, device_name: &str) -> String {
+	if device_name.starts_with("luks-") {
+		format!("/dev/mapper/{}", device_name)
+	} else {
+		let mapper_path = format!("/dev/mapper/{}", device_name);
+		if runner.path_exists(&mapper_path) {
+			mapper_path
+		} else {
+			format!("/dev/{}", device_name)
+		}
+	}
+}
+
+async fn is_mounted(runner: &dyn CommandRunner, partition: &str) -> Result<bool> {
+	let device_path = get_device_path(runner, partition);
+	let output = runner
+		.output("findmnt", &["-n", &device_path])
+		.await
+		.context("Failed to check mount status")?;
+	Ok(output.status.success())
+}
+
+async fn get_device_mount_point(runner: &dyn CommandRunner, device_path: &str) -> Option<String> {
+	let output = runner.output("findmnt", &["-n", "-o", "TARGET", device_path]).await.ok()?;
+	if output.status.success() {
+		let mount_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
+		if !mount_str.is_empty() {
+			return Some(mount_str);
+		}
+	}
+	None
+}
+
+/// Checks a mount point given by the user. /run is only allowed under
+/// /run/media, where desktops mount removable drives.
+pub(crate) fn validate_mount_target(path: &str) -> Result<()> {
+	if !path.starts_with('/') || path == "/" {
+		return Err(anyhow!("Invalid mount point: must be an absolute directory path other than /"));
+	}
+	if path.split('/').any(|component| component == "..") {
+		return Err(anyhow!("Invalid mount point: contains path traversal characters"));
+	}
+	if path.chars().any(char::is_control) {
+		return Err(anyhow!("Invalid mount point: contains illegal characters"));
+	}
+	if path.len() > 4096 {
+		return Err(anyhow!("Invalid mount point: too long"));
+	}
+	let virtual_fs = ["/dev", "/proc", "/sys", "/run"].iter().any(|dir| is_under(path, dir));
+	if virtual_fs && !is_under(path, "/run/media") {
+		return Err(anyhow!("Invalid mount point: {} is not on a regular filesystem", path));
+	}
+	Ok(())
+}
+
+fn is_under(path: &str, dir: &str) -> bool {
+	path == dir || path.strip_prefix(dir).is_some_and(|rest| rest.starts_with('/'))
+}
+
+async fn mount_partition(
+	runner: &dyn CommandRunner,
+	device: &str,
+	target: Option<&str>,
+	options: &MountOptions,
+	writer: &mut ResponseWriter,
+) -> Result<()> {
+	validate_device_name(device)?;
+
+	if is_mounted(runner, device).await? {
+		writer.notify("warning", format!("{} already mounted", device))?;
+		return Ok(());
+	}
+
+	let device_path = get_device_path(runner, device);
+	if !runner.path_exists(&device_path) {
+		return Err(anyhow!("Device {} does not exist", device_path));
+	}
+
+	let mount_point = match target {
+		Some(target) => {
+			let target = target.trim_end_matches('/');
+			validate_mount_target(target)?;
+			target.to_string()
+		}
+		None => format!("/mnt/{}", device),
+	};
+	let fs_type = probe_value(runner, &device_path, "TYPE").await?;
+	let mount_options = options.mount_arg(&fs_type)?;
+
+	// Mounting on top of another mount or of existing files hides them.
+	let existing = runner.path_exists(&mount_point);
+	if existing {
+		let output = runner.output("findmnt", &["-n", "--mountpoint", &mount_point]).await?;
+		if output.status.success() {
+			return Err(anyhow!("{} is already a mount point", mount_point));
+		}
+		let output = runner.output("ls", &["-A", &mount_point]).await?;
+		if !output.stdout.is_empty() {
+			return Err(anyhow!("{} is not empty", mount_point));
+		}
+	} else {
+		run_checked(runner, "mkdir", &["-p", &mount_point]).await?;
+	}
+
+	writer.progress_start(format!("Mounting {}...", device))?;
+
+	let mut args = Vec::new();
+	if let Some(mount_options) = &mount_options {
+		args.extend(["-o", mount_options.as_str()]);
+	}
+	args.extend([device_path.as_str(), mount_point.as_str()]);
+	let output = runner.output("mount", &args).await.context("Failed to execute mount")?;
+
+	writer.progress_end()?;
+
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		if !existing {
+			let _ = runner.output("rmdir", &[&mount_point]).await;
+		}
+		return Err(anyhow!("Mount failed: {}", err));
+	}
+
+	if options.is_default() {
+		writer.notify("info", format!("Mounted {} at {}", device, mount_point))?;
+	} else {
+		writer.notify("info", format!("Mounted {} at {} ({})", device, mount_point, options.summary()))?;
+	}
+	Ok(())
+}
+
+async fn unmount_partition(runner: &dyn CommandRunner, device: &str, writer: &mut ResponseWriter) -> Result<()> {
+	validate_device_name(device)?;
+
+	if !is_mounted(runner, device).await? {
+		writer.notify("warning", format!("{} not mounted", device))?;
+		return Ok(());
+	}
+
+	let device_path = get_device_path(runner, device);
+	let actual_mount_point = get_device_mount_point(runner, &device_path).await;
+
+	writer.progress_start(format!("Unmounting {}...", device))?;
+
+	let output = runner
+		.output("umount", &[&device_path])
+		.await
+		.context("Failed to execute umount")?;
+
+	writer.progress_end()?;
+
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		if err.contains("target is busy") || err.contains("device is busy") {
+			writer.notify("warning", format!("Device {} is busy. Attempting lazy unmount...", device))?;
+			let lazy_output = runner.output("umount", &["-l", &device_path]).await?;
+			if !lazy_output.status.success() {
+				return Err(anyhow!("Lazy unmount failed"));
+			}
+			if let Some(ref mp) = actual_mount_point
+				&& mp.starts_with("/mnt/")
+			{
+				let _ = runner.output("rmdir", &[mp]).await;
+			}
+			writer.notify("warning", format!("Lazy unmount initiated for {}. Device still in use.", device))?;
+			return Ok(());
+		}
+		return Err(anyhow!("Unmount failed: {}", err));
+	}
+
+	if let Some(ref mp) = actual_mount_point
+		&& mp.starts_with("/mnt/")
+	{
+		let _ = runner.output("rmdir", &[mp]).await;
+	}
+
+	writer.notify("info", format!("Unmounted {}", device))?;
+	Ok(())
+}
+
+/// Checks a path for a swap file given by the user. Swap files go on a
+/// mounted filesystem, never into the kernel's virtual ones.
+pub(crate) fn validate_file_path(path: &str) -> Result<()> {
+	if !path.starts_with('/') || path.ends_with('/') {
+		return Err(anyhow!("Invalid path: must be an absolute file path"));
+	}
+	if path.split('/').any(|component| component == "..") {
+		return Err(anyhow!("Invalid path: contains path traversal characters"));
+	}
+	if path.chars().any(char::is_control) {
+		return Err(anyhow!("Invalid path: contains illegal characters"));
+	}
+	if path.len() > 4096 {
+		return Err(anyhow!("Invalid path: too long"));
+	}
+	if ["/dev/", "/proc/", "/sys/", "/run/"].iter().any(|dir| path.starts_with(dir)) {
+		return Err(anyhow!("Invalid path: {} is not on a regular filesystem", path));
+	}
+	Ok(())
+}
+
+/// Paths of the active swap areas, as the kernel lists them: device mapper
+/// devices appear as /dev/dm-N.
+async fn active_swaps(runner: &dyn CommandRunner) -> Vec<String> {
+	runner
+		.output("swapon", &["--show=NAME", "--noheadings", "--raw"])
+		.await
+		.map(|output| String::from_utf8_lossy(&output.stdout).lines().map(unescape_udev).collect())
+		.unwrap_or_default()
+}
+
+async fn is_active_swap(runner: &dyn CommandRunner, path: &str) -> bool {
+	let swaps = active_swaps(runner).await;
+	if swaps.is_empty() {
+		return false;
+	}
+	let canonical = match path.starts_with("/dev/mapper/") {
+		true => runner
+			.output("readlink", &["-f", path])
+			.await
+			.ok()
+			.map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string()),
+		false => None,
+	};
+	swaps.iter().any(|swap| swap == path || Some(swap) == canonical.as_ref())
+}
+
+/// Swap requests name either a device or the absolute path of a swap file.
+fn swap_target_path(runner: &dyn CommandRunner, target: &str) -> Result<String> {
+	if target.starts_with('/') {
+		validate_file_path(target)?;
+		Ok(target.to_string())
+	} else {
+		validate_device_name(target)?;
+		Ok(get_device_path(runner, target))
+	}
+}
+
+async fn swap_on(runner: &dyn CommandRunner, target: &str, writer: &mut ResponseWriter) -> Result<()> {
+	let path = swap_target_path(runner, target)?;
+
+	if is_active_swap(runner, &path).await {
+		writer.notify("warning", format!("Swap on {} is already enabled", target))?;
+		return Ok(());
+	}
+	if !runner.path_exists(&path) {
+		return Err(anyhow!("{} does not exist", path));
+	}
+
+	writer.progress_start(format!("Enabling swap on {}...", target))?;
+
+	let output = runner.output("swapon", &[&path]).await.context("Failed to execute swapon")?;
+
+	writer.progress_end()?;
+
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("Failed to enable swap: {}", err));
+	}
+
+	writer.notify("info", format!("Enabled swap on {}", target))?;
+	Ok(())
+}
+
+async fn swap_off(runner: &dyn CommandRunner, target: &str, writer: &mut ResponseWriter) -> Result<()> {
+	let path = swap_target_path(runner, target)?;
+
+	if !is_active_swap(runner, &path).await {
+		writer.notify("warning", format!("{} is not an active swap area", target))?;
+		return Ok(());
+	}
+
+	// Pages in use move back to memory, which can take a while.
+	writer.progress_start(format!("Disabling swap on {}...", target))?;
+
+	let output = runner.output("swapoff", &[&path]).await.context("Failed to execute swapoff")?;
+
+	writer.progress_end()?;
+
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("Failed to disable swap: {}", err));
+	}
+
+	writer.notify("info", format!("Disabled swap on {}", target))?;
+	Ok(())
+}
+
+/// Smallest swap file mkswap accepts with room to spare.
+const MIN_SWAP_FILE: u64 = 1024 * 1024;
+
+async fn run_checked(runner: &dyn CommandRunner, program: &str, args: &[&str]) -> Result<()> {
+	let output = runner
+		.output(program, args)
+		.await
+		.with_context(|| format!("Failed to execute {}", program))?;
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("{} failed: {}", program, err.trim()));
+	}
+	Ok(())
+}
+
+async fn create_swap_file(runner: &dyn CommandRunner, path: &str, size: &str, writer: &mut ResponseWriter) -> Result<()> {
+	validate_file_path(path)?;
+	if runner.path_exists(path) {
+		return Err(anyhow!("{} already exists", path));
+	}
+
+	// Swap is used in whole pages.
+	let size = match size.parse::<Amount>()? {
+		Amount::Bytes(bytes) => bytes / 4096 * 4096,
+		Amount::Sectors(_) => return Err(anyhow!("Swap file sizes are given in bytes, not sectors")),
+	};
+	if size < MIN_SWAP_FILE {
+		return Err(anyhow!("Swap file must be at least 1 MiB"));
+	}
+	let size_arg = size.to_string();
+
+	let dir = match path.rsplit_once('/') {
+		Some(("", _)) | None => "/",
+		Some((dir, _)) => dir,
+	};
+	let output = runner
+		.output("findmnt", &["-n", "-o", "FSTYPE", "--target", dir])
+		.await
+		.context("Failed to execute findmnt")?;
+	let fs_type = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+	writer.progress_start(format!("Creating swap file {}...", path))?;
+
+	// btrfs needs a file without copy-on-write or compression, which its
+	// own command sets up.
+	let result = async {
+		if fs_type == "btrfs" {
+			run_checked(runner, "btrfs", &["filesystem", "mkswapfile", "--size", &size_arg, path]).await?;
+		} else {
+			run_checked(runner, "fallocate", &["-l", &size_arg, path]).await?;
+			run_checked(runner, "chmod", &["600", path]).await?;
+			run_checked(runner, "mkswap", &[path]).await?;
+		}
+		run_checked(runner, "swapon", &[path]).await
+	}
+	.await;
+
+	writer.progress_end()?;
+
+	if let Err(e) = result {
+		let _ = runner.output("rm", &["-f", path]).await;
+		return Err(e);
+	}
+
+	writer.notify("info", format!("Created and enabled swap file {} ({})", path, format_bytes(size)))?;
+	Ok(())
+}
+
+async fn format_partition(
+	runner: &dyn CommandRunner,
+	device: &str,
+	fs_type: &str,
+	label: Option<&str>,
+	writer: &mut ResponseWriter,
+) -> Result<()> {
+	validate_device_name(device)?;
+	if let Some(label) = label {
+		fslabel::validate_label(fs_type, label)?;
+	}
+
+	let device_path = get_device_path(runner, device);
+	if !runner.path_exists(&device_path) {
+		return Err(anyhow!("Device {} does not exist", device_path));
+	}
+	ensure_detached(runner, device, writer).await?;
+
+	let cmd = match fs_type {
+		"ext4" => "mkfs.ext4",
+		"fat32" | "vfat" => "mkfs.fat",
+		"ntfs" => "mkfs.ntfs",
+		"exfat" => "mkfs.exfat",
+		"btrfs" => "mkfs.btrfs",
+		"xfs" => "mkfs.xfs",
+		"swap" => "mkswap",
+		_ => return Err(anyhow!("Unsupported filesystem type: {}", fs_type)),
+	};
+
+	if is_active_swap(runner, &device_path).await {
+		swap_off(runner, device, writer).await?;
+	}
+
+	let which_output = runner.output("which", &[cmd]).await?;
+	if !which_output.status.success() {
+		return Err(anyhow!("{} not found. Install the appropriate package.", cmd));
+	}
+
+	writer.progress_start(format!("Formatting {} as {}...", device, fs_type))?;
+
+	let mut args: Vec<&str> = match fs_type {
+		"fat32" | "vfat" => vec!["-F", "32"],
+		"ntfs" => vec!["-f", "-Q"],
+		"btrfs" | "xfs" => vec!["-f"],
+		_ => vec![],
+	};
+	args.extend(fslabel::mkfs_label_args(fs_type, label));
+	args.push(&device_path);
+
+	let output = runner.output(cmd, &args).await.context("Failed to execute mkfs")?;
+
+	writer.progress_end()?;
+
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("Format failed: {}", err));
+	}
+
+	writer.notify("info", format!("Formatted {} as {}", device, fs_type))?;
+	Ok(())
+}
+
+/// Reads a tag such as TYPE or UUID of the device from blkid. Empty when
+/// the device has no such tag.
+async fn probe_value(runner: &dyn CommandRunner, device_path: &str, tag: &str) -> Result<String> {
+	let output = runner
+		.output("blkid", &["-p", "-o", "value", "-s", tag, device_path])
+		.await
+		.context("Failed to execute blkid")?;
+	Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Runs the checker of the device's filesystem, streaming its output, and
+/// reports what its exit code means.
+async fn check_filesystem(runner: &dyn CommandRunner, device: &str, repair: bool, writer: &mut ResponseWriter) -> Result<String> {
+	validate_device_name(device)?;
+
+	let device_path = get_device_path(runner, device);
+	if !runner.path_exists(&device_path) {
+		return Err(anyhow!("Device {} does not exist", device_path));
+	}
+	if is_mounted(runner, device).await? {
+		return Err(anyhow!("{} is mounted. Unmount it first", device));
+	}
+
+	let fs_type = probe_value(runner, &device_path, "TYPE").await?;
+	let checker = Checker::for_filesystem(&fs_type)?;
+	let mut args = checker.args(repair)?;
+	args.push(&device_path);
+
+	let which_output = runner.output("which", &[checker.program()]).await?;
+	if !which_output.status.success() {
+		return Err(anyhow!("{} not found. Install the appropriate package.", checker.program()));
+	}
+
+	let mode = if repair { "Repairing" } else { "Checking" };
+	writer.progress_start(format!("{} {} ({})...", mode, device, fs_type))?;
+
+	// Lines that cannot be sent are dropped rather than failing the check.
+	let mut on_line = |line: &str| {
+		let _ = writer.send(Response::output(line));
+	};
+	let output = runner
+		.output_streaming(checker.program(), &args, &mut on_line)
+		.await
+		.with_context(|| format!("Failed to execute {}", checker.program()))?;
+
+	writer.progress_end()?;
+
+	let exit_code = output.status.code();
+	let (status, meaning) = checker.interpret(repair, exit_code);
+	let level = match status {
+		CheckStatus::Clean | CheckStatus::Corrected => "info",
+		CheckStatus::Errors => "warning",
+		CheckStatus::Failed => "error",
+	};
+	writer.notify(level, format!("{}: {}", device, meaning))?;
+
+	let report = CheckReport {
+		device: device.to_string(),
+		fs_type,
+		repair,
+		exit_code,
+		status,
+		meaning,
+	};
+	Ok(serde_json::to_string(&report)?)
+}
+
+/// Sets or clears the label of the filesystem on `device`. Only ext and
+/// btrfs can be relabelled while mounted.
+async fn set_label(runner: &dyn CommandRunner, device: &str, label: &str, writer: &mut ResponseWriter) -> Result<()> {
+	validate_device_name(device)?;
+
+	let device_path = get_device_path(runner, device);
+	if !runner.path_exists(&device_path) {
+		return Err(anyhow!("Device {} does not exist", device_path));
+	}
+	let fs_type = probe_value(runner, &device_path, "TYPE").await?;
+	fslabel::validate_label(&fs_type, label)?;
+
+	// btrfs takes the mount point of a mounted filesystem.
+	let mount_point = get_device_mount_point(runner, &device_path).await;
+	let target = match mount_point {
+		Some(mount_point) if fs_type == "btrfs" => mount_point,
+		Some(_) if fslabel::can_relabel_mounted(&fs_type) => device_path,
+		Some(_) => return Err(anyhow!("{} is mounted. Unmount it before changing the {} label", device, fs_type)),
+		None => device_path,
+	};
+
+	let (program, args) = fslabel::label_command(&fs_type, &target, label)?;
+	let which_output = runner.output("which", &[program]).await?;
+	if !which_output.status.success() {
+		return Err(anyhow!("{} not found. Install the appropriate package.", program));
+	}
+	run_checked(runner, program, &args).await?;
+
+	if label.is_empty() {
+		writer.notify("info", format!("Cleared the label of {}", device))?;
+	} else {
+		writer.notify("info", format!("Labelled {} as \"{}\"", device, label))?;
+	}
+	Ok(())
+}
+
+/// Gives the filesystem on `device` a new random UUID, for clones whose
+/// UUID collides with the original.
+async fn regenerate_uuid(runner: &dyn CommandRunner, device: &str, writer: &mut ResponseWriter) -> Result<()> {
+	validate_device_name(device)?;
+
+	let device_path = get_device_path(runner, device);
+	if !runner.path_exists(&device_path) {
+		return Err(anyhow!("Device {} does not exist", device_path));
+	}
+	if is_mounted(runner, device).await? {
+		return Err(anyhow!("{} is mounted. Unmount it first", device));
+	}
+
+	let fs_type = probe_value(runner, &device_path, "TYPE").await?;
+	let (program, args) = fslabel::uuid_command(&fs_type, &device_path)?;
+	let which_output = runner.output("which", &[program]).await?;
+	if !which_output.status.success() {
+		return Err(anyhow!("{} not found. Install the appropriate package.", program));
+	}
+	let old_uuid = probe_value(runner, &device_path, "UUID").await?;
+
+	writer.progress_start(format!("Regenerating UUID of {}...", device))?;
+
+	// tune2fs refuses to rewrite checksummed metadata unless the
+	// filesystem was checked just before.
+	if fs_type.starts_with("ext") {
+		let output = runner.output("e2fsck", &["-f", "-p", &device_path]).await.context("Failed to execute e2fsck")?;
+		if output.status.code().is_none_or(|code| code > 1) {
+			writer.progress_end()?;
+			return Err(anyhow!("{} has errors. Check and repair it first", device));
+		}
+	}
+	let result = run_checked(runner, program, &args).await;
+
+	writer.progress_end()?;
+	result?;
+
+	let new_uuid = probe_value(runner, &device_path, "UUID").await?;
+	writer.notify(
+		"warning",
+		format!(
+			"UUID of {} changed from {} to {}. Update /etc/fstab and /etc/crypttab entries that use the old UUID",
+			device, old_uuid, new_uuid
+		),
+	)?;
+	Ok(())
+}
+
+/// Reads a boot table such as /etc/fstab. A missing table is empty.
+async fn read_table(runner: &dyn CommandRunner, path: &str) -> Result<String> {
+	if !runner.path_exists(path) {
+		return Ok(String::new());
+	}
+	let output = runner.output("cat", &[path]).await.with_context(|| format!("Failed to read {}", path))?;
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("Failed to read {}: {}", path, err.trim()));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Runs `findmnt --verify` on an fstab file and returns its report when it
+/// finds errors.
+async fn verify_fstab(runner: &dyn CommandRunner, path: &str) -> Result<Option<String>> {
+	let output = runner
+		.output("findmnt", &["--verify", "--tab-file", path])
+		.await
+		.context("Failed to execute findmnt")?;
+	if output.status.success() {
+		return Ok(None);
+	}
+	let report = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+	Ok(Some(report.trim().to_string()))
+}
+
+/// Refuses a new fstab in which `findmnt --verify` finds errors, unless the
+/// current one has errors already, which would block every change.
+async fn check_fstab(runner: &dyn CommandRunner, updated: &str, writer: &mut ResponseWriter) -> Result<()> {
+	let Some(report) = verify_fstab(runner, updated).await? else {
+		return Ok(());
+	};
+	if !runner.path_exists(fstab::FSTAB) || verify_fstab(runner, fstab::FSTAB).await?.is_none() {
+		return Err(anyhow!("findmnt --verify rejected the new {}: {}", fstab::FSTAB, report));
+	}
+	writer.notify("warning", format!("{} already had errors: {}", fstab::FSTAB, report))?;
+	Ok(())
+}
+
+/// Replaces a boot table and returns where the previous version was kept.
+/// The contents go to a temporary file first, where fstab is verified, so
+/// that the table is never left half written.
+async fn write_table(runner: &dyn CommandRunner, path: &str, contents: &str, writer: &mut ResponseWriter) -> Result<Option<String>> {
+	let temporary = format!("{}.disktui-new", path);
+	let output = runner
+		.output_with_stdin("tee", &[&temporary], contents.as_bytes())
+		.await
+		.context("Failed to execute tee")?;
+	let checked = match output.status.success() {
+		false => Err(anyhow!("Failed to write {}: {}", path, String::from_utf8_lossy(&output.stderr).trim())),
+		// The temporary file is only planned during a dry run.
+		true if path == fstab::FSTAB && !runner.is_dry_run() => check_fstab(runner, &temporary, writer).await,
+		true => Ok(()),
+	};
+	if let Err(e) = checked {
+		let _ = runner.output("rm", &["-f", &temporary]).await;
+		return Err(e);
+	}
+
+	let existing = runner.path_exists(path);
+	let backup = match existing {
+		true => {
+			let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or(0);
+			let backup = format!("{}.disktui-{}", path, stamp);
+			run_checked(runner, "cp", &["-p", path, &backup]).await?;
+			run_checked(runner, "chmod", &["--reference", path, &temporary]).await?;
+			Some(backup)
+		}
+		false => None,
+	};
+	run_checked(runner, "mv", &["-f", &temporary, path]).await?;
+
+	// systemd generates mount and cryptsetup units from the tables. Systems
+	// without it have nothing to reload.
+	let _ = runner.output("systemctl", &["daemon-reload"]).await;
+	Ok(backup)
+}
+
+fn backup_note(backup: Option<String>) -> String {
+	backup.map(|backup| format!(" (previous version kept as {})", backup)).unwrap_or_default()
+}
+
+/// Adds or replaces the fstab entry of the filesystem on `device`. Entries
+/// name the filesystem by UUID, which survives device renames.
+async fn set_fstab_entry(
+	runner: &dyn CommandRunner,
+	device: &str,
+	target: &str,
+	options: &str,
+	pass: Option<u32>,
+	writer: &mut ResponseWriter,
+) -> Result<()> {
+	validate_device_name(device)?;
+
+	let device_path = get_device_path(runner, device);
+	if !runner.path_exists(&device_path) {
+		return Err(anyhow!("Device {} does not exist", device_path));
+	}
+	let fs_type = probe_value(runner, &device_path, "TYPE").await?;
+	match fs_type.as_str() {
+		"" => return Err(anyhow!("No filesystem detected on {}", device)),
+		"crypto_LUKS" => {
+			return Err(anyhow!(
+				"{} is encrypted. Add it to {} and the unlocked device to {}",
+				device,
+				fstab::CRYPTTAB,
+				fstab::FSTAB
+			));
+		}
+		_ => {}
+	}
+	let uuid = probe_value(runner, &device_path, "UUID").await?;
+	if uuid.is_empty() {
+		return Err(anyhow!("{} has no filesystem UUID", device));
+	}
+	fstab::validate_options(options)?;
+
+	let swap = fs_type == "swap";
+	let target = match swap {
+		true => "none".to_string(),
+		false => {
+			let target = target.trim_end_matches('/');
+			validate_mount_target(target)?;
+			target.to_string()
+		}
+	};
+	let pass = match swap {
+		true => 0,
+		false => pass.unwrap_or_else(|| fstab::default_pass(&fs_type)),
+	};
+	if pass > 2 {
+		return Err(anyhow!("The pass number is 0, 1 or 2"));
+	}
+	let entry = FstabEntry {
+		source: format!("UUID={}", uuid),
+		target,
+		fs_type,
+		options: options.to_string(),
+		dump: 0,
+		pass,
+	};
+
+	let contents = read_table(runner, fstab::FSTAB).await?;
+	let by_uuid = format!("/dev/disk/by-uuid/{}", uuid);
+	// An entry for the same mount point whose device is gone was meant for
+	// this filesystem before it was reformatted, so it is replaced too.
+	let is_same = |existing: &FstabEntry| {
+		let path = fstab::source_path(&existing.source);
+		path.as_ref().is_some_and(|path| *path == by_uuid || *path == device_path)
+			|| (!swap && existing.target == entry.target && path.is_some_and(|path| !runner.path_exists(&path)))
+	};
+	let clash = fstab::parse_fstab(&contents)
+		.into_iter()
+		.find(|existing| !swap && existing.target == entry.target && !is_same(existing));
+	if let Some(other) = clash {
+		return Err(anyhow!("{} already mounts {} at {}", fstab::FSTAB, other.source, other.target));
+	}
+	let updated = fstab::update_fstab(&contents, is_same, Some(&entry));
+
+	// findmnt --verify counts a missing mount point as an error.
+	let created = !swap && !runner.path_exists(&entry.target);
+	if created {
+		run_checked(runner, "mkdir", &["-p", &entry.target]).await?;
+	}
+	let result = write_table(runner, fstab::FSTAB, &updated, writer).await;
+	if result.is_err() && created {
+		let _ = runner.output("rmdir", &[&entry.target]).await;
+	}
+	let backup = result?;
+
+	writer.notify(
+		"info",
+		format!("Added {} to {} at {}{}", device, fstab::FSTAB, entry.target, backup_note(backup)),
+	)?;
+	Ok(())
+}
+
+async fn remove_fstab_entry(runner: &dyn CommandRunner, source: &str, writer: &mut ResponseWriter) -> Result<()> {
+	let contents = read_table(runner, fstab::FSTAB).await?;
+	if !fstab::parse_fstab(&contents).iter().any(|entry| entry.source == source) {
+		return Err(anyhow!("{} has no entry for {}", fstab::FSTAB, source));
+	}
+	let updated = fstab::update_fstab(&contents, |entry| entry.source == source, None);
+	let backup = write_table(runner, fstab::FSTAB, &updated, writer).await?;
+
+	writer.notify("info", format!("Removed {} from {}{}", source, fstab::FSTAB, backup_note(backup)))?;
+	Ok(())
+}
+
+/// Adds or replaces the crypttab entry of a LUKS partition, named by its
+/// LUKS UUID. Without a key file the passphrase is asked for at boot.
+async fn set_crypttab_entry(
+	runner: &dyn CommandRunner,
+	device: &str,
+	name: &str,
+	options: &str,
+	writer: &mut ResponseWriter,
+) -> Result<()> {
+	validate_device_name(device)?;
+	validate_device_name(name)?;
+	if !options.is_empty() {
+		fstab::validate_options(options)?;
+	}
+
+	let device_path = format!("/dev/{}", device);
+	if !runner.path_exists(&device_path) {
+		return Err(anyhow!("Device {} does not exist", device_path));
+	}
+	if !is_luks_device(runner, device).await {
+		return Err(anyhow!("{} is not a LUKS device", device));
+	}
+	let uuid = probe_value(runner, &device_path, "UUID").await?;
+	if uuid.is_empty() {
+		return Err(anyhow!("{} has no LUKS UUID", device));
+	}
+	let entry = CrypttabEntry {
+		name: name.to_string(),
+		device: format!("UUID={}", uuid),
+		key_file: "none".to_string(),
+		options: options.to_string(),
+	};
+
+	let contents = read_table(runner, fstab::CRYPTTAB).await?;
+	let by_uuid = format!("/dev/disk/by-uuid/{}", uuid);
+	let is_same = |existing: &CrypttabEntry| {
+		let path = fstab::source_path(&existing.device);
+		path.as_ref().is_some_and(|path| *path == by_uuid || *path == device_path)
+			|| (existing.name == name && path.is_some_and(|path| !runner.path_exists(&path)))
+	};
+	let clash = fstab::parse_crypttab(&contents)
+		.into_iter()
+		.find(|existing| existing.name == name && !is_same(existing));
+	if let Some(other) = clash {
+		return Err(anyhow!("{} already unlocks {} as {}", fstab::CRYPTTAB, other.device, name));
+	}
+	let updated = fstab::update_crypttab(&contents, is_same, Some(&entry));
+	let backup = write_table(runner, fstab::CRYPTTAB, &updated, writer).await?;
+
+	writer.notify(
+		"info",
+		format!("Added {} to {} as {}{}", device, fstab::CRYPTTAB, name, backup_note(backup)),
+	)?;
+	Ok(())
+}
+
+async fn remove_crypttab_entry(runner: &dyn CommandRunner, name: &str, writer: &mut ResponseWriter) -> Result<()> {
+	validate_device_name(name)?;
+
+	let contents = read_table(runner, fstab::CRYPTTAB).await?;
+	if !fstab::parse_crypttab(&contents).iter().any(|entry| entry.name == name) {
+		return Err(anyhow!("{} has no entry named {}", fstab::CRYPTTAB, name));
+	}
+	let updated = fstab::update_crypttab(&contents, |entry| entry.name == name, None);
+	let backup = write_table(runner, fstab::CRYPTTAB, &updated, writer).await?;
+
+	writer.notify("info", format!("Removed {} from {}{}", name, fstab::CRYPTTAB, backup_note(backup)))?;
+	Ok(())
+}
+
+async fn start_self_test(runner: &dyn CommandRunner, disk: &str, test: SelfTestKind, writer: &mut ResponseWriter) -> Result<()> {
+	validate_device_name(disk)?;
+	let path = format!("/dev/{}", disk);
+	if !runner.path_exists(&path) {
+		return Err(anyhow!("{} does not exist", path));
+	}
+
+	let output = runner
+		.output("smartctl", &["-t", test.arg(), &path])
+		.await
+		.context("Failed to execute smartctl")?;
+	// smartctl explains on stdout, below its section header, why it could
+	// not start the test, such as another test still running.
+	if !output.status.success() {
+		let report = String::from_utf8_lossy(&output.stdout);
+		let reason = report
+			.lines()
+			.skip_while(|line| !line.starts_with("==="))
+			.skip(1)
+			.map(str::trim)
+			.filter(|line| !line.is_empty())
+			.collect::<Vec<_>>()
+			.join(" ");
+		let reason = if reason.is_empty() { String::from_utf8_lossy(&output.stderr).trim().to_string() } else { reason };
+		return Err(anyhow!("Failed to start {} self-test on {}: {}", test, disk, reason));
+	}
+
+	writer.notify("info", format!("Started {} SMART self-test on {}", test, disk))?;
+	Ok(())
+}
+
+/// Refuses to overwrite a device that a volume group, a running md array or
+/// a btrfs filesystem on several devices still uses. Only the signature
+/// decides which of them to ask, so plain filesystems cost one blkid call.
+/// A member signature that is not refused, such as that of a stopped array,
+/// is still reported as a warning.
+async fn ensure_detached(runner: &dyn CommandRunner, device: &str, writer: &mut ResponseWriter) -> Result<()> {
+	let path = get_device_path(runner, device);
+	match probe_value(runner, &path, "TYPE").await?.as_str() {
+		"LVM2_member" => {
+			let output = runner.output("pvs", &["--noheadings", "-o", "vg_name", &path]).await.context("Failed to execute pvs")?;
+			let vg = String::from_utf8_lossy(&output.stdout).trim().to_string();
+			if output.status.success() && !vg.is_empty() {
+				return Err(anyhow!("{} is a physical volume of {}. Move its extents with 'pvmove' and remove it with 'vgreduce' first", device, vg));
+			}
+			writer.notify("warning", format!("{} is an LVM physical volume that belongs to no known volume group", device))?;
+		}
+		"linux_raid_member" => {
+			let holders = runner.read_dir_names(&format!("/sys/class/block/{}/holders", device)).unwrap_or_default();
+			if let Some(array) = holders.iter().find(|holder| holder.starts_with("md")) {
+				return Err(anyhow!("{} is a member of {}. Fail and remove it from the array first", device, array));
+			}
+			writer.notify("warning", format!("{} is a member of an md array that is not running", device))?;
+		}
+		"btrfs" => {
+			let devices = match runner.output("btrfs", &["filesystem", "show", &path]).await {
+				Ok(output) if output.status.success() => btrfs::parse_device_count(&String::from_utf8_lossy(&output.stdout)),
+				_ => None,
+			};
+			match devices {
+				Some(devices) if devices > 1 => {
+					return Err(anyhow!("{} is one of {} devices of a btrfs filesystem. Remove it with 'btrfs device remove' first", device, devices));
+				}
+				Some(_) => {}
+				None => writer.notify(
+					"warning",
+					format!("Could not check whether {} belongs to a btrfs filesystem on several devices", device),
+				)?,
+			}
+		}
+		_ => {}
+	}
+	Ok(())
+}
+
+/// [`ensure_detached`] for a disk about to be wiped and its partitions.
+async fn ensure_disk_detached(runner: &dyn CommandRunner, disk: &str, writer: &mut ResponseWriter) -> Result<()> {
+	ensure_detached(runner, disk, writer).await?;
+	for partition in list_partitions(runner, disk).await? {
+		ensure_detached(runner, &partition, writer).await?;
+	}
+	Ok(())
+}
+
+/// Checks that `device` can be given to LVM or an md array: it exists,
+/// holds nothing in use and belongs to no other volume group, array or
+/// btrfs filesystem. Returns its path.
+async fn unused_device_path(runner: &dyn CommandRunner, device: &str, writer: &mut ResponseWriter) -> Result<String> {
+	validate_device_name(device)?;
+	let path = format!("/dev/{}", device);
+	if !runner.path_exists(&path) {
+		return Err(anyhow!("Device {} does not exist", path));
+	}
+	if is_mounted(runner, device).await? {
+		return Err(anyhow!("{} is mounted. Unmount it first", device));
+	}
+	if is_active_swap(runner, &path).await {
+		return Err(anyhow!("{} is in use as swap. Disable it first", device));
+	}
+	ensure_detached(runner, device, writer).await?;
+	Ok(path)
+}
+
+/// Validates the names of a logical volume and returns its mapper name.
+fn logical_volume_name(vg: &str, name: &str) -> Result<String> {
+	lvm::validate_name(vg)?;
+	lvm::validate_name(name)?;
+	let mapper = lvm::mapper_name(vg, name);
+	validate_device_name(&mapper)?;
+	Ok(mapper)
+}
+
+async fn create_physical_volume(runner: &dyn CommandRunner, device: &str, writer: &mut ResponseWriter) -> Result<()> {
+	let path = unused_device_path(runner, device, writer).await?;
+	run_checked(runner, "pvcreate", &["-y", &path]).await?;
+	writer.notify("info", format!("Created physical volume on {}", device))?;
+	Ok(())
+}
+
+async fn create_volume_group(runner: &dyn CommandRunner, name: &str, devices: &[String], writer: &mut ResponseWriter) -> Result<()> {
+	lvm::validate_name(name)?;
+	if devices.is_empty() {
+		return Err(anyhow!("A volume group needs at least one device"));
+	}
+	let mut paths = Vec::new();
+	for device in devices {
+		paths.push(unused_device_path(runner, device, writer).await?);
+	}
+
+	let mut args = vec![name];
+	args.extend(paths.iter().map(String::as_str));
+	writer.progress_start(format!("Creating volume group {}...", name))?;
+	let result = run_checked(runner, "vgcreate", &args).await;
+	writer.progress_end()?;
+	result?;
+
+	writer.notify("info", format!("Created volume group {} on {}", name, devices.join(", ")))?;
+	Ok(())
+}
+
+async fn extend_volume_group(runner: &dyn CommandRunner, name: &str, device: &str, writer: &mut ResponseWriter) -> Result<()> {
+	lvm::validate_name(name)?;
+	let path = unused_device_path(runner, device, writer).await?;
+	run_checked(runner, "vgextend", &[name, &path]).await?;
+	writer.notify("info", format!("Added {} to volume group {}", device, name))?;
+	Ok(())
+}
+
+async fn create_logical_volume(
+	runner: &dyn CommandRunner,
+	vg: &str,
+	name: &str,
+	size: &str,
+	fs_type: Option<&str>,
+	writer: &mut ResponseWriter,
+) -> Result<()> {
+	let mapper = logical_volume_name(vg, name)?;
+	let size: SizeSpec = size.parse()?;
+
+	let report = LvmReport::load(runner).await?;
+	let group = report.vg(vg).ok_or_else(|| anyhow!("Volume group {} not found", vg))?;
+	if report.lv(vg, name).is_some() {
+		return Err(anyhow!("Logical volume {}/{} already exists", vg, name));
+	}
+	let extents = lvm::extents(size, group.free, group.extent_size)?;
+	if extents == 0 {
+		return Err(anyhow!("Size must be at least one extent ({})", format_bytes(group.extent_size)));
+	}
+	if extents > group.free_extents {
+		return Err(anyhow!("Not enough free space in {}: {} available", vg, format_bytes(group.free)));
+	}
+
+	let extents_arg = extents.to_string();
+	writer.progress_start(format!("Creating logical volume {}/{}...", vg, name))?;
+	let result = run_checked(runner, "lvcreate", &["-y", "-n", name, "-l", &extents_arg, vg]).await;
+	writer.progress_end()?;
+	result?;
+	writer.notify("info", format!("Created logical volume {}/{} ({})", vg, name, format_bytes(extents * group.extent_size)))?;
+
+	if let Some(fs_type) = fs_type {
+		format_partition(runner, &mapper, fs_type, None, writer).await?;
+	}
+	Ok(())
+}
+
+async fn resize_logical_volume(runner: &dyn CommandRunner, vg: &str, name: &str, size: &str, writer: &mut ResponseWriter) -> Result<()> {
+	let mapper = logical_volume_name(vg, name)?;
+	let size: SizeSpec = size.parse()?;
+
+	let report = LvmReport::load(runner).await?;
+	let group = report.vg(vg).ok_or_else(|| anyhow!("Volume group {} not found", vg))?;
+	let volume = report.lv(vg, name).ok_or_else(|| anyhow!("Logical volume {}/{} not found", vg, name))?;
+	if is_mounted(runner, &mapper).await? {
+		return Err(anyhow!("{}/{} is mounted. Unmount it first", vg, name));
+	}
+	let device_path = format!("/dev/mapper/{}", mapper);
+	if is_active_swap(runner, &device_path).await {
+		return Err(anyhow!("{}/{} is in use as swap. Disable it first", vg, name));
+	}
+
+	let extent_size = group.extent_size.max(1);
+	let current = volume.size / extent_size;
+	let extents = lvm::extents(size, volume.size + group.free, extent_size)?;
+	if extents == 0 {
+		return Err(anyhow!("New size must be greater than zero"));
+	}
+	if extents == current {
+		return Err(anyhow!("{}/{} is already {}", vg, name, format_bytes(volume.size)));
+	}
+	if extents > current + group.free_extents {
+		return Err(anyhow!("Not enough free space in {}: {} available", vg, format_bytes(group.free)));
+	}
+	let new_size_bytes = extents * extent_size;
+	let is_growing = extents > current;
+
+	let filesystem = probe_value(runner, &device_path, "TYPE").await?;
+	let filesystem = Some(filesystem.as_str()).filter(|fs| !fs.is_empty());
+	let target = format!("{}/{}", vg, name);
+	let extents_arg = extents.to_string();
+
+	writer.progress_start(format!("Resizing {}...", target))?;
+	let result = async {
+		if !is_growing {
+			writer.notify("info", "Shrinking filesystem...")?;
+			resize_filesystem(runner, &device_path, &mapper, filesystem, new_size_bytes, false, writer).await?;
+			writer.notify("info", "Shrinking logical volume...")?;
+			run_checked(runner, "lvresize", &["-f", "-l", &extents_arg, &target]).await
+		} else {
+			writer.notify("info", "Growing logical volume...")?;
+			run_checked(runner, "lvresize", &["-l", &extents_arg, &target]).await?;
+			writer.notify("info", "Expanding filesystem...")?;
+			resize_filesystem(runner, &device_path, &mapper, filesystem, new_size_bytes, true, writer).await
+		}
+	}
+	.await;
+	writer.progress_end()?;
+	result?;
+
+	writer.notify("info", format!("Resized {} to {}", target, format_bytes(new_size_bytes)))?;
+	Ok(())
+}
+
+async fn remove_logical_volume(runner: &dyn CommandRunner, vg: &str, name: &str, writer: &mut ResponseWriter) -> Result<()> {
+	let mapper = logical_volume_name(vg, name)?;
+	if is_mounted(runner, &mapper).await? {
+		return Err(anyhow!("{}/{} is mounted. Unmount it first", vg, name));
+	}
+	if is_active_swap(runner, &format!("/dev/mapper/{}", mapper)).await {
+		return Err(anyhow!("{}/{} is in use as swap. Disable it first", vg, name));
+	}
+
+	let target = format!("{}/{}", vg, name);
+	run_checked(runner, "lvremove", &["-y", &target]).await?;
+	writer.notify("info", format!("Removed logical volume {}", target))?;
+	Ok(())
+}
+
+/// Loads the arrays and returns the running array `name`.
+async fn raid_array(runner: &dyn CommandRunner, name: &str) -> Result<(RaidReport, RaidArray)> {
+	validate_device_name(name)?;
+	let report = RaidReport::load(runner).await?;
+	let array = report.array(name).cloned().ok_or_else(|| anyhow!("Array {} is not running", name))?;
+	Ok((report, array))
+}
+
+async fn create_raid(runner: &dyn CommandRunner, name: &str, level: &str, devices: &[String], writer: &mut ResponseWriter) -> Result<()> {
+	raid::validate_array_name(name)?;
+	let min = raid::min_devices(level)?;
+	if devices.len() < min {
+		return Err(anyhow!("A {} array needs at least {} devices", level, min));
+	}
+	if let Some(device) = devices.iter().enumerate().find_map(|(i, device)| devices[..i].contains(device).then_some(device)) {
+		return Err(anyhow!("{} is listed twice", device));
+	}
+	let array_path = format!("/dev/{}", name);
+	if runner.path_exists(&array_path) {
+		return Err(anyhow!("{} already exists", array_path));
+	}
+	let report = RaidReport::load(runner).await?;
+	let mut paths = Vec::new();
+	for device in devices {
+		if let Some(array) = report.array_of(device) {
+			return Err(anyhow!("{} is already a member of {}", device, array.name));
+		}
+		paths.push(unused_device_path(runner, device, writer).await?);
+	}
+
+	let level_arg = format!("--level={}", level);
+	let count_arg = format!("--raid-devices={}", devices.len());
+	// --run skips the question mdadm asks when a member holds a filesystem;
+	// the confirmation dialog asked it already.
+	let mut args = vec!["--create", &array_path, "--run", &level_arg, &count_arg];
+	args.extend(paths.iter().map(String::as_str));
+	writer.progress_start(format!("Creating {} array {}...", level, name))?;
+	let result = run_checked(runner, "mdadm", &args).await;
+	writer.progress_end()?;
+	result?;
+
+	writer.notify("info", format!("Created {} array {} on {}", level, name, devices.join(", ")))?;
+	Ok(())
+}
+
+async fn add_raid_member(runner: &dyn CommandRunner, array: &str, device: &str, writer: &mut ResponseWriter) -> Result<()> {
+	let (report, _) = raid_array(runner, array).await?;
+	if let Some(current) = report.array_of(device) {
+		return Err(anyhow!("{} is already a member of {}", device, current.name));
+	}
+	let path = unused_device_path(runner, device, writer).await?;
+	run_checked(runner, "mdadm", &["--manage", &format!("/dev/{}", array), "--add", &path]).await?;
+	writer.notify("info", format!("Added {} to {}", device, array))?;
+	Ok(())
+}
+
+async fn fail_raid_member(runner: &dyn CommandRunner, array: &str, device: &str, writer: &mut ResponseWriter) -> Result<()> {
+	validate_device_name(device)?;
+	let (_, current) = raid_array(runner, array).await?;
+	let member = current.member(device).ok_or_else(|| anyhow!("{} is not a member of {}", device, array))?;
+	if member.faulty {
+		return Err(anyhow!("{} is already marked as failed", device));
+	}
+	run_checked(runner, "mdadm", &["--manage", &format!("/dev/{}", array), "--fail", &format!("/dev/{}", device)]).await?;
+	writer.notify("info", format!("Marked {} as failed in {}", device, array))?;
+	Ok(())
+}
+
+async fn remove_raid_member(runner: &dyn CommandRunner, array: &str, device: &str, writer: &mut ResponseWriter) -> Result<()> {
+	validate_device_name(device)?;
+	let (_, current) = raid_array(runner, array).await?;
+	let member = current.member(device).ok_or_else(|| anyhow!("{} is not a member of {}", device, array))?;
+	if member.is_active() {
+		return Err(anyhow!("{} is an active member of {}. Mark it as failed first", device, array));
+	}
+
+	let path = format!("/dev/{}", device);
+	run_checked(runner, "mdadm", &["--manage", &format!("/dev/{}", array), "--remove", &path]).await?;
+	// Without its superblock the device is not assembled into the array
+	// again on the next boot.
+	run_checked(runner, "mdadm", &["--zero-superblock", &path]).await?;
+	writer.notify("info", format!("Removed {} from {}", device, array))?;
+	Ok(())
+}
+
+async fn stop_raid(runner: &dyn CommandRunner, array: &str, writer: &mut ResponseWriter) -> Result<()> {
+	raid_array(runner, array).await?;
+	if is_mounted(runner, array).await? {
+		return Err(anyhow!("{} is mounted. Unmount it first", array));
+	}
+	let path = format!("/dev/{}", array);
+	if is_active_swap(runner, &path).await {
+		return Err(anyhow!("{} is in use as swap. Disable it first", array));
+	}
+	run_checked(runner, "mdadm", &["--stop", &path]).await?;
+	writer.notify("info", format!("Stopped {}", array))?;
+	Ok(())
+}
+
+async fn assemble_raid(runner: &dyn CommandRunner, uuid: &str, writer: &mut ResponseWriter) -> Result<()> {
+	raid::validate_uuid(uuid)?;
+	run_checked(runner, "mdadm", &["--assemble", "--scan", &format!("--uuid={}", uuid)]).await?;
+	writer.notify("info", format!("Assembled array {}", uuid))?;
+	Ok(())
+}
+
+/// Where the btrfs filesystem on `device` is mounted, and the subvolume
+/// mounted there as findmnt's FSROOT.
+async fn btrfs_mount(runner: &dyn CommandRunner, device: &str) -> Result<(String, String)> {
+	validate_device_name(device)?;
+	let path = get_device_path(runner, device);
+	let output = runner
+		.output("findmnt", &["-n", "-f", "-P", "-o", "TARGET,FSTYPE,FSROOT", &path])
+		.await
+		.context("Failed to check mount status")?;
+	if !output.status.success() {
+		return Err(anyhow!("{} is not mounted. Mount it to manage its subvolumes", device));
+	}
+	// Pairs such as TARGET="/mnt/data"; quotes inside values are escaped.
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let parts: Vec<&str> = stdout.trim().split('"').collect();
+	let value = |key: &str| {
+		parts
+			.chunks(2)
+			.find(|pair| pair[0].trim().strip_suffix('=') == Some(key))
+			.and_then(|pair| pair.get(1))
+			.map(|value| unescape_udev(value))
+	};
+	match value("FSTYPE").as_deref() {
+		Some("btrfs") => {}
+		Some(other) => return Err(anyhow!("{} holds {}, not btrfs", device, other)),
+		None => return Err(anyhow!("{} is not mounted. Mount it to manage its subvolumes", device)),
+	}
+	let target = value("TARGET").ok_or_else(|| anyhow!("Could not find where {} is mounted", device))?;
+	Ok((target, value("FSROOT").unwrap_or_default()))
+}
+
+async fn list_subvolumes(runner: &dyn CommandRunner, device: &str) -> Result<SubvolumeList> {
+	let (mount_point, fs_root) = btrfs_mount(runner, device).await?;
+	SubvolumeList::load(runner, &mount_point, &fs_root).await
+}
+
+async fn create_subvolume(runner: &dyn CommandRunner, device: &str, path: &str, writer: &mut ResponseWriter) -> Result<()> {
+	btrfs::validate_path(path)?;
+	let (mount_point, _) = btrfs_mount(runner, device).await?;
+	let target = btrfs::join(&mount_point, path);
+	if runner.path_exists(&target) {
+		return Err(anyhow!("{} already exists", target));
+	}
+	run_checked(runner, "btrfs", &["subvolume", "create", &target]).await?;
+	writer.notify("info", format!("Created subvolume {}", target))?;
+	Ok(())
+}
+
+async fn delete_subvolume(runner: &dyn CommandRunner, device: &str, id: u64, writer: &mut ResponseWriter) -> Result<()> {
+	let list = list_subvolumes(runner, device).await?;
+	let subvolume = list.subvolume(id).ok_or_else(|| anyhow!("{} has no subvolume {}", device, id))?;
+	if id == btrfs::TOP_LEVEL_ID {
+		return Err(anyhow!("The top level subvolume cannot be deleted"));
+	}
+	if id == list.default_id {
+		return Err(anyhow!("{} is the default subvolume. Make another one the default first", subvolume.path));
+	}
+	if list.local_path(subvolume).as_deref() == Some("") {
+		return Err(anyhow!("{} is mounted at {}. Unmount it first", subvolume.path, list.mount_point));
+	}
+	if let Some(nested) = list.subvolumes.iter().find(|nested| nested.top_level == id) {
+		return Err(anyhow!("{} contains subvolume {}. Delete it first", subvolume.path, nested.path));
+	}
+	// By ID, so subvolumes outside the mounted one can be deleted too.
+	run_checked(runner, "btrfs", &["subvolume", "delete", "--subvolid", &id.to_string(), &list.mount_point]).await?;
+	writer.notify("info", format!("Deleted subvolume {}", subvolume.path))?;
+	Ok(())
+}
+
+async fn snapshot_subvolume(runner: &dyn CommandRunner, device: &str, id: u64, path: &str, writer: &mut ResponseWriter) -> Result<()> {
+	btrfs::validate_path(path)?;
+	let list = list_subvolumes(runner, device).await?;
+	let subvolume = list.subvolume(id).ok_or_else(|| anyhow!("{} has no subvolume {}", device, id))?;
+	let source = list
+		.absolute_path(subvolume)
+		.ok_or_else(|| anyhow!("{} is outside the mounted subvolume. Mount the top level to snapshot it", subvolume.path))?;
+	let target = btrfs::join(&list.mount_point, path);
+	if runner.path_exists(&target) {
+		return Err(anyhow!("{} already exists", target));
+	}
+	run_checked(runner, "btrfs", &["subvolume", "snapshot", "-r", &source, &target]).await?;
+	writer.notify("info", format!("Created read-only snapshot {} of {}", target, subvolume.display_path()))?;
+	Ok(())
+}
+
+async fn set_default_subvolume(runner: &dyn CommandRunner, device: &str, id: u64, writer: &mut ResponseWriter) -> Result<()> {
+	let list = list_subvolumes(runner, device).await?;
+	let subvolume = list.subvolume(id).ok_or_else(|| anyhow!("{} has no subvolume {}", device, id))?;
+	if id == list.default_id {
+		return Err(anyhow!("{} is already the default subvolume", subvolume.display_path()));
+	}
+	run_checked(runner, "btrfs", &["subvolume", "set-default", &id.to_string(), &list.mount_point]).await?;
+	writer.notify("info", format!("Made {} the default subvolume of {}", subvolume.display_path(), device))?;
+	Ok(())
+}
+
+async fn create_partition_table(runner: &dyn CommandRunner, disk: &str, table_type: &str, writer: &mut ResponseWriter) -> Result<()> {
+	validate_device_name(disk)?;
+
+	let label = match table_type {
+		"gpt" => "gpt",
+		"mbr" | "msdos" => "msdos",
+		_ => return Err(anyhow!("Unsupported partition table type: {}", table_type)),
+	};
+	ensure_disk_detached(runner, disk, writer).await?;
+
+	writer.progress_start(format!("Creating {} partition table on {}...", table_type, disk))?;
+
+	let output = runner
+		.output("parted", &["-s", &format!("/dev/{}", disk), "mklabel", label])
+		.await
+		.context("Failed to execute parted")?;
+
+	writer.progress_end()?;
+
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("Failed to create partition table: {}", err));
+	}
+
+	writer.notify("info", format!("Created {} partition table on {}", table_type, disk))?;
+	Ok(())
+}
+
+/// The partition table type and the byte ranges of the partitions of
+/// `disk`, with exclusive ends. A disk parted cannot read has neither.
+async fn read_partition_extents(runner: &dyn CommandRunner, disk: &str) -> Result<(Option<String>, Vec<(u64, u64)>)> {
+	let output = runner
+		.output("parted", &["-s", "-m", &format!("/dev/{}", disk), "unit", "B", "print"])
+		.await
+		.context("Failed to execute parted")?;
+
+	if !output.status.success() {
+		return Ok((None, Vec::new()));
+	}
+
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let mut table_type = None;
+	let mut extents = Vec::new();
+
+	for line in stdout.lines() {
+		let parts: Vec<&str> = line.split(':').collect();
+		if parts[0].starts_with("/dev/") {
+			table_type = parts.get(5).filter(|t| !t.is_empty() && **t != "unknown").map(|t| t.to_string());
+		} else if parts.len() >= 3
+			&& let Ok(_part_num) = parts[0].parse::<u32>()
+			&& let Some(Ok(start)) = parts[1].strip_suffix('B').map(str::parse::<u64>)
+			&& let Some(Ok(end)) = parts[2].strip_suffix('B').map(str::parse::<u64>)
+		{
+			// parted reports the last byte, not the end.
+			extents.push((start, end + 1));
+		}
+	}
+
+	Ok((table_type, extents))
+}
+
+async fn list_partitions(runner: &dyn CommandRunner, disk: &str) -> Result<Vec<String>> {
+	let lsblk_output = runner.output("lsblk", &["-J", "-o", "NAME", &format!("/dev/{}", disk)]).await?;
+	let lsblk_str = String::from_utf8_lossy(&lsblk_output.stdout);
+
+	Ok(serde_json::from_str::<serde_json::Value>(&lsblk_str)
+		.ok()
+		.and_then(|json| {
+			json["blockdevices"][0]["children"]
+				.as_array()
+				.map(|arr| arr.iter().filter_map(|p| p["name"].as_str().map(|s| s.to_string())).collect())
+		})
+		.unwrap_or_default())
+}
+
+/// Size in bytes and sector geometry of `disk`. Missing sector sizes fall
+/// back to 512 bytes.
+async fn read_disk_geometry(runner: &dyn CommandRunner, disk: &str) -> Result<(u64, DiskGeometry)> {
+	let output = runner
+		.output("lsblk", &["-b", "-d", "-n", "-o", "SIZE,LOG-SEC,PHY-SEC,OPT-IO", &format!("/dev/{}", disk)])
+		.await
+		.context("Failed to execute lsblk")?;
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let mut fields = stdout.split_whitespace().map(|field| field.parse::<u64>().ok());
+	let mut next = || fields.next().flatten();
+
+	let size = next().unwrap_or(0);
+	let defaults = DiskGeometry::default();
+	let geometry = DiskGeometry {
+		logical_sector_size: next().filter(|&n| n > 0).unwrap_or(defaults.logical_sector_size),
+		physical_sector_size: next().filter(|&n| n > 0).unwrap_or(defaults.physical_sector_size),
+		optimal_io_size: next().unwrap_or(0),
+	};
+	Ok((size, geometry))
+}
+
+/// What to set on a partition entry, shared by creating and modifying
+/// partitions.
+#[derive(Debug, Default)]
+struct EntryChanges<'a> {
+	part_type: Option<&'a str>,
+	name: Option<&'a str>,
+	flags: &'a [FlagChange],
+}
+
+impl EntryChanges<'_> {
+	fn is_empty(&self) -> bool {
+		self.part_type.is_none() && self.name.is_none() && self.flags.is_empty()
+	}
+}
+
+/// GPT names are at most 36 UTF-16 code units.
+fn validate_partition_name(name: &str) -> Result<()> {
+	if name.encode_utf16().count() > 36 {
+		return Err(anyhow!("Partition name is longer than 36 characters"));
+	}
+	if name.chars().any(char::is_control) {
+		return Err(anyhow!("Partition name contains control characters"));
+	}
+	Ok(())
+}
+
+/// Checks `changes` against the table type and returns the type code to
+/// hand to sfdisk, before anything is written.
+fn check_entry_changes(changes: &EntryChanges, table_type: Option<&str>) -> Result<Option<String>> {
+	if table_type.is_none() {
+		return Err(anyhow!("Disk has no partition table"));
+	}
+	let gpt = table_type == Some("gpt");
+	if let Some(name) = changes.name {
+		if !gpt {
+			return Err(anyhow!("Partition names need a GPT partition table"));
+		}
+		validate_partition_name(name)?;
+	}
+	if !gpt && changes.flags.iter().any(|change| change.flag == PartitionFlag::LegacyBoot) {
+		return Err(anyhow!("The legacy_boot flag needs a GPT partition table"));
+	}
+	changes.part_type.map(|part_type| parttype::resolve(part_type, table_type)).transpose()
+}
+
+/// Writes the type, then the flags, then the name of partition `part_num`.
+/// The type goes first since the esp flag changes it again.
+async fn apply_entry_changes(
+	runner: &dyn CommandRunner,
+	disk: &str,
+	part_num: &str,
+	part_type: Option<&str>,
+	changes: &EntryChanges<'_>,
+) -> Result<()> {
+	let device = format!("/dev/{}", disk);
+
+	if let Some(part_type) = part_type {
+		let output = runner
+			.output("sfdisk", &["--part-type", &device, part_num, part_type])
+			.await
+			.context("Failed to execute sfdisk")?;
+		if !output.status.success() {
+			let err = String::from_utf8_lossy(&output.stderr);
+			return Err(anyhow!("Failed to set partition type: {}", err));
+		}
+	}
+
+	for change in changes.flags {
+		let state = if change.enabled { "on" } else { "off" };
+		let output = runner
+			.output("parted", &["-s", &device, "set", part_num, change.flag.as_str(), state])
+			.await
+			.context("Failed to execute parted")?;
+		if !output.status.success() {
+			let err = String::from_utf8_lossy(&output.stderr);
+			return Err(anyhow!("Failed to set {} flag: {}", change.flag, err));
+		}
+	}
+
+	if let Some(name) = changes.name {
+		let output = runner
+			.output("sfdisk", &["--part-label", &device, part_num, name])
+			.await
+			.context("Failed to execute sfdisk")?;
+		if !output.status.success() {
+			let err = String::from_utf8_lossy(&output.stderr);
+			return Err(anyhow!("Failed to set partition name: {}", err));
+		}
+	}
+
+	Ok(())
+}
+
+async fn modify_partition(runner: &dyn CommandRunner, partition: &str, changes: EntryChanges<'_>, writer: &mut ResponseWriter) -> Result<()> {
+	validate_device_name(partition)?;
+	if changes.is_empty() {
+		return Err(anyhow!("Nothing to change on {}", partition));
+	}
+
+	let (disk, part_num) = split_partition_name(runner, partition)?;
+	let (table_type, _) = read_partition_extents(runner, disk).await?;
+	let part_type = check_entry_changes(&changes, table_type.as_deref())?;
+
+	writer.progress_start(format!("Updating partition {}...", partition))?;
+	let result = apply_entry_changes(runner, disk, part_num, part_type.as_deref(), &changes).await;
+	writer.progress_end()?;
+	result?;
+
+	let _ = runner.output("partprobe", &[&format!("/dev/{}", disk)]).await;
+
+	writer.notify("info", format!("Updated partition {}", partition))?;
+	Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn create_partition(
+	runner: &dyn CommandRunner,
+	disk: &str,
+	start: Option<u64>,
+	size: &str,
+	fs_type: Option<&str>,
+	alignment: Alignment,
+	entry: EntryChanges<'_>,
+	writer: &mut ResponseWriter,
+) -> Result<String> {
+	validate_device_name(disk)?;
+
+	let (table_type, extents) = read_partition_extents(runner, disk).await?;
+	let part_type = if entry.is_empty() { None } else { check_entry_changes(&entry, table_type.as_deref())? };
+	let (disk_size, geometry) = read_disk_geometry(runner, disk).await?;
+
+	let gaps = layout::free_gaps(disk_size, table_type.as_deref(), geometry.logical_sector_size, &extents);
+	let (first_sector, last_sector) = layout::place_partition(&gaps, start, size, &geometry, alignment)?.mkpart_bounds();
+
+	let existing = list_partitions(runner, disk).await?;
+
+	writer.progress_start(format!("Creating partition on {}...", disk))?;
+
+	let output = runner
+		.output("parted", &["-s", &format!("/dev/{}", disk), "mkpart", "primary", &first_sector, &last_sector])
+		.await
+		.context("Failed to execute parted")?;
+
+	if !output.status.success() {
+		writer.progress_end()?;
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("Create partition failed: {}", err));
+	}
+
+	runner.sleep(Duration::from_secs(2)).await;
+
+	// Nothing was created during a dry run; parted takes the lowest free number.
+	// A partition made in a hole can get a lower number than existing ones,
+	// so look for the name that was not there before.
+	let new_partition = if runner.is_dry_run() {
+		let used: Vec<u32> = existing
+			.iter()
+			.filter_map(|name| split_partition_name(runner, name).ok())
+			.filter_map(|(_, num)| num.parse().ok())
+			.collect();
+		(1..).find(|n| !used.contains(n)).map(|n| partition_name(disk, n))
+	} else {
+		list_partitions(runner, disk).await?.into_iter().find(|name| !existing.contains(name))
+	};
+
+	let partition_name = new_partition.ok_or_else(|| anyhow!("Failed to find new partition"))?;
+
+	if !entry.is_empty() {
+		let (_, part_num) = split_partition_name(runner, &partition_name)?;
+		if let Err(e) = apply_entry_changes(runner, disk, part_num, part_type.as_deref(), &entry).await {
+			writer.progress_end()?;
+			return Err(e);
+		}
+	}
+
+	if let Some(fs) = fs_type {
+		format_partition(runner, &partition_name, fs, None, writer).await?;
+	}
+
+	writer.progress_end()?;
+	writer.notify("info", format!("Created partition {}", partition_name))?;
+
+	Ok(partition_name)
+}
+
+/// Kernel naming: disks whose name ends in a digit (nvme0n1, mmcblk0, loop0)
+/// separate the partition number with a 'p'.
+fn partition_name(disk: &str, part_num: u32) -> String {
+	if disk.ends_with(|c: char| c.is_ascii_digit()) {
+		format!("{}p{}", disk, part_num)
+	} else {
+		format!("{}{}", disk, part_num)
+	}
+}
+
+/// Splits a partition name into its disk and partition number, refusing
+/// whole devices such as "nvme0n1" or "md0".
+fn split_partition_name<'a>(runner: &dyn CommandRunner, partition: &'a str) -> Result<(&'a str, &'a str)> {
+	protocol::split_partition(partition, sysfs_block(runner, partition))
+		.ok_or_else(|| anyhow!("Invalid partition name format: {}", partition))
+}
+
+/// Looks `name` up in /sys/class/block, `None` when no such device exists.
+fn sysfs_block(runner: &dyn CommandRunner, name: &str) -> Option<SysfsBlock> {
+	let sys = format!("/sys/class/block/{}", name);
+	if !runner.path_exists(&sys) {
+		return None;
+	}
+	if !runner.path_exists(&format!("{}/partition", sys)) {
+		return Some(SysfsBlock::Whole);
+	}
+	// A partition's directory sits below the one of its disk.
+	let base = name.trim_end_matches(|c: char| c.is_ascii_digit());
+	[base, base.strip_suffix('p').unwrap_or(base)]
+		.into_iter()
+		.find(|disk| runner.path_exists(&format!("/sys/class/block/{}/{}", disk, name)))
+		.map(|disk| SysfsBlock::Partition { disk: disk.to_string() })
+}
+
+async fn delete_partition(runner: &dyn CommandRunner, partition: &str, writer: &mut ResponseWriter) -> Result<()> {
+	validate_device_name(partition)?;
+	ensure_detached(runner, partition, writer).await?;
+
+	if is_mounted(runner, partition).await? {
+		unmount_partition(runner, partition, writer).await?;
+	}
+	if is_active_swap(runner, &format!("/dev/{}", partition)).await {
+		swap_off(runner, partition, writer).await?;
+	}
+
+	let (disk, part_num) = split_partition_name(runner, partition)?;
+
+	writer.progress_start(format!("Deleting partition {}...", partition))?;
+
+	let output = runner
+		.output("parted", &["-s", &format!("/dev/{}", disk), "rm", part_num])
+		.await
+		.context("Failed to execute parted")?;
+
+	writer.progress_end()?;
+
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("Delete partition failed: {}", err));
+	}
+
+	let _ = runner.output("partprobe", &[&format!("/dev/{}", disk)]).await;
+
+	writer.notify("info", format!("Deleted partition {}", partition))?;
+	Ok(())
+}
+
+async fn unlock_luks(
+	runner: &dyn CommandRunner,
+	device: &str,
+	passphrase: &str,
+	mapper_name: &str,
+	writer: &mut ResponseWriter,
+) -> Result<()> {
+	validate_device_name(device)?;
+	validate_device_name(mapper_name)?;
+
+	let device_path = format!("/dev/{}", device);
+
+	writer.progress_start(format!("Unlocking {}...", device))?;
+
+	let output = runner
+		.output_with_stdin("cryptsetup", &["open", &device_path, mapper_name], format!("{}\n", passphrase).as_bytes())
+		.await?;
+
+	writer.progress_end()?;
+
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("Failed to unlock: {}", err));
+	}
+
+	writer.notify("info", format!("Unlocked {} as {}", device, mapper_name))?;
+	Ok(())
+}
+
+async fn lock_luks(runner: &dyn CommandRunner, mapper_name: &str, writer: &mut ResponseWriter) -> Result<()> {
+	validate_device_name(mapper_name)?;
+
+	let mapper_path = format!("/dev/mapper/{}", mapper_name);
+
+	if runner.path_exists(&mapper_path)
+		&& let Some(mount_point) = get_device_mount_point(runner, &mapper_path).await
+	{
+		writer.notify("info", format!("Unmounting {} first...", mapper_name))?;
+		let _ = runner.output("umount", &[&mapper_path]).await;
+		if mount_point.starts_with("/mnt/") {
+			let _ = runner.output("rmdir", &[&mount_point]).await;
+		}
+	}
+
+	writer.progress_start(format!("Locking {}...", mapper_name))?;
+
+	let output = runner
+		.output("cryptsetup", &["close", mapper_name])
+		.await
+		.context("Failed to execute cryptsetup close")?;
+
+	writer.progress_end()?;
+
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("Failed to lock: {}", err));
+	}
+
+	writer.notify("info", format!("Locked {}", mapper_name))?;
+	Ok(())
+}
+
+async fn encrypt_partition(runner: &dyn CommandRunner, partition: &str, passphrase: &str, writer: &mut ResponseWriter) -> Result<()> {
+	validate_device_name(partition)?;
+	ensure_detached(runner, partition, writer).await?;
+
+	let device_path = format!("/dev/{}", partition);
+
+	writer.progress_start(format!("Encrypting {}...", partition))?;
+
+	let output = runner
+		.output_with_stdin(
+			"cryptsetup",
+			&["luksFormat", "--type", "luks2", "-q", &device_path],
+			format!("{}\n", passphrase).as_bytes(),
+		)
+		.await?;
+
+	writer.progress_end()?;
+
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("Encryption failed: {}", err));
+	}
+
+	writer.notify("info", format!("Encrypted {} with LUKS2", partition))?;
+	Ok(())
+}
+
+async fn encrypt_and_format(
+	runner: &dyn CommandRunner,
+	partition: &str,
+	passphrase: &str,
+	fs_type: &str,
+	writer: &mut ResponseWriter,
+) -> Result<()> {
+	encrypt_partition(runner, partition, passphrase, writer).await?;
+
+	runner.sleep(Duration::from_millis(500)).await;
+
+	let mapper_name = format!("luks-{}", partition);
+	unlock_luks(runner, partition, passphrase, &mapper_name, writer).await?;
+
+	let mapper_path = format!("/dev/mapper/{}", mapper_name);
+	for _ in 0..10 {
+		if runner.path_exists(&mapper_path) {
+			break;
+		}
+		runner.sleep(Duration::from_millis(500)).await;
+	}
+
+	format_partition(runner, &mapper_name, fs_type, None, writer).await?;
+
+	writer.notify("info", format!("Partition {} encrypted and formatted", partition))?;
+	Ok(())
+}
+
+async fn format_whole_disk(
+	runner: &dyn CommandRunner,
+	disk: &str,
+	fs_type: &str,
+	label: Option<&str>,
+	writer: &mut ResponseWriter,
+) -> Result<()> {
+	validate_device_name(disk)?;
+	if let Some(label) = label {
+		fslabel::validate_label(fs_type, label)?;
+	}
+	ensure_disk_detached(runner, disk, writer).await?;
+
+	writer.progress_start(format!("Formatting entire disk {}...", disk))?;
+
+	let output = runner.output("parted", &["-s", &format!("/dev/{}", disk), "mklabel", "gpt"]).await?;
+
+	if !output.status.success() {
+		writer.progress_end()?;
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("Failed to create partition table: {}", err));
+	}
+
+	let output = runner
+		.output("parted", &["-s", &format!("/dev/{}", disk), "mkpart", "primary", "1MiB", "100%"])
+		.await?;
+
+	if !output.status.success() {
+		writer.progress_end()?;
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("Failed to create partition: {}", err));
+	}
+
+	let _ = runner.output("partprobe", &[&format!("/dev/{}", disk)]).await;
+	runner.sleep(Duration::from_secs(2)).await;
+
+	let partition = partition_name(disk, 1);
+
+	format_partition(runner, &partition, fs_type, label, writer).await?;
+
+	writer.progress_end()?;
+	writer.notify("info", format!("Formatted {} as whole disk with {}", disk, fs_type))?;
+	Ok(())
+}
+
+async fn is_luks_device(runner: &dyn CommandRunner, device: &str) -> bool {
+	runner
+		.output("cryptsetup", &["isLuks", &format!("/dev/{}", device)])
+		.await
+		.map(|output| output.status.success())
+		.unwrap_or(false)
+}
+
+/// Size, start offset in bytes and filesystem of a partition.
+async fn get_partition_size_and_fs(runner: &dyn CommandRunner, partition: &str) -> Result<(u64, u64, Option<String>)> {
+	let output = runner
+		.output("lsblk", &["-J", "-b", "-d", "-o", "SIZE,START,FSTYPE", &format!("/dev/{}", partition)])
+		.await
+		.context("Failed to execute lsblk")?;
+
+	if !output.status.success() {
+		return Err(anyhow!("Partition {} not found", partition));
+	}
+
+	let json: serde_json::Value = serde_json::from_slice(&output.stdout).context("Failed to parse lsblk JSON")?;
+	let device = &json["blockdevices"][0];
+	let size = device["size"].as_u64().ok_or_else(|| anyhow!("Partition {} not found", partition))?;
+	// lsblk reports the start in 512-byte units.
+	let start = device["start"].as_u64().unwrap_or(0) * 512;
+	let fs = device["fstype"].as_str().map(|s| s.to_string());
+	Ok((size, start, fs))
+}
+
+/// Free bytes right after `end`, the first byte past a partition, in whole
+/// sectors.
+async fn free_space_after(
+	runner: &dyn CommandRunner,
+	disk: &str,
+	disk_size: u64,
+	geometry: &DiskGeometry,
+	end: u64,
+) -> Result<u64> {
+	let (table_type, extents) = read_partition_extents(runner, disk).await?;
+	let sector_size = geometry.logical_sector_size;
+	Ok(layout::free_gaps(disk_size, table_type.as_deref(), sector_size, &extents)
+		.into_iter()
+		.find(|gap| gap.start == end)
+		.map(|gap| gap.size() / sector_size * sector_size)
+		.unwrap_or(0))
+}
+
+fn rewrite_partition_size(table: &str, disk: &str, part_num: &str, size_sectors: u64) -> Result<String> {
+	let expected_dev = format!("/dev/{}{}", disk, part_num);
+	let expected_dev_p = format!("/dev/{}p{}", disk, part_num);
+	let mut new_table = String::new();
+	let mut found = false;
+
+	for line in table.lines() {
+		let parts: Vec<&str> = line.split(&[':', ','][..]).collect();
+		let device_part = parts[0].trim();
+
+		if device_part != expected_dev && device_part != expected_dev_p {
+			new_table.push_str(line);
+			new_table.push('\n');
+			continue;
+		}
+
+		found = true;
+
+		let mut start_str = String::new();
+		let mut other_attrs = Vec::new();
+
+		for part in parts.iter().skip(1) {
+			let trimmed = part.trim();
+			if trimmed.starts_with("start") {
+				start_str = trimmed.to_string();
+			} else if trimmed.starts_with("size") {
+				continue;
+			} else if !trimmed.is_empty() {
+				other_attrs.push(trimmed.to_string());
+			}
+		}
+
+		if start_str.is_empty() {
+			return Err(anyhow!("Could not parse partition table: start sector not found"));
+		}
+
+		let mut new_line = format!("{} : {}, size={}", device_part, start_str, size_sectors);
+		for attr in other_attrs {
+			new_line.push_str(", ");
+			new_line.push_str(&attr);
+		}
+
+		new_table.push_str(&new_line);
+		new_table.push('\n');
+	}
+
+	if !found {
+		return Err(anyhow!("Partition {}{} not found in partition table", disk, part_num));
+	}
+
+	Ok(new_table)
+}
+
+async fn mount_temporarily(runner: &dyn CommandRunner, device_path: &str, partition: &str) -> Result<String> {
+	let mount_point = format!("/tmp/disktui_resize_{}", partition);
+	runner.output("mkdir", &["-p", &mount_point]).await?;
+
+	let output = runner
+		.output("mount", &[device_path, &mount_point])
+		.await
+		.context("Failed to execute mount")?;
+
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		let _ = runner.output("rmdir", &[&mount_point]).await;
+		return Err(anyhow!("Failed to mount for filesystem resize: {}", err));
+	}
+
+	Ok(mount_point)
+}
+
+async fn unmount_temporary(runner: &dyn CommandRunner, mount_point: &str, writer: &mut ResponseWriter) -> Result<()> {
+	let output = runner.output("umount", &[mount_point]).await;
+	if output.map(|o| o.status.success()).unwrap_or(false) {
+		let _ = runner.output("rmdir", &[mount_point]).await;
+	} else {
+		writer.notify("warning", format!("Failed to unmount temp mount at {}", mount_point))?;
+	}
+	Ok(())
+}
+
+/// Resizes the filesystem on `device_path`; `name` is the device name used
+/// for the temporary mount point.
+async fn resize_filesystem(
+	runner: &dyn CommandRunner,
+	device_path: &str,
+	name: &str,
+	filesystem: Option<&str>,
+	new_size_bytes: u64,
+	is_growing: bool,
+	writer: &mut ResponseWriter,
+) -> Result<()> {
+	let Some(fs) = filesystem else {
+		writer.notify("warning", "No filesystem detected, skipping filesystem resize")?;
+		return Ok(());
+	};
+
+	match fs {
+		"ext4" | "ext3" | "ext2" => {
+			// resize2fs refuses to shrink a filesystem that was not
+			// checked just before.
+			if !is_growing {
+				let output = runner.output("e2fsck", &["-f", "-p", device_path]).await.context("Failed to execute e2fsck")?;
+				if output.status.code().is_none_or(|code| code > 1) {
+					return Err(anyhow!("{} has errors. Check and repair it first", name));
+				}
+			}
+			let size_k = format!("{}K", new_size_bytes / 1024);
+			let args: Vec<&str> = if is_growing { vec![device_path] } else { vec![device_path, &size_k] };
+			let output = runner.output("resize2fs", &args).await.context("Failed to execute resize2fs")?;
+			if !output.status.success() {
+				let err = String::from_utf8_lossy(&output.stderr);
+				return Err(anyhow!("Filesystem resize failed: {}", err));
+			}
+		}
+		"xfs" => {
+			if !is_growing {
+				return Err(anyhow!("XFS does not support shrinking"));
+			}
+
+			let mount_point = mount_temporarily(runner, device_path, name).await?;
+			let output = runner.output("xfs_growfs", &[&mount_point]).await;
+			unmount_temporary(runner, &mount_point, writer).await?;
+
+			let output = output.context("Failed to execute xfs_growfs")?;
+			if !output.status.success() {
+				let err = String::from_utf8_lossy(&output.stderr);
+				return Err(anyhow!("XFS resize failed: {}", err));
+			}
+		}
+		"ntfs" => {
+			let size_str = new_size_bytes.to_string();
+			let args: Vec<&str> = if is_growing {
+				vec!["-f", "-f", device_path]
+			} else {
+				vec!["-f", "-f", "-s", &size_str, device_path]
+			};
+
+			let output = runner
+				.output_with_stdin("ntfsresize", &args, b"y\n")
+				.await
+				.map_err(|_| anyhow!("ntfsresize not found. Install ntfs-3g package."))?;
+			if !output.status.success() {
+				let err = String::from_utf8_lossy(&output.stderr);
+				return Err(anyhow!("NTFS resize failed: {}", err));
+			}
+		}
+		"btrfs" => {
+			let size_arg = if is_growing {
+				"max".to_string()
+			} else {
+				new_size_bytes.to_string()
+			};
+
+			let mount_point = mount_temporarily(runner, device_path, name).await?;
+			let output = runner.output("btrfs", &["filesystem", "resize", &size_arg, &mount_point]).await;
+			unmount_temporary(runner, &mount_point, writer).await?;
+
+			let output = output.context("Failed to execute btrfs")?;
+			if !output.status.success() {
+				let err = String::from_utf8_lossy(&output.stderr);
+				return Err(anyhow!("Btrfs resize failed: {}", err));
+			}
+		}
+		// Shrinking the device under a filesystem that was not shrunk
+		// would cut off its end, so only growing goes ahead with a warning.
+		"vfat" | "fat32" | "exfat" if !is_growing => {
+			return Err(anyhow!("{} filesystems cannot be shrunk. Consider reformatting.", fs));
+		}
+		"vfat" | "fat32" | "exfat" => {
+			writer.notify("warning", format!("{} filesystem cannot be easily resized. Consider reformatting.", fs))?;
+		}
+		_ if !is_growing => {
+			return Err(anyhow!("Shrinking '{}' filesystems is not supported", fs));
+		}
+		_ => {
+			writer.notify("warning", format!("Filesystem '{}' resize not supported. Partition resized only.", fs))?;
+		}
+	}
+
+	Ok(())
+}
+
+async fn resize_partition(
+	runner: &dyn CommandRunner,
+	partition: &str,
+	new_size: &str,
+	alignment: Alignment,
+	writer: &mut ResponseWriter,
+) -> Result<()> {
+	validate_device_name(partition)?;
+
+	if is_luks_device(runner, partition).await {
+		return Err(anyhow!(
+			"{} is an encrypted partition. Resizing encrypted partitions is not supported as it risks data corruption.",
+			partition
+		));
+	}
+	ensure_detached(runner, partition, writer).await?;
+
+	if is_mounted(runner, partition).await? {
+		return Err(anyhow!("{} is mounted. Unmount it first", partition));
+	}
+	if is_active_swap(runner, &format!("/dev/{}", partition)).await {
+		return Err(anyhow!("{} is in use as swap. Disable it first", partition));
+	}
+
+	let (disk, part_num) = split_partition_name(runner, partition)?;
+	let new_size: SizeSpec = new_size.parse()?;
+
+	let (current_size, start, filesystem) = get_partition_size_and_fs(runner, partition).await?;
+	let (disk_size, geometry) = read_disk_geometry(runner, disk).await?;
+	let available = current_size + free_space_after(runner, disk, disk_size, &geometry, start + current_size).await?;
+	let new_size_bytes = new_size.resolve(available, geometry.logical_sector_size)?;
+	if new_size_bytes == 0 {
+		return Err(anyhow!("New size must be greater than zero"));
+	}
+	let new_size_bytes = layout::aligned_resize(current_size, new_size_bytes, alignment.bytes(&geometry));
+	if new_size_bytes > available {
+		return Err(anyhow!("Not enough free space after {}: {} available", partition, format_bytes(available)));
+	}
+	let is_growing = new_size_bytes > current_size;
+
+	writer.progress_start(format!("Resizing {}...", partition))?;
+	let result =
+		resize_partition_inner(runner, partition, disk, part_num, filesystem.as_deref(), new_size_bytes, is_growing, writer).await;
+	writer.progress_end()?;
+	result?;
+
+	writer.notify("info", format!("Resized {} to {}", partition, crate::utils::format_bytes(new_size_bytes)))?;
+	Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn resize_partition_inner(
+	runner: &dyn CommandRunner,
+	partition: &str,
+	disk: &str,
+	part_num: &str,
+	filesystem: Option<&str>,
+	new_size_bytes: u64,
+	is_growing: bool,
+	writer: &mut ResponseWriter,
+) -> Result<()> {
+	if !is_growing {
+		writer.notify("info", "Shrinking filesystem...")?;
+		resize_filesystem(runner, &format!("/dev/{}", partition), partition, filesystem, new_size_bytes, false, writer).await?;
+	}
+
+	writer.notify("info", "Resizing partition...")?;
+
+	let output = runner
+		.output("sfdisk", &["-d", &format!("/dev/{}", disk)])
+		.await
+		.context("Failed to dump partition table")?;
+
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("Failed to read partition table: {}", err));
+	}
+
+	let table = String::from_utf8_lossy(&output.stdout);
+	let sector_size = table
+		.lines()
+		.find_map(|line| line.strip_prefix("sector-size:"))
+		.and_then(|size| size.trim().parse::<u64>().ok())
+		.filter(|&size| size > 0)
+		.unwrap_or(512);
+	let new_table = rewrite_partition_size(&table, disk, part_num, new_size_bytes.div_ceil(sector_size))?;
+
+	let output = runner
+		.output_with_stdin("sfdisk", &["--force", "--no-reread", &format!("/dev/{}", disk)], new_table.as_bytes())
+		.await
+		.context("Failed to spawn sfdisk")?;
+
+	if !output.status.success() {
+		let err = String::from_utf8_lossy(&output.stderr);
+		return Err(anyhow!("Failed to resize partition: {}", err));
+	}
+
+	runner.sleep(Duration::from_secs(1)).await;
+	let _ = runner.output("partprobe", &[&format!("/dev/{}", disk)]).await;
+	runner.sleep(Duration::from_secs(2)).await;
+
+	if is_growing {
+		writer.notify("info", "Expanding filesystem...")?;
+		resize_filesystem(runner, &format!("/dev/{}", partition), partition, filesystem, new_size_bytes, true, writer).await?;
+	}
+
+	Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn create_encrypted_partition(
+	runner: &dyn CommandRunner,
+	disk: &str,
+	start: Option<u64>,
+	size: &str,
+	passphrase: &str,
+	fs_type: &str,
+	alignment: Alignment,
+	writer: &mut ResponseWriter,
+) -> Result<()> {
+	let partition = create_partition(runner, disk, start, size, None, alignment, EntryChanges::default(), writer).await?;
+	encrypt_and_format(runner, &partition, passphrase, fs_type, writer).await?;
+	Ok(())
+}
+
+/// Executes a single request, reporting progress and notifications through
+/// `writer`, and returns the data for the final `Ok` response. The final
+/// `Ok`/`Error` response itself is left to the caller.
+/// `Request::Shutdown` is a no-op here; the request loop handles it.
+pub async fn handle_request(request: Request, writer: &mut ResponseWriter, runner: &dyn CommandRunner) -> Result<Option<String>> {
+	match request {
+		Request::Mount { device, target, options } => mount_partition(runner, &device, target.as_deref(), &options, writer).await?,
+		Request::Unmount { device } => unmount_partition(runner, &device, writer).await?,
+		Request::Format { device, fs_type, label } => format_partition(runner, &device, &fs_type, label.as_deref(), writer).await?,
+		Request::FormatWholeDisk { disk, fs_type, label } => {
+			format_whole_disk(runner, &disk, &fs_type, label.as_deref(), writer).await?
+		}
+		Request::CreatePartitionTable { disk, table_type } => create_partition_table(runner, &disk, &table_type, writer).await?,
+		Request::CreatePartition { disk, start, size, fs_type, alignment, part_type, name } => {
+			let entry = EntryChanges { part_type: part_type.as_deref(), name: name.as_deref(), flags: &[] };
+			create_partition(runner, &disk, start, &size, fs_type.as_deref(), alignment, entry, writer).await?;
+		}
+		Request::CreateEncryptedPartition { disk, start, size, passphrase, fs_type, alignment } => {
+			create_encrypted_partition(runner, &disk, start, &size, &passphrase, &fs_type, alignment, writer).await?
+		}
+		Request::DeletePartition { partition } => delete_partition(runner, &partition, writer).await?,
+		Request::ResizePartition { partition, new_size, alignment } => {
+			resize_partition(runner, &partition, &new_size, alignment, writer).await?
+		}
+		Request::ModifyPartition { partition, part_type, name, flags } => {
+			let changes = EntryChanges { part_type: part_type.as_deref(), name: name.as_deref(), flags: &flags };
+			modify_partition(runner, &partition, changes, writer).await?
+		}
+		Request::CheckFilesystem { device, repair } => {
+			return check_filesystem(runner, &device, repair, writer).await.map(Some);
+		}
+		Request::SetLabel { device, label } => set_label(runner, &device, &label, writer).await?,
+		Request::RegenerateUuid { device } => regenerate_uuid(runner, &device, writer).await?,
+		Request::SetFstabEntry { device, target, options, pass } => {
+			set_fstab_entry(runner, &device, &target, &options, pass, writer).await?
+		}
+		Request::RemoveFstabEntry { source } => remove_fstab_entry(runner, &source, writer).await?,
+		Request::SetCrypttabEntry { device, name, options } => set_crypttab_entry(runner, &device, &name, &options, writer).await?,
+		Request::RemoveCrypttabEntry { name } => remove_crypttab_entry(runner, &name, writer).await?,
+		Request::StartSelfTest { disk, test } => start_self_test(runner, &disk, test, writer).await?,
+		Request::ListLvm => {
+			let report = LvmReport::load(runner).await?;
+			return Ok(Some(serde_json::to_string(&report)?));
+		}
+		Request::CreatePhysicalVolume { device } => create_physical_volume(runner, &device, writer).await?,
+		Request::CreateVolumeGroup { name, devices } => create_volume_group(runner, &name, &devices, writer).await?,
+		Request::ExtendVolumeGroup { name, device } => extend_volume_group(runner, &name, &device, writer).await?,
+		Request::CreateLogicalVolume { vg, name, size, fs_type } => {
+			create_logical_volume(runner, &vg, &name, &size, fs_type.as_deref(), writer).await?
+		}
+		Request::ResizeLogicalVolume { vg, name, size } => resize_logical_volume(runner, &vg, &name, &size, writer).await?,
+		Request::RemoveLogicalVolume { vg, name } => remove_logical_volume(runner, &vg, &name, writer).await?,
+		Request::ListRaid => {
+			let report = RaidReport::load(runner).await?;
+			return Ok(Some(serde_json::to_string(&report)?));
+		}
+		Request::CreateRaid { name, level, devices } => create_raid(runner, &name, &level, &devices, writer).await?,
+		Request::AddRaidMember { array, device } => add_raid_member(runner, &array, &device, writer).await?,
+		Request::FailRaidMember { array, device } => fail_raid_member(runner, &array, &device, writer).await?,
+		Request::RemoveRaidMember { array, device } => remove_raid_member(runner, &array, &device, writer).await?,
+		Request::StopRaid { array } => stop_raid(runner, &array, writer).await?,
+		Request::AssembleRaid { uuid } => assemble_raid(runner, &uuid, writer).await?,
+		Request::ListSubvolumes { device } => {
+			let list = list_subvolumes(runner, &device).await?;
+			return Ok(Some(serde_json::to_string(&list)?));
+		}
+		Request::CreateSubvolume { device, path } => create_subvolume(runner, &device, &path, writer).await?,
+		Request::DeleteSubvolume { device, id } => delete_subvolume(runner, &device, id, writer).await?,
+		Request::SnapshotSubvolume { device, id, path } => snapshot_subvolume(runner, &device, id, &path, writer).await?,
+		Request::SetDefaultSubvolume { device, id } => set_default_subvolume(runner, &device, id, writer).await?,
+		Request::SwapOn { target } => swap_on(runner, &target, writer).await?,
+		Request::SwapOff { target } => swap_off(runner, &target, writer).await?,
+		Request::CreateSwapFile { path, size } => create_swap_file(runner, &path, &size, writer).await?,
+		Request::UnlockLuks { device, passphrase, mapper_name } => {
+			unlock_luks(runner, &device, &passphrase, &mapper_name, writer).await?
+		}
+		Request::LockLuks { mapper_name } => lock_luks(runner, &mapper_name, writer).await?,
+		Request::EncryptPartition { partition, passphrase } => encrypt_partition(runner, &partition, &passphrase, writer).await?,
+		Request::EncryptAndFormat { partition, passphrase, fs_type } => {
+			encrypt_and_format(runner, &partition, &passphrase, &fs_type, writer).await?
+		}
+		Request::DryRun { .. } => return Err(anyhow!("Dry runs are planned by the helper session")),
+		Request::AuditLog { .. } => return Err(anyhow!("Audit logging is disabled")),
+		Request::ListBackups { .. } | Request::RestoreTable { .. } => {
+			return Err(anyhow!("Partition table backups are disabled"));
+		}
+		Request::Shutdown => {}
+	}
+	Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::mount::Owner;
+	use crate::runner::{FakeResponse, FakeRunner};
+	use std::sync::{Arc, Mutex};
+
+	#[derive(Clone, Default)]
+	struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+	impl Write for SharedBuffer {
+		fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+			self.0.lock().unwrap().extend_from_slice(buf);
+			Ok(buf.len())
+		}
+
+		fn flush(&mut self) -> std::io::Result<()> {
+			Ok(())
+		}
+	}
+
+	impl SharedBuffer {
+		fn responses(&self) -> Vec<Response> {
+			String::from_utf8(self.0.lock().unwrap().clone())
+				.unwrap()
+				.lines()
+				.map(|line| serde_json::from_str(line).unwrap())
+				.collect()
+		}
+	}
+
+	const SFDISK_DUMP: &str = "\
+label: gpt
+device: /dev/sdb
+unit: sectors
+
+/dev/sdb1 : start=        2048, size=     3906250, type=0FC63DAF-8483-4772-8E79-3D69D8477DE4, uuid=1234
+/dev/sdb2 : start=     3908298, size=     2000000, type=0FC63DAF-8483-4772-8E79-3D69D8477DE4
+";
+
+	fn resize_runner(current_size: u64) -> FakeRunner {
+		let runner = FakeRunner::new();
+		runner
+			.on("cryptsetup isLuks", FakeResponse::fail(1, ""))
+			.on("findmnt -n /dev/sdb1", FakeResponse::fail(1, ""))
+			.on(
+				"lsblk -J -b -d -o SIZE,START,FSTYPE /dev/sdb1",
+				FakeResponse::ok(format!(r#"{{"blockdevices":[{{"size":{},"start":2048,"fstype":"ext4"}}]}}"#, current_size)),
+			)
+			.on("sfdisk -d /dev/sdb", FakeResponse::ok(SFDISK_DUMP))
+			.on("lsblk -b -d -n -o SIZE,LOG-SEC,PHY-SEC,OPT-IO /dev/sdb", FakeResponse::ok("8000000000 512 4096 0\n"))
+			.on(
+				"parted -s -m /dev/sdb unit B print",
+				FakeResponse::ok(format!(
+					"BYT;\n/dev/sdb:8000000000B:scsi:512:4096:gpt:Disk:;\n1:1048576B:{}B:{}B:ext4::;\n",
+					1048576 + current_size - 1,
+					current_size
+				)),
+			);
+		runner
+	}
+
+	#[test]
+	fn split_partition_name_handles_p_separated_devices() {
+		let runner = FakeRunner::new();
+		let split = |name| split_partition_name(&runner, name);
+		assert_eq!(split("sdb2").unwrap(), ("sdb", "2"));
+		assert_eq!(split("nvme0n1p3").unwrap(), ("nvme0n1", "3"));
+		assert_eq!(split("mmcblk0p1").unwrap(), ("mmcblk0", "1"));
+		assert_eq!(split("loop7p12").unwrap(), ("loop7", "12"));
+		assert_eq!(split("sdp1").unwrap(), ("sdp", "1"));
+		assert!(split("sdb").is_err());
+		assert!(split("nvme0n1").is_err());
+		assert!(split("md0").is_err());
+		assert!(split("ubda1").is_err());
+		assert_eq!(partition_name("loop3", 1), "loop3p1");
+		assert_eq!(partition_name("sdb", 2), "sdb2");
+	}
+
+	/// Devices that exist are looked up in sysfs instead of going by name.
+	#[test]
+	fn split_partition_name_prefers_sysfs() {
+		let runner = FakeRunner::new();
+		runner
+			.with_path("/sys/class/block/ubda1")
+			.with_path("/sys/class/block/ubda1/partition")
+			.with_path("/sys/class/block/ubda/ubda1")
+			.with_path("/sys/class/block/sdb2");
+		assert_eq!(split_partition_name(&runner, "ubda1").unwrap(), ("ubda", "1"));
+		assert!(split_partition_name(&runner, "sdb2").is_err());
+	}
+
+
+	#[test]
+	fn rewrite_partition_size_only_touches_target_partition() {
+		let table = rewrite_partition_size(SFDISK_DUMP, "sdb", "1", 1000).unwrap();
+
+		assert!(table.contains("/dev/sdb1 : start=        2048, size=1000, type=0FC63DAF-8483-4772-8E79-3D69D8477DE4, uuid=1234"));
+		assert!(table.contains("/dev/sdb2 : start=     3908298, size=     2000000,"));
+		assert!(rewrite_partition_size(SFDISK_DUMP, "sdb", "3", 1000).is_err());
+	}
+
+	/// Shrinking to 1GB rounds up to the next MiB, 954 MiB.
+	#[tokio::test]
+	async fn shrinking_resizes_filesystem_before_partition() {
+		let runner = resize_runner(2_000_000_000);
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::ResizePartition { partition: "sdb1".into(), new_size: "1GB".into(), alignment: Alignment::Mib };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+
+		let e2fsck = runner.position("e2fsck -f -p /dev/sdb1").unwrap();
+		let resize2fs = runner.position("resize2fs /dev/sdb1 976896K").unwrap();
+		let sfdisk = runner.position("sfdisk --force --no-reread /dev/sdb").unwrap();
+		assert!(e2fsck < resize2fs && resize2fs < sfdisk);
+
+		let table = runner.calls()[sfdisk].stdin.clone().unwrap();
+		assert!(String::from_utf8(table).unwrap().contains("/dev/sdb1 : start=        2048, size=1953792,"));
+	}
+
+	#[tokio::test]
+	async fn shrinking_refuses_filesystems_with_errors() {
+		let runner = resize_runner(2_000_000_000);
+		runner.on("e2fsck", FakeResponse::fail(4, ""));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::ResizePartition { partition: "sdb1".into(), new_size: "1GB".into(), alignment: Alignment::Mib };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+
+		assert!(err.to_string().contains("sdb1 has errors"), "{}", err);
+		assert!(runner.position("resize2fs").is_none());
+		assert!(runner.position("sfdisk --force").is_none());
+	}
+
+	#[tokio::test]
+	async fn growing_resizes_filesystem_after_partition() {
+		let runner = resize_runner(1_000_000_000);
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::ResizePartition { partition: "sdb1".into(), new_size: "2G".into(), alignment: Alignment::Mib };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+
+		let sfdisk = runner.position("sfdisk --force").unwrap();
+		let resize2fs = runner.position("resize2fs").unwrap();
+		assert!(sfdisk < resize2fs);
+		assert_eq!(runner.command_lines()[resize2fs], "resize2fs /dev/sdb1");
+	}
+
+	/// Growing to 2GB with a 4 MiB optimal I/O size rounds down to 476 * 4 MiB.
+	#[tokio::test]
+	async fn growing_rounds_down_to_the_optimal_alignment() {
+		let runner = resize_runner(1_000_000_000);
+		runner.once("lsblk -b -d -n -o SIZE,LOG-SEC,PHY-SEC,OPT-IO /dev/sdb", FakeResponse::ok("8000000000 512 4096 4194304\n"));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::ResizePartition { partition: "sdb1".into(), new_size: "2GB".into(), alignment: Alignment::Optimal };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+
+		let sfdisk = runner.position("sfdisk --force --no-reread /dev/sdb").unwrap();
+		let table = runner.calls()[sfdisk].stdin.clone().unwrap();
+		assert!(String::from_utf8(table).unwrap().contains("/dev/sdb1 : start=        2048, size=3899392,"));
+	}
+
+	/// Relative sizes are taken of the partition plus the free space after
+	/// it, as in the TUI's preview: 1 GiB followed by 2 GiB free here.
+	#[tokio::test]
+	async fn relative_sizes_resolve_against_the_space_after_the_partition() {
+		let parted = "BYT;\n/dev/sdb:8000000000B:scsi:512:4096:gpt:Disk:;\n1:1048576B:1074790399B:1073741824B:ext4::;\n2:3222274048B:4296015871B:1073741824B:ext4::;\n";
+		for (new_size, sectors) in [("rest", 6291456), ("50%", 3145728), ("-1G", 4194304)] {
+			let runner = resize_runner(1 << 30);
+			runner
+				.once(
+					"lsblk -J -b -d -o SIZE,START,FSTYPE /dev/sdb1",
+					FakeResponse::ok(r#"{"blockdevices":[{"size":1073741824,"start":2048,"fstype":"ext4"}]}"#),
+				)
+				.once("parted -s -m /dev/sdb unit B print", FakeResponse::ok(parted));
+			let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+			let request = Request::ResizePartition { partition: "sdb1".into(), new_size: new_size.into(), alignment: Alignment::Mib };
+			handle_request(request, &mut writer, &runner).await.unwrap();
+
+			let sfdisk = runner.position("sfdisk --force --no-reread /dev/sdb").unwrap();
+			let table = String::from_utf8(runner.calls()[sfdisk].stdin.clone().unwrap()).unwrap();
+			assert!(table.contains(&format!("/dev/sdb1 : start=        2048, size={},", sectors)), "{}: {}", new_size, table);
+		}
+	}
+
+	#[tokio::test]
+	async fn growing_past_the_next_partition_is_refused() {
+		let parted = "BYT;\n/dev/sdb:8000000000B:scsi:512:4096:gpt:Disk:;\n1:1048576B:1074790399B:1073741824B:ext4::;\n2:3222274048B:4296015871B:1073741824B:ext4::;\n";
+		let runner = resize_runner(1 << 30);
+		runner.once("parted -s -m /dev/sdb unit B print", FakeResponse::ok(parted));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::ResizePartition { partition: "sdb1".into(), new_size: "4G".into(), alignment: Alignment::Mib };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+
+		assert!(err.to_string().contains("Not enough free space after sdb1"), "{}", err);
+		assert!(runner.position("sfdisk --force").is_none());
+	}
+
+	#[tokio::test]
+	async fn resize_refuses_luks_partitions() {
+		let runner = FakeRunner::new();
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::ResizePartition { partition: "sdb1".into(), new_size: "1G".into(), alignment: Alignment::Mib };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+
+		assert!(err.to_string().contains("encrypted partition"));
+		assert!(runner.position("sfdisk").is_none());
+	}
+
+	#[tokio::test]
+	async fn encrypt_and_format_pipes_passphrase_and_formats_mapper() {
+		let runner = FakeRunner::new();
+		runner.with_path("/dev/mapper/luks-sdb1");
+		let buffer = SharedBuffer::default();
+		let mut writer = ResponseWriter::new(Box::new(buffer.clone()));
+
+		let request = Request::EncryptAndFormat {
+			partition: "sdb1".into(),
+			passphrase: "hunter2".into(),
+			fs_type: "ext4".into(),
+		};
+		handle_request(request, &mut writer, &runner).await.unwrap();
+
+		let calls = runner.calls();
+		let lines = runner.command_lines();
+		let format = runner.position("cryptsetup luksFormat").unwrap();
+		let open = runner.position("cryptsetup open /dev/sdb1 luks-sdb1").unwrap();
+		let mkfs = runner.position("mkfs.ext4 /dev/mapper/luks-sdb1").unwrap();
+		assert!(format < open && open < mkfs, "{:?}", lines);
+		assert_eq!(calls[format].stdin.as_deref(), Some(&b"hunter2\n"[..]));
+		assert_eq!(calls[open].stdin.as_deref(), Some(&b"hunter2\n"[..]));
+
+		let responses = buffer.responses();
+		assert!(matches!(
+			responses.last(),
+			Some(Response::Notification { message, .. }) if message == "Partition sdb1 encrypted and formatted"
+		));
+	}
+
+	#[tokio::test]
+	async fn modify_partition_sets_type_then_flags_then_name() {
+		let runner = FakeRunner::new();
+		runner.on("parted -s -m /dev/sdb unit B print", FakeResponse::ok("BYT;\n/dev/sdb:8000000000B:scsi:512:4096:gpt:Disk:;\n"));
+
+		let request = Request::ModifyPartition {
+			partition: "sdb1".into(),
+			part_type: Some("linux".into()),
+			name: Some("root fs".into()),
+			flags: vec![FlagChange { flag: PartitionFlag::LegacyBoot, enabled: true }],
+		};
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+		handle_request(request, &mut writer, &runner).await.unwrap();
+
+		let part_type = runner.position("sfdisk --part-type /dev/sdb 1 0FC63DAF-8483-4772-8E79-3D69D8477DE4").unwrap();
+		let flag = runner.position("parted -s /dev/sdb set 1 legacy_boot on").unwrap();
+		let name = runner.position("sfdisk --part-label /dev/sdb 1 root fs").unwrap();
+		assert!(part_type < flag && flag < name);
+	}
+
+	#[tokio::test]
+	async fn modify_partition_rejects_gpt_only_changes_on_mbr() {
+		let runner = FakeRunner::new();
+		runner.on("parted -s -m /dev/sdb unit B print", FakeResponse::ok("BYT;\n/dev/sdb:8000000000B:scsi:512:512:msdos:Disk:;\n"));
+
+		let request = Request::ModifyPartition {
+			partition: "sdb1".into(),
+			part_type: None,
+			name: Some("data".into()),
+			flags: Vec::new(),
+		};
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+
+		assert_eq!(err.to_string(), "Partition names need a GPT partition table");
+		assert!(runner.command_lines().iter().all(|c| !c.starts_with("sfdisk")));
+	}
+
+	#[tokio::test]
+	async fn create_swap_file_prepares_file_before_enabling_it() {
+		let runner = FakeRunner::new();
+		runner.on("findmnt -n -o FSTYPE --target /home", FakeResponse::ok("ext4\n"));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::CreateSwapFile { path: "/home/swapfile".into(), size: "1G".into() };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+
+		let allocate = runner.position("fallocate -l 1073741824 /home/swapfile").unwrap();
+		let chmod = runner.position("chmod 600 /home/swapfile").unwrap();
+		let mkswap = runner.position("mkswap /home/swapfile").unwrap();
+		let swapon = runner.position("swapon /home/swapfile").unwrap();
+		assert!(allocate < chmod && chmod < mkswap && mkswap < swapon);
+	}
+
+	#[tokio::test]
+	async fn create_swap_file_removes_file_when_swapon_fails() {
+		let runner = FakeRunner::new();
+		runner
+			.on("findmnt -n -o FSTYPE --target /", FakeResponse::ok("btrfs\n"))
+			.on("swapon /swapfile", FakeResponse::fail(1, "swapon: /swapfile: Invalid argument"));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::CreateSwapFile { path: "/swapfile".into(), size: "512M".into() };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+
+		assert_eq!(err.to_string(), "swapon failed: swapon: /swapfile: Invalid argument");
+		assert!(runner.position("btrfs filesystem mkswapfile --size 536870912 /swapfile").is_some());
+		assert!(runner.position("rm -f /swapfile").is_some());
+		assert!(validate_file_path("/proc/swapfile").is_err());
+		assert!(validate_file_path("/home/../etc/swapfile").is_err());
+	}
+
+	#[tokio::test]
+	async fn check_streams_checker_output_and_reports_exit_code() {
+		let runner = FakeRunner::new();
+		runner
+			.with_path("/dev/sdb1")
+			.on("findmnt -n /dev/sdb1", FakeResponse::fail(1, ""))
+			.on("blkid -p -o value -s TYPE /dev/sdb1", FakeResponse::ok("ext4\n"))
+			.on(
+				"e2fsck -f -y /dev/sdb1",
+				FakeResponse { code: 1, stdout: b"Pass 1: Checking inodes\nFIXED.\n".to_vec(), stderr: Vec::new() },
+			);
+		let buffer = SharedBuffer::default();
+		let mut writer = ResponseWriter::new(Box::new(buffer.clone()));
+
+		let request = Request::CheckFilesystem { device: "sdb1".into(), repair: true };
+		let data = handle_request(request, &mut writer, &runner).await.unwrap().unwrap();
+
+		let report: CheckReport = serde_json::from_str(&data).unwrap();
+		assert_eq!(report.status, CheckStatus::Corrected);
+		assert_eq!(report.exit_code, Some(1));
+		let lines: Vec<String> = buffer
+			.responses()
+			.into_iter()
+			.filter_map(|response| match response {
+				Response::Output { line } => Some(line),
+				_ => None,
+			})
+			.collect();
+		assert_eq!(lines, vec!["Pass 1: Checking inodes", "FIXED."]);
+	}
+
+	#[tokio::test]
+	async fn check_refuses_mounted_devices() {
+		let runner = FakeRunner::new();
+		runner.with_path("/dev/sdb1").on("findmnt -n /dev/sdb1", FakeResponse::ok("/mnt/sdb1 /dev/sdb1 ext4 rw\n"));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::CheckFilesystem { device: "sdb1".into(), repair: false };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+
+		assert_eq!(err.to_string(), "sdb1 is mounted. Unmount it first");
+		assert!(runner.position("e2fsck").is_none());
+	}
+
+	#[tokio::test]
+	async fn mount_uses_target_and_options() {
+		let runner = FakeRunner::new();
+		runner
+			.with_path("/dev/sdb1")
+			.on("findmnt -n /dev/sdb1", FakeResponse::fail(1, ""))
+			.on("blkid -p -o value -s TYPE /dev/sdb1", FakeResponse::ok("vfat\n"));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::Mount {
+			device: "sdb1".into(),
+			target: Some("/run/media/alex/STICK/".into()),
+			options: MountOptions { noatime: true, owner: Some(Owner { uid: 1000, gid: 1000 }), ..MountOptions::default() },
+		};
+		handle_request(request, &mut writer, &runner).await.unwrap();
+
+		assert!(runner.position("mkdir -p /run/media/alex/STICK").is_some());
+		assert!(runner.position("mount -o noatime,uid=1000,gid=1000 /dev/sdb1 /run/media/alex/STICK").is_some());
+	}
+
+	#[tokio::test]
+	async fn mount_refuses_to_hide_existing_files() {
+		let runner = FakeRunner::new();
+		runner
+			.with_path("/dev/sdb1")
+			.with_path("/home/alex/usb")
+			.on("findmnt -n /dev/sdb1", FakeResponse::fail(1, ""))
+			.on("findmnt -n --mountpoint", FakeResponse::fail(1, ""))
+			.on("blkid -p -o value -s TYPE /dev/sdb1", FakeResponse::ok("ext4\n"))
+			.on("ls -A /home/alex/usb", FakeResponse::ok("notes.txt\n"));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::Mount {
+			device: "sdb1".into(),
+			target: Some("/home/alex/usb".into()),
+			options: MountOptions::default(),
+		};
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+
+		assert_eq!(err.to_string(), "/home/alex/usb is not empty");
+		assert!(runner.position("mount ").is_none());
+		assert!(validate_mount_target("/proc/usb").is_err());
+		assert!(validate_mount_target("/run/user/1000/usb").is_err());
+	}
+
+	#[tokio::test]
+	async fn mounted_btrfs_is_labelled_through_its_mount_point() {
+		let runner = FakeRunner::new();
+		runner
+			.with_path("/dev/sdb1")
+			.with_path("/dev/sdc1")
+			.on("blkid -p -o value -s TYPE /dev/sdb1", FakeResponse::ok("btrfs\n"))
+			.on("findmnt -n -o TARGET /dev/sdb1", FakeResponse::ok("/mnt/data\n"))
+			.on("blkid -p -o value -s TYPE /dev/sdc1", FakeResponse::ok("vfat\n"))
+			.on("findmnt -n -o TARGET /dev/sdc1", FakeResponse::ok("/boot/efi\n"));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::SetLabel { device: "sdb1".into(), label: "backup".into() };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+		assert!(runner.position("btrfs filesystem label /mnt/data backup").is_some());
+
+		let request = Request::SetLabel { device: "sdc1".into(), label: "EFI".into() };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+		assert_eq!(err.to_string(), "sdc1 is mounted. Unmount it before changing the vfat label");
+		assert!(runner.position("fatlabel").is_none());
+	}
+
+	#[tokio::test]
+	async fn uuid_regeneration_checks_ext_filesystems_first() {
+		let runner = FakeRunner::new();
+		runner
+			.with_path("/dev/sdb1")
+			.on("findmnt -n /dev/sdb1", FakeResponse::fail(1, ""))
+			.on("blkid -p -o value -s TYPE /dev/sdb1", FakeResponse::ok("ext4\n"));
+		let buffer = SharedBuffer::default();
+		let mut writer = ResponseWriter::new(Box::new(buffer.clone()));
+
+		let request = Request::RegenerateUuid { device: "sdb1".into() };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+
+		let check = runner.position("e2fsck -f -p /dev/sdb1").unwrap();
+		let tune = runner.position("tune2fs -U random /dev/sdb1").unwrap();
+		assert!(check < tune);
+		assert!(buffer.responses().iter().any(|response| matches!(
+			response,
+			Response::Notification { level, .. } if level == "warning"
+		)));
+	}
+
+	fn fstab_runner() -> FakeRunner {
+		let runner = FakeRunner::new();
+		runner
+			.with_path("/dev/sdb1")
+			.with_path("/etc/fstab")
+			.on("blkid -p -o value -s TYPE /dev/sdb1", FakeResponse::ok("ext4\n"))
+			.on("blkid -p -o value -s UUID /dev/sdb1", FakeResponse::ok("0f3c\n"))
+			.on("cat /etc/fstab", FakeResponse::ok("# static file system information\nUUID=dead /data ext4 defaults 0 2\n"));
+		runner
+	}
+
+	/// The old entry for /data names a UUID that no longer exists and is
+	/// replaced.
+	#[tokio::test]
+	async fn fstab_entries_are_keyed_by_uuid_and_backed_up() {
+		let runner = fstab_runner();
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::SetFstabEntry {
+			device: "sdb1".into(),
+			target: "/data/".into(),
+			options: "defaults,nofail".into(),
+			pass: None,
+		};
+		handle_request(request, &mut writer, &runner).await.unwrap();
+
+		let mkdir = runner.position("mkdir -p /data").unwrap();
+		let write = runner.position("tee /etc/fstab.disktui-new").unwrap();
+		let verify = runner.position("findmnt --verify --tab-file /etc/fstab.disktui-new").unwrap();
+		let backup = runner.position("cp -p /etc/fstab /etc/fstab.disktui-").unwrap();
+		let replace = runner.position("mv -f /etc/fstab.disktui-new /etc/fstab").unwrap();
+		assert!(mkdir < verify && write < verify && verify < backup && backup < replace);
+
+		let written = String::from_utf8(runner.calls()[write].stdin.clone().unwrap()).unwrap();
+		assert_eq!(written, "# static file system information\nUUID=0f3c\t/data\text4\tdefaults,nofail\t0\t2\n");
+	}
+
+	#[tokio::test]
+	async fn fstab_changes_findmnt_rejects_are_not_written() {
+		let runner = fstab_runner();
+		runner.once("findmnt --verify", FakeResponse::fail(1, "[E] unknown filesystem type"));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::SetFstabEntry {
+			device: "sdb1".into(),
+			target: "/srv/data".into(),
+			options: "defaults".into(),
+			pass: Some(2),
+		};
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+
+		assert!(err.to_string().contains("[E] unknown filesystem type"));
+		assert!(runner.position("rm -f /etc/fstab.disktui-new").is_some());
+		assert!(runner.position("rmdir /srv/data").is_some());
+		assert!(runner.position("mv").is_none());
+	}
+
+	#[tokio::test]
+	async fn unlock_failure_reports_cryptsetup_error() {
+		let runner = FakeRunner::new();
+		runner.on("cryptsetup open", FakeResponse::fail(2, "No key available with this passphrase."));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::UnlockLuks {
+			device: "sdb1".into(),
+			passphrase: "wrong".into(),
+			mapper_name: "luks-sdb1".into(),
+		};
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+
+		assert_eq!(err.to_string(), "Failed to unlock: No key available with this passphrase.");
+	}
+
+	#[tokio::test]
+	async fn self_test_refusals_report_smartctl_reason() {
+		let runner = FakeRunner::new();
+		let report = "=== START OF OFFLINE IMMEDIATE AND SELF-TEST SECTION ===\nCan't start self-test without aborting current test (90% remaining),\nadd '-t force' option to override, or run 'smartctl -X' to abort test.\n";
+		runner.with_path("/dev/sda").on("smartctl -t short", FakeResponse { stdout: report.into(), ..FakeResponse::fail(4, "") });
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::StartSelfTest { disk: "sda".into(), test: SelfTestKind::Short };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+
+		assert!(err.to_string().starts_with("Failed to start short self-test on sda: Can't start self-test without aborting current test (90% remaining), add"));
+		assert_eq!(runner.command_lines(), vec!["smartctl -t short /dev/sda"]);
+	}
+
+	/// A volume group "data" with 4 MiB extents: an 8 GiB "home" volume and
+	/// 2 GiB free.
+	fn lvm_runner() -> FakeRunner {
+		let runner = FakeRunner::new();
+		runner
+			.on("pvs", FakeResponse::ok(r#"{"report":[{"pv":[{"pv_name":"/dev/sdb1","vg_name":"data","pv_size":"10737418240","pv_free":"2147483648"}]}]}"#))
+			.on(
+				"vgs",
+				FakeResponse::ok(
+					r#"{"report":[{"vg":[{"vg_name":"data","vg_size":"10737418240","vg_free":"2147483648","vg_extent_size":"4194304","vg_free_count":"512","pv_count":"1","lv_count":"1"}]}]}"#,
+				),
+			)
+			.on("lvs", FakeResponse::ok(r#"{"report":[{"lv":[{"lv_name":"home","vg_name":"data","lv_size":"8589934592","lv_path":"/dev/data/home","lv_active":"active"}]}]}"#))
+			.on("findmnt -n /dev/mapper", FakeResponse::fail(1, ""))
+			.with_path("/dev/mapper/data-home");
+		runner
+	}
+
+	#[tokio::test]
+	async fn logical_volume_is_created_in_extents_and_formatted() {
+		let runner = lvm_runner();
+		runner.with_path("/dev/mapper/data-srv");
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let too_big = Request::CreateLogicalVolume { vg: "data".into(), name: "srv".into(), size: "3G".into(), fs_type: None };
+		let err = handle_request(too_big, &mut writer, &runner).await.unwrap_err();
+		assert_eq!(err.to_string(), "Not enough free space in data: 2.1GB available");
+
+		let request = Request::CreateLogicalVolume {
+			vg: "data".into(),
+			name: "srv".into(),
+			size: "50%".into(),
+			fs_type: Some("ext4".into()),
+		};
+		handle_request(request, &mut writer, &runner).await.unwrap();
+
+		let lvcreate = runner.position("lvcreate -y -n srv -l 256 data").unwrap();
+		let mkfs = runner.position("mkfs.ext4 /dev/mapper/data-srv").unwrap();
+		assert!(lvcreate < mkfs);
+	}
+
+	#[tokio::test]
+	async fn shrinking_logical_volume_resizes_filesystem_first() {
+		let runner = lvm_runner();
+		runner.on("blkid -p -o value -s TYPE /dev/mapper/data-home", FakeResponse::ok("ext4\n"));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::ResizeLogicalVolume { vg: "data".into(), name: "home".into(), size: "6G".into() };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+
+		let resize2fs = runner.position("resize2fs /dev/mapper/data-home 6291456K").unwrap();
+		let lvresize = runner.position("lvresize -f -l 1536 data/home").unwrap();
+		assert!(resize2fs < lvresize);
+
+		let request = Request::ResizeLogicalVolume { vg: "data".into(), name: "home".into(), size: "rest".into() };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+		let lvresize = runner.position("lvresize -l 2560 data/home").unwrap();
+		assert_eq!(runner.command_lines()[lvresize + 1], "resize2fs /dev/mapper/data-home");
+	}
+
+	#[tokio::test]
+	async fn shrinking_is_refused_when_the_filesystem_cannot_shrink() {
+		let runner = lvm_runner();
+		runner.on("blkid -p -o value -s TYPE /dev/mapper/data-home", FakeResponse::ok("vfat\n"));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::ResizeLogicalVolume { vg: "data".into(), name: "home".into(), size: "6G".into() };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+		assert!(err.to_string().contains("vfat filesystems cannot be shrunk"), "{}", err);
+		assert!(runner.position("lvresize").is_none());
+
+		let runner = resize_runner(2_000_000_000);
+		runner.once(
+			"lsblk -J -b -d -o SIZE,START,FSTYPE /dev/sdb1",
+			FakeResponse::ok(r#"{"blockdevices":[{"size":2000000000,"start":2048,"fstype":"swap"}]}"#),
+		);
+		let request = Request::ResizePartition { partition: "sdb1".into(), new_size: "1GB".into(), alignment: Alignment::Mib };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+		assert!(err.to_string().contains("Shrinking 'swap' filesystems"), "{}", err);
+		assert!(runner.position("sfdisk --force").is_none());
+	}
+
+	const MDSTAT_DEGRADED: &str = "\
+md0 : active raid1 sdc1[1](F) sdb1[0]
+      1046528 blocks super 1.2 [2/1] [U_]
+
+unused devices: <none>
+";
+
+	fn raid_runner() -> FakeRunner {
+		let runner = FakeRunner::new();
+		runner
+			.on("cat /proc/mdstat", FakeResponse::ok(MDSTAT_DEGRADED))
+			.on("findmnt -n", FakeResponse::fail(1, ""))
+			.with_path("/dev/sdd1")
+			.with_path("/dev/sde1");
+		runner
+	}
+
+	#[tokio::test]
+	async fn raid_array_is_created_from_unused_devices() {
+		let runner = raid_runner();
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::CreateRaid { name: "md1".into(), level: "raid5".into(), devices: vec!["sdd1".into(), "sde1".into()] };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+		assert_eq!(err.to_string(), "A raid5 array needs at least 3 devices");
+
+		let request = Request::CreateRaid { name: "md1".into(), level: "raid1".into(), devices: vec!["sdb1".into(), "sdd1".into()] };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+		assert_eq!(err.to_string(), "sdb1 is already a member of md0");
+
+		let request = Request::CreateRaid { name: "md1".into(), level: "raid1".into(), devices: vec!["sdd1".into(), "sde1".into()] };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+		assert!(runner.position("mdadm --create /dev/md1 --run --level=raid1 --raid-devices=2 /dev/sdd1 /dev/sde1").is_some());
+	}
+
+	#[tokio::test]
+	async fn active_raid_members_must_fail_before_removal() {
+		let runner = raid_runner();
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::RemoveRaidMember { array: "md0".into(), device: "sdb1".into() };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+		assert_eq!(err.to_string(), "sdb1 is an active member of md0. Mark it as failed first");
+
+		let request = Request::RemoveRaidMember { array: "md0".into(), device: "sdc1".into() };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+		let remove = runner.position("mdadm --manage /dev/md0 --remove /dev/sdc1").unwrap();
+		assert_eq!(runner.command_lines()[remove + 1], "mdadm --zero-superblock /dev/sdc1");
+
+		let request = Request::AddRaidMember { array: "md0".into(), device: "sdd1".into() };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+		assert!(runner.position("mdadm --manage /dev/md0 --add /dev/sdd1").is_some());
+	}
+
+	fn btrfs_runner() -> FakeRunner {
+		let runner = FakeRunner::new();
+		runner
+			.on("findmnt -n -f -P", FakeResponse::ok("TARGET=\"/mnt/my\\x20data\" FSTYPE=\"btrfs\" FSROOT=\"/@\"\n"))
+			.on("btrfs subvolume list -r", FakeResponse::ok("ID 258 gen 20 top level 5 path @snapshots/home-1\n"))
+			.on(
+				"btrfs subvolume list",
+				FakeResponse::ok(
+					"ID 256 gen 30 top level 5 path @\n\
+					 ID 257 gen 31 top level 5 path @home\n\
+					 ID 258 gen 20 top level 5 path @snapshots/home-1\n\
+					 ID 259 gen 32 top level 256 path @/srv\n",
+				),
+			)
+			.on("btrfs subvolume get-default", FakeResponse::ok("ID 256 gen 30 top level 5 path @\n"));
+		runner
+	}
+
+	#[tokio::test]
+	async fn subvolumes_are_snapshotted_below_the_mount_point() {
+		let runner = btrfs_runner();
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::SnapshotSubvolume { device: "sdb1".into(), id: 259, path: "srv-1".into() };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+		assert!(runner.position("btrfs subvolume snapshot -r /mnt/my data/srv /mnt/my data/srv-1").is_some());
+
+		let request = Request::SnapshotSubvolume { device: "sdb1".into(), id: 257, path: "home-2".into() };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+		assert_eq!(err.to_string(), "@home is outside the mounted subvolume. Mount the top level to snapshot it");
+	}
+
+	#[tokio::test]
+	async fn default_and_mounted_subvolumes_are_not_deleted() {
+		let runner = btrfs_runner();
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::DeleteSubvolume { device: "sdb1".into(), id: 256 };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+		assert_eq!(err.to_string(), "@ is the default subvolume. Make another one the default first");
+
+		let request = Request::SetDefaultSubvolume { device: "sdb1".into(), id: 257 };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+		assert!(runner.position("btrfs subvolume set-default 257 /mnt/my data").is_some());
+
+		let request = Request::DeleteSubvolume { device: "sdb1".into(), id: 258 };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+		assert!(runner.position("btrfs subvolume delete --subvolid 258 /mnt/my data").is_some());
+
+		let runner = FakeRunner::new();
+		runner.on("findmnt -n -f -P", FakeResponse::ok("TARGET=\"/mnt/data\" FSTYPE=\"ext4\" FSROOT=\"/\"\n"));
+		let request = Request::ListSubvolumes { device: "sdb1".into() };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+		assert_eq!(err.to_string(), "sdb1 holds ext4, not btrfs");
+	}
+
+	#[tokio::test]
+	async fn attached_members_are_not_overwritten() {
+		let runner = FakeRunner::new();
+		runner
+			.on("blkid -p -o value -s TYPE /dev/sdb1", FakeResponse::ok("LVM2_member\n"))
+			.on("pvs --noheadings -o vg_name /dev/sdb1", FakeResponse::ok("  vg0\n"))
+			.on("blkid -p -o value -s TYPE /dev/sdc1", FakeResponse::ok("linux_raid_member\n"))
+			.on("blkid -p -o value -s TYPE /dev/sdd1", FakeResponse::ok("linux_raid_member\n"))
+			.on("blkid -p -o value -s TYPE /dev/sde1", FakeResponse::ok("btrfs\n"))
+			.on("btrfs filesystem show /dev/sde1", FakeResponse::fail(1, "ERROR: not a valid btrfs filesystem"))
+			.on("findmnt -n", FakeResponse::fail(1, ""))
+			.with_dir("/sys/class/block/sdc1/holders", &["md0"])
+			.with_path("/dev/sdb1");
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::Format { device: "sdb1".into(), fs_type: "ext4".into(), label: None };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+		assert_eq!(err.to_string(), "sdb1 is a physical volume of vg0. Move its extents with 'pvmove' and remove it with 'vgreduce' first");
+
+		let request = Request::DeletePartition { partition: "sdc1".into() };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+		assert_eq!(err.to_string(), "sdc1 is a member of md0. Fail and remove it from the array first");
+
+		// A member of a stopped array is only left with its superblock, and
+		// one whose btrfs filesystem cannot be looked up may still be in use:
+		// both are deleted with a warning.
+		let buffer = SharedBuffer::default();
+		let mut writer = ResponseWriter::new(Box::new(buffer.clone()));
+		let request = Request::DeletePartition { partition: "sdd1".into() };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+		assert!(runner.position("parted -s /dev/sdd rm 1").is_some());
+		let request = Request::DeletePartition { partition: "sde1".into() };
+		handle_request(request, &mut writer, &runner).await.unwrap();
+		assert!(runner.position("parted -s /dev/sde rm 1").is_some());
+
+		let warnings: Vec<String> = buffer
+			.responses()
+			.into_iter()
+			.filter_map(|response| match response {
+				Response::Notification { level, message } if level == "warning" => Some(message),
+				_ => None,
+			})
+			.collect();
+		assert_eq!(
+			warnings,
+			vec![
+				"sdd1 is a member of an md array that is not running",
+				"Could not check whether sde1 belongs to a btrfs filesystem on several devices",
+			]
+		);
+	}
+
+	#[tokio::test]
+	async fn members_are_not_given_to_lvm_or_raid() {
+		let runner = FakeRunner::new();
+		runner
+			.on("findmnt -n", FakeResponse::fail(1, ""))
+			.on("blkid -p -o value -s TYPE /dev/sdb1", FakeResponse::ok("btrfs\n"))
+			.on("btrfs filesystem show /dev/sdb1", FakeResponse::ok("Label: none  uuid: 4b0c\n\tTotal devices 2 FS bytes used 144.00KiB\n"))
+			.on("blkid -p -o value -s TYPE /dev/sdc1", FakeResponse::ok("LVM2_member\n"))
+			.on("pvs --noheadings -o vg_name /dev/sdc1", FakeResponse::ok("  vg0\n"))
+			.with_path("/dev/sdb1")
+			.with_path("/dev/sdc1")
+			.with_path("/dev/sdd1");
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let request = Request::CreatePhysicalVolume { device: "sdb1".into() };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+		assert_eq!(err.to_string(), "sdb1 is one of 2 devices of a btrfs filesystem. Remove it with 'btrfs device remove' first");
+
+		let request = Request::CreateRaid { name: "md1".into(), level: "raid1".into(), devices: vec!["sdd1".into(), "sdc1".into()] };
+		let err = handle_request(request, &mut writer, &runner).await.unwrap_err();
+		assert_eq!(err.to_string(), "sdc1 is a physical volume of vg0. Move its extents with 'pvmove' and remove it with 'vgreduce' first");
+
+		assert!(runner.position("pvcreate").is_none());
+		assert!(runner.position("mdadm --create").is_none());
+	}
+}
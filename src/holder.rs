@@ -0,0 +1,247 @@
+use crate::lvm::{self, PhysicalVolume};
+use crate::operations::BlockDevice;
+use crate::partition::Partition;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// A structure spanning several devices that a partition is part of. Such
+/// partitions look like free filesystems to a plain listing, so formatting,
+/// deleting or mounting them has to wait until they are detached.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Holder {
+    /// One device of a btrfs filesystem; `devices` are the others.
+    Btrfs { devices: Vec<String> },
+    /// An LVM physical volume, with its volume group once it is in one.
+    Lvm { vg: Option<String> },
+    /// A member of an md array, named while the array runs.
+    Raid { array: Option<String> },
+}
+
+impl Holder {
+    /// Finds the holder of a partition from its filesystem type and the
+    /// devices stacked on it: md arrays such as "md0" and device-mapper
+    /// names such as "vg0-root". Volume groups without an active logical
+    /// volume are filled in by [`attach_volume_groups`].
+    pub fn detect(filesystem: Option<&str>, stacked: &[String]) -> Option<Self> {
+        match filesystem? {
+            "LVM2_member" => Some(Holder::Lvm {
+                vg: stacked.iter().find_map(|name| lvm::vg_of_mapper(name)),
+            }),
+            "linux_raid_member" => Some(Holder::Raid {
+                array: stacked.iter().find(|name| is_md(name)).cloned(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Short description for the partition table.
+    pub fn label(&self) -> String {
+        match self {
+            Holder::Btrfs { devices } => format!("btrfs, {} devices", devices.len() + 1),
+            Holder::Lvm { vg: Some(vg) } => format!("LVM: {}", vg),
+            Holder::Lvm { vg: None } => "LVM PV".to_string(),
+            Holder::Raid { array: Some(array) } => format!("RAID: {}", array),
+            Holder::Raid { array: None } => "RAID (stopped)".to_string(),
+        }
+    }
+
+    /// Why `partition` cannot be formatted, deleted, resized or encrypted
+    /// while it is attached, and how to detach it. `None` once it is only
+    /// left with the signature of the structure.
+    pub fn blocker(&self, partition: &str) -> Option<String> {
+        match self {
+            Holder::Btrfs { devices } => Some(format!(
+                "{} is one of the devices of a btrfs filesystem with {}. Remove it with 'btrfs device remove' first",
+                partition,
+                devices.join(", ")
+            )),
+            Holder::Lvm { vg: Some(vg) } => Some(format!(
+                "{} is a physical volume of {}. Move its extents with 'pvmove' and remove it with 'vgreduce' first",
+                partition, vg
+            )),
+            Holder::Raid { array: Some(array) } => Some(format!(
+                "{} is a member of {}. Fail and remove it in the RAID view first",
+                partition, array
+            )),
+            Holder::Lvm { vg: None } | Holder::Raid { array: None } => None,
+        }
+    }
+
+    /// What is lost by overwriting `partition` when nothing is attached to
+    /// it any more, for the confirmation dialog.
+    pub fn warning(&self, partition: &str) -> String {
+        match self {
+            Holder::Btrfs { .. } => {
+                format!("{} belongs to a multi-device btrfs filesystem", partition)
+            }
+            Holder::Lvm { .. } => {
+                format!("{} is an LVM physical volume in no volume group", partition)
+            }
+            Holder::Raid { .. } => format!(
+                "{} is a member of a stopped md array, which cannot be assembled with it any more",
+                partition
+            ),
+        }
+    }
+
+    /// Why `partition` cannot be mounted by itself.
+    pub fn mount_blocker(&self, partition: &str) -> Option<String> {
+        match self {
+            Holder::Btrfs { .. } => None,
+            Holder::Lvm { .. } => Some(format!(
+                "{} is an LVM physical volume. Mount its logical volumes instead",
+                partition
+            )),
+            Holder::Raid { .. } => Some(format!(
+                "{} is a RAID member. Mount the array instead",
+                partition
+            )),
+        }
+    }
+}
+
+/// Whether a kernel device name is an md array, such as "md0".
+fn is_md(name: &str) -> bool {
+    name.strip_prefix("md")
+        .is_some_and(|number| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Marks the partitions of btrfs filesystems that span several of them,
+/// found by their shared filesystem UUID.
+pub fn attach_btrfs_holders(devices: &mut [BlockDevice]) {
+    let is_btrfs = |p: &Partition| p.filesystem.as_deref() == Some("btrfs");
+    let mut members: HashMap<String, Vec<String>> = HashMap::new();
+    for part in devices
+        .iter()
+        .flat_map(|d| &d.partitions)
+        .filter(|p| is_btrfs(p))
+    {
+        if let Some(uuid) = &part.uuid {
+            members
+                .entry(uuid.clone())
+                .or_default()
+                .push(part.name.clone());
+        }
+    }
+    for part in devices
+        .iter_mut()
+        .flat_map(|d| d.partitions.iter_mut())
+        .filter(|p| is_btrfs(p))
+    {
+        let Some(names) = part.uuid.as_ref().and_then(|uuid| members.get(uuid)) else {
+            continue;
+        };
+        if names.len() > 1 {
+            part.holder = Some(Holder::Btrfs {
+                devices: names
+                    .iter()
+                    .filter(|name| **name != part.name)
+                    .cloned()
+                    .collect(),
+            });
+        }
+    }
+}
+
+/// Names the volume group of physical volumes that have no active logical
+/// volume, which the devices stacked on them cannot tell.
+pub fn attach_volume_groups(devices: &mut [BlockDevice], pvs: &[PhysicalVolume]) {
+    for part in devices.iter_mut().flat_map(|d| d.partitions.iter_mut()) {
+        if let Some(Holder::Lvm { vg: vg @ None }) = &mut part.holder {
+            let path = format!("/dev/{}", part.name);
+            *vg = pvs
+                .iter()
+                .find(|pv| pv.name == path)
+                .and_then(|pv| pv.vg_name.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::DiskGeometry;
+
+    #[test]
+    fn members_are_found_by_type_and_stacked_devices() {
+        let stacked = vec!["my--vg-root".to_string()];
+        assert_eq!(
+            Holder::detect(Some("LVM2_member"), &stacked),
+            Some(Holder::Lvm {
+                vg: Some("my-vg".into())
+            })
+        );
+        assert_eq!(
+            Holder::detect(Some("linux_raid_member"), &["md127".into()]),
+            Some(Holder::Raid {
+                array: Some("md127".into())
+            })
+        );
+        assert_eq!(
+            Holder::detect(Some("linux_raid_member"), &[]),
+            Some(Holder::Raid { array: None })
+        );
+        assert_eq!(Holder::detect(Some("ext4"), &stacked), None);
+
+        let mut devices = vec![BlockDevice {
+            name: "sdb".into(),
+            size: 0,
+            model: None,
+            serial: None,
+            partition_table: None,
+            geometry: DiskGeometry::default(),
+            partitions: vec![Partition {
+                name: "sdb1".into(),
+                holder: Holder::detect(Some("LVM2_member"), &[]),
+                ..Partition::default()
+            }],
+        }];
+        let pv = PhysicalVolume {
+            name: "/dev/sdb1".into(),
+            vg_name: Some("data".into()),
+            size: 0,
+            free: 0,
+        };
+        attach_volume_groups(&mut devices, &[pv]);
+        let holder = devices[0].partitions[0].holder.as_ref().unwrap();
+        assert_eq!(holder.label(), "LVM: data");
+        assert!(holder.blocker("sdb1").is_some());
+        assert!(Holder::Raid { array: None }.blocker("sdb1").is_none());
+        assert!(Holder::Raid { array: None }.mount_blocker("sdb1").is_some());
+    }
+
+    #[test]
+    fn btrfs_members_share_a_uuid() {
+        let member = |name: &str, uuid: &str| Partition {
+            name: name.into(),
+            filesystem: Some("btrfs".into()),
+            uuid: Some(uuid.into()),
+            ..Partition::default()
+        };
+        let disk = |name: &str, partitions| BlockDevice {
+            name: name.into(),
+            size: 0,
+            model: None,
+            serial: None,
+            partition_table: None,
+            geometry: DiskGeometry::default(),
+            partitions,
+        };
+        let mut devices = vec![
+            disk("sdb", vec![member("sdb1", "a"), member("sdb2", "b")]),
+            disk("sdc", vec![member("sdc1", "a")]),
+        ];
+        attach_btrfs_holders(&mut devices);
+        assert_eq!(
+            devices[0].partitions[0].holder,
+            Some(Holder::Btrfs {
+                devices: vec!["sdc1".into()]
+            })
+        );
+        assert_eq!(devices[0].partitions[1].holder, None);
+        assert_eq!(
+            devices[0].partitions[0].holder.as_ref().unwrap().label(),
+            "btrfs, 2 devices"
+        );
+    }
+}
@@ -0,0 +1,419 @@
+use crate::operations::BlockDevice;
+use crate::partition::Partition;
+use crate::utils::SizeSpec;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const MIB: u64 = 1_048_576;
+
+/// Size of the GPT partition entry array, stored after the primary header
+/// and before the backup header.
+const GPT_ENTRIES_BYTES: u64 = 16_384;
+
+/// Sector sizes and preferred I/O size of a disk, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskGeometry {
+    pub logical_sector_size: u64,
+    pub physical_sector_size: u64,
+    /// Zero when the disk does not report one.
+    pub optimal_io_size: u64,
+}
+
+impl Default for DiskGeometry {
+    fn default() -> Self {
+        Self {
+            logical_sector_size: 512,
+            physical_sector_size: 512,
+            optimal_io_size: 0,
+        }
+    }
+}
+
+/// Where new partitions start, and where resized partitions end.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Alignment {
+    #[default]
+    #[serde(rename = "1MiB")]
+    Mib,
+    /// The optimal I/O size the disk reports, combined with 1 MiB.
+    #[serde(rename = "optimal")]
+    Optimal,
+}
+
+impl Alignment {
+    /// The alignment in bytes for a disk. It is always a multiple of the
+    /// physical sector size, and falls back to 1 MiB when the disk reports
+    /// no usable optimal I/O size.
+    pub fn bytes(self, geometry: &DiskGeometry) -> u64 {
+        let physical = geometry.physical_sector_size.max(1);
+        let base = lcm(MIB, physical);
+        let io = geometry.optimal_io_size;
+        match self {
+            Alignment::Optimal if io > 0 && io.is_multiple_of(physical) => lcm(base, io),
+            _ => base,
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Alignment::default()
+    }
+}
+
+impl fmt::Display for Alignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Alignment::Mib => write!(f, "1MiB"),
+            Alignment::Optimal => write!(f, "optimal"),
+        }
+    }
+}
+
+impl std::str::FromStr for Alignment {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "1mib" | "mib" => Ok(Alignment::Mib),
+            "optimal" => Ok(Alignment::Optimal),
+            _ => Err(anyhow!(
+                "Unsupported alignment: {} (use 1MiB or optimal)",
+                s
+            )),
+        }
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+fn lcm(a: u64, b: u64) -> u64 {
+    a / gcd(a, b) * b
+}
+
+fn align_up(offset: u64, alignment: u64) -> u64 {
+    offset.div_ceil(alignment) * alignment
+}
+
+fn align_down(offset: u64, alignment: u64) -> u64 {
+    offset / alignment * alignment
+}
+
+/// An unallocated region of a disk, in bytes. `end` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FreeSpace {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl FreeSpace {
+    pub fn size(&self) -> u64 {
+        self.end.saturating_sub(self.start)
+    }
+
+    pub fn contains(&self, offset: u64) -> bool {
+        self.start <= offset && offset < self.end
+    }
+}
+
+/// Where a new partition goes: its byte range and the disk's sector size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub range: FreeSpace,
+    pub sector_size: u64,
+}
+
+impl Placement {
+    /// Start and end arguments for `parted mkpart`, in sectors. parted
+    /// takes the last sector of the partition as its end.
+    pub fn mkpart_bounds(&self) -> (String, String) {
+        (
+            format!("{}s", self.range.start / self.sector_size),
+            format!("{}s", self.range.end / self.sector_size - 1),
+        )
+    }
+}
+
+/// One row of a disk's layout: a partition or a gap between partitions.
+#[derive(Debug, Clone, Copy)]
+pub enum LayoutRow<'a> {
+    Partition(&'a Partition),
+    Free(FreeSpace),
+}
+
+/// Unallocated regions of at least 1 MiB on a disk of `disk_size` bytes,
+/// given the partitions as `(start, end)` byte ranges with an exclusive end.
+/// Space taken by the partition table itself is left out.
+pub fn free_gaps(
+    disk_size: u64,
+    table_type: Option<&str>,
+    sector_size: u64,
+    extents: &[(u64, u64)],
+) -> Vec<FreeSpace> {
+    let (first_usable, usable_end) = if table_type == Some("gpt") {
+        // Protective MBR, header and entries at the start; entries and the
+        // backup header at the end.
+        (
+            2 * sector_size + GPT_ENTRIES_BYTES,
+            disk_size.saturating_sub(GPT_ENTRIES_BYTES + sector_size),
+        )
+    } else {
+        (sector_size, disk_size)
+    };
+
+    let mut extents = extents.to_vec();
+    extents.sort();
+
+    let mut gaps = Vec::new();
+    let mut cursor = first_usable;
+    for (start, end) in extents.into_iter().chain([(usable_end, usable_end)]) {
+        let gap = FreeSpace {
+            start: cursor,
+            end: start.min(usable_end),
+        };
+        if gap.size() >= MIB {
+            gaps.push(gap);
+        }
+        cursor = cursor.max(end);
+    }
+    gaps
+}
+
+/// Whether the disk is formatted as a whole instead of partitioned; it is
+/// then listed with itself as its only partition.
+pub fn is_whole_disk(device: &BlockDevice) -> bool {
+    device.partitions.len() == 1 && device.partitions[0].name == device.name
+}
+
+/// Gaps of a disk as listed. Disks without a partition table, or with
+/// partitions whose position is unknown, have none.
+pub fn device_gaps(device: &BlockDevice) -> Vec<FreeSpace> {
+    if device.partition_table.is_none() || is_whole_disk(device) {
+        return Vec::new();
+    }
+    let extents: Option<Vec<(u64, u64)>> = device
+        .partitions
+        .iter()
+        .map(|part| {
+            part.geometry
+                .map(|geometry| (geometry.start_bytes(), geometry.end_bytes()))
+        })
+        .collect();
+    match extents {
+        Some(extents) => free_gaps(
+            device.size,
+            device.partition_table.as_deref(),
+            device.geometry.logical_sector_size,
+            &extents,
+        ),
+        None => Vec::new(),
+    }
+}
+
+/// Partitions and gaps of a disk in on-disk order.
+pub fn layout_rows(device: &BlockDevice) -> Vec<LayoutRow<'_>> {
+    let mut rows: Vec<LayoutRow> = device.partitions.iter().map(LayoutRow::Partition).collect();
+    rows.extend(device_gaps(device).into_iter().map(LayoutRow::Free));
+    rows.sort_by_key(|row| match row {
+        LayoutRow::Partition(part) => part.start().unwrap_or(0),
+        LayoutRow::Free(gap) => gap.start,
+    });
+    rows
+}
+
+/// Partitions of `device` that do not start on the alignment boundary.
+pub fn misaligned_partitions(device: &BlockDevice, alignment: Alignment) -> Vec<&Partition> {
+    let bytes = alignment.bytes(&device.geometry);
+    device
+        .partitions
+        .iter()
+        .filter(|part| {
+            part.geometry
+                .is_some_and(|geometry| !geometry.is_aligned(bytes))
+        })
+        .collect()
+}
+
+/// Picks where a new partition goes: inside the gap containing `start`, or
+/// the last gap on the disk when no start is given. The start is moved up
+/// to the alignment boundary. An empty `size` takes the rest of the gap up
+/// to the last boundary in it, and relative sizes such as "50%" or "-1G"
+/// are taken from that space. Sizes are rounded down to whole sectors.
+pub fn place_partition(
+    gaps: &[FreeSpace],
+    start: Option<u64>,
+    size: &str,
+    geometry: &DiskGeometry,
+    alignment: Alignment,
+) -> Result<Placement> {
+    let gap = match start {
+        Some(start) => *gaps
+            .iter()
+            .find(|gap| gap.contains(start))
+            .ok_or_else(|| anyhow!("No free space at offset {}", start))?,
+        None => *gaps
+            .last()
+            .ok_or_else(|| anyhow!("No free space available"))?,
+    };
+    let sector_size = geometry.logical_sector_size;
+    let align = alignment.bytes(geometry);
+
+    let first = align_up(start.unwrap_or(gap.start).max(gap.start), align);
+    let last = align_down(gap.end, sector_size);
+    if first >= last {
+        return Err(anyhow!("No aligned free space available"));
+    }
+
+    let available = match align_down(gap.end, align) {
+        end if end > first => end - first,
+        _ => last - first,
+    };
+    let spec: SizeSpec = size.parse()?;
+    let requested = align_down(spec.resolve(available, sector_size)?, sector_size);
+    if requested == 0 {
+        return Err(anyhow!("Size must be at least one sector"));
+    }
+    if requested > last - first {
+        return Err(anyhow!("Requested size exceeds available space"));
+    }
+    Ok(Placement {
+        range: FreeSpace {
+            start: first,
+            end: first + requested,
+        },
+        sector_size,
+    })
+}
+
+/// The largest size `part` can be resized to: its own size plus the free
+/// space right after it.
+pub fn max_partition_size(device: &BlockDevice, part: &Partition) -> u64 {
+    let Some(end) = part.geometry.map(|geometry| geometry.end_bytes()) else {
+        return part.size;
+    };
+    let following = device_gaps(device)
+        .into_iter()
+        .find(|gap| gap.start == end)
+        .map(|gap| align_down(gap.size(), device.geometry.logical_sector_size))
+        .unwrap_or(0);
+    part.size + following
+}
+
+/// New size for a partition being resized from `current` to `requested`
+/// bytes, so that it ends on an alignment boundary if it starts on one.
+/// Shrinking rounds up so the shrunk filesystem still fits; growing rounds
+/// down so the partition stays within the requested size.
+pub fn aligned_resize(current: u64, requested: u64, alignment: u64) -> u64 {
+    let aligned = if requested < current {
+        align_up(requested, alignment)
+    } else {
+        align_down(requested, alignment)
+    };
+    if aligned == 0 { requested } else { aligned }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gaps_include_holes_between_partitions() {
+        let gib = 1024 * MIB;
+        // 1 MiB - 1 GiB, hole, 2 GiB - 3 GiB, rest of an 8 GiB GPT disk free.
+        let extents = [(2 * gib, 3 * gib), (MIB, gib)];
+
+        let gaps = free_gaps(8 * gib, Some("gpt"), 512, &extents);
+
+        assert_eq!(
+            gaps,
+            vec![
+                FreeSpace {
+                    start: gib,
+                    end: 2 * gib
+                },
+                FreeSpace {
+                    start: 3 * gib,
+                    end: 8 * gib - 33 * 512
+                },
+            ]
+        );
+        assert_eq!(
+            free_gaps(8 * gib, Some("dos"), 4096, &[]),
+            vec![FreeSpace {
+                start: 4096,
+                end: 8 * gib
+            }]
+        );
+    }
+
+    #[test]
+    fn place_partition_aligns_inside_the_selected_gap() {
+        let gib = 1024 * MIB;
+        let gaps = [
+            FreeSpace {
+                start: gib + 512,
+                end: 2 * gib,
+            },
+            FreeSpace {
+                start: 3 * gib,
+                end: 8 * gib - 33 * 512,
+            },
+        ];
+        let geometry = DiskGeometry::default();
+
+        let hole = place_partition(&gaps, Some(gib + 512), "", &geometry, Alignment::Mib).unwrap();
+        assert_eq!(
+            hole.range,
+            FreeSpace {
+                start: gib + MIB,
+                end: 2 * gib
+            }
+        );
+        assert_eq!(
+            hole.mkpart_bounds(),
+            ("2099200s".to_string(), "4194303s".to_string())
+        );
+
+        let last = place_partition(&gaps, None, "1G", &geometry, Alignment::Mib).unwrap();
+        assert_eq!(
+            last.mkpart_bounds(),
+            ("6291456s".to_string(), "8388607s".to_string())
+        );
+        let rest = place_partition(&gaps, None, "", &geometry, Alignment::Mib).unwrap();
+        assert_eq!(rest.range.end, 8 * gib - MIB);
+        let leave_free = place_partition(&gaps, None, "-1G", &geometry, Alignment::Mib).unwrap();
+        assert_eq!(leave_free.range.end, 7 * gib - MIB);
+        let half =
+            place_partition(&gaps, Some(gib + 512), "50%", &geometry, Alignment::Mib).unwrap();
+        assert_eq!(half.range.size(), (gib - MIB) / 2);
+
+        assert!(place_partition(&gaps, Some(gib), "", &geometry, Alignment::Mib).is_err());
+        assert!(place_partition(&gaps, Some(gib + 512), "2G", &geometry, Alignment::Mib).is_err());
+    }
+
+    #[test]
+    fn optimal_alignment_uses_the_io_size_on_4k_disks() {
+        let geometry = DiskGeometry {
+            logical_sector_size: 4096,
+            physical_sector_size: 4096,
+            optimal_io_size: 3 * MIB,
+        };
+        assert_eq!(Alignment::Mib.bytes(&geometry), MIB);
+        assert_eq!(Alignment::Optimal.bytes(&geometry), 3 * MIB);
+        assert_eq!(Alignment::Optimal.bytes(&DiskGeometry::default()), MIB);
+
+        let gaps = free_gaps(8 * 1024 * MIB, Some("gpt"), 4096, &[]);
+        assert_eq!(gaps[0].start, 24_576);
+        let placement = place_partition(&gaps, None, "1GB", &geometry, Alignment::Optimal).unwrap();
+        assert_eq!(placement.range.start, 3 * MIB);
+        assert_eq!(placement.range.size(), 999_997_440);
+        assert_eq!(placement.mkpart_bounds().0, "768s");
+
+        assert_eq!(aligned_resize(2_000_000_000, 1_000_000_000, MIB), 954 * MIB);
+        assert_eq!(
+            aligned_resize(1_000_000_000, 2_000_000_000, MIB),
+            1907 * MIB
+        );
+    }
+}
@@ -1,12 +1,33 @@
 pub mod app;
+pub mod audit;
+pub mod backup;
+pub mod btrfs;
+pub mod cli;
 pub mod config;
 pub mod disk;
 pub mod event;
+pub mod fsck;
+pub mod fslabel;
+pub mod fstab;
 pub mod handler;
+pub mod helper;
+pub mod holder;
+pub mod layout;
+pub mod lvm;
+pub mod monitor;
+pub mod mount;
 pub mod notification;
 pub mod operations;
 pub mod partition;
+pub mod parttype;
 pub mod protocol;
+pub mod queue;
+pub mod raid;
+pub mod runner;
+pub mod session;
+pub mod smart;
+pub mod swap;
+pub mod sysfs;
 pub mod theme;
 pub mod tui;
 pub mod ui;
@@ -0,0 +1,284 @@
+use crate::runner::CommandRunner;
+use crate::utils::SizeSpec;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Report options shared by `pvs`, `vgs` and `lvs`: JSON with sizes in
+/// plain bytes.
+const REPORT_ARGS: [&str; 5] = ["--reportformat", "json", "--units", "b", "--nosuffix"];
+
+const PV_FIELDS: &str = "pv_name,vg_name,pv_size,pv_free";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PhysicalVolume {
+    /// Device path, such as "/dev/sdb1".
+    pub name: String,
+    /// `None` for a PV that is not in a volume group yet.
+    pub vg_name: Option<String>,
+    pub size: u64,
+    pub free: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VolumeGroup {
+    pub name: String,
+    pub size: u64,
+    pub free: u64,
+    pub extent_size: u64,
+    pub free_extents: u64,
+    pub pv_count: u64,
+    pub lv_count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogicalVolume {
+    pub name: String,
+    pub vg_name: String,
+    pub size: u64,
+    /// "/dev/<vg>/<lv>".
+    pub path: String,
+    pub active: bool,
+}
+
+impl LogicalVolume {
+    /// Name of the device-mapper node under /dev/mapper.
+    pub fn mapper_name(&self) -> String {
+        mapper_name(&self.vg_name, &self.name)
+    }
+}
+
+/// Physical volumes, volume groups and logical volumes as the LVM tools
+/// report them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LvmReport {
+    pub pvs: Vec<PhysicalVolume>,
+    pub vgs: Vec<VolumeGroup>,
+    pub lvs: Vec<LogicalVolume>,
+}
+
+impl LvmReport {
+    pub async fn load(runner: &dyn CommandRunner) -> Result<Self> {
+        let pvs = report(runner, "pvs", PV_FIELDS).await?;
+        let vgs = report(
+            runner,
+            "vgs",
+            "vg_name,vg_size,vg_free,vg_extent_size,vg_free_count,pv_count,lv_count",
+        )
+        .await?;
+        let lvs = report(runner, "lvs", "lv_name,vg_name,lv_size,lv_path,lv_active").await?;
+        Self::parse(&pvs, &vgs, &lvs)
+    }
+
+    pub fn parse(pvs: &str, vgs: &str, lvs: &str) -> Result<Self> {
+        let pvs = parse_pvs(pvs)?;
+        let vgs = rows(vgs, "vg")?
+            .iter()
+            .map(|row| VolumeGroup {
+                name: text(&row["vg_name"]),
+                size: number(&row["vg_size"]),
+                free: number(&row["vg_free"]),
+                extent_size: number(&row["vg_extent_size"]),
+                free_extents: number(&row["vg_free_count"]),
+                pv_count: number(&row["pv_count"]),
+                lv_count: number(&row["lv_count"]),
+            })
+            .collect();
+        let lvs = rows(lvs, "lv")?
+            .iter()
+            .map(|row| LogicalVolume {
+                name: text(&row["lv_name"]),
+                vg_name: text(&row["vg_name"]),
+                size: number(&row["lv_size"]),
+                path: text(&row["lv_path"]),
+                active: text(&row["lv_active"]) == "active",
+            })
+            .collect();
+        Ok(Self { pvs, vgs, lvs })
+    }
+
+    pub fn vg(&self, name: &str) -> Option<&VolumeGroup> {
+        self.vgs.iter().find(|vg| vg.name == name)
+    }
+
+    pub fn lv(&self, vg: &str, name: &str) -> Option<&LogicalVolume> {
+        self.lvs
+            .iter()
+            .find(|lv| lv.vg_name == vg && lv.name == name)
+    }
+
+    pub fn pv(&self, path: &str) -> Option<&PhysicalVolume> {
+        self.pvs.iter().find(|pv| pv.name == path)
+    }
+}
+
+/// The physical volumes alone, with the volume group of each whether or
+/// not any of its logical volumes is active.
+pub async fn physical_volumes(runner: &dyn CommandRunner) -> Result<Vec<PhysicalVolume>> {
+    parse_pvs(&report(runner, "pvs", PV_FIELDS).await?)
+}
+
+fn parse_pvs(pvs: &str) -> Result<Vec<PhysicalVolume>> {
+    Ok(rows(pvs, "pv")?
+        .iter()
+        .map(|row| PhysicalVolume {
+            name: text(&row["pv_name"]),
+            vg_name: Some(text(&row["vg_name"])).filter(|name| !name.is_empty()),
+            size: number(&row["pv_size"]),
+            free: number(&row["pv_free"]),
+        })
+        .collect())
+}
+
+async fn report(runner: &dyn CommandRunner, command: &str, fields: &str) -> Result<String> {
+    let mut args = REPORT_ARGS.to_vec();
+    args.extend(["-o", fields]);
+    let output = runner
+        .output(command, &args)
+        .await
+        .with_context(|| format!("Failed to execute {}. Install the lvm2 package.", command))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The rows of a `--reportformat json` report, which wraps them as
+/// `{"report": [{"<kind>": [...]}]}`.
+fn rows(json: &str, kind: &str) -> Result<Vec<Value>> {
+    let root: Value =
+        serde_json::from_str(json).map_err(|e| anyhow!("Unexpected {}s output: {}", kind, e))?;
+    Ok(root["report"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|report| report[kind].as_array())
+        .flatten()
+        .cloned()
+        .collect())
+}
+
+fn text(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+/// Numbers come as strings in LVM reports.
+fn number(value: &Value) -> u64 {
+    value
+        .as_str()
+        .and_then(|value| value.trim().parse().ok())
+        .or_else(|| value.as_u64())
+        .unwrap_or(0)
+}
+
+/// Checks a volume group or logical volume name. LVM allows a few more
+/// characters, but these are the ones that also make valid device names.
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() {
+        return Err(anyhow!("Name cannot be empty"));
+    }
+    if name.len() > 64 {
+        return Err(anyhow!("Name is too long (at most 64 characters)"));
+    }
+    if name.starts_with('-') {
+        return Err(anyhow!("Name cannot start with '-'"));
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '_' && *c != '-')
+    {
+        return Err(anyhow!(
+            "Name cannot contain '{}'; use letters, digits, '-' and '_'",
+            c
+        ));
+    }
+    Ok(())
+}
+
+/// Number of extents for a logical volume of `size`, where `available` is
+/// the space it may take. Fixed amounts round up so the volume is at least
+/// as big as asked; relative sizes round down to fit.
+pub fn extents(size: SizeSpec, available: u64, extent_size: u64) -> Result<u64> {
+    let extent_size = extent_size.max(1);
+    // LVM sizes in sectors are always 512-byte sectors.
+    let bytes = size.resolve(available, 512)?;
+    Ok(match size {
+        SizeSpec::Amount(_) => bytes.div_ceil(extent_size),
+        _ => bytes / extent_size,
+    })
+}
+
+/// The device-mapper name of a logical volume, in which dashes of the
+/// names are doubled so the separator stays unambiguous.
+pub fn mapper_name(vg: &str, lv: &str) -> String {
+    format!("{}-{}", vg.replace('-', "--"), lv.replace('-', "--"))
+}
+
+/// The volume group of a logical volume's mapper name, undoing the
+/// doubled dashes of [`mapper_name`]. `None` for names without a single
+/// dash, which are not logical volumes.
+pub fn vg_of_mapper(name: &str) -> Option<String> {
+    let mut vg = String::new();
+    let mut chars = name.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '-' if chars.peek() == Some(&'-') => {
+                chars.next();
+                vg.push('-');
+            }
+            '-' => return (!vg.is_empty()).then_some(vg),
+            c => vg.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_are_parsed() {
+        let pvs = r#"{"report": [{"pv": [
+            {"pv_name":"/dev/sdb1", "vg_name":"data", "pv_size":"10733223936", "pv_free":"2143289344"},
+            {"pv_name":"/dev/sdc1", "vg_name":"", "pv_size":"5368709120", "pv_free":"5368709120"}
+        ]}]}"#;
+        let vgs = r#"{"report": [{"vg": [
+            {"vg_name":"data", "vg_size":"10733223936", "vg_free":"2143289344", "vg_extent_size":"4194304",
+             "vg_free_count":"511", "pv_count":"1", "lv_count":"1"}
+        ]}]}"#;
+        let lvs = r#"{"report": [{"lv": [
+            {"lv_name":"home", "vg_name":"data", "lv_size":"8589934592", "lv_path":"/dev/data/home", "lv_active":"active"}
+        ]}]}"#;
+        let report = LvmReport::parse(pvs, vgs, lvs).unwrap();
+
+        assert_eq!(report.pvs[1].vg_name, None);
+        assert_eq!(report.vg("data").unwrap().free_extents, 511);
+        let lv = report.lv("data", "home").unwrap();
+        assert!(lv.active);
+        assert_eq!(lv.size, 8 << 30);
+        assert_eq!(mapper_name("my-vg", "root"), "my--vg-root");
+        assert!(validate_name("root_2").is_ok());
+        assert!(validate_name("-x").is_err());
+        assert!(validate_name("a/b").is_err());
+
+        let extent = 4 << 20;
+        let available = 511 * extent + 100;
+        assert_eq!(
+            extents("5M".parse().unwrap(), available, extent).unwrap(),
+            2
+        );
+        assert_eq!(
+            extents("rest".parse().unwrap(), available, extent).unwrap(),
+            511
+        );
+        assert_eq!(
+            extents("50%".parse().unwrap(), available, extent).unwrap(),
+            255
+        );
+    }
+}
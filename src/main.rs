@@ -1,16 +1,22 @@
 use std::io;
 use std::sync::Arc;
+use std::time::Duration;
 use ratatui::backend::CrosstermBackend;
 use ratatui::Terminal;
 
-use disktui::app::{App, AppResult};
+use disktui::app::{App, AppResult, CommandPreview};
 use disktui::config::Config;
 use disktui::event::{Event, EventHandler};
-use disktui::handler::handle_key_events;
+use disktui::handler::{handle_key_events, poll_raid, poll_smart_data, preview_commands, reload_lvm, reload_raid, reload_subvolumes};
 use disktui::tui::Tui;
 
 #[tokio::main]
 async fn main() -> AppResult<()> {
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	if !args.is_empty() {
+		std::process::exit(disktui::cli::run(&args).await);
+	}
+
 	let config = Arc::new(Config::new());
 
 	let backend = CrosstermBackend::new(io::stdout());
@@ -20,6 +26,13 @@ async fn main() -> AppResult<()> {
 	tui.init()?;
 
 	let mut app = App::new(None).await?;
+	app.alignment = config.partitioning.alignment;
+	app.size_units = config.display.units;
+	app.mount_defaults = config.mounts.clone();
+	app.start_monitoring(
+		config.monitoring.thresholds.clone(),
+		Duration::from_secs(config.monitoring.smart_interval),
+	);
 
     while app.running {
         tui.draw(&mut app)?;
@@ -27,20 +40,28 @@ async fn main() -> AppResult<()> {
         match tui.events.next().await? {
             Event::Tick => {
                 app.tick().await?;
+                poll_smart_data(&mut app, &tui.events.sender);
+                poll_raid(&mut app, &tui.events.sender);
             }
             Event::Key(key_event) => {
                 handle_key_events(key_event, &mut app, tui.events.sender.clone(), config.clone()).await?;
+                preview_commands(&mut app, &tui.events.sender);
             }
             Event::Notification(notification) => {
                 app.notifications.push(notification);
             }
             Event::Refresh => {
                 app.refresh().await?;
+                reload_lvm(&mut app, &tui.events.sender);
+                reload_raid(&mut app, &tui.events.sender);
+                reload_subvolumes(&mut app, &tui.events.sender);
             }
             Event::StartProgress(message) => {
                 app.progress.show_dialog = true;
                 app.progress.message = message;
                 app.progress.spinner_index = 0;
+                app.progress.percent = None;
+                app.progress.raid = None;
             }
             Event::EndProgress => {
                 app.progress.show_dialog = false;
@@ -49,6 +70,81 @@ async fn main() -> AppResult<()> {
                 app.progress.disk_model.clear();
                 app.operation_in_progress.store(false, std::sync::atomic::Ordering::Release);
             }
+            Event::QueueFinished(completed) => {
+                app.queue.finish(completed);
+            }
+            Event::CommandPreview(description, preview) => {
+                let current = app.confirmation_dialog.operation.request("").map(|r| r.description());
+                if app.confirmation_dialog.show_dialog
+                    && app.confirmation_dialog.commands == CommandPreview::Loading
+                    && current.as_ref() == Some(&description)
+                {
+                    app.confirmation_dialog.commands = preview;
+                }
+            }
+            Event::AuditLog(result) => {
+                app.audit_log.loading = false;
+                match result {
+                    Ok(entries) => {
+                        // Newest first
+                        app.audit_log.entries = entries.into_iter().rev().collect();
+                        app.audit_log
+                            .state
+                            .select((!app.audit_log.entries.is_empty()).then_some(0));
+                    }
+                    Err(message) => app.audit_log.error = Some(message),
+                }
+            }
+            Event::Backups(result) => {
+                app.restore_dialog.loading = false;
+                match result {
+                    Ok(list) => {
+                        app.restore_dialog.current = list.current;
+                        app.restore_dialog.backups = list.backups;
+                        app.restore_dialog
+                            .state
+                            .select((!app.restore_dialog.backups.is_empty()).then_some(0));
+                    }
+                    Err(message) => app.restore_dialog.error = Some(message),
+                }
+            }
+            Event::Lvm(result) => {
+                app.lvm.loading = false;
+                match result {
+                    Ok(report) => app.lvm.set_report(report),
+                    Err(message) => app.lvm.error = Some(message),
+                }
+            }
+            Event::Raid(result) => {
+                app.raid.loading = false;
+                match result {
+                    Ok(report) => app.set_raid_report(report),
+                    Err(message) => {
+                        app.raid.follow = None;
+                        app.raid.error = Some(message);
+                    }
+                }
+            }
+            Event::Subvolumes(result) => {
+                app.subvolumes.loading = false;
+                match result {
+                    Ok(list) => app.subvolumes.set_list(list),
+                    Err(message) => app.subvolumes.error = Some(message),
+                }
+            }
+            Event::CheckOutput(line) => {
+                app.check_log.push_line(line);
+            }
+            Event::SmartData(disk, smart_data) => {
+                app.update_smart_data(&disk, smart_data.map(|data| *data));
+            }
+            Event::CheckFinished(result) => {
+                app.check_log.running = false;
+                match result {
+                    Ok(report) => app.check_log.report = Some(report),
+                    Err(message) => app.check_log.error = Some(message),
+                }
+            }
         }
     }
 
@@ -0,0 +1,324 @@
+use crate::smart::{Level, SmartData};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+use std::time::SystemTime;
+
+/// Readings kept per disk. At the default interval this covers five days.
+const HISTORY_LEN: usize = 720;
+
+const REALLOCATED_SECTORS: u8 = 5;
+const PENDING_SECTORS: u8 = 197;
+
+/// When background SMART readings raise an alert, from the `[monitoring]`
+/// table of the config file.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Thresholds {
+    /// Degrees Celsius.
+    pub temperature_warning: i32,
+    pub temperature_critical: i32,
+    /// NVMe percentage of the rated endurance used.
+    pub percentage_used: u64,
+    /// Counts above which reallocated and pending sectors are reported.
+    pub reallocated_sectors: u64,
+    pub pending_sectors: u64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Self {
+            temperature_warning: 55,
+            temperature_critical: 65,
+            percentage_used: 90,
+            reallocated_sectors: 0,
+            pending_sectors: 0,
+        }
+    }
+}
+
+impl Thresholds {
+    /// The health shown for a disk: the worst of what the drive reports and
+    /// the configured thresholds.
+    pub fn level(&self, data: &SmartData) -> Level {
+        let sample = Sample::of(data);
+        let temperature = match sample.temperature {
+            Some(t) if t >= self.temperature_critical => Level::Failing,
+            Some(t) if t >= self.temperature_warning => Level::Warning,
+            _ => Level::Ok,
+        };
+        let above = |value: Option<u64>, threshold: u64| match value {
+            Some(value) if value > threshold => Level::Warning,
+            _ => Level::Ok,
+        };
+        let used = match sample.percentage_used {
+            Some(used) if used >= self.percentage_used => Level::Warning,
+            _ => Level::Ok,
+        };
+        [
+            data.level(),
+            temperature,
+            above(sample.reallocated, self.reallocated_sectors),
+            above(sample.pending, self.pending_sectors),
+            used,
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or(Level::Ok)
+    }
+}
+
+/// The values tracked over time from one SMART reading.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub at: SystemTime,
+    pub passed: Option<bool>,
+    pub temperature: Option<i32>,
+    pub reallocated: Option<u64>,
+    pub pending: Option<u64>,
+    pub percentage_used: Option<u64>,
+    /// Entries in the device error log.
+    pub errors: Option<u64>,
+    /// Power-on hours and result of the newest self-test log entry.
+    last_test: Option<(Option<u64>, bool, String)>,
+}
+
+impl Sample {
+    pub fn of(data: &SmartData) -> Self {
+        let raw = |id| data.attribute(id).map(|attribute| attribute.raw_value);
+        Self {
+            at: SystemTime::now(),
+            passed: data.passed,
+            temperature: data.temperature,
+            reallocated: raw(REALLOCATED_SECTORS),
+            pending: raw(PENDING_SECTORS),
+            percentage_used: data.nvme.as_ref().map(|nvme| nvme.percentage_used),
+            errors: data.error_count,
+            last_test: data
+                .self_test_log
+                .first()
+                .map(|entry| (entry.power_on_hours, entry.passed, entry.status.clone())),
+        }
+    }
+}
+
+/// Something a new reading shows that the previous one did not.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    pub level: Level,
+    pub message: String,
+}
+
+/// SMART readings per disk and the alerts they raise.
+#[derive(Debug, Default)]
+pub struct Monitor {
+    pub thresholds: Thresholds,
+    history: HashMap<String, VecDeque<Sample>>,
+}
+
+impl Monitor {
+    pub fn new(thresholds: Thresholds) -> Self {
+        Self {
+            thresholds,
+            history: HashMap::new(),
+        }
+    }
+
+    pub fn history(&self, disk: &str) -> Option<&VecDeque<Sample>> {
+        self.history.get(disk)
+    }
+
+    /// Drops the history of disks that are gone.
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        self.history.retain(|disk, _| keep(disk));
+    }
+
+    /// Adds a reading and returns the alerts for what changed since the
+    /// previous one. The first reading of a disk only reports values that
+    /// are already past a threshold.
+    pub fn record(&mut self, disk: &str, data: &SmartData) -> Vec<Alert> {
+        let sample = Sample::of(data);
+        let history = self.history.entry(disk.to_string()).or_default();
+        let alerts = compare(disk, &self.thresholds, history.back(), &sample);
+        if history.len() == HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(sample);
+        alerts
+    }
+}
+
+fn compare(
+    disk: &str,
+    thresholds: &Thresholds,
+    previous: Option<&Sample>,
+    current: &Sample,
+) -> Vec<Alert> {
+    let mut alerts = Vec::new();
+    let mut alert = |level, message: String| alerts.push(Alert { level, message });
+
+    if current.passed == Some(false) && previous.is_none_or(|p| p.passed != Some(false)) {
+        alert(
+            Level::Failing,
+            format!("{}: SMART overall health assessment FAILED", disk),
+        );
+    }
+
+    if let Some(temperature) = current.temperature {
+        let before = previous.and_then(|p| p.temperature);
+        let crossed = |limit: i32| temperature >= limit && before.is_none_or(|t| t < limit);
+        if crossed(thresholds.temperature_critical) {
+            alert(
+                Level::Failing,
+                format!(
+                    "{} is at {}°C (critical from {}°C)",
+                    disk, temperature, thresholds.temperature_critical
+                ),
+            );
+        } else if crossed(thresholds.temperature_warning)
+            && temperature < thresholds.temperature_critical
+        {
+            alert(
+                Level::Warning,
+                format!(
+                    "{} is at {}°C (warning from {}°C)",
+                    disk, temperature, thresholds.temperature_warning
+                ),
+            );
+        }
+    }
+
+    let counters = [
+        (
+            "reallocated sectors",
+            current.reallocated,
+            previous.and_then(|p| p.reallocated),
+            thresholds.reallocated_sectors,
+        ),
+        (
+            "pending sectors",
+            current.pending,
+            previous.and_then(|p| p.pending),
+            thresholds.pending_sectors,
+        ),
+    ];
+    for (name, count, before, threshold) in counters {
+        let Some(count) = count.filter(|&count| count > threshold) else {
+            continue;
+        };
+        match before {
+            Some(before) if count <= before => {}
+            Some(before) => alert(
+                Level::Warning,
+                format!("{}: {} {} (was {})", disk, count, name, before),
+            ),
+            None => alert(Level::Warning, format!("{}: {} {}", disk, count, name)),
+        }
+    }
+
+    if let Some(used) = current.percentage_used {
+        let before = previous.and_then(|p| p.percentage_used);
+        if used >= thresholds.percentage_used
+            && before.is_none_or(|b| b < thresholds.percentage_used)
+        {
+            alert(
+                Level::Warning,
+                format!("{} has used {}% of its rated endurance", disk, used),
+            );
+        }
+    }
+
+    // Errors and self-tests from before the first reading are history, not
+    // news.
+    let Some(previous) = previous else {
+        return alerts;
+    };
+    if let (Some(errors), Some(before)) = (current.errors, previous.errors)
+        && errors > before
+    {
+        alert(
+            Level::Warning,
+            format!("{} logged {} new error(s)", disk, errors - before),
+        );
+    }
+    if let Some((_, passed, status)) = &current.last_test
+        && current.last_test != previous.last_test
+    {
+        if *passed {
+            alert(Level::Ok, format!("{}: self-test {}", disk, status));
+        } else {
+            alert(Level::Failing, format!("{}: self-test {}", disk, status));
+        }
+    }
+    alerts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::smart::{Attribute, SelfTestEntry};
+
+    fn reading(temperature: i32, reallocated: u64) -> SmartData {
+        SmartData {
+            passed: Some(true),
+            temperature: Some(temperature),
+            attributes: vec![Attribute {
+                id: REALLOCATED_SECTORS,
+                name: "Reallocated_Sector_Ct".into(),
+                value: 100,
+                worst: 100,
+                thresh: 10,
+                raw: reallocated.to_string(),
+                raw_value: reallocated,
+                prefailure: true,
+                when_failed: String::new(),
+            }],
+            error_count: Some(0),
+            ..SmartData::default()
+        }
+    }
+
+    #[test]
+    fn alerts_fire_when_thresholds_are_crossed() {
+        let mut monitor = Monitor::default();
+
+        assert!(monitor.record("sda", &reading(40, 0)).is_empty());
+        // Staying hot raises the warning only once.
+        let alerts = monitor.record("sda", &reading(58, 0));
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].level, Level::Warning);
+        assert!(monitor.record("sda", &reading(59, 0)).is_empty());
+        assert_eq!(
+            monitor.record("sda", &reading(66, 0))[0].level,
+            Level::Failing
+        );
+
+        let alerts = monitor.record("sda", &reading(40, 8));
+        assert_eq!(alerts[0].message, "sda: 8 reallocated sectors (was 0)");
+        assert!(monitor.record("sda", &reading(40, 8)).is_empty());
+        assert_eq!(monitor.history("sda").unwrap().len(), 6);
+    }
+
+    #[test]
+    fn new_errors_and_failed_tests_are_reported_after_the_first_reading() {
+        let mut monitor = Monitor::default();
+        let mut data = reading(40, 3);
+        data.error_count = Some(2);
+
+        // Existing bad sectors are worth knowing about, old errors are not.
+        let alerts = monitor.record("sdb", &data);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].message, "sdb: 3 reallocated sectors");
+
+        data.error_count = Some(5);
+        data.self_test_log = vec![SelfTestEntry {
+            kind: "Extended offline".into(),
+            status: "Completed: read failure".into(),
+            passed: false,
+            power_on_hours: Some(9000),
+        }];
+        let alerts = monitor.record("sdb", &data);
+        assert_eq!(alerts[0].message, "sdb logged 3 new error(s)");
+        assert_eq!(alerts[1].level, Level::Failing);
+        assert_eq!(monitor.thresholds.level(&data), Level::Warning);
+    }
+}
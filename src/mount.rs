@@ -0,0 +1,220 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+/// Owner given to every file of a filesystem without Unix permissions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Owner {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl Owner {
+    /// The user running this process.
+    pub fn current() -> Self {
+        // SAFETY: getuid and getgid cannot fail and touch no memory.
+        unsafe {
+            Owner {
+                uid: libc::getuid(),
+                gid: libc::getgid(),
+            }
+        }
+    }
+}
+
+/// Options for mounting a filesystem. Everything off mounts it the way
+/// `mount` does by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MountOptions {
+    #[serde(default)]
+    pub read_only: bool,
+    #[serde(default)]
+    pub noatime: bool,
+    /// Only for FAT, exFAT and NTFS, which have no owners of their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<Owner>,
+    /// Btrfs compression, such as "zstd" or "zstd:3".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compress: Option<String>,
+    /// Btrfs subvolume to mount instead of the default one, such as "@home".
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subvol: Option<String>,
+}
+
+/// Whether files on the filesystem get their owner from mount options.
+pub fn supports_owner(fs_type: &str) -> bool {
+    matches!(
+        fs_type,
+        "vfat" | "fat32" | "fat16" | "fat12" | "exfat" | "ntfs" | "ntfs3"
+    )
+}
+
+pub fn supports_compress(fs_type: &str) -> bool {
+    fs_type == "btrfs"
+}
+
+/// Checks a btrfs compression setting: an algorithm and, for zstd and
+/// zlib, an optional level.
+pub fn validate_compress(value: &str) -> Result<()> {
+    let (algorithm, level) = match value.split_once(':') {
+        Some((algorithm, level)) => (algorithm, Some(level)),
+        None => (value, None),
+    };
+    let max_level = match algorithm {
+        "zstd" => 15,
+        "zlib" => 9,
+        "lzo" => 0,
+        _ => {
+            return Err(anyhow!(
+                "Unknown compression: {} (use zstd, zlib or lzo)",
+                algorithm
+            ));
+        }
+    };
+    match level.map(str::parse::<u32>) {
+        None => Ok(()),
+        Some(Ok(level)) if (1..=max_level).contains(&level) => Ok(()),
+        Some(_) if max_level == 0 => Err(anyhow!("{} has no compression levels", algorithm)),
+        Some(_) => Err(anyhow!("{} levels go from 1 to {}", algorithm, max_level)),
+    }
+}
+
+/// Checks a btrfs subvolume given to `subvol=`, a path from the top level.
+pub fn validate_subvol(value: &str) -> Result<()> {
+    if value.is_empty() {
+        return Err(anyhow!("Subvolume cannot be empty"));
+    }
+    if let Some(c) = value
+        .chars()
+        .find(|c| *c == ',' || c.is_whitespace() || c.is_control())
+    {
+        return Err(anyhow!("Subvolume cannot contain {:?}", c));
+    }
+    Ok(())
+}
+
+impl MountOptions {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The comma separated list for `mount -o`, or `None` without options.
+    /// Fails for options the filesystem does not take.
+    pub fn mount_arg(&self, fs_type: &str) -> Result<Option<String>> {
+        if self.owner.is_some() && !supports_owner(fs_type) {
+            return Err(anyhow!(
+                "{} keeps its own file owners; ownership only applies to FAT, exFAT and NTFS",
+                fs_type
+            ));
+        }
+        if let Some(compress) = &self.compress {
+            if !supports_compress(fs_type) {
+                return Err(anyhow!("Compression only applies to btrfs"));
+            }
+            validate_compress(compress)?;
+        }
+        if let Some(subvol) = &self.subvol {
+            if fs_type != "btrfs" {
+                return Err(anyhow!("Subvolumes only apply to btrfs"));
+            }
+            validate_subvol(subvol)?;
+        }
+        let options = self.items();
+        Ok((!options.is_empty()).then(|| options.join(",")))
+    }
+
+    /// The options field of an fstab entry, "defaults" without options.
+    pub fn fstab_field(&self) -> String {
+        let options = self.items();
+        if options.is_empty() {
+            "defaults".to_string()
+        } else {
+            options.join(",")
+        }
+    }
+
+    fn items(&self) -> Vec<String> {
+        let mut options = Vec::new();
+        if self.read_only {
+            options.push("ro".to_string());
+        }
+        if self.noatime {
+            options.push("noatime".to_string());
+        }
+        if let Some(owner) = self.owner {
+            options.push(format!("uid={}", owner.uid));
+            options.push(format!("gid={}", owner.gid));
+        }
+        if let Some(compress) = &self.compress {
+            options.push(format!("compress={}", compress));
+        }
+        if let Some(subvol) = &self.subvol {
+            options.push(format!("subvol={}", subvol));
+        }
+        options
+    }
+
+    /// Short description for notifications, such as "read-only, noatime".
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if self.read_only {
+            parts.push("read-only".to_string());
+        }
+        if self.noatime {
+            parts.push("noatime".to_string());
+        }
+        if let Some(owner) = self.owner {
+            parts.push(format!("owned by uid {}", owner.uid));
+        }
+        if let Some(compress) = &self.compress {
+            parts.push(format!("compress={}", compress));
+        }
+        if let Some(subvol) = &self.subvol {
+            parts.push(format!("subvolume {}", subvol));
+        }
+        parts.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_are_checked_against_the_filesystem() {
+        let options = MountOptions {
+            read_only: true,
+            owner: Some(Owner {
+                uid: 1000,
+                gid: 100,
+            }),
+            ..MountOptions::default()
+        };
+        assert_eq!(
+            options.mount_arg("vfat").unwrap().as_deref(),
+            Some("ro,uid=1000,gid=100")
+        );
+        assert!(options.mount_arg("ext4").is_err());
+        assert_eq!(MountOptions::default().mount_arg("ext4").unwrap(), None);
+
+        let compressed = MountOptions {
+            compress: Some("zstd:3".into()),
+            subvol: Some("@home".into()),
+            ..MountOptions::default()
+        };
+        assert_eq!(
+            compressed.mount_arg("btrfs").unwrap().as_deref(),
+            Some("compress=zstd:3,subvol=@home")
+        );
+        assert!(compressed.mount_arg("xfs").is_err());
+        assert_eq!(compressed.fstab_field(), "compress=zstd:3,subvol=@home");
+        assert_eq!(MountOptions::default().fstab_field(), "defaults");
+    }
+
+    #[test]
+    fn compression_levels_depend_on_the_algorithm() {
+        assert!(validate_compress("zlib:9").is_ok());
+        assert!(validate_compress("zstd:16").is_err());
+        assert!(validate_compress("lzo:1").is_err());
+        assert!(validate_compress("lz4").is_err());
+    }
+}
@@ -61,24 +61,26 @@ impl Notification {
         frame.render_widget(Clear, area);
         frame.render_widget(block, area);
     }
-    pub fn send(
-        message: String,
-        level: NotificationLevel,
-        sender: &UnboundedSender<Event>,
-    ) -> AppResult<()> {
+    pub fn new(message: String, level: NotificationLevel) -> Self {
         let ttl = match level {
             NotificationLevel::Error => 100,
             NotificationLevel::Warning => 60,
             NotificationLevel::Info => 40,
         };
 
-        let notif = Notification {
+        Notification {
             message,
             level,
             ttl,
-        };
+        }
+    }
 
-        sender.send(Event::Notification(notif))?;
+    pub fn send(
+        message: String,
+        level: NotificationLevel,
+        sender: &UnboundedSender<Event>,
+    ) -> AppResult<()> {
+        sender.send(Event::Notification(Notification::new(message, level)))?;
 
         Ok(())
     }
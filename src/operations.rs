@@ -1,14 +1,21 @@
 use crate::event::Event;
+use crate::holder::{Holder, attach_btrfs_holders, attach_volume_groups};
+use crate::layout::{self, Alignment, DiskGeometry};
+use crate::lvm;
 use crate::notification::{Notification, NotificationLevel};
-use crate::partition::Partition;
+use crate::partition::{Partition, PartitionGeometry};
+use crate::parttype::PartitionFlag;
 use crate::protocol::{Request, Response};
-use crate::utils::format_bytes;
+use crate::runner::CommandRunner;
+use crate::smart::{self, SmartData};
+use crate::swap::{attach_swap_areas, parse_swapon};
+use crate::sysfs::SysfsReader;
 use anyhow::{Context, Result, anyhow};
+use serde::Serialize;
 use serde_json::Value;
 use std::io::{BufRead, BufReader, Write};
 use std::process::{Child, ChildStdin, ChildStdout, Stdio};
 use std::sync::{Arc, Mutex};
-use tokio::process::Command;
 use tokio::sync::mpsc::UnboundedSender;
 
 pub struct HelperConnection {
@@ -52,8 +59,14 @@ impl HelperConnection {
 
 	pub fn read_response(&self) -> Result<Response> {
 		let mut stdout = self.stdout.lock().map_err(|_| anyhow!("Lock error"))?;
+		Self::read_line(&mut stdout)
+	}
+
+	fn read_line(stdout: &mut BufReader<ChildStdout>) -> Result<Response> {
 		let mut line = String::new();
-		stdout.read_line(&mut line)?;
+		if stdout.read_line(&mut line)? == 0 {
+			return Err(anyhow!("Helper exited unexpectedly"));
+		}
 		let response: Response = serde_json::from_str(&line)?;
 		Ok(response)
 	}
@@ -63,15 +76,10 @@ impl HelperConnection {
 		request: Request,
 		sender: &UnboundedSender<Event>,
 	) -> Result<Option<String>> {
-		self.send_request(&request)?;
-
-		loop {
-			let response = self.read_response()?;
+		self.request_with(request, |response| {
 			match response {
-				Response::Ok { data } => return Ok(data),
 				Response::Error { message } => {
-					Notification::send(message.clone(), NotificationLevel::Error, sender)?;
-					return Err(anyhow!(message));
+					Notification::send(message, NotificationLevel::Error, sender)?;
 				}
 				Response::Notification { level, message } => {
 					let level = match level.as_str() {
@@ -90,6 +98,33 @@ impl HelperConnection {
 						sender.send(Event::EndProgress)?;
 					}
 				}
+				Response::Ok { .. } | Response::Output { .. } => {}
+			}
+			Ok(())
+		})
+	}
+
+	/// Sends a request and passes every intermediate response, plus a final
+	/// error if there is one, to `on_response` until the helper finishes.
+	/// The response stream stays locked for the whole exchange so that
+	/// concurrent callers (such as a dry-run preview while an operation is
+	/// running) cannot read each other's responses.
+	pub fn request_with<F>(&self, request: Request, mut on_response: F) -> Result<Option<String>>
+	where
+		F: FnMut(Response) -> Result<()>,
+	{
+		let mut stdout = self.stdout.lock().map_err(|_| anyhow!("Lock error"))?;
+		self.send_request(&request)?;
+
+		loop {
+			let response = Self::read_line(&mut stdout)?;
+			match response {
+				Response::Ok { data } => return Ok(data),
+				Response::Error { message } => {
+					on_response(Response::error(message.clone()))?;
+					return Err(anyhow!(message));
+				}
+				other => on_response(other)?,
 			}
 		}
 	}
@@ -129,41 +164,12 @@ fn validate_device_name(name: &str) -> Result<()> {
     Ok(())
 }
 
-async fn get_last_partition_end_bytes(disk: &str) -> Result<u64> {
-    let output = Command::new("parted")
-        .args(["-s", "-m", &format!("/dev/{}", disk), "unit", "B", "print"])
-        .output()
-        .await
-        .context("Failed to execute parted")?;
-
-    if !output.status.success() {
-        return Ok(1_048_576);
-    }
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let mut last_end: u64 = 1_048_576;
-
-    for line in stdout.lines() {
-        let parts: Vec<&str> = line.split(':').collect();
-        if parts.len() >= 3
-            && let Ok(_part_num) = parts[0].parse::<u32>()
-                && let Some(end_str) = parts[2].strip_suffix('B')
-                    && let Ok(end) = end_str.parse::<u64>()
-                        && end > last_end {
-                            last_end = end;
-                        }
-    }
-
-    let aligned = ((last_end + 1_048_576) / 1_048_576) * 1_048_576;
-    Ok(aligned)
-}
-
-fn get_device_path(device_name: &str) -> String {
+fn get_device_path(runner: &dyn CommandRunner, device_name: &str) -> String {
     if device_name.starts_with("luks-") {
         format!("/dev/mapper/{}", device_name)
     } else {
         let mapper_path = format!("/dev/mapper/{}", device_name);
-        if std::path::Path::new(&mapper_path).exists() {
+        if runner.path_exists(&mapper_path) {
             mapper_path
         } else {
             format!("/dev/{}", device_name)
@@ -171,44 +177,42 @@ fn get_device_path(device_name: &str) -> String {
     }
 }
 
-async fn wait_for_device(device_path: &str, timeout_secs: u64) -> Result<()> {
-    let start = std::time::Instant::now();
-    let timeout = std::time::Duration::from_secs(timeout_secs);
-
-    while start.elapsed() < timeout {
-        if std::path::Path::new(device_path).exists() {
-            let verify = Command::new("blockdev")
-                .args(["--getsize64", device_path])
-                .output()
+async fn wait_for_device(
+    runner: &dyn CommandRunner,
+    device_path: &str,
+    timeout_secs: u64,
+) -> Result<()> {
+    // Count polls rather than wall-clock time so the loop also terminates when
+    // the runner's sleep is a no-op.
+    for _ in 0..timeout_secs * 5 {
+        if runner.path_exists(device_path) {
+            let verify = runner
+                .output("blockdev", &["--getsize64", device_path])
                 .await;
 
             if verify.is_ok() && verify.unwrap().status.success() {
-                tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+                runner.sleep(tokio::time::Duration::from_millis(500)).await;
                 return Ok(());
             }
         }
-        tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+        runner.sleep(tokio::time::Duration::from_millis(200)).await;
     }
 
     Err(anyhow!("Timeout waiting for device: {}", device_path))
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct BlockDevice {
     pub name: String,
     pub size: u64,
     pub model: Option<String>,
     pub serial: Option<String>,
+    /// Partition table type as reported by udev ("gpt", "dos"), if any.
+    pub partition_table: Option<String>,
+    pub geometry: DiskGeometry,
     pub partitions: Vec<Partition>,
 }
 
-#[derive(Debug, Clone)]
-pub struct SmartData {
-    pub health: String,
-    pub temperature: Option<i32>,
-    pub power_on_hours: Option<u64>,
-}
-
 #[derive(Debug, Clone)]
 pub struct LuksInfo {
     pub version: String,
@@ -232,6 +236,7 @@ pub enum FilesystemType {
     Exfat,
     Btrfs,
     Xfs,
+    Swap,
 }
 
 impl FilesystemType {
@@ -243,6 +248,7 @@ impl FilesystemType {
             FilesystemType::Exfat => "exfat",
             FilesystemType::Btrfs => "btrfs",
             FilesystemType::Xfs => "xfs",
+            FilesystemType::Swap => "swap",
         }
     }
 
@@ -254,65 +260,52 @@ impl FilesystemType {
             FilesystemType::Exfat,
             FilesystemType::Btrfs,
             FilesystemType::Xfs,
+            FilesystemType::Swap,
         ]
     }
 }
 
+impl std::str::FromStr for FilesystemType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "ext4" => Ok(FilesystemType::Ext4),
+            "fat32" | "vfat" => Ok(FilesystemType::Fat32),
+            "ntfs" => Ok(FilesystemType::Ntfs),
+            "exfat" => Ok(FilesystemType::Exfat),
+            "btrfs" => Ok(FilesystemType::Btrfs),
+            "xfs" => Ok(FilesystemType::Xfs),
+            "swap" => Ok(FilesystemType::Swap),
+            _ => Err(anyhow!("Unsupported filesystem type: {}", s)),
+        }
+    }
+}
+
 impl std::fmt::Display for FilesystemType {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", self.as_str())
     }
 }
 
-fn parse_size(input: &str) -> Result<u64> {
-    let input = input.trim().to_uppercase();
-    let input = input.trim_end_matches('B');
-
-    let (num_str, unit) = if input.ends_with("TB") || input.ends_with('T') {
-        let len = if input.ends_with("TB") {
-            input.len() - 2
-        } else {
-            input.len() - 1
-        };
-        (&input[..len], 1_000_000_000_000u64)
-    } else if input.ends_with("GB") || input.ends_with('G') {
-        let len = if input.ends_with("GB") {
-            input.len() - 2
-        } else {
-            input.len() - 1
-        };
-        (&input[..len], 1_000_000_000u64)
-    } else if input.ends_with("MB") || input.ends_with('M') {
-        let len = if input.ends_with("MB") {
-            input.len() - 2
-        } else {
-            input.len() - 1
-        };
-        (&input[..len], 1_000_000u64)
-    } else if input.ends_with("KB") || input.ends_with('K') {
-        let len = if input.ends_with("KB") {
-            input.len() - 2
-        } else {
-            input.len() - 1
-        };
-        (&input[..len], 1_000u64)
-    } else {
-        (input, 1u64)
-    };
-
-    let num: f64 = num_str
-        .parse()
-        .map_err(|_| anyhow!("Invalid size format. Use format like: 100M, 2.5GB, 1TB"))?;
-
-    Ok((num * unit as f64).round() as u64)
-}
+async fn get_filesystem_usage(
+    runner: &dyn CommandRunner,
+    mount_point: &str,
+    filesystem: Option<&str>,
+) -> Option<(u64, u64)> {
+    // df cannot account for btrfs profiles and devices, so ask btrfs itself
+    // and only fall back to df when that fails.
+    if filesystem == Some("btrfs")
+        && let Ok(output) = runner
+            .output("btrfs", &["filesystem", "usage", "-b", mount_point])
+            .await
+        && output.status.success()
+        && let Some(usage) = crate::btrfs::parse_usage(&String::from_utf8_lossy(&output.stdout))
+    {
+        return Some(usage);
+    }
 
-async fn get_filesystem_usage(mount_point: &str) -> Option<(u64, u64)> {
-    let output = Command::new("df")
-        .args(["-B1", mount_point])
-        .output()
-        .await
-        .ok()?;
+    let output = runner.output("df", &["-B1", mount_point]).await.ok()?;
 
     if !output.status.success() {
         return None;
@@ -336,10 +329,9 @@ async fn get_filesystem_usage(mount_point: &str) -> Option<(u64, u64)> {
     Some((used, available))
 }
 
-async fn get_device_mount_point(device_path: &str) -> Option<String> {
-    let output = Command::new("findmnt")
-        .args(["-n", "-o", "TARGET", device_path])
-        .output()
+async fn get_device_mount_point(runner: &dyn CommandRunner, device_path: &str) -> Option<String> {
+    let output = runner
+        .output("findmnt", &["-n", "-o", "TARGET", device_path])
         .await
         .ok()?;
 
@@ -352,31 +344,149 @@ async fn get_device_mount_point(device_path: &str) -> Option<String> {
     None
 }
 
-async fn get_mapper_mount_point(mapper_name: &str, fallback: Option<String>) -> Option<String> {
-    let mapper_mount_check = Command::new("findmnt")
-        .args(["-n", "-o", "TARGET", &format!("/dev/mapper/{}", mapper_name)])
-        .output()
+async fn get_mapper_mount_point(
+    runner: &dyn CommandRunner,
+    mapper_name: &str,
+    fallback: Option<String>,
+) -> Option<String> {
+    let mapper_mount_check = runner
+        .output(
+            "findmnt",
+            &[
+                "-n",
+                "-o",
+                "TARGET",
+                &format!("/dev/mapper/{}", mapper_name),
+            ],
+        )
         .await;
 
     if let Ok(output) = mapper_mount_check
-        && output.status.success() {
-            let mount_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
-            if !mount_str.is_empty() {
-                return Some(mount_str);
-            }
+        && output.status.success()
+    {
+        let mount_str = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !mount_str.is_empty() {
+            return Some(mount_str);
         }
+    }
     fallback
 }
 
-pub async fn list_block_devices() -> Result<Vec<BlockDevice>> {
-    let output = Command::new("lsblk")
-        .args([
-            "-J",
-            "-b",
-            "-o",
-            "NAME,SIZE,TYPE,MODEL,SERIAL,MOUNTPOINT,FSTYPE,LABEL",
-        ])
-        .output()
+/// Builds a partition entry from lsblk's view of it, adding the LUKS state,
+/// the mount point of an unlocked mapper and filesystem usage.
+async fn probe_partition(
+    runner: &dyn CommandRunner,
+    name: String,
+    geometry: Option<PartitionGeometry>,
+    size: u64,
+    filesystem: Option<String>,
+    mount_point: Option<String>,
+    label: Option<String>,
+) -> Partition {
+    let is_encrypted = is_luks_device(runner, &name).await.unwrap_or(false);
+    let (encryption_type, luks_uuid, mapper_device) = if is_encrypted {
+        let (luks_info, luks_status) =
+            futures::join!(get_luks_info(runner, &name), get_luks_status(runner, &name));
+        let luks_info = luks_info.ok();
+        (
+            luks_info.as_ref().map(|info| info.version.clone()),
+            luks_info.as_ref().map(|info| info.uuid.clone()),
+            luks_status.ok().and_then(|status| status.mapper_name),
+        )
+    } else {
+        (None, None, None)
+    };
+
+    let actual_mount_point = if let Some(ref mapper_name) = mapper_device {
+        get_mapper_mount_point(runner, mapper_name, mount_point).await
+    } else {
+        mount_point
+    };
+
+    let (used_bytes, available_bytes) = if let Some(ref mp) = actual_mount_point {
+        if let Some((used, avail)) = get_filesystem_usage(runner, mp, filesystem.as_deref()).await {
+            (Some(used), Some(avail))
+        } else {
+            (None, None)
+        }
+    } else {
+        (None, None)
+    };
+
+    Partition {
+        name,
+        geometry,
+        size,
+        filesystem,
+        mount_point: actual_mount_point.clone(),
+        is_mounted: actual_mount_point.is_some(),
+        label,
+        uuid: None,
+        used_bytes,
+        available_bytes,
+        is_encrypted,
+        encryption_type,
+        luks_uuid,
+        mapper_device,
+        part_type: None,
+        part_uuid: None,
+        part_label: None,
+        flags: Vec::new(),
+        swap: None,
+        swap_files: Vec::new(),
+        holder: None,
+    }
+}
+
+type PartitionEntry = (
+    Option<String>,
+    Option<String>,
+    Option<String>,
+    Vec<PartitionFlag>,
+    Option<String>,
+    Option<Holder>,
+);
+
+/// Type, PARTUUID, name, flags, filesystem UUID and holder of a partition
+/// from its lsblk entry. lsblk lists the devices stacked on a partition,
+/// such as logical volumes and md arrays, as its children.
+fn partition_entry(part: &Value) -> PartitionEntry {
+    let text = |value: &Value| value.as_str().filter(|s| !s.is_empty()).map(str::to_string);
+    let part_type = text(&part["parttype"]);
+    let attributes = text(&part["partflags"])
+        .and_then(|flags| u64::from_str_radix(flags.trim_start_matches("0x"), 16).ok())
+        .unwrap_or(0);
+    let flags = PartitionFlag::from_entry(part_type.as_deref(), attributes);
+    let stacked: Vec<String> = part["children"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|child| text(&child["name"]))
+        .collect();
+    (
+        part_type,
+        text(&part["partuuid"]),
+        text(&part["partlabel"]),
+        flags,
+        text(&part["uuid"]),
+        Holder::detect(text(&part["fstype"]).as_deref(), &stacked),
+    )
+}
+
+/// Lists disks through lsblk, probing every partition with cryptsetup,
+/// findmnt and df. Partitions are probed concurrently. Operations use this
+/// since it goes through the runner; the TUI prefers `scan_block_devices`.
+pub async fn list_block_devices(runner: &dyn CommandRunner) -> Result<Vec<BlockDevice>> {
+    let output = runner
+        .output(
+            "lsblk",
+            &[
+                "-J",
+                "-b",
+                "-o",
+                "NAME,SIZE,TYPE,MODEL,SERIAL,MOUNTPOINT,FSTYPE,LABEL,UUID,PTTYPE,START,LOG-SEC,PHY-SEC,OPT-IO,PARTTYPE,PARTUUID,PARTLABEL,PARTFLAGS",
+            ],
+        )
         .await
         .context("Failed to execute lsblk")?;
 
@@ -387,7 +497,10 @@ pub async fn list_block_devices() -> Result<Vec<BlockDevice>> {
     let json: Value =
         serde_json::from_slice(&output.stdout).context("Failed to parse lsblk JSON")?;
 
-    let mut devices = Vec::new();
+    let text = |value: &Value| value.as_str().map(|s| s.to_string());
+    // lsblk shows active swap as a "[SWAP]" mount point.
+    let mount = |value: &Value| text(value).filter(|mount| mount != "[SWAP]");
+    let mut disks = Vec::new();
 
     if let Some(blockdevices) = json["blockdevices"].as_array() {
         for device in blockdevices {
@@ -401,142 +514,143 @@ pub async fn list_block_devices() -> Result<Vec<BlockDevice>> {
             let size = device["size"].as_u64().unwrap_or(0);
             let model = device["model"].as_str().map(|s| s.trim().to_string());
             let serial = device["serial"].as_str().map(|s| s.trim().to_string());
+            let partition_table = text(&device["pttype"]);
+            let defaults = DiskGeometry::default();
+            let geometry = DiskGeometry {
+                logical_sector_size: device["log-sec"]
+                    .as_u64()
+                    .unwrap_or(defaults.logical_sector_size),
+                physical_sector_size: device["phy-sec"]
+                    .as_u64()
+                    .unwrap_or(defaults.physical_sector_size),
+                optimal_io_size: device["opt-io"].as_u64().unwrap_or(0),
+            };
 
-            let mut partitions = Vec::new();
+            let mut probes = Vec::new();
+            let mut entries = Vec::new();
             if let Some(children) = device["children"].as_array() {
                 for part in children {
-                    let part_name = part["name"].as_str().unwrap_or("").to_string();
+                    entries.push(partition_entry(part));
                     let part_size = part["size"].as_u64().unwrap_or(0);
-                    let filesystem = part["fstype"].as_str().map(|s| s.to_string());
-                    let mount_point = part["mountpoint"].as_str().map(|s| s.to_string());
-                    let label = part["label"].as_str().map(|s| s.to_string());
-
-                    let is_encrypted = is_luks_device(&part_name).await.unwrap_or(false);
-                    let (encryption_type, luks_uuid, mapper_device) = if is_encrypted {
-                        let luks_info = get_luks_info(&part_name).await.ok();
-                        let luks_status = get_luks_status(&part_name).await.ok();
-                        (
-                            luks_info.as_ref().map(|info| info.version.clone()),
-                            luks_info.as_ref().map(|info| info.uuid.clone()),
-                            luks_status.and_then(|status| status.mapper_name),
-                        )
-                    } else {
-                        (None, None, None)
-                    };
-
-                    let actual_mount_point = if let Some(ref mapper_name) = mapper_device {
-                        get_mapper_mount_point(mapper_name, mount_point.clone()).await
-                    } else {
-                        mount_point.clone()
-                    };
-
-                    let (used_bytes, available_bytes) = if let Some(ref mp) = actual_mount_point {
-                        if let Some((used, avail)) = get_filesystem_usage(mp).await {
-                            (Some(used), Some(avail))
-                        } else {
-                            (None, None)
-                        }
-                    } else {
-                        (None, None)
-                    };
-
-                    partitions.push(Partition {
-                        name: part_name,
-                        size: part_size,
-                        filesystem,
-                        mount_point: actual_mount_point.clone(),
-                        is_mounted: actual_mount_point.is_some(),
-                        label,
-                        used_bytes,
-                        available_bytes,
-                        is_encrypted,
-                        encryption_type,
-                        luks_uuid,
-                        mapper_device,
+                    // lsblk reports the start in 512-byte units whatever the
+                    // sector size.
+                    let part_geometry = part["start"].as_u64().map(|start| PartitionGeometry {
+                        start: start * 512 / geometry.logical_sector_size,
+                        sectors: part_size / geometry.logical_sector_size,
+                        logical_sector_size: geometry.logical_sector_size,
+                        physical_sector_size: geometry.physical_sector_size,
                     });
+                    probes.push(probe_partition(
+                        runner,
+                        part["name"].as_str().unwrap_or("").to_string(),
+                        part_geometry,
+                        part_size,
+                        text(&part["fstype"]),
+                        mount(&part["mountpoint"]),
+                        text(&part["label"]),
+                    ));
                 }
             } else {
-                let disk_fs = device["fstype"].as_str().map(|s| s.to_string());
-                let disk_mount = device["mountpoint"].as_str().map(|s| s.to_string());
-                let disk_label = device["label"].as_str().map(|s| s.to_string());
+                let disk_fs = text(&device["fstype"]);
+                let disk_mount = mount(&device["mountpoint"]);
 
                 if disk_fs.is_some() || disk_mount.is_some() {
-                    let is_encrypted = is_luks_device(&name).await.unwrap_or(false);
-                    let (encryption_type, luks_uuid, mapper_device) = if is_encrypted {
-                        let luks_info = get_luks_info(&name).await.ok();
-                        let luks_status = get_luks_status(&name).await.ok();
-                        (
-                            luks_info.as_ref().map(|info| info.version.clone()),
-                            luks_info.as_ref().map(|info| info.uuid.clone()),
-                            luks_status.and_then(|status| status.mapper_name),
-                        )
-                    } else {
-                        (None, None, None)
-                    };
-
-                    let actual_mount_point = if let Some(ref mapper_name) = mapper_device {
-                        get_mapper_mount_point(mapper_name, disk_mount.clone()).await
-                    } else {
-                        disk_mount.clone()
-                    };
-
-                    let (used_bytes, available_bytes) = if let Some(ref mp) = actual_mount_point {
-                        if let Some((used, avail)) = get_filesystem_usage(mp).await {
-                            (Some(used), Some(avail))
-                        } else {
-                            (None, None)
-                        }
-                    } else {
-                        (None, None)
-                    };
-
-                    partitions.push(Partition {
-                        name: name.clone(),
+                    entries.push(partition_entry(device));
+                    probes.push(probe_partition(
+                        runner,
+                        name.clone(),
+                        None,
                         size,
-                        filesystem: disk_fs,
-                        mount_point: actual_mount_point.clone(),
-                        is_mounted: actual_mount_point.is_some(),
-                        label: disk_label,
-                        used_bytes,
-                        available_bytes,
-                        is_encrypted,
-                        encryption_type,
-                        luks_uuid,
-                        mapper_device,
-                    });
+                        disk_fs,
+                        disk_mount,
+                        text(&device["label"]),
+                    ));
                 }
             }
 
-            devices.push(BlockDevice {
-                name,
-                size,
-                model,
-                serial,
-                partitions,
+            disks.push(async move {
+                BlockDevice {
+                    name,
+                    size,
+                    model,
+                    serial,
+                    partition_table,
+                    geometry,
+                    partitions: futures::future::join_all(probes)
+                        .await
+                        .into_iter()
+                        .zip(entries)
+                        .map(|(mut partition, (part_type, part_uuid, part_label, flags, uuid, holder))| {
+                            partition.uuid = uuid;
+                            partition.holder = holder;
+                            partition.part_type = part_type;
+                            partition.part_uuid = part_uuid;
+                            partition.part_label = part_label;
+                            partition.flags = flags;
+                            partition
+                        })
+                        .collect(),
+                }
             });
         }
     }
 
+    let mut devices = futures::future::join_all(disks).await;
+    let swaps = runner
+        .output(
+            "swapon",
+            &["--show=NAME,TYPE,SIZE,USED,PRIO", "--bytes", "--noheadings", "--raw"],
+        )
+        .await
+        .map(|output| parse_swapon(&String::from_utf8_lossy(&output.stdout)))
+        .unwrap_or_default();
+    attach_swap_areas(&mut devices, &swaps);
+    attach_btrfs_holders(&mut devices);
+    Ok(devices)
+}
+
+/// Lists disks for display. Reads sysfs, the udev database and mountinfo
+/// directly, which needs no external processes, and falls back to
+/// `list_block_devices` where those are not available.
+pub async fn scan_block_devices(runner: &dyn CommandRunner) -> Result<Vec<BlockDevice>> {
+    let native = tokio::task::spawn_blocking(|| SysfsReader::default().list_block_devices()).await;
+    let mut devices = match native {
+        Ok(Ok(devices)) => devices,
+        _ => list_block_devices(runner).await?,
+    };
+    // pvs only runs for physical volumes whose group has no active volume.
+    // Without lvm2, or the rights to run it, the helper still finds the
+    // group before anything is overwritten.
+    let unresolved = devices
+        .iter()
+        .flat_map(|d| &d.partitions)
+        .any(|p| matches!(p.holder, Some(Holder::Lvm { vg: None })));
+    if unresolved && let Ok(pvs) = lvm::physical_volumes(runner).await {
+        attach_volume_groups(&mut devices, &pvs);
+    }
     Ok(devices)
 }
 
-pub async fn is_mounted(partition: &str) -> Result<bool> {
-    let device_path = get_device_path(partition);
-    let output = Command::new("findmnt")
-        .args(["-n", &device_path])
-        .output()
+pub async fn is_mounted(runner: &dyn CommandRunner, partition: &str) -> Result<bool> {
+    let device_path = get_device_path(runner, partition);
+    let output = runner
+        .output("findmnt", &["-n", &device_path])
         .await
         .context("Failed to execute findmnt")?;
 
     Ok(output.status.success())
 }
 
-pub async fn mount_partition(partition: &str, sender: &UnboundedSender<Event>) -> Result<()> {
+pub async fn mount_partition(
+    runner: &dyn CommandRunner,
+    partition: &str,
+    sender: &UnboundedSender<Event>,
+) -> Result<()> {
     validate_device_name(partition)?;
 
-    let is_luks = is_luks_device(partition).await.unwrap_or(false);
+    let is_luks = is_luks_device(runner, partition).await.unwrap_or(false);
     if is_luks {
-        let luks_status = get_luks_status(partition).await?;
+        let luks_status = get_luks_status(runner, partition).await?;
         if luks_status.is_active {
             if let Some(mapper_name) = luks_status.mapper_name {
                 Notification::send(
@@ -564,7 +678,7 @@ pub async fn mount_partition(partition: &str, sender: &UnboundedSender<Event>) -
         }
     }
 
-    if is_mounted(partition).await? {
+    if is_mounted(runner, partition).await? {
         Notification::send(
             format!("{} already mounted", partition),
             NotificationLevel::Warning,
@@ -573,9 +687,9 @@ pub async fn mount_partition(partition: &str, sender: &UnboundedSender<Event>) -
         return Ok(());
     }
 
-    let device_path = get_device_path(partition);
+    let device_path = get_device_path(runner, partition);
 
-    if !std::path::Path::new(&device_path).exists() {
+    if !runner.path_exists(&device_path) {
         Notification::send(
             format!(
                 "Device {} does not exist. If this is a LUKS device, ensure it is unlocked first.",
@@ -589,15 +703,13 @@ pub async fn mount_partition(partition: &str, sender: &UnboundedSender<Event>) -
 
     let mount_point = format!("/mnt/{}", partition);
 
-    Command::new("mkdir")
-        .args(["-p", &mount_point])
-        .output()
+    runner
+        .output("mkdir", &["-p", &mount_point])
         .await
         .context("Failed to create mount point")?;
 
-    let output = Command::new("mount")
-        .args([&device_path, &mount_point])
-        .output()
+    let output = runner
+        .output("mount", &[&device_path, &mount_point])
         .await
         .context("Failed to mount partition")?;
 
@@ -620,12 +732,16 @@ pub async fn mount_partition(partition: &str, sender: &UnboundedSender<Event>) -
     Ok(())
 }
 
-pub async fn unmount_partition(partition: &str, sender: &UnboundedSender<Event>) -> Result<()> {
+pub async fn unmount_partition(
+    runner: &dyn CommandRunner,
+    partition: &str,
+    sender: &UnboundedSender<Event>,
+) -> Result<()> {
     validate_device_name(partition)?;
 
-    let is_luks = is_luks_device(partition).await.unwrap_or(false);
+    let is_luks = is_luks_device(runner, partition).await.unwrap_or(false);
     if is_luks {
-        let luks_status = get_luks_status(partition).await?;
+        let luks_status = get_luks_status(runner, partition).await?;
         if luks_status.is_active {
             Notification::send(
                 format!(
@@ -641,7 +757,7 @@ pub async fn unmount_partition(partition: &str, sender: &UnboundedSender<Event>)
         }
     }
 
-    if !is_mounted(partition).await? {
+    if !is_mounted(runner, partition).await? {
         Notification::send(
             format!("{} not mounted", partition),
             NotificationLevel::Warning,
@@ -650,9 +766,9 @@ pub async fn unmount_partition(partition: &str, sender: &UnboundedSender<Event>)
         return Ok(());
     }
 
-    let device_path = get_device_path(partition);
+    let device_path = get_device_path(runner, partition);
 
-    if !std::path::Path::new(&device_path).exists() {
+    if !runner.path_exists(&device_path) {
         Notification::send(
             format!(
                 "Device {} does not exist. If this is a LUKS device, ensure it is unlocked first.",
@@ -664,71 +780,73 @@ pub async fn unmount_partition(partition: &str, sender: &UnboundedSender<Event>)
         return Err(anyhow!("Device does not exist: {}", device_path));
     }
 
-    let actual_mount_point = get_device_mount_point(&device_path).await;
+    let actual_mount_point = get_device_mount_point(runner, &device_path).await;
 
     sender.send(Event::StartProgress(format!("Unmounting {}...", partition)))?;
 
-    let unmount_future = Command::new("umount").arg(&device_path).output();
+    let umount_args = [device_path.as_str()];
+    let unmount_future = runner.output("umount", &umount_args);
 
-    let output =
-        match tokio::time::timeout(tokio::time::Duration::from_secs(5), unmount_future).await {
-            Ok(Ok(output)) => output,
-            Ok(Err(e)) => {
-                sender.send(Event::EndProgress)?;
-                Notification::send(
-                    format!("Failed to unmount: {}", e),
-                    NotificationLevel::Error,
-                    sender,
-                )?;
-                return Err(anyhow!("Failed to execute unmount"));
-            }
-            Err(_) => {
-                sender.send(Event::EndProgress)?;
-                Notification::send(
-                    "Device is busy. Attempting lazy unmount...".to_string(),
-                    NotificationLevel::Info,
-                    sender,
-                )?;
-
-                sender.send(Event::StartProgress(format!(
-                    "Lazy unmounting {}...",
-                    partition
-                )))?;
-
-                let lazy_output = Command::new("umount")
-                    .args(["-l", &device_path])
-                    .output()
-                    .await
-                    .context("Failed to lazy unmount")?;
+    let output = match tokio::time::timeout(tokio::time::Duration::from_secs(5), unmount_future)
+        .await
+    {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => {
+            sender.send(Event::EndProgress)?;
+            Notification::send(
+                format!("Failed to unmount: {}", e),
+                NotificationLevel::Error,
+                sender,
+            )?;
+            return Err(anyhow!("Failed to execute unmount"));
+        }
+        Err(_) => {
+            sender.send(Event::EndProgress)?;
+            Notification::send(
+                "Device is busy. Attempting lazy unmount...".to_string(),
+                NotificationLevel::Info,
+                sender,
+            )?;
 
-                sender.send(Event::EndProgress)?;
+            sender.send(Event::StartProgress(format!(
+                "Lazy unmounting {}...",
+                partition
+            )))?;
 
-                if !lazy_output.status.success() {
-                    let err = String::from_utf8_lossy(&lazy_output.stderr);
-                    Notification::send(
-                        format!("Lazy unmount failed: {}", err),
-                        NotificationLevel::Error,
-                        sender,
-                    )?;
-                    return Err(anyhow!("Lazy unmount failed"));
-                }
+            let lazy_output = runner
+                .output("umount", &["-l", &device_path])
+                .await
+                .context("Failed to lazy unmount")?;
 
-                if let Some(ref mp) = actual_mount_point
-                    && mp.starts_with("/mnt/") {
-                        let _ = Command::new("rmdir").arg(mp).output().await;
-                    }
+            sender.send(Event::EndProgress)?;
 
+            if !lazy_output.status.success() {
+                let err = String::from_utf8_lossy(&lazy_output.stderr);
                 Notification::send(
-                    format!(
-                        "Lazy unmount initiated for {}. Device still in use - wait before formatting/deleting!",
-                        partition
-                    ),
-                    NotificationLevel::Warning,
+                    format!("Lazy unmount failed: {}", err),
+                    NotificationLevel::Error,
                     sender,
                 )?;
-                return Ok(());
+                return Err(anyhow!("Lazy unmount failed"));
+            }
+
+            if let Some(ref mp) = actual_mount_point
+                && mp.starts_with("/mnt/")
+            {
+                let _ = runner.output("rmdir", &[mp]).await;
             }
-        };
+
+            Notification::send(
+                format!(
+                    "Lazy unmount initiated for {}. Device still in use - wait before formatting/deleting!",
+                    partition
+                ),
+                NotificationLevel::Warning,
+                sender,
+            )?;
+            return Ok(());
+        }
+    };
 
     sender.send(Event::EndProgress)?;
 
@@ -747,9 +865,8 @@ pub async fn unmount_partition(partition: &str, sender: &UnboundedSender<Event>)
                 partition
             )))?;
 
-            let lazy_output = Command::new("umount")
-                .args(["-l", &device_path])
-                .output()
+            let lazy_output = runner
+                .output("umount", &["-l", &device_path])
                 .await
                 .context("Failed to lazy unmount")?;
 
@@ -766,9 +883,10 @@ pub async fn unmount_partition(partition: &str, sender: &UnboundedSender<Event>)
             }
 
             if let Some(ref mp) = actual_mount_point
-                && mp.starts_with("/mnt/") {
-                    let _ = Command::new("rmdir").arg(mp).output().await;
-                }
+                && mp.starts_with("/mnt/")
+            {
+                let _ = runner.output("rmdir", &[mp]).await;
+            }
 
             Notification::send(
                 format!(
@@ -790,9 +908,10 @@ pub async fn unmount_partition(partition: &str, sender: &UnboundedSender<Event>)
     }
 
     if let Some(ref mp) = actual_mount_point
-        && mp.starts_with("/mnt/") {
-            let _ = Command::new("rmdir").arg(mp).output().await;
-        }
+        && mp.starts_with("/mnt/")
+    {
+        let _ = runner.output("rmdir", &[mp]).await;
+    }
 
     Notification::send(
         format!("Unmounted {}", partition),
@@ -804,28 +923,28 @@ pub async fn unmount_partition(partition: &str, sender: &UnboundedSender<Event>)
 }
 
 pub async fn format_whole_disk(
+    runner: &dyn CommandRunner,
     disk: &str,
     fs_type: FilesystemType,
     sender: UnboundedSender<Event>,
 ) -> Result<()> {
     validate_device_name(disk)?;
 
-    let devices = list_block_devices().await?;
+    let devices = list_block_devices(runner).await?;
     if let Some(device) = devices.iter().find(|d| d.name == disk) {
         for partition in &device.partitions {
-            let luks_status = get_luks_status(&partition.name).await?;
+            let luks_status = get_luks_status(runner, &partition.name).await?;
             if luks_status.is_active {
                 if let Some(mapper_name) = luks_status.mapper_name {
                     let mapper_path = format!("/dev/mapper/{}", mapper_name);
-                    let mapper_mounted = Command::new("findmnt")
-                        .args(["-n", &mapper_path])
-                        .output()
+                    let mapper_mounted = runner
+                        .output("findmnt", &["-n", &mapper_path])
                         .await
                         .map(|output| output.status.success())
                         .unwrap_or(false);
 
                     if mapper_mounted {
-                        unmount_partition(&mapper_name, &sender).await?;
+                        unmount_partition(runner, &mapper_name, &sender).await?;
                     }
 
                     Notification::send(
@@ -833,7 +952,7 @@ pub async fn format_whole_disk(
                         NotificationLevel::Info,
                         &sender,
                     )?;
-                    lock_luks_device(&mapper_name, &sender).await?;
+                    lock_luks_device(runner, &mapper_name, &sender).await?;
                 }
             } else if partition.is_mounted {
                 Notification::send(
@@ -841,7 +960,7 @@ pub async fn format_whole_disk(
                     NotificationLevel::Info,
                     &sender,
                 )?;
-                unmount_partition(&partition.name, &sender).await?;
+                unmount_partition(runner, &partition.name, &sender).await?;
             }
         }
     }
@@ -853,9 +972,10 @@ pub async fn format_whole_disk(
         FilesystemType::Exfat => "mkfs.exfat",
         FilesystemType::Btrfs => "mkfs.btrfs",
         FilesystemType::Xfs => "mkfs.xfs",
+        FilesystemType::Swap => "mkswap",
     };
 
-    let check_cmd = Command::new("which").arg(cmd).output().await;
+    let check_cmd = runner.output("which", &[cmd]).await;
 
     if check_cmd.is_err() || !check_cmd.unwrap().status.success() {
         Notification::send(
@@ -874,9 +994,11 @@ pub async fn format_whole_disk(
         disk
     )))?;
 
-    let output = Command::new("parted")
-        .args(["-s", &format!("/dev/{}", disk), "mklabel", "gpt"])
-        .output()
+    let output = runner
+        .output(
+            "parted",
+            &["-s", &format!("/dev/{}", disk), "mklabel", "gpt"],
+        )
         .await
         .context("Failed to create partition table")?;
 
@@ -891,18 +1013,20 @@ pub async fn format_whole_disk(
         return Err(anyhow!("Failed to create partition table"));
     }
 
-    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    runner.sleep(tokio::time::Duration::from_secs(1)).await;
 
-    let output = Command::new("parted")
-        .args([
-            "-s",
-            &format!("/dev/{}", disk),
-            "mkpart",
-            "primary",
-            "0%",
-            "100%",
-        ])
-        .output()
+    let output = runner
+        .output(
+            "parted",
+            &[
+                "-s",
+                &format!("/dev/{}", disk),
+                "mkpart",
+                "primary",
+                "0%",
+                "100%",
+            ],
+        )
         .await
         .context("Failed to create partition")?;
 
@@ -917,46 +1041,47 @@ pub async fn format_whole_disk(
         return Err(anyhow!("Failed to create partition"));
     }
 
-    let _ = Command::new("partprobe")
-        .arg(format!("/dev/{}", disk))
-        .output()
+    let _ = runner
+        .output("partprobe", &[&format!("/dev/{}", disk)])
         .await;
 
-    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    runner.sleep(tokio::time::Duration::from_secs(2)).await;
 
-    let devices = list_block_devices().await?;
+    let devices = list_block_devices(runner).await?;
     let device = devices.iter().find(|d| d.name == disk);
 
     if let Some(device) = device
-        && let Some(new_partition) = device.partitions.first() {
-            let part_name = new_partition.name.clone();
-            let fs_str = fs_type.as_str().to_string();
-            format_partition(&part_name, fs_type, sender.clone()).await?;
+        && let Some(new_partition) = device.partitions.first()
+    {
+        let part_name = new_partition.name.clone();
+        let fs_str = fs_type.as_str().to_string();
+        format_partition(runner, &part_name, fs_type, sender.clone()).await?;
 
-            sender.send(Event::EndProgress)?;
+        sender.send(Event::EndProgress)?;
 
-            Notification::send(
-                format!("Formatted {} as whole disk with {}", disk, fs_str),
-                NotificationLevel::Info,
-                &sender,
-            )?;
-            return Ok(());
-        }
+        Notification::send(
+            format!("Formatted {} as whole disk with {}", disk, fs_str),
+            NotificationLevel::Info,
+            &sender,
+        )?;
+        return Ok(());
+    }
 
     sender.send(Event::EndProgress)?;
     Err(anyhow!("Failed to find new partition"))
 }
 
 pub async fn format_partition(
+    runner: &dyn CommandRunner,
     partition: &str,
     fs_type: FilesystemType,
     sender: UnboundedSender<Event>,
 ) -> Result<()> {
     validate_device_name(partition)?;
 
-    let is_luks = is_luks_device(partition).await.unwrap_or(false);
+    let is_luks = is_luks_device(runner, partition).await.unwrap_or(false);
     let actual_device = if is_luks {
-        let luks_status = get_luks_status(partition).await?;
+        let luks_status = get_luks_status(runner, partition).await?;
         if luks_status.is_active {
             if let Some(mapper_name) = luks_status.mapper_name {
                 Notification::send(
@@ -988,7 +1113,7 @@ pub async fn format_partition(
         partition.to_string()
     };
 
-    if is_mounted(&actual_device).await? {
+    if is_mounted(runner, &actual_device).await? {
         Notification::send(
             format!("{} is mounted. Unmount it first (press 'm')", actual_device),
             NotificationLevel::Error,
@@ -997,9 +1122,9 @@ pub async fn format_partition(
         return Err(anyhow!("Partition is mounted"));
     }
 
-    let device_path = get_device_path(&actual_device);
+    let device_path = get_device_path(runner, &actual_device);
 
-    if !std::path::Path::new(&device_path).exists() {
+    if !runner.path_exists(&device_path) {
         Notification::send(
             format!(
                 "Device {} does not exist. If this is a LUKS device, ensure it is unlocked first.",
@@ -1026,9 +1151,10 @@ pub async fn format_partition(
         FilesystemType::Exfat => ("mkfs.exfat", vec![&device_path]),
         FilesystemType::Btrfs => ("mkfs.btrfs", vec!["-f", &device_path]),
         FilesystemType::Xfs => ("mkfs.xfs", vec!["-f", &device_path]),
+        FilesystemType::Swap => ("mkswap", vec!["-f", &device_path]),
     };
 
-    let check_cmd = Command::new("which").arg(cmd).output().await;
+    let check_cmd = runner.output("which", &[cmd]).await;
 
     if check_cmd.is_err() || !check_cmd.unwrap().status.success() {
         Notification::send(
@@ -1048,7 +1174,7 @@ pub async fn format_partition(
         fs_type.as_str()
     )))?;
 
-    let output = match Command::new(cmd).args(&args).output().await {
+    let output = match runner.output(cmd, &args).await {
         Ok(output) => output,
         Err(e) => {
             sender.send(Event::EndProgress)?;
@@ -1083,15 +1209,18 @@ pub async fn format_partition(
 }
 
 pub async fn create_partition_table(
+    runner: &dyn CommandRunner,
     disk: &str,
     table_type: &str,
     sender: &UnboundedSender<Event>,
 ) -> Result<()> {
     validate_device_name(disk)?;
 
-    let output = Command::new("parted")
-        .args(["-s", &format!("/dev/{}", disk), "mklabel", table_type])
-        .output()
+    let output = runner
+        .output(
+            "parted",
+            &["-s", &format!("/dev/{}", disk), "mklabel", table_type],
+        )
         .await
         .context("Failed to execute parted")?;
 
@@ -1115,13 +1244,16 @@ pub async fn create_partition_table(
 }
 
 async fn create_partition_raw(
+    runner: &dyn CommandRunner,
     disk: &str,
+    start: Option<u64>,
     size_input: &str,
+    alignment: Alignment,
     sender: &UnboundedSender<Event>,
 ) -> Result<String> {
     validate_device_name(disk)?;
 
-    let devices = list_block_devices().await?;
+    let devices = list_block_devices(runner).await?;
     let device = devices.iter().find(|d| d.name == disk);
 
     if device.is_none() {
@@ -1134,51 +1266,46 @@ async fn create_partition_raw(
     }
 
     let device = device.unwrap();
-    let start_offset = get_last_partition_end_bytes(disk).await?;
-    let free_space = device.size.saturating_sub(start_offset);
-
-    if free_space == 0 {
+    if device.partition_table.is_none() {
         Notification::send(
-            "No free space available".to_string(),
+            format!(
+                "No partition table on {}. Press 'p' to create one first.",
+                disk
+            ),
             NotificationLevel::Error,
             sender,
         )?;
-        return Err(anyhow!("No free space"));
+        return Err(anyhow!("No partition table"));
     }
 
-    let requested_size = if size_input.trim().is_empty() {
-        free_space
-    } else {
-        parse_size(size_input)?
+    let placement = match layout::place_partition(
+        &layout::device_gaps(device),
+        start,
+        size_input,
+        &device.geometry,
+        alignment,
+    ) {
+        Ok(placement) => placement,
+        Err(e) => {
+            Notification::send(e.to_string(), NotificationLevel::Error, sender)?;
+            return Err(e);
+        }
     };
-
-    if requested_size > free_space {
-        Notification::send(
-            format!(
-                "Requested size exceeds available space ({} > {})",
-                format_bytes(requested_size),
-                format_bytes(free_space)
-            ),
-            NotificationLevel::Error,
-            sender,
-        )?;
-        return Err(anyhow!("Size too large"));
-    }
-
-    let start_mb = start_offset / 1_000_000;
-    let end_offset = start_offset + requested_size;
-    let end_mb = end_offset / 1_000_000;
-
-    let output = Command::new("parted")
-        .args([
-            "-s",
-            &format!("/dev/{}", disk),
-            "mkpart",
-            "primary",
-            &format!("{}MB", start_mb),
-            &format!("{}MB", end_mb),
-        ])
-        .output()
+    let (start_mb, end_mb) = placement.mkpart_bounds();
+    let existing: Vec<String> = device.partitions.iter().map(|p| p.name.clone()).collect();
+
+    let output = runner
+        .output(
+            "parted",
+            &[
+                "-s",
+                &format!("/dev/{}", disk),
+                "mkpart",
+                "primary",
+                &start_mb,
+                &end_mb,
+            ],
+        )
         .await
         .context("Failed to execute parted")?;
 
@@ -1198,22 +1325,29 @@ async fn create_partition_raw(
         return Err(anyhow!("Create partition failed"));
     }
 
-    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
+    runner.sleep(tokio::time::Duration::from_secs(2)).await;
 
-    let devices = list_block_devices().await?;
+    let devices = list_block_devices(runner).await?;
     let device = devices.iter().find(|d| d.name == disk);
 
     if let Some(device) = device
-        && let Some(new_partition) = device.partitions.last() {
-            return Ok(new_partition.name.clone());
-        }
+        && let Some(new_partition) = device
+            .partitions
+            .iter()
+            .find(|p| !existing.contains(&p.name))
+    {
+        return Ok(new_partition.name.clone());
+    }
 
     Err(anyhow!("Failed to find new partition"))
 }
 
 pub async fn create_partition_with_fs(
+    runner: &dyn CommandRunner,
     disk: &str,
+    start: Option<u64>,
     size_input: &str,
+    alignment: Alignment,
     fs_type: FilesystemType,
     sender: &UnboundedSender<Event>,
 ) -> Result<()> {
@@ -1222,7 +1356,7 @@ pub async fn create_partition_with_fs(
         disk
     )))?;
 
-    let part_name = create_partition_raw(disk, size_input, sender).await?;
+    let part_name = create_partition_raw(runner, disk, start, size_input, alignment, sender).await?;
 
     Notification::send(
         format!("Formatting {} as {}...", part_name, fs_type),
@@ -1230,7 +1364,7 @@ pub async fn create_partition_with_fs(
         sender,
     )?;
 
-    format_partition(&part_name, fs_type, sender.clone()).await?;
+    format_partition(runner, &part_name, fs_type, sender.clone()).await?;
 
     sender.send(Event::EndProgress)?;
 
@@ -1243,36 +1377,40 @@ pub async fn create_partition_with_fs(
     Ok(())
 }
 
-pub async fn delete_partition(partition: &str, sender: &UnboundedSender<Event>) -> Result<()> {
+pub async fn delete_partition(
+    runner: &dyn CommandRunner,
+    partition: &str,
+    sender: &UnboundedSender<Event>,
+) -> Result<()> {
     validate_device_name(partition)?;
 
-    let is_luks = is_luks_device(partition).await.unwrap_or(false);
+    let is_luks = is_luks_device(runner, partition).await.unwrap_or(false);
 
-    let luks_status = get_luks_status(partition).await?;
+    let luks_status = get_luks_status(runner, partition).await?;
     if luks_status.is_active
-        && let Some(mapper_name) = luks_status.mapper_name {
-            let mapper_path = format!("/dev/mapper/{}", mapper_name);
-            let mapper_mounted = Command::new("findmnt")
-                .args(["-n", &mapper_path])
-                .output()
-                .await
-                .map(|output| output.status.success())
-                .unwrap_or(false);
-
-            if mapper_mounted {
-                unmount_partition(&mapper_name, sender).await?;
-            }
-
-            Notification::send(
-                format!("Closing encrypted device {}...", mapper_name),
-                NotificationLevel::Info,
-                sender,
-            )?;
-            lock_luks_device(&mapper_name, sender).await?;
+        && let Some(mapper_name) = luks_status.mapper_name
+    {
+        let mapper_path = format!("/dev/mapper/{}", mapper_name);
+        let mapper_mounted = runner
+            .output("findmnt", &["-n", &mapper_path])
+            .await
+            .map(|output| output.status.success())
+            .unwrap_or(false);
+
+        if mapper_mounted {
+            unmount_partition(runner, &mapper_name, sender).await?;
         }
 
-    if is_mounted(partition).await? {
-        unmount_partition(partition, sender).await?;
+        Notification::send(
+            format!("Closing encrypted device {}...", mapper_name),
+            NotificationLevel::Info,
+            sender,
+        )?;
+        lock_luks_device(runner, &mapper_name, sender).await?;
+    }
+
+    if is_mounted(runner, partition).await? {
+        unmount_partition(runner, partition, sender).await?;
     }
 
     if is_luks {
@@ -1283,10 +1421,7 @@ pub async fn delete_partition(partition: &str, sender: &UnboundedSender<Event>)
         )?;
 
         let device_path = format!("/dev/{}", partition);
-        let wipe_output = Command::new("wipefs")
-            .args(["-a", &device_path])
-            .output()
-            .await;
+        let wipe_output = runner.output("wipefs", &["-a", &device_path]).await;
 
         match wipe_output {
             Ok(output) if output.status.success() => {
@@ -1299,7 +1434,10 @@ pub async fn delete_partition(partition: &str, sender: &UnboundedSender<Event>)
             Ok(output) => {
                 let err = String::from_utf8_lossy(&output.stderr);
                 Notification::send(
-                    format!("Warning: Failed to wipe LUKS header: {}. Continuing with deletion...", err),
+                    format!(
+                        "Warning: Failed to wipe LUKS header: {}. Continuing with deletion...",
+                        err
+                    ),
                     NotificationLevel::Warning,
                     sender,
                 )?;
@@ -1333,9 +1471,8 @@ pub async fn delete_partition(partition: &str, sender: &UnboundedSender<Event>)
         sender,
     )?;
 
-    let output = Command::new("parted")
-        .args(["-s", &format!("/dev/{}", disk), "rm", part_num])
-        .output()
+    let output = runner
+        .output("parted", &["-s", &format!("/dev/{}", disk), "rm", part_num])
         .await
         .context("Failed to execute parted")?;
 
@@ -1349,312 +1486,28 @@ pub async fn delete_partition(partition: &str, sender: &UnboundedSender<Event>)
         return Err(anyhow!("Delete partition failed: {}", err));
     }
 
-    let partprobe_output = Command::new("partprobe")
-        .arg(format!("/dev/{}", disk))
-        .output()
+    let partprobe_output = runner
+        .output("partprobe", &[&format!("/dev/{}", disk)])
         .await;
 
     if let Ok(output) = partprobe_output
-        && !output.status.success() {
-            let err = String::from_utf8_lossy(&output.stderr);
-            Notification::send(
-                format!("Warning: partprobe failed: {}. Partition deleted but you may need to reboot.", err),
-                NotificationLevel::Warning,
-                sender,
-            )?;
-        }
-
-    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-
-    Notification::send(
-        format!("Successfully deleted partition {}", partition),
-        NotificationLevel::Info,
-        sender,
-    )?;
-
-    Ok(())
-}
-
-pub async fn get_smart_data(disk: &str) -> Result<SmartData> {
-    validate_device_name(disk)?;
-
-    let output = Command::new("smartctl")
-        .args(["-H", "-A", &format!("/dev/{}", disk)])
-        .output()
-        .await;
-
-    if output.is_err() {
-        return Ok(SmartData {
-            health: "N/A".to_string(),
-            temperature: None,
-            power_on_hours: None,
-        });
-    }
-
-    let output = output.unwrap();
-    let text = String::from_utf8_lossy(&output.stdout);
-
-    let health = if text.contains("PASSED") {
-        "PASSED".to_string()
-    } else if text.contains("FAILED") {
-        "FAILED".to_string()
-    } else {
-        "N/A".to_string()
-    };
-
-    let temperature = text
-        .lines()
-        .find(|l| l.contains("Temperature_Celsius") || l.contains("Temperature"))
-        .and_then(|l| {
-            l.split_whitespace()
-                .filter_map(|s| s.parse::<i32>().ok())
-                .find(|&n| n > 0 && n < 100)
-        });
-
-    let power_on_hours = text
-        .lines()
-        .find(|l| l.contains("Power_On_Hours"))
-        .and_then(|l| {
-            l.split_whitespace()
-                .filter_map(|s| s.parse::<u64>().ok())
-                .find(|&n| n > 0)
-        });
-
-    Ok(SmartData {
-        health,
-        temperature,
-        power_on_hours,
-    })
-}
-
-pub async fn resize_partition_and_filesystem(
-    partition: &str,
-    new_size_input: &str,
-    sender: &UnboundedSender<Event>,
-) -> Result<()> {
-    validate_device_name(partition)?;
-
-    let is_luks = is_luks_device(partition).await.unwrap_or(false);
-    if is_luks {
-        Notification::send(
-            format!(
-                "{} is an encrypted partition. Resizing encrypted partitions is not supported as it risks data corruption. To resize: backup data, delete partition, create larger partition, restore data.",
-                partition
-            ),
-            NotificationLevel::Error,
-            sender,
-        )?;
-        return Err(anyhow!(
-            "Cannot resize encrypted partitions - too risky for data integrity"
-        ));
-    }
-
-    if is_mounted(partition).await? {
-        Notification::send(
-            format!("{} is mounted. Unmount it first (press 'm')", partition),
-            NotificationLevel::Error,
-            sender,
-        )?;
-        return Err(anyhow!("Partition is mounted"));
-    }
-
-    sender.send(Event::StartProgress(format!("Resizing {}...", partition)))?;
-
-    let (disk, part_num) = if partition.starts_with("nvme") || partition.starts_with("mmcblk") {
-        let parts: Vec<&str> = partition.rsplitn(2, 'p').collect();
-        if parts.len() == 2 {
-            (parts[1].to_string(), parts[0].parse::<usize>()?)
-        } else {
-            sender.send(Event::EndProgress)?;
-            return Err(anyhow!("Invalid partition name format: {}", partition));
-        }
-    } else {
-        let disk = partition.trim_end_matches(|c: char| c.is_numeric());
-        let part_num_str = partition.trim_start_matches(disk);
-        (disk.to_string(), part_num_str.parse::<usize>()?)
-    };
-
-    let new_size_bytes = parse_size(new_size_input)?;
-
-    let devices = list_block_devices().await?;
-    let device = devices
-        .iter()
-        .find(|d| d.name == disk)
-        .ok_or_else(|| anyhow!("Disk {} not found", disk))?;
-
-    let current_partition = device
-        .partitions
-        .iter()
-        .find(|p| p.name == partition)
-        .ok_or_else(|| anyhow!("Partition {} not found", partition))?;
-
-    let current_size = current_partition.size;
-    let filesystem = current_partition.filesystem.clone();
-
-    let is_growing = new_size_bytes > current_size;
-
-    if !is_growing {
-        Notification::send(
-            "Shrinking filesystem...".to_string(),
-            NotificationLevel::Info,
-            sender,
-        )?;
-        resize_filesystem(partition, &filesystem, new_size_bytes, false, sender).await?;
-    }
-
-    Notification::send(
-        "Resizing partition...".to_string(),
-        NotificationLevel::Info,
-        sender,
-    )?;
-
-    let output = Command::new("sfdisk")
-        .args(["-d", &format!("/dev/{}", disk)])
-        .output()
-        .await
-        .context("Failed to dump partition table")?;
-
-    if !output.status.success() {
-        sender.send(Event::EndProgress)?;
-        let err = String::from_utf8_lossy(&output.stderr);
-        Notification::send(
-            format!("Failed to read partition table: {}", err),
-            NotificationLevel::Error,
-            sender,
-        )?;
-        return Err(anyhow!("Failed to read partition table"));
-    }
-
-    let table = String::from_utf8_lossy(&output.stdout);
-    let mut new_table = String::new();
-    let mut found = false;
-
-    for line in table.lines() {
-        if line.contains(&format!("/dev/{}{}", disk, part_num))
-            || line.contains(&format!("/dev/{}p{}", disk, part_num))
-        {
-            found = true;
-
-            let parts: Vec<&str> = line.split(&[':', ','][..]).collect();
-
-            if parts.is_empty() {
-                sender.send(Event::EndProgress)?;
-                return Err(anyhow!("Invalid partition table format"));
-            }
-
-            let device_part = parts[0].trim();
-
-            let expected_dev = format!("/dev/{}{}", disk, part_num);
-            let expected_dev_p = format!("/dev/{}p{}", disk, part_num);
-            if device_part != expected_dev && device_part != expected_dev_p {
-                new_table.push_str(line);
-                new_table.push('\n');
-                continue;
-            }
-
-            let mut start_str = String::new();
-            let mut other_attrs = Vec::new();
-
-            for part in parts.iter().skip(1) {
-                let trimmed = part.trim();
-                if trimmed.starts_with("start") {
-                    start_str = trimmed.to_string();
-                } else if trimmed.starts_with("size") {
-                    continue;
-                } else if !trimmed.is_empty() {
-                    other_attrs.push(trimmed.to_string());
-                }
-            }
-
-            if start_str.is_empty() {
-                sender.send(Event::EndProgress)?;
-                Notification::send(
-                    "Could not parse partition table".to_string(),
-                    NotificationLevel::Error,
-                    sender,
-                )?;
-                return Err(anyhow!("Could not find start sector"));
-            }
-
-            let size_sectors = new_size_bytes.div_ceil(512);
-
-            let mut new_line = format!("{} : {}, size={}", device_part, start_str, size_sectors);
-            for attr in other_attrs {
-                new_line.push_str(", ");
-                new_line.push_str(&attr);
-            }
-
-            new_table.push_str(&new_line);
-            new_table.push('\n');
-        } else {
-            new_table.push_str(line);
-            new_table.push('\n');
-        }
-    }
-
-    if !found {
-        sender.send(Event::EndProgress)?;
-        Notification::send(
-            format!("Partition {} not found in partition table", partition),
-            NotificationLevel::Error,
-            sender,
-        )?;
-        return Err(anyhow!("Partition not found in table"));
-    }
-
-    let mut child = Command::new("sfdisk")
-        .args(["--force", "--no-reread", &format!("/dev/{}", disk)])
-        .stdin(std::process::Stdio::piped())
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
-        .context("Failed to spawn sfdisk")?;
-
+        && !output.status.success()
     {
-        if let Some(mut stdin) = child.stdin.take() {
-            use tokio::io::AsyncWriteExt;
-            stdin.write_all(new_table.as_bytes()).await?;
-            stdin.flush().await?;
-            drop(stdin);
-        }
-    }
-
-    let output = child.wait_with_output().await?;
-
-    if !output.status.success() {
-        sender.send(Event::EndProgress)?;
         let err = String::from_utf8_lossy(&output.stderr);
         Notification::send(
-            format!("Failed to resize partition: {}", err),
-            NotificationLevel::Error,
+            format!(
+                "Warning: partprobe failed: {}. Partition deleted but you may need to reboot.",
+                err
+            ),
+            NotificationLevel::Warning,
             sender,
         )?;
-        return Err(anyhow!("Failed to resize partition"));
     }
 
-    tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-    let _ = Command::new("partprobe")
-        .arg(format!("/dev/{}", disk))
-        .output()
-        .await;
-    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-
-    if is_growing {
-        Notification::send(
-            "Expanding filesystem...".to_string(),
-            NotificationLevel::Info,
-            sender,
-        )?;
-        resize_filesystem(partition, &filesystem, new_size_bytes, true, sender).await?;
-    }
+    runner.sleep(tokio::time::Duration::from_millis(500)).await;
 
-    sender.send(Event::EndProgress)?;
     Notification::send(
-        format!(
-            "Successfully resized {} to {}",
-            partition,
-            format_bytes(new_size_bytes)
-        ),
+        format!("Successfully deleted partition {}", partition),
         NotificationLevel::Info,
         sender,
     )?;
@@ -1662,269 +1515,51 @@ pub async fn resize_partition_and_filesystem(
     Ok(())
 }
 
-async fn resize_filesystem(
-    partition: &str,
-    filesystem: &Option<String>,
-    new_size_bytes: u64,
-    is_growing: bool,
-    sender: &UnboundedSender<Event>,
-) -> Result<()> {
-    let fs = match filesystem {
-        Some(fs) => fs.as_str(),
-        None => {
-            Notification::send(
-                "No filesystem detected, skipping filesystem resize".to_string(),
-                NotificationLevel::Warning,
-                sender,
-            )?;
-            return Ok(());
-        }
-    };
-
-    let device_path = format!("/dev/{}", partition);
-
-    match fs {
-        "ext4" | "ext3" | "ext2" => {
-            let output = if is_growing {
-                Command::new("resize2fs")
-                    .arg(&device_path)
-                    .output()
-                    .await
-                    .context("Failed to execute resize2fs")?
-            } else {
-                let size_k = format!("{}K", new_size_bytes / 1024);
-                Command::new("resize2fs")
-                    .args([&device_path, &size_k])
-                    .output()
-                    .await
-                    .context("Failed to execute resize2fs")?
-            };
-
-            if !output.status.success() {
-                let err = String::from_utf8_lossy(&output.stderr);
-                Notification::send(
-                    format!("Filesystem resize failed: {}", err),
-                    NotificationLevel::Error,
-                    sender,
-                )?;
-                return Err(anyhow!("resize2fs failed"));
-            }
-        }
-        "xfs" => {
-            if !is_growing {
-                Notification::send(
-                    "XFS does not support shrinking".to_string(),
-                    NotificationLevel::Error,
-                    sender,
-                )?;
-                return Err(anyhow!("XFS cannot be shrunk"));
-            }
-
-            let mount_point = format!("/tmp/disktui_resize_{}", partition.replace('/', "_"));
-            Command::new("mkdir")
-                .args(["-p", &mount_point])
-                .output()
-                .await?;
-
-            let mount_output = Command::new("mount")
-                .args([&device_path, &mount_point])
-                .output()
-                .await?;
-
-            if mount_output.status.success() {
-                let resize_output = Command::new("xfs_growfs").arg(&mount_point).output().await;
+/// Reads the SMART attributes, health log and self-test state of a disk.
+pub async fn get_smart_data(runner: &dyn CommandRunner, disk: &str) -> Result<SmartData> {
+    validate_device_name(disk)?;
 
-                let umount_result = Command::new("umount").arg(&mount_point).output().await;
-                if umount_result.is_ok() && umount_result.as_ref().unwrap().status.success() {
-                    let _ = Command::new("rmdir").arg(&mount_point).output().await;
-                } else {
-                    Notification::send(
-                        format!("Warning: Failed to unmount temp mount at {}", mount_point),
-                        NotificationLevel::Warning,
-                        sender,
-                    )?;
-                }
+    let output = runner
+        .output("smartctl", &["--json", "-a", &format!("/dev/{}", disk)])
+        .await
+        .context("Failed to execute smartctl")?;
 
-                if let Ok(output) = resize_output {
-                    if !output.status.success() {
-                        let err = String::from_utf8_lossy(&output.stderr);
-                        Notification::send(
-                            format!("XFS resize failed: {}", err),
-                            NotificationLevel::Error,
-                            sender,
-                        )?;
-                        return Err(anyhow!("xfs_growfs failed"));
-                    }
-                } else {
-                    Notification::send(
-                        "Failed to resize XFS filesystem".to_string(),
-                        NotificationLevel::Error,
-                        sender,
-                    )?;
-                    return Err(anyhow!("xfs_growfs failed"));
-                }
-            } else {
-                let err = String::from_utf8_lossy(&mount_output.stderr);
-                let _ = Command::new("rmdir").arg(&mount_point).output().await;
-                Notification::send(
-                    format!("Failed to mount for XFS resize: {}", err),
-                    NotificationLevel::Error,
-                    sender,
-                )?;
-                return Err(anyhow!("Mount failed"));
-            }
-        }
-        "ntfs" => {
-            let size_str = new_size_bytes.to_string();
-            let args: Vec<&str> = if is_growing {
-                vec!["-f", "-f", &device_path]
-            } else {
-                vec!["-f", "-f", "-s", &size_str, &device_path]
-            };
+    // The exit status is a bit mask that is also set for failing disks, so
+    // the JSON is read either way.
+    smart::parse(&String::from_utf8_lossy(&output.stdout))
+}
 
-            let child = Command::new("ntfsresize")
-                .args(&args)
-                .stdin(std::process::Stdio::piped())
-                .stdout(std::process::Stdio::piped())
-                .stderr(std::process::Stdio::piped())
-                .spawn();
-
-            match child {
-                Ok(mut child) => {
-                    if let Some(mut stdin) = child.stdin.take() {
-                        use tokio::io::AsyncWriteExt;
-                        let _ = stdin.write_all(b"y\n").await;
-                        let _ = stdin.flush().await;
-                        drop(stdin);
-                    }
+/// Exit status smartctl is asked to use when it skips a disk in standby.
+/// Bits 0 and 1 are never set together otherwise.
+const SMART_STANDBY_STATUS: i32 = 3;
 
-                    match child.wait_with_output().await {
-                        Ok(output) => {
-                            if !output.status.success() {
-                                let err = String::from_utf8_lossy(&output.stderr);
-                                Notification::send(
-                                    format!("NTFS resize failed: {}", err),
-                                    NotificationLevel::Error,
-                                    sender,
-                                )?;
-                                return Err(anyhow!("ntfsresize failed"));
-                            }
-                        }
-                        Err(e) => {
-                            Notification::send(
-                                format!("NTFS resize failed: {}", e),
-                                NotificationLevel::Error,
-                                sender,
-                            )?;
-                            return Err(anyhow!("ntfsresize failed"));
-                        }
-                    }
-                }
-                Err(_) => {
-                    Notification::send(
-                        "ntfsresize not found. Install ntfs-3g package.".to_string(),
-                        NotificationLevel::Error,
-                        sender,
-                    )?;
-                    return Err(anyhow!("ntfsresize not found"));
-                }
-            }
-        }
-        "btrfs" => {
-            let mount_point = format!("/tmp/disktui_resize_{}", partition.replace('/', "_"));
-            Command::new("mkdir")
-                .args(["-p", &mount_point])
-                .output()
-                .await?;
-
-            let mount_output = Command::new("mount")
-                .args([&device_path, &mount_point])
-                .output()
-                .await?;
-
-            if mount_output.status.success() {
-                let size_arg = if is_growing {
-                    "max".to_string()
-                } else {
-                    new_size_bytes.to_string()
-                };
-
-                let resize_output = Command::new("btrfs")
-                    .args(["filesystem", "resize", &size_arg, &mount_point])
-                    .output()
-                    .await;
-
-                let umount_result = Command::new("umount").arg(&mount_point).output().await;
-                if umount_result.is_ok() && umount_result.as_ref().unwrap().status.success() {
-                    let _ = Command::new("rmdir").arg(&mount_point).output().await;
-                } else {
-                    Notification::send(
-                        format!("Warning: Failed to unmount temp mount at {}", mount_point),
-                        NotificationLevel::Warning,
-                        sender,
-                    )?;
-                }
+/// Like [`get_smart_data`], but leaves a spun down disk asleep and returns
+/// `None` for it.
+pub async fn get_smart_data_if_awake(
+    runner: &dyn CommandRunner,
+    disk: &str,
+) -> Result<Option<SmartData>> {
+    validate_device_name(disk)?;
 
-                if let Ok(output) = resize_output {
-                    if !output.status.success() {
-                        let err = String::from_utf8_lossy(&output.stderr);
-                        Notification::send(
-                            format!("Btrfs resize failed: {}", err),
-                            NotificationLevel::Error,
-                            sender,
-                        )?;
-                        return Err(anyhow!("btrfs resize failed"));
-                    }
-                } else {
-                    Notification::send(
-                        "Failed to resize Btrfs filesystem".to_string(),
-                        NotificationLevel::Error,
-                        sender,
-                    )?;
-                    return Err(anyhow!("btrfs resize failed"));
-                }
-            } else {
-                let err = String::from_utf8_lossy(&mount_output.stderr);
-                let _ = Command::new("rmdir").arg(&mount_point).output().await;
-                Notification::send(
-                    format!("Failed to mount for Btrfs resize: {}", err),
-                    NotificationLevel::Error,
-                    sender,
-                )?;
-                return Err(anyhow!("Mount failed"));
-            }
-        }
-        "vfat" | "fat32" | "exfat" => {
-            Notification::send(
-                format!(
-                    "{} filesystem cannot be easily resized. Consider reformatting.",
-                    fs
-                ),
-                NotificationLevel::Warning,
-                sender,
-            )?;
-        }
-        _ => {
-            Notification::send(
-                format!(
-                    "Filesystem '{}' resize not supported. Partition resized only.",
-                    fs
-                ),
-                NotificationLevel::Warning,
-                sender,
-            )?;
-        }
+    let standby = format!("standby,{}", SMART_STANDBY_STATUS);
+    let output = runner
+        .output(
+            "smartctl",
+            &["--json", "-a", "-n", &standby, &format!("/dev/{}", disk)],
+        )
+        .await
+        .context("Failed to execute smartctl")?;
+    if output.status.code() == Some(SMART_STANDBY_STATUS) {
+        return Ok(None);
     }
-
-    Ok(())
+    smart::parse(&String::from_utf8_lossy(&output.stdout)).map(Some)
 }
 
-pub async fn is_luks_device(device: &str) -> Result<bool> {
+pub async fn is_luks_device(runner: &dyn CommandRunner, device: &str) -> Result<bool> {
     validate_device_name(device)?;
 
-    let output = Command::new("cryptsetup")
-        .args(["isLuks", &format!("/dev/{}", device)])
-        .output()
+    let output = runner
+        .output("cryptsetup", &["isLuks", &format!("/dev/{}", device)])
         .await;
 
     match output {
@@ -1933,12 +1568,11 @@ pub async fn is_luks_device(device: &str) -> Result<bool> {
     }
 }
 
-pub async fn get_luks_info(device: &str) -> Result<LuksInfo> {
+pub async fn get_luks_info(runner: &dyn CommandRunner, device: &str) -> Result<LuksInfo> {
     validate_device_name(device)?;
 
-    let output = Command::new("cryptsetup")
-        .args(["luksDump", &format!("/dev/{}", device)])
-        .output()
+    let output = runner
+        .output("cryptsetup", &["luksDump", &format!("/dev/{}", device)])
         .await
         .context("Failed to execute cryptsetup luksDump")?;
 
@@ -1978,10 +1612,10 @@ pub async fn get_luks_info(device: &str) -> Result<LuksInfo> {
     })
 }
 
-pub async fn get_luks_status(device: &str) -> Result<LuksStatus> {
+pub async fn get_luks_status(runner: &dyn CommandRunner, device: &str) -> Result<LuksStatus> {
     validate_device_name(device)?;
 
-    let mapper_entries = std::fs::read_dir("/dev/mapper");
+    let mapper_entries = runner.read_dir_names("/dev/mapper");
 
     if mapper_entries.is_err() {
         return Ok(LuksStatus {
@@ -1991,16 +1625,12 @@ pub async fn get_luks_status(device: &str) -> Result<LuksStatus> {
         });
     }
 
-    for entry in mapper_entries.unwrap().flatten() {
-        let mapper_name = entry.file_name().to_string_lossy().to_string();
+    for mapper_name in mapper_entries.unwrap() {
         if mapper_name == "control" {
             continue;
         }
 
-        let output = Command::new("cryptsetup")
-            .args(["status", &mapper_name])
-            .output()
-            .await;
+        let output = runner.output("cryptsetup", &["status", &mapper_name]).await;
 
         if let Ok(output) = output
             && output.status.success()
@@ -2030,6 +1660,7 @@ pub async fn get_luks_status(device: &str) -> Result<LuksStatus> {
 }
 
 pub async fn unlock_luks_device(
+    runner: &dyn CommandRunner,
     device: &str,
     passphrase: &str,
     mapper_name: &str,
@@ -2038,7 +1669,7 @@ pub async fn unlock_luks_device(
     validate_device_name(device)?;
     validate_device_name(mapper_name)?;
 
-    let status = get_luks_status(device).await?;
+    let status = get_luks_status(runner, device).await?;
     if status.is_active {
         Notification::send(
             format!("{} is already unlocked", device),
@@ -2052,26 +1683,15 @@ pub async fn unlock_luks_device(
 
     let device_path = format!("/dev/{}", device);
 
-    let mut child = Command::new("cryptsetup")
-        .args(["open", &device_path, mapper_name])
-        .stdin(std::process::Stdio::piped())
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
+    let output = runner
+        .output_with_stdin(
+            "cryptsetup",
+            &["open", &device_path, mapper_name],
+            format!("{}\n", passphrase).as_bytes(),
+        )
+        .await
         .context("Failed to spawn cryptsetup")?;
 
-    {
-        if let Some(mut stdin) = child.stdin.take() {
-            use tokio::io::AsyncWriteExt;
-            stdin.write_all(passphrase.as_bytes()).await?;
-            stdin.write_all(b"\n").await?;
-            stdin.flush().await?;
-            drop(stdin);
-        }
-    }
-
-    let output = child.wait_with_output().await?;
-
     if !output.status.success() {
         sender.send(Event::EndProgress)?;
         let err = String::from_utf8_lossy(&output.stderr);
@@ -2086,12 +1706,9 @@ pub async fn unlock_luks_device(
         return Err(anyhow!("Unlock failed"));
     }
 
-    let _ = Command::new("udevadm")
-        .args(["settle", "--timeout=10"])
-        .output()
-        .await;
+    let _ = runner.output("udevadm", &["settle", "--timeout=10"]).await;
 
-    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    runner.sleep(tokio::time::Duration::from_millis(500)).await;
 
     sender.send(Event::EndProgress)?;
 
@@ -2104,14 +1721,17 @@ pub async fn unlock_luks_device(
     Ok(())
 }
 
-pub async fn lock_luks_device(mapper_name: &str, sender: &UnboundedSender<Event>) -> Result<()> {
+pub async fn lock_luks_device(
+    runner: &dyn CommandRunner,
+    mapper_name: &str,
+    sender: &UnboundedSender<Event>,
+) -> Result<()> {
     validate_device_name(mapper_name)?;
 
     let mapper_path = format!("/dev/mapper/{}", mapper_name);
 
-    let is_mounted = Command::new("findmnt")
-        .args(["-n", &mapper_path])
-        .output()
+    let is_mounted = runner
+        .output("findmnt", &["-n", &mapper_path])
         .await
         .map(|output| output.status.success())
         .unwrap_or(false);
@@ -2127,9 +1747,8 @@ pub async fn lock_luks_device(mapper_name: &str, sender: &UnboundedSender<Event>
 
     sender.send(Event::StartProgress(format!("Locking {}...", mapper_name)))?;
 
-    let close_future = Command::new("cryptsetup")
-        .args(["close", mapper_name])
-        .output();
+    let close_args = ["close", mapper_name];
+    let close_future = runner.output("cryptsetup", &close_args);
 
     let output = match tokio::time::timeout(tokio::time::Duration::from_secs(10), close_future)
         .await
@@ -2178,10 +1797,7 @@ pub async fn lock_luks_device(mapper_name: &str, sender: &UnboundedSender<Event>
         return Err(anyhow!("Lock failed"));
     }
 
-    let _ = Command::new("udevadm")
-        .args(["settle", "--timeout=10"])
-        .output()
-        .await;
+    let _ = runner.output("udevadm", &["settle", "--timeout=10"]).await;
 
     sender.send(Event::EndProgress)?;
 
@@ -2195,13 +1811,14 @@ pub async fn lock_luks_device(mapper_name: &str, sender: &UnboundedSender<Event>
 }
 
 pub async fn encrypt_partition(
+    runner: &dyn CommandRunner,
     partition: &str,
     passphrase: &str,
     sender: &UnboundedSender<Event>,
 ) -> Result<()> {
     validate_device_name(partition)?;
 
-    if is_mounted(partition).await? {
+    if is_mounted(runner, partition).await? {
         Notification::send(
             format!("{} is mounted. Unmount it first.", partition),
             NotificationLevel::Error,
@@ -2214,32 +1831,21 @@ pub async fn encrypt_partition(
 
     let device_path = format!("/dev/{}", partition);
 
-    let mut child = Command::new("cryptsetup")
-        .args([
-            "luksFormat",
-            "--type",
-            "luks2",
-            "--batch-mode",
-            &device_path,
-        ])
-        .stdin(std::process::Stdio::piped())
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .spawn()
+    let output = runner
+        .output_with_stdin(
+            "cryptsetup",
+            &[
+                "luksFormat",
+                "--type",
+                "luks2",
+                "--batch-mode",
+                &device_path,
+            ],
+            format!("{}\n", passphrase).as_bytes(),
+        )
+        .await
         .context("Failed to spawn cryptsetup")?;
 
-    {
-        if let Some(mut stdin) = child.stdin.take() {
-            use tokio::io::AsyncWriteExt;
-            stdin.write_all(passphrase.as_bytes()).await?;
-            stdin.write_all(b"\n").await?;
-            stdin.flush().await?;
-            drop(stdin);
-        }
-    }
-
-    let output = child.wait_with_output().await?;
-
     sender.send(Event::EndProgress)?;
 
     if !output.status.success() {
@@ -2262,6 +1868,7 @@ pub async fn encrypt_partition(
 }
 
 pub async fn encrypt_and_format_partition(
+    runner: &dyn CommandRunner,
     partition: &str,
     passphrase: &str,
     fs_type: FilesystemType,
@@ -2271,7 +1878,8 @@ pub async fn encrypt_and_format_partition(
 
     sender.send(Event::StartProgress(format!("Encrypting {}...", partition)))?;
 
-    let result = encrypt_and_format_partition_inner(partition, passphrase, fs_type, sender).await;
+    let result =
+        encrypt_and_format_partition_inner(runner, partition, passphrase, fs_type, sender).await;
 
     sender.send(Event::EndProgress)?;
 
@@ -2290,14 +1898,15 @@ pub async fn encrypt_and_format_partition(
 }
 
 async fn encrypt_and_format_partition_inner(
+    runner: &dyn CommandRunner,
     partition: &str,
     passphrase: &str,
     fs_type: FilesystemType,
     sender: &UnboundedSender<Event>,
 ) -> Result<()> {
-    encrypt_partition(partition, passphrase, sender).await?;
+    encrypt_partition(runner, partition, passphrase, sender).await?;
 
-    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    runner.sleep(tokio::time::Duration::from_millis(500)).await;
 
     let mapper_name = format!("luks-{}", partition);
 
@@ -2307,17 +1916,14 @@ async fn encrypt_and_format_partition_inner(
         sender,
     )?;
 
-    unlock_luks_device(partition, passphrase, &mapper_name, sender).await?;
+    unlock_luks_device(runner, partition, passphrase, &mapper_name, sender).await?;
 
     let mapper_path = format!("/dev/mapper/{}", mapper_name);
-    wait_for_device(&mapper_path, 10).await?;
+    wait_for_device(runner, &mapper_path, 10).await?;
 
-    let _ = Command::new("udevadm")
-        .args(["settle", "--timeout=10"])
-        .output()
-        .await;
+    let _ = runner.output("udevadm", &["settle", "--timeout=10"]).await;
 
-    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    runner.sleep(tokio::time::Duration::from_millis(500)).await;
 
     Notification::send(
         format!("Formatting with {}...", fs_type),
@@ -2325,14 +1931,18 @@ async fn encrypt_and_format_partition_inner(
         sender,
     )?;
 
-    format_partition(&mapper_name, fs_type, sender.clone()).await?;
+    format_partition(runner, &mapper_name, fs_type, sender.clone()).await?;
 
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn create_encrypted_partition_with_fs(
+    runner: &dyn CommandRunner,
     disk: &str,
+    start: Option<u64>,
     size_input: &str,
+    alignment: Alignment,
     passphrase: &str,
     fs_type: FilesystemType,
     sender: &UnboundedSender<Event>,
@@ -2342,9 +1952,9 @@ pub async fn create_encrypted_partition_with_fs(
         disk
     )))?;
 
-    let part_name = create_partition_raw(disk, size_input, sender).await?;
+    let part_name = create_partition_raw(runner, disk, start, size_input, alignment, sender).await?;
 
-    encrypt_and_format_partition(&part_name, passphrase, fs_type, sender).await?;
+    encrypt_and_format_partition(runner, &part_name, passphrase, fs_type, sender).await?;
 
     Notification::send(
         format!("Created encrypted partition on {}", disk),
@@ -2354,3 +1964,107 @@ pub async fn create_encrypted_partition_with_fs(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{FakeResponse, FakeRunner};
+
+    const LSBLK: &str = r#"{
+        "blockdevices": [
+            {"name": "loop0", "size": 4096, "type": "loop", "model": null, "serial": null,
+             "mountpoint": "/snap/core", "fstype": "squashfs", "label": null},
+            {"name": "sdb", "size": 16000000000, "type": "disk", "model": "Flash Drive  ", "serial": " ABC123",
+             "mountpoint": null, "fstype": null, "label": null,
+             "children": [
+                {"name": "sdb1", "size": 8000000000, "type": "part", "model": null, "serial": null,
+                 "mountpoint": "/mnt/sdb1", "fstype": "ext4", "label": "data"},
+                {"name": "sdb2", "size": 8000000000, "type": "part", "model": null, "serial": null,
+                 "mountpoint": null, "fstype": "crypto_LUKS", "label": null}
+             ]}
+        ]
+    }"#;
+
+    fn lsblk_rule() -> &'static str {
+        "lsblk -J -b -o NAME,SIZE,TYPE,MODEL,SERIAL,MOUNTPOINT,FSTYPE,LABEL,UUID,PTTYPE,START,LOG-SEC,PHY-SEC,OPT-IO,PARTTYPE,PARTUUID,PARTLABEL,PARTFLAGS"
+    }
+
+    #[tokio::test]
+    async fn list_block_devices_parses_disks_partitions_and_luks() {
+        let runner = FakeRunner::new();
+        runner
+            .on(lsblk_rule(), FakeResponse::ok(LSBLK))
+            .on("cryptsetup isLuks /dev/sdb1", FakeResponse::fail(1, ""))
+            .on("cryptsetup isLuks /dev/sdb2", FakeResponse::ok(""))
+            .on(
+                "cryptsetup luksDump /dev/sdb2",
+                FakeResponse::ok("Version:       \t2\nUUID:          \tdeadbeef\n"),
+            )
+            .on(
+                "cryptsetup status luks-sdb2",
+                FakeResponse::ok("  type:    LUKS2\n  device:  /dev/sdb2\n"),
+            )
+            .on(
+                "findmnt -n -o TARGET /dev/mapper/luks-sdb2",
+                FakeResponse::ok("/mnt/luks-sdb2\n"),
+            )
+            .on(
+                "df -B1 /mnt/sdb1",
+                FakeResponse::ok("Filesystem 1B-blocks Used Available Use% Mounted on\n/dev/sdb1 8000000000 3000000000 5000000000 38% /mnt/sdb1\n"),
+            )
+            .with_dir("/dev/mapper", &["control", "luks-sdb2"]);
+
+        let devices = list_block_devices(&runner).await.unwrap();
+
+        assert_eq!(devices.len(), 1);
+        let disk = &devices[0];
+        assert_eq!(disk.name, "sdb");
+        assert_eq!(disk.model.as_deref(), Some("Flash Drive"));
+        assert_eq!(disk.serial.as_deref(), Some("ABC123"));
+        assert_eq!(disk.partitions.len(), 2);
+
+        let data = &disk.partitions[0];
+        assert!(data.is_mounted && !data.is_encrypted);
+        assert_eq!(data.label.as_deref(), Some("data"));
+        assert_eq!(data.used_bytes, Some(3_000_000_000));
+        assert_eq!(data.available_bytes, Some(5_000_000_000));
+
+        let luks = &disk.partitions[1];
+        assert!(luks.is_encrypted);
+        assert_eq!(luks.encryption_type.as_deref(), Some("LUKS2"));
+        assert_eq!(luks.luks_uuid.as_deref(), Some("deadbeef"));
+        assert_eq!(luks.mapper_device.as_deref(), Some("luks-sdb2"));
+        assert_eq!(luks.mount_point.as_deref(), Some("/mnt/luks-sdb2"));
+    }
+
+    #[tokio::test]
+    async fn list_block_devices_fails_when_lsblk_fails() {
+        let runner = FakeRunner::new();
+        runner.on("lsblk", FakeResponse::fail(32, "not found"));
+
+        assert!(list_block_devices(&runner).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn unlock_reports_incorrect_passphrase() {
+        let runner = FakeRunner::new();
+        runner.on(
+            "cryptsetup open /dev/sdb2 luks-sdb2",
+            FakeResponse::fail(2, "No key available with this passphrase."),
+        );
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+
+        let result = unlock_luks_device(&runner, "sdb2", "wrong", "luks-sdb2", &sender).await;
+
+        assert!(result.is_err());
+        let open = runner.position("cryptsetup open").unwrap();
+        assert_eq!(runner.calls()[open].stdin.as_deref(), Some(&b"wrong\n"[..]));
+        let mut messages = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            if let Event::Notification(notification) = event {
+                messages.push(notification.message);
+            }
+        }
+        assert_eq!(messages, vec!["Incorrect passphrase"]);
+    }
+}
@@ -1,25 +1,83 @@
 use serde::{Deserialize, Serialize};
-use crate::utils::format_bytes;
+use crate::holder::Holder;
+use crate::parttype::PartitionFlag;
+use crate::swap::SwapArea;
+use crate::utils::{SizeUnits, format_size};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Partition {
     pub name: String,
+    /// Position on the disk, when known.
+    #[serde(default)]
+    pub geometry: Option<PartitionGeometry>,
     pub size: u64,
     pub filesystem: Option<String>,
     pub mount_point: Option<String>,
     pub is_mounted: bool,
     pub label: Option<String>,
+    /// Filesystem UUID, or the LUKS UUID of an encrypted partition.
+    #[serde(default)]
+    pub uuid: Option<String>,
     pub used_bytes: Option<u64>,
     pub available_bytes: Option<u64>,
     pub is_encrypted: bool,
     pub encryption_type: Option<String>,
     pub luks_uuid: Option<String>,
     pub mapper_device: Option<String>,
+    /// GPT type GUID, or the MBR system id as "0x83".
+    #[serde(default)]
+    pub part_type: Option<String>,
+    #[serde(default)]
+    pub part_uuid: Option<String>,
+    /// GPT partition name.
+    #[serde(default)]
+    pub part_label: Option<String>,
+    #[serde(default)]
+    pub flags: Vec<PartitionFlag>,
+    /// The active swap area on the partition or its mapper device.
+    #[serde(default)]
+    pub swap: Option<SwapArea>,
+    /// Active swap files on the filesystem mounted from the partition.
+    #[serde(default)]
+    pub swap_files: Vec<SwapArea>,
+    /// The btrfs filesystem, volume group or md array the partition is
+    /// part of.
+    #[serde(default)]
+    pub holder: Option<Holder>,
+}
+
+/// Where a partition sits on its disk, in logical sectors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartitionGeometry {
+    pub start: u64,
+    pub sectors: u64,
+    pub logical_sector_size: u64,
+    pub physical_sector_size: u64,
+}
+
+impl PartitionGeometry {
+    pub fn start_bytes(&self) -> u64 {
+        self.start * self.logical_sector_size
+    }
+
+    /// First byte after the partition.
+    pub fn end_bytes(&self) -> u64 {
+        (self.start + self.sectors) * self.logical_sector_size
+    }
+
+    /// Whether the partition starts on a multiple of `alignment` bytes.
+    pub fn is_aligned(&self, alignment: u64) -> bool {
+        alignment == 0 || self.start_bytes().is_multiple_of(alignment)
+    }
 }
 
 impl Partition {
-    pub fn size_str(&self) -> String {
-        format_bytes(self.size)
+    pub fn start(&self) -> Option<u64> {
+        self.geometry.map(|geometry| geometry.start_bytes())
+    }
+
+    pub fn size_str(&self, units: SizeUnits) -> String {
+        format_size(self.size, units)
     }
 
     pub fn usage_percentage(&self) -> Option<u8> {
@@ -47,15 +105,15 @@ impl Partition {
         )
     }
 
-    pub fn usage_str(&self, filled_char: &str, empty_char: &str, length: u8) -> String {
+    pub fn usage_str(&self, filled_char: &str, empty_char: &str, length: u8, units: SizeUnits) -> String {
         match self.usage_percentage() {
             Some(percentage) => {
                 match (self.used_bytes, self.available_bytes) {
                     (Some(used), Some(avail)) => {
                         let total = used + avail;
                         format!("{}/{} {}",
-                            format_bytes(used),
-                            format_bytes(total),
+                            format_size(used, units),
+                            format_size(total, units),
                             self.usage_bar(percentage, filled_char, empty_char, length)
                         )
                     }
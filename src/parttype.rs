@@ -0,0 +1,240 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A partition type the create and change type dialogs offer, with its
+/// GPT type GUID and MBR system id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartitionType {
+    /// Short name used in requests and on the command line.
+    pub alias: &'static str,
+    pub name: &'static str,
+    pub guid: &'static str,
+    /// MBR system id; `None` for types that only exist on GPT.
+    pub mbr: Option<&'static str>,
+    /// Whether partitions of this type normally hold a filesystem.
+    pub has_filesystem: bool,
+}
+
+pub const LINUX_FILESYSTEM: &str = "linux";
+
+pub const PARTITION_TYPES: [PartitionType; 7] = [
+    PartitionType {
+        alias: LINUX_FILESYSTEM,
+        name: "Linux filesystem",
+        guid: "0FC63DAF-8483-4772-8E79-3D69D8477DE4",
+        mbr: Some("83"),
+        has_filesystem: true,
+    },
+    PartitionType {
+        alias: "esp",
+        name: "EFI System",
+        guid: "C12A7328-F81F-11D2-BA4B-00A0C93EC93B",
+        mbr: Some("ef"),
+        has_filesystem: true,
+    },
+    PartitionType {
+        alias: "swap",
+        name: "Linux swap",
+        guid: "0657FD6D-A4AB-43C4-84E5-0933C84B4F4F",
+        mbr: Some("82"),
+        has_filesystem: false,
+    },
+    PartitionType {
+        alias: "lvm",
+        name: "Linux LVM",
+        guid: "E6D6D379-F507-44C2-A23C-238F2A3DF928",
+        mbr: Some("8e"),
+        has_filesystem: false,
+    },
+    PartitionType {
+        alias: "raid",
+        name: "Linux RAID",
+        guid: "A19D880F-05FC-4D3B-A006-743F0F84911E",
+        mbr: Some("fd"),
+        has_filesystem: false,
+    },
+    PartitionType {
+        alias: "msdata",
+        name: "Microsoft basic data",
+        guid: "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7",
+        mbr: Some("07"),
+        has_filesystem: true,
+    },
+    PartitionType {
+        alias: "bios-boot",
+        name: "BIOS boot",
+        guid: "21686148-6449-6E6F-744E-656564454649",
+        mbr: None,
+        has_filesystem: false,
+    },
+];
+
+impl PartitionType {
+    /// Whether the type can be used on a table of `table_type` ("gpt",
+    /// "dos" or "msdos").
+    pub fn supports(&self, table_type: Option<&str>) -> bool {
+        table_type == Some("gpt") || self.mbr.is_some()
+    }
+}
+
+/// Types usable on a table of `table_type`, in picker order.
+pub fn types_for(table_type: Option<&str>) -> Vec<&'static PartitionType> {
+    PARTITION_TYPES
+        .iter()
+        .filter(|part_type| part_type.supports(table_type))
+        .collect()
+}
+
+/// The known type with this alias, GPT GUID or MBR id. udev reports MBR
+/// ids as "0x83".
+pub fn lookup(code: &str) -> Option<&'static PartitionType> {
+    let code = code.trim();
+    let mbr = code.strip_prefix("0x").unwrap_or(code);
+    PARTITION_TYPES.iter().find(|part_type| {
+        part_type.alias == code
+            || part_type.guid.eq_ignore_ascii_case(code)
+            || part_type.mbr.is_some_and(|id| {
+                u8::from_str_radix(id, 16).ok() == u8::from_str_radix(mbr, 16).ok()
+            })
+    })
+}
+
+/// Display name of a type code, or the code itself for unknown types.
+pub fn type_name(code: &str) -> String {
+    lookup(code)
+        .map(|part_type| part_type.name.to_string())
+        .unwrap_or_else(|| code.to_string())
+}
+
+fn is_guid(code: &str) -> bool {
+    let groups: Vec<&str> = code.split('-').collect();
+    groups.iter().map(|group| group.len()).eq([8, 4, 4, 4, 12])
+        && groups
+            .iter()
+            .all(|group| group.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// The code `sfdisk --part-type` takes for `input` on a table of
+/// `table_type`. `input` is a type alias, a GPT GUID or an MBR id.
+pub fn resolve(input: &str, table_type: Option<&str>) -> Result<String> {
+    let input = input.trim();
+    let gpt = table_type == Some("gpt");
+    if let Some(part_type) = PARTITION_TYPES.iter().find(|t| t.alias == input) {
+        return if gpt {
+            Ok(part_type.guid.to_string())
+        } else {
+            part_type
+                .mbr
+                .map(str::to_string)
+                .ok_or_else(|| anyhow!("{} partitions need a GPT partition table", part_type.name))
+        };
+    }
+    if gpt && is_guid(input) {
+        return Ok(input.to_uppercase());
+    }
+    let id = input.strip_prefix("0x").unwrap_or(input);
+    if !gpt && !id.is_empty() && id.len() <= 2 && u8::from_str_radix(id, 16).is_ok() {
+        return Ok(id.to_lowercase());
+    }
+    Err(anyhow!("Unknown partition type: {}", input))
+}
+
+/// Flags that can be set on a partition, named like in parted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PartitionFlag {
+    /// EFI System Partition. Setting it changes the partition type.
+    Esp,
+    /// GPT attribute bit 2, for BIOSes booting from GPT disks.
+    LegacyBoot,
+    /// GPT attribute bit 62, or a hidden MBR type.
+    Hidden,
+}
+
+impl PartitionFlag {
+    pub const ALL: [PartitionFlag; 3] = [
+        PartitionFlag::Esp,
+        PartitionFlag::LegacyBoot,
+        PartitionFlag::Hidden,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PartitionFlag::Esp => "esp",
+            PartitionFlag::LegacyBoot => "legacy_boot",
+            PartitionFlag::Hidden => "hidden",
+        }
+    }
+
+    /// Reads the flags of a partition from its type and the attribute bits
+    /// udev reports in ID_PART_ENTRY_FLAGS.
+    pub fn from_entry(part_type: Option<&str>, attributes: u64) -> Vec<PartitionFlag> {
+        let mut flags = Vec::new();
+        if part_type.and_then(lookup).is_some_and(|t| t.alias == "esp") {
+            flags.push(PartitionFlag::Esp);
+        }
+        if attributes & (1 << 2) != 0 {
+            flags.push(PartitionFlag::LegacyBoot);
+        }
+        if attributes & (1 << 62) != 0 {
+            flags.push(PartitionFlag::Hidden);
+        }
+        flags
+    }
+}
+
+impl fmt::Display for PartitionFlag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for PartitionFlag {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        PartitionFlag::ALL
+            .into_iter()
+            .find(|flag| flag.as_str() == s.trim().replace('-', "_"))
+            .ok_or_else(|| anyhow!("Unsupported flag: {} (use esp, legacy_boot or hidden)", s))
+    }
+}
+
+/// A flag to turn on or off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlagChange {
+    pub flag: PartitionFlag,
+    pub enabled: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_maps_aliases_to_the_table_type() {
+        assert_eq!(
+            resolve("esp", Some("gpt")).unwrap(),
+            "C12A7328-F81F-11D2-BA4B-00A0C93EC93B"
+        );
+        assert_eq!(resolve("esp", Some("dos")).unwrap(), "ef");
+        assert!(resolve("bios-boot", Some("dos")).is_err());
+        assert_eq!(resolve("0x8e", Some("dos")).unwrap(), "8e");
+        assert!(resolve("8e", Some("gpt")).is_err());
+        assert_eq!(
+            resolve("e6d6d379-f507-44c2-a23c-238f2a3df928", Some("gpt")).unwrap(),
+            "E6D6D379-F507-44C2-A23C-238F2A3DF928"
+        );
+    }
+
+    #[test]
+    fn flags_come_from_type_and_attribute_bits() {
+        assert_eq!(
+            PartitionFlag::from_entry(Some("c12a7328-f81f-11d2-ba4b-00a0c93ec93b"), 1 << 2),
+            vec![PartitionFlag::Esp, PartitionFlag::LegacyBoot]
+        );
+        assert_eq!(type_name("0x82"), "Linux swap");
+        assert_eq!(type_name("0xa5"), "0xa5");
+    }
+}
@@ -1,10 +1,20 @@
+use crate::layout::Alignment;
+use crate::mount::MountOptions;
+use crate::parttype::{self, FlagChange};
+use crate::smart::SelfTestKind;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "op", rename_all = "snake_case")]
 pub enum Request {
+	/// Mounts on `target`, or `/mnt/<device>` when unset. The directory is
+	/// created if it is missing.
 	Mount {
 		device: String,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		target: Option<String>,
+		#[serde(default, skip_serializing_if = "MountOptions::is_default")]
+		options: MountOptions,
 	},
 	Unmount {
 		device: String,
@@ -12,25 +22,46 @@ pub enum Request {
 	Format {
 		device: String,
 		fs_type: String,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		label: Option<String>,
 	},
 	FormatWholeDisk {
 		disk: String,
 		fs_type: String,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		label: Option<String>,
 	},
 	CreatePartitionTable {
 		disk: String,
 		table_type: String,
 	},
+	/// `start` is a byte offset inside the free space to use; without it
+	/// the partition goes into the last free space on the disk. The start
+	/// is moved up to the next `alignment` boundary.
 	CreatePartition {
 		disk: String,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		start: Option<u64>,
 		size: String,
 		fs_type: Option<String>,
+		#[serde(default, skip_serializing_if = "Alignment::is_default")]
+		alignment: Alignment,
+		/// Type alias, GPT type GUID or MBR id; the table default if unset.
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		part_type: Option<String>,
+		/// GPT partition name.
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		name: Option<String>,
 	},
 	CreateEncryptedPartition {
 		disk: String,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		start: Option<u64>,
 		size: String,
 		passphrase: String,
 		fs_type: String,
+		#[serde(default, skip_serializing_if = "Alignment::is_default")]
+		alignment: Alignment,
 	},
 	DeletePartition {
 		partition: String,
@@ -38,6 +69,32 @@ pub enum Request {
 	ResizePartition {
 		partition: String,
 		new_size: String,
+		#[serde(default, skip_serializing_if = "Alignment::is_default")]
+		alignment: Alignment,
+	},
+	/// Changes the type, GPT name or flags of a partition. Unset fields are
+	/// left alone; an empty name clears it.
+	ModifyPartition {
+		partition: String,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		part_type: Option<String>,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		name: Option<String>,
+		#[serde(default, skip_serializing_if = "Vec::is_empty")]
+		flags: Vec<FlagChange>,
+	},
+	/// `target` is a device name or the absolute path of a swap file.
+	SwapOn {
+		target: String,
+	},
+	SwapOff {
+		target: String,
+	},
+	/// Creates a swap file of `size` bytes at `path` on a mounted
+	/// filesystem and enables it.
+	CreateSwapFile {
+		path: String,
+		size: String,
 	},
 	UnlockLuks {
 		device: String,
@@ -56,9 +113,373 @@ pub enum Request {
 		passphrase: String,
 		fs_type: String,
 	},
+	/// Checks the filesystem on an unmounted device, read-only unless
+	/// `repair` is set. The checker's output is streamed as `Output`
+	/// responses and a `CheckReport` is returned as JSON.
+	CheckFilesystem {
+		device: String,
+		repair: bool,
+	},
+	/// Sets the filesystem label; an empty label clears it.
+	SetLabel {
+		device: String,
+		label: String,
+	},
+	/// Gives the filesystem on an unmounted device a new random UUID.
+	RegenerateUuid {
+		device: String,
+	},
+	/// Adds or replaces the fstab entry of the filesystem on `device`,
+	/// keyed by its UUID. Swap ignores `target` and `pass`; without a
+	/// `pass` the usual one for the filesystem is used.
+	SetFstabEntry {
+		device: String,
+		target: String,
+		options: String,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		pass: Option<u32>,
+	},
+	/// Removes the fstab entries whose source is `source`, such as "UUID=…".
+	RemoveFstabEntry {
+		source: String,
+	},
+	/// Adds or replaces the crypttab entry of the LUKS device `device`,
+	/// keyed by its LUKS UUID, so that it is unlocked as `name` at boot.
+	SetCrypttabEntry {
+		device: String,
+		name: String,
+		options: String,
+	},
+	RemoveCrypttabEntry {
+		name: String,
+	},
+	/// Starts a SMART self-test, which the disk runs in the background.
+	StartSelfTest {
+		disk: String,
+		test: SelfTestKind,
+	},
+	/// Returns the LVM physical volumes, volume groups and logical volumes
+	/// as a JSON [`crate::lvm::LvmReport`].
+	ListLvm,
+	CreatePhysicalVolume {
+		device: String,
+	},
+	/// Creates a volume group on the devices, which become physical volumes
+	/// if they are not yet.
+	CreateVolumeGroup {
+		name: String,
+		devices: Vec<String>,
+	},
+	ExtendVolumeGroup {
+		name: String,
+		device: String,
+	},
+	/// Creates a logical volume of `size`, a size spec resolved against the
+	/// free space of the volume group, and formats it when `fs_type` is set.
+	CreateLogicalVolume {
+		vg: String,
+		name: String,
+		size: String,
+		#[serde(default, skip_serializing_if = "Option::is_none")]
+		fs_type: Option<String>,
+	},
+	/// Resizes an unmounted logical volume together with its filesystem.
+	ResizeLogicalVolume {
+		vg: String,
+		name: String,
+		size: String,
+	},
+	RemoveLogicalVolume {
+		vg: String,
+		name: String,
+	},
+	/// Returns the md arrays as a JSON [`crate::raid::RaidReport`].
+	ListRaid,
+	/// Creates an md array such as "md0" of `level` on the devices.
+	CreateRaid {
+		name: String,
+		level: String,
+		devices: Vec<String>,
+	},
+	/// Adds a device to an array, as a spare if no member is missing.
+	AddRaidMember {
+		array: String,
+		device: String,
+	},
+	/// Marks a member as failed so it can be removed.
+	FailRaidMember {
+		array: String,
+		device: String,
+	},
+	RemoveRaidMember {
+		array: String,
+		device: String,
+	},
+	StopRaid {
+		array: String,
+	},
+	/// Assembles a stopped array from the members with this UUID.
+	AssembleRaid {
+		uuid: String,
+	},
+	/// Returns the subvolumes of the btrfs filesystem mounted from `device`
+	/// as a JSON [`crate::btrfs::SubvolumeList`].
+	ListSubvolumes {
+		device: String,
+	},
+	/// Creates a subvolume at `path`, relative to where `device` is mounted.
+	CreateSubvolume {
+		device: String,
+		path: String,
+	},
+	DeleteSubvolume {
+		device: String,
+		id: u64,
+	},
+	/// Takes a read-only snapshot of subvolume `id` at `path`, relative to
+	/// where `device` is mounted.
+	SnapshotSubvolume {
+		device: String,
+		id: u64,
+		path: String,
+	},
+	/// Makes subvolume `id` the one mounted when no `subvol=` is given.
+	SetDefaultSubvolume {
+		device: String,
+		id: u64,
+	},
+	/// Validates the wrapped request and returns the command lines it would
+	/// run, one per line, without changing anything.
+	DryRun {
+		request: Box<Request>,
+	},
+	/// Returns the latest `limit` audit log entries as a JSON array,
+	/// optionally only those for `disk`.
+	AuditLog {
+		disk: Option<String>,
+		limit: usize,
+	},
+	/// Returns the disk's current table and its saved backups as JSON.
+	ListBackups {
+		disk: String,
+	},
+	RestoreTable {
+		disk: String,
+		backup: String,
+	},
 	Shutdown,
 }
 
+impl Request {
+	/// Human readable summary of the request.
+	pub fn description(&self) -> String {
+		match self {
+			Request::Mount { device, target: Some(target), .. } => format!("Mount {} at {}", device, target),
+			Request::Mount { device, .. } => format!("Mount {}", device),
+			Request::Unmount { device } => format!("Unmount {}", device),
+			Request::Format { device, fs_type, .. } => format!("Format {} as {}", device, fs_type),
+			Request::FormatWholeDisk { disk, fs_type, .. } => format!("Format entire disk {} as {}", disk, fs_type),
+			Request::CreatePartitionTable { disk, table_type } => {
+				format!("Create {} partition table on {}", table_type.to_uppercase(), disk)
+			}
+			Request::CreatePartition { disk, size, fs_type, .. } => {
+				let size = if size.trim().is_empty() { "max size" } else { size.as_str() };
+				match fs_type {
+					Some(fs) => format!("Create {} {} partition on {}", size, fs, disk),
+					None => format!("Create {} partition on {}", size, disk),
+				}
+			}
+			Request::CreateEncryptedPartition { disk, size, fs_type, .. } => {
+				let size = if size.trim().is_empty() { "max size" } else { size.as_str() };
+				format!("Create {} encrypted {} partition on {}", size, fs_type, disk)
+			}
+			Request::DeletePartition { partition } => format!("Delete {}", partition),
+			Request::ResizePartition { partition, new_size, .. } => format!("Resize {} to {}", partition, new_size),
+			Request::ModifyPartition { partition, part_type, name, flags } => {
+				let mut changes = Vec::new();
+				if let Some(part_type) = part_type {
+					changes.push(format!("type {}", parttype::type_name(part_type)));
+				}
+				if let Some(name) = name {
+					changes.push(format!("name \"{}\"", name));
+				}
+				for change in flags {
+					changes.push(format!("{} {}", if change.enabled { "set" } else { "clear" }, change.flag));
+				}
+				format!("Change {} of {}", changes.join(", "), partition)
+			}
+			Request::CheckFilesystem { device, repair: false } => format!("Check filesystem on {}", device),
+			Request::CheckFilesystem { device, repair: true } => format!("Check and repair filesystem on {}", device),
+			Request::SetLabel { device, label } if label.is_empty() => format!("Clear label of {}", device),
+			Request::SetLabel { device, label } => format!("Label {} as \"{}\"", device, label),
+			Request::RegenerateUuid { device } => format!("Regenerate UUID of {}", device),
+			Request::SetFstabEntry { device, target, .. } => format!("Add {} to fstab at {}", device, target),
+			Request::RemoveFstabEntry { source } => format!("Remove {} from fstab", source),
+			Request::SetCrypttabEntry { device, name, .. } => format!("Add {} to crypttab as {}", device, name),
+			Request::RemoveCrypttabEntry { name } => format!("Remove {} from crypttab", name),
+			Request::StartSelfTest { disk, test } => format!("Start {} SMART self-test on {}", test, disk),
+			Request::ListLvm => "List LVM volumes".to_string(),
+			Request::CreatePhysicalVolume { device } => format!("Create LVM physical volume on {}", device),
+			Request::CreateVolumeGroup { name, devices } => format!("Create volume group {} on {}", name, devices.join(", ")),
+			Request::ExtendVolumeGroup { name, device } => format!("Add {} to volume group {}", device, name),
+			Request::CreateLogicalVolume { vg, name, size, fs_type } => {
+				let size = if size.trim().is_empty() { "max size" } else { size.as_str() };
+				match fs_type {
+					Some(fs) => format!("Create {} {} logical volume {}/{}", size, fs, vg, name),
+					None => format!("Create {} logical volume {}/{}", size, vg, name),
+				}
+			}
+			Request::ResizeLogicalVolume { vg, name, size } => format!("Resize logical volume {}/{} to {}", vg, name, size),
+			Request::RemoveLogicalVolume { vg, name } => format!("Remove logical volume {}/{}", vg, name),
+			Request::ListRaid => "List RAID arrays".to_string(),
+			Request::CreateRaid { name, level, devices } => format!("Create {} array {} on {}", level, name, devices.join(", ")),
+			Request::AddRaidMember { array, device } => format!("Add {} to {}", device, array),
+			Request::FailRaidMember { array, device } => format!("Mark {} as failed in {}", device, array),
+			Request::RemoveRaidMember { array, device } => format!("Remove {} from {}", device, array),
+			Request::StopRaid { array } => format!("Stop {}", array),
+			Request::AssembleRaid { uuid } => format!("Assemble array {}", uuid),
+			Request::ListSubvolumes { device } => format!("List subvolumes of {}", device),
+			Request::CreateSubvolume { device, path } => format!("Create subvolume {} on {}", path, device),
+			Request::DeleteSubvolume { device, id } => format!("Delete subvolume {} of {}", id, device),
+			Request::SnapshotSubvolume { device, id, path } => format!("Snapshot subvolume {} of {} to {}", id, device, path),
+			Request::SetDefaultSubvolume { device, id } => format!("Make subvolume {} the default of {}", id, device),
+			Request::SwapOn { target } => format!("Enable swap on {}", target),
+			Request::SwapOff { target } => format!("Disable swap on {}", target),
+			Request::CreateSwapFile { path, size } => format!("Create {} swap file {}", size, path),
+			Request::UnlockLuks { device, mapper_name, .. } => format!("Unlock {} as {}", device, mapper_name),
+			Request::LockLuks { mapper_name } => format!("Lock {}", mapper_name),
+			Request::EncryptPartition { partition, .. } => format!("Encrypt {}", partition),
+			Request::EncryptAndFormat { partition, fs_type, .. } => format!("Encrypt {} and format as {}", partition, fs_type),
+			Request::DryRun { request } => format!("Dry run: {}", request.description()),
+			Request::AuditLog { .. } => "Read audit log".to_string(),
+			Request::ListBackups { disk } => format!("List partition table backups of {}", disk),
+			Request::RestoreTable { disk, backup } => format!("Restore partition table of {} from {}", disk, backup),
+			Request::Shutdown => "Shut down helper".to_string(),
+		}
+	}
+
+	/// The disk the request acts on, derived from the device it names.
+	/// Partition and mapper names are reduced to their parent disk.
+	pub fn disk(&self) -> String {
+		self.disk_with(&sysfs_block)
+	}
+
+	/// Like [`Request::disk`], asking `sysfs` about the device names.
+	fn disk_with(&self, sysfs: &dyn Fn(&str) -> Option<SysfsBlock>) -> String {
+		let name = match self {
+			Request::FormatWholeDisk { disk, .. }
+			| Request::CreatePartitionTable { disk, .. }
+			| Request::CreatePartition { disk, .. }
+			| Request::CreateEncryptedPartition { disk, .. }
+			| Request::ListBackups { disk }
+			| Request::RestoreTable { disk, .. }
+			| Request::StartSelfTest { disk, .. } => return disk.clone(),
+			Request::Mount { device, .. }
+			| Request::Unmount { device }
+			| Request::Format { device, .. }
+			| Request::CheckFilesystem { device, .. }
+			| Request::SetLabel { device, .. }
+			| Request::RegenerateUuid { device }
+			| Request::SetFstabEntry { device, .. }
+			| Request::SetCrypttabEntry { device, .. }
+			| Request::UnlockLuks { device, .. }
+			| Request::CreatePhysicalVolume { device }
+			| Request::ExtendVolumeGroup { device, .. }
+			| Request::AddRaidMember { device, .. }
+			| Request::FailRaidMember { device, .. }
+			| Request::RemoveRaidMember { device, .. }
+			| Request::ListSubvolumes { device }
+			| Request::CreateSubvolume { device, .. }
+			| Request::DeleteSubvolume { device, .. }
+			| Request::SnapshotSubvolume { device, .. }
+			| Request::SetDefaultSubvolume { device, .. } => device,
+			Request::DeletePartition { partition }
+			| Request::ResizePartition { partition, .. }
+			| Request::ModifyPartition { partition, .. }
+			| Request::EncryptPartition { partition, .. }
+			| Request::EncryptAndFormat { partition, .. } => partition,
+			Request::LockLuks { mapper_name } => mapper_name,
+			// Swap files are not tied to a disk here.
+			Request::SwapOn { target } | Request::SwapOff { target } if target.starts_with('/') => return String::new(),
+			Request::SwapOn { target } | Request::SwapOff { target } => target,
+			Request::CreateSwapFile { .. } => return String::new(),
+			Request::RemoveFstabEntry { .. } | Request::RemoveCrypttabEntry { .. } => return String::new(),
+			// Volume groups can span several disks.
+			Request::CreateVolumeGroup { devices, .. } if devices.len() == 1 => &devices[0],
+			Request::ListLvm
+			| Request::CreateVolumeGroup { .. }
+			| Request::CreateLogicalVolume { .. }
+			| Request::ResizeLogicalVolume { .. }
+			| Request::RemoveLogicalVolume { .. } => return String::new(),
+			// Arrays span several disks as well.
+			Request::ListRaid | Request::CreateRaid { .. } | Request::StopRaid { .. } | Request::AssembleRaid { .. } => return String::new(),
+			Request::DryRun { request } => return request.disk_with(sysfs),
+			Request::AuditLog { disk, .. } => return disk.clone().unwrap_or_default(),
+			Request::Shutdown => return String::new(),
+		};
+		let name = name.strip_prefix("luks-").unwrap_or(name);
+		parent_disk(name, sysfs(name))
+	}
+}
+
+/// What /sys/class/block says about a device name.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SysfsBlock {
+	/// A whole device: a disk, an array, a loop or mapper device.
+	Whole,
+	/// A partition of `disk`.
+	Partition { disk: String },
+}
+
+/// Looks `name` up in /sys/class/block, `None` when no such device exists.
+pub(crate) fn sysfs_block(name: &str) -> Option<SysfsBlock> {
+	let sys = std::path::Path::new("/sys/class/block").join(name);
+	if name.is_empty() || !sys.exists() {
+		return None;
+	}
+	if !sys.join("partition").exists() {
+		return Some(SysfsBlock::Whole);
+	}
+	let path = std::fs::canonicalize(&sys).ok()?;
+	let disk = path.parent()?.file_name()?.to_string_lossy().to_string();
+	Some(SysfsBlock::Partition { disk })
+}
+
+/// The disk a partition is on, or `name` itself when it is not a partition.
+/// `sysfs` decides for devices that exist; otherwise the kernel's naming is
+/// followed where it is unambiguous: "nvme0n1p3" and "mmcblk0p1" carry a
+/// "p" before the number, "sdb2" does not, and "nvme0n1", "md0" or
+/// "loop0" are whole devices.
+fn parent_disk(name: &str, sysfs: Option<SysfsBlock>) -> String {
+	match sysfs {
+		Some(SysfsBlock::Partition { disk }) => disk,
+		sysfs => split_partition(name, sysfs).map_or(name, |(disk, _)| disk).to_string(),
+	}
+}
+
+/// Splits a partition name into its disk and partition number, `None` when
+/// `name` is not a partition. `sysfs` and the kernel's naming decide as in
+/// [`parent_disk`].
+pub(crate) fn split_partition(name: &str, sysfs: Option<SysfsBlock>) -> Option<(&str, &str)> {
+	let is_digit = |c: char| c.is_ascii_digit();
+	let disk = name.trim_end_matches(is_digit);
+	let part_num = &name[disk.len()..];
+	if disk.is_empty() || part_num.is_empty() {
+		return None;
+	}
+	let disk = match sysfs {
+		Some(SysfsBlock::Whole) => return None,
+		Some(SysfsBlock::Partition { disk: parent }) if disk == parent => disk,
+		Some(SysfsBlock::Partition { disk: parent }) => disk.strip_suffix('p').filter(|base| *base == parent)?,
+		None => match disk.strip_suffix('p') {
+			Some(base) if base.ends_with(is_digit) => base,
+			_ if ["sd", "vd", "hd", "xvd"].iter().any(|prefix| disk.starts_with(prefix)) && !disk.contains(is_digit) => disk,
+			_ => return None,
+		},
+	};
+	Some((disk, part_num))
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum Response {
@@ -76,6 +497,11 @@ pub enum Response {
 		action: String,
 		message: Option<String>,
 	},
+	/// A line printed by a long running command, such as a filesystem
+	/// checker, while it runs.
+	Output {
+		line: String,
+	},
 }
 
 impl Response {
@@ -109,4 +535,39 @@ impl Response {
 			message: None,
 		}
 	}
+
+	pub fn output(line: impl Into<String>) -> Self {
+		Self::Output { line: line.into() }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn disk_strips_partition_suffix() {
+		let unknown = |_: &str| None;
+		assert_eq!(Request::DeletePartition { partition: "nvme0n1p3".into() }.disk_with(&unknown), "nvme0n1");
+		assert_eq!(Request::Format { device: "luks-sdb2".into(), fs_type: "ext4".into(), label: None }.disk_with(&unknown), "sdb");
+		assert_eq!(Request::LockLuks { mapper_name: "luks-mmcblk0p1".into() }.disk_with(&unknown), "mmcblk0");
+		assert_eq!(Request::Format { device: "nvme0n1".into(), fs_type: "ext4".into(), label: None }.disk_with(&unknown), "nvme0n1");
+		assert_eq!(parent_disk("mmcblk0", None), "mmcblk0");
+		assert_eq!(parent_disk("md0", None), "md0");
+		assert_eq!(parent_disk("vg0-root", None), "vg0-root");
+	}
+
+	#[test]
+	fn disk_prefers_sysfs_for_existing_devices() {
+		let sysfs = |name: &str| match name {
+			"ubda1" => Some(SysfsBlock::Partition { disk: "ubda".into() }),
+			"md0" => Some(SysfsBlock::Whole),
+			_ => None,
+		};
+		assert_eq!(Request::DeletePartition { partition: "ubda1".into() }.disk_with(&sysfs), "ubda");
+		assert_eq!(Request::DeletePartition { partition: "ubda1".into() }.disk_with(&|_: &str| None), "ubda1");
+		assert_eq!(Request::Format { device: "md0".into(), fs_type: "ext4".into(), label: None }.disk_with(&sysfs), "md0");
+		assert_eq!(split_partition("ubda1", sysfs("ubda1")), Some(("ubda", "1")));
+		assert_eq!(split_partition("md0", sysfs("md0")), None);
+	}
 }
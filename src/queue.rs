@@ -0,0 +1,539 @@
+use crate::layout::{self, MIB};
+use crate::operations::BlockDevice;
+use crate::partition::{Partition, PartitionGeometry};
+use crate::parttype::{self, PartitionFlag};
+use crate::protocol::Request;
+use crate::utils::SizeSpec;
+use ratatui::widgets::ListState;
+
+/// Pending operations collected while queue mode is on. Nothing reaches the
+/// helper until the whole queue is applied.
+#[derive(Debug, Default)]
+pub struct OperationQueue {
+    pub enabled: bool,
+    pub operations: Vec<Request>,
+    pub state: ListState,
+}
+
+impl OperationQueue {
+    /// Only layout-changing operations are queued; mount, unmount and the
+    /// LUKS operations always run immediately.
+    pub fn is_queueable(request: &Request) -> bool {
+        matches!(
+            request,
+            Request::Format { .. }
+                | Request::FormatWholeDisk { .. }
+                | Request::CreatePartitionTable { .. }
+                | Request::CreatePartition { .. }
+                | Request::DeletePartition { .. }
+                | Request::ResizePartition { .. }
+                | Request::ModifyPartition { .. }
+        )
+    }
+
+    pub fn push(&mut self, request: Request) {
+        self.operations.push(request);
+        self.state.select(Some(self.operations.len() - 1));
+    }
+
+    /// Removes the most recently queued operation.
+    pub fn undo(&mut self) -> Option<Request> {
+        let request = self.operations.pop();
+        self.clamp_selection();
+        request
+    }
+
+    pub fn remove_selected(&mut self) -> Option<Request> {
+        let idx = self.state.selected()?;
+        if idx >= self.operations.len() {
+            return None;
+        }
+        let request = self.operations.remove(idx);
+        self.clamp_selection();
+        Some(request)
+    }
+
+    pub fn move_selected_up(&mut self) {
+        if let Some(idx) = self.state.selected()
+            && idx > 0
+            && idx < self.operations.len()
+        {
+            self.operations.swap(idx, idx - 1);
+            self.state.select(Some(idx - 1));
+        }
+    }
+
+    pub fn move_selected_down(&mut self) {
+        if let Some(idx) = self.state.selected()
+            && idx + 1 < self.operations.len()
+        {
+            self.operations.swap(idx, idx + 1);
+            self.state.select(Some(idx + 1));
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if let Some(idx) = self.state.selected()
+            && idx + 1 < self.operations.len()
+        {
+            self.state.select(Some(idx + 1));
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        if let Some(idx) = self.state.selected() {
+            self.state.select(Some(idx.saturating_sub(1)));
+        }
+    }
+
+    /// Drops the first `completed` operations after an apply run.
+    pub fn finish(&mut self, completed: usize) {
+        self.operations
+            .drain(..completed.min(self.operations.len()));
+        self.clamp_selection();
+    }
+
+    pub fn clear(&mut self) {
+        self.operations.clear();
+        self.state.select(None);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Queued operations that touch `disk`.
+    pub fn for_disk<'a>(&'a self, disk: &'a str) -> impl Iterator<Item = &'a Request> {
+        self.operations
+            .iter()
+            .filter(move |request| request.disk() == disk)
+    }
+
+    fn clamp_selection(&mut self) {
+        if self.operations.is_empty() {
+            self.state.select(None);
+        } else {
+            let idx = self
+                .state
+                .selected()
+                .unwrap_or(0)
+                .min(self.operations.len() - 1);
+            self.state.select(Some(idx));
+        }
+    }
+}
+
+/// Kernel naming: disks whose name ends in a digit separate the partition
+/// number with a 'p'.
+fn partition_name(disk: &str, part_num: u32) -> String {
+    if disk.ends_with(|c: char| c.is_ascii_digit()) {
+        format!("{}p{}", disk, part_num)
+    } else {
+        format!("{}{}", disk, part_num)
+    }
+}
+
+fn partition_number(disk: &str, partition: &str) -> Option<u32> {
+    let suffix = partition.strip_prefix(disk)?;
+    suffix.strip_prefix('p').unwrap_or(suffix).parse().ok()
+}
+
+fn empty_partition(
+    device: &BlockDevice,
+    name: String,
+    start: u64,
+    size: u64,
+    filesystem: Option<String>,
+) -> Partition {
+    let sector_size = device.geometry.logical_sector_size;
+    Partition {
+        name,
+        geometry: Some(PartitionGeometry {
+            start: start / sector_size,
+            sectors: size / sector_size,
+            logical_sector_size: sector_size,
+            physical_sector_size: device.geometry.physical_sector_size,
+        }),
+        size,
+        filesystem,
+        mount_point: None,
+        is_mounted: false,
+        label: None,
+        uuid: None,
+        used_bytes: None,
+        available_bytes: None,
+        is_encrypted: false,
+        encryption_type: None,
+        luks_uuid: None,
+        mapper_device: None,
+        part_type: None,
+        part_uuid: None,
+        part_label: None,
+        flags: Vec::new(),
+        swap: None,
+        swap_files: Vec::new(),
+        holder: None,
+    }
+}
+
+/// Applies the queued operations for `device` to a copy of it, giving the
+/// layout the disk will have once the queue is applied. Operations that
+/// cannot be simulated, such as an unparsable size, leave the layout as is.
+pub fn simulate(device: &BlockDevice, operations: &[Request]) -> BlockDevice {
+    let mut after = device.clone();
+    let disk = device.name.as_str();
+
+    for request in operations {
+        match request {
+            Request::CreatePartitionTable {
+                disk: target,
+                table_type,
+            } if target == disk => {
+                after.partitions.clear();
+                // udev calls an MBR table "dos".
+                after.partition_table = Some(match table_type.as_str() {
+                    "gpt" => "gpt".to_string(),
+                    _ => "dos".to_string(),
+                });
+            }
+            Request::FormatWholeDisk {
+                disk: target,
+                fs_type,
+                label,
+            } if target == disk => {
+                after.partition_table = Some("gpt".to_string());
+                let mut part = empty_partition(
+                    device,
+                    partition_name(disk, 1),
+                    MIB,
+                    device.size.saturating_sub(MIB),
+                    Some(fs_type.clone()),
+                );
+                part.label = label.clone().filter(|l| !l.is_empty());
+                after.partitions = vec![part];
+            }
+            Request::CreatePartition {
+                disk: target,
+                start,
+                size,
+                fs_type,
+                alignment,
+                part_type,
+                name,
+            } if target == disk => {
+                let Ok(placement) = layout::place_partition(
+                    &layout::device_gaps(&after),
+                    *start,
+                    size,
+                    &after.geometry,
+                    *alignment,
+                ) else {
+                    continue;
+                };
+                // parted takes the lowest free partition number.
+                let used: Vec<u32> = after
+                    .partitions
+                    .iter()
+                    .filter_map(|p| partition_number(disk, &p.name))
+                    .collect();
+                let next = (1..).find(|n| !used.contains(n)).unwrap_or(1);
+                let mut part = empty_partition(
+                    device,
+                    partition_name(disk, next),
+                    placement.range.start,
+                    placement.range.size(),
+                    fs_type.clone(),
+                );
+                let part_type = part_type.as_deref().unwrap_or(parttype::LINUX_FILESYSTEM);
+                part.part_type =
+                    parttype::resolve(part_type, after.partition_table.as_deref()).ok();
+                part.part_label = name.clone();
+                after.partitions.push(part);
+                after.partitions.sort_by_key(|p| p.start());
+            }
+            Request::DeletePartition { partition } => {
+                after.partitions.retain(|p| &p.name != partition);
+            }
+            Request::Format {
+                device,
+                fs_type,
+                label,
+            } => {
+                if let Some(part) = after
+                    .partitions
+                    .iter_mut()
+                    .find(|p| &p.name == device || p.mapper_device.as_ref() == Some(device))
+                {
+                    part.filesystem = Some(fs_type.clone());
+                    part.label = label.clone().filter(|l| !l.is_empty());
+                    part.uuid = None;
+                    part.used_bytes = None;
+                    part.available_bytes = None;
+                }
+            }
+            Request::ResizePartition {
+                partition,
+                new_size,
+                alignment,
+            } => {
+                let align = alignment.bytes(&after.geometry);
+                let Some(idx) = after.partitions.iter().position(|p| &p.name == partition) else {
+                    continue;
+                };
+                let available = layout::max_partition_size(&after, &after.partitions[idx]);
+                let size = new_size
+                    .parse::<SizeSpec>()
+                    .and_then(|spec| spec.resolve(available, after.geometry.logical_sector_size));
+                if let Ok(size) = size {
+                    let part = &mut after.partitions[idx];
+                    part.size = layout::aligned_resize(part.size, size, align);
+                    if let Some(geometry) = part.geometry.as_mut() {
+                        geometry.sectors = part.size / geometry.logical_sector_size;
+                    }
+                }
+            }
+            Request::ModifyPartition {
+                partition,
+                part_type,
+                name,
+                flags,
+            } => {
+                let table_type = after.partition_table.clone();
+                let Some(part) = after.partitions.iter_mut().find(|p| &p.name == partition) else {
+                    continue;
+                };
+                if let Some(part_type) = part_type
+                    && let Ok(code) = parttype::resolve(part_type, table_type.as_deref())
+                {
+                    part.part_type = Some(code);
+                }
+                if let Some(name) = name {
+                    part.part_label = Some(name.clone()).filter(|name| !name.is_empty());
+                }
+                for change in flags {
+                    part.flags.retain(|flag| *flag != change.flag);
+                    if change.enabled {
+                        part.flags.push(change.flag);
+                    }
+                    // parted implements the esp flag as the partition type.
+                    if change.flag == PartitionFlag::Esp && change.enabled {
+                        part.part_type = parttype::resolve("esp", table_type.as_deref()).ok();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    after
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::{Alignment, DiskGeometry};
+    use crate::parttype::FlagChange;
+
+    /// A GPT disk with the partitions laid out back to back from 1 MiB.
+    fn disk(name: &str, size: u64, partitions: &[(&str, u64)]) -> BlockDevice {
+        let mut device = BlockDevice {
+            name: name.to_string(),
+            size,
+            model: None,
+            serial: None,
+            partition_table: Some("gpt".to_string()),
+            geometry: DiskGeometry::default(),
+            partitions: Vec::new(),
+        };
+        let mut start = MIB;
+        for (name, size) in partitions {
+            let part =
+                empty_partition(&device, name.to_string(), start, *size, Some("ext4".into()));
+            device.partitions.push(part);
+            start = (start + size).div_ceil(MIB) * MIB;
+        }
+        device
+    }
+
+    fn layout(device: &BlockDevice) -> Vec<(String, u64, Option<String>)> {
+        device
+            .partitions
+            .iter()
+            .map(|p| (p.name.clone(), p.size, p.filesystem.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn simulate_applies_operations_in_order() {
+        let device = disk(
+            "sdb",
+            10_000_000_000,
+            &[("sdb1", 2_000_000_000), ("sdb2", 3_000_000_000)],
+        );
+        let operations = vec![
+            Request::DeletePartition {
+                partition: "sdb2".into(),
+            },
+            Request::ResizePartition {
+                partition: "sdb1".into(),
+                new_size: "4GB".into(),
+                alignment: Alignment::Mib,
+            },
+            Request::CreatePartition {
+                disk: "sdb".into(),
+                start: None,
+                size: String::new(),
+                fs_type: Some("xfs".into()),
+                alignment: Alignment::Mib,
+                part_type: None,
+                name: None,
+            },
+            Request::Format {
+                device: "sdb1".into(),
+                fs_type: "btrfs".into(),
+                label: Some("data".into()),
+            },
+        ];
+
+        let after = simulate(&device, &operations);
+
+        assert_eq!(
+            layout(&after),
+            vec![
+                // Growing to 4GB rounds down to 3814 MiB.
+                ("sdb1".to_string(), 3_999_268_864, Some("btrfs".to_string())),
+                // From the MiB after sdb1 to the last MiB before the GPT backup.
+                ("sdb2".to_string(), 5_998_903_296, Some("xfs".to_string())),
+            ]
+        );
+        assert_eq!(after.partitions[0].label.as_deref(), Some("data"));
+        assert_eq!(layout(&device).len(), 2);
+    }
+
+    #[test]
+    fn simulate_ignores_other_disks_and_uses_p_separator() {
+        let device = disk("nvme0n1", 8_000_000_000, &[]);
+        let operations = vec![
+            Request::CreatePartitionTable {
+                disk: "sdb".into(),
+                table_type: "gpt".into(),
+            },
+            Request::CreatePartition {
+                disk: "nvme0n1".into(),
+                start: None,
+                size: "1GB".into(),
+                fs_type: None,
+                alignment: Alignment::Mib,
+                part_type: None,
+                name: None,
+            },
+        ];
+
+        let after = simulate(&device, &operations);
+
+        assert_eq!(
+            layout(&after),
+            vec![("nvme0n1p1".to_string(), 1_000_000_000, None)]
+        );
+    }
+
+    #[test]
+    fn simulate_fills_the_hole_at_the_requested_start() {
+        let device = disk(
+            "sdb",
+            10_000_000_000,
+            &[("sdb1", 2_000_000_000), ("sdb2", 3_000_000_000)],
+        );
+        let operations = vec![
+            Request::DeletePartition {
+                partition: "sdb1".into(),
+            },
+            Request::CreatePartition {
+                disk: "sdb".into(),
+                start: Some(MIB),
+                size: "1GB".into(),
+                fs_type: None,
+                alignment: Alignment::Mib,
+                part_type: None,
+                name: None,
+            },
+        ];
+
+        let after = simulate(&device, &operations);
+
+        assert_eq!(
+            layout(&after),
+            vec![
+                ("sdb1".to_string(), 1_000_000_000, None),
+                ("sdb2".to_string(), 3_000_000_000, Some("ext4".to_string())),
+            ]
+        );
+        assert_eq!(after.partitions[0].start(), Some(MIB));
+    }
+
+    #[test]
+    fn simulate_tracks_partition_types_and_flags() {
+        let device = disk("sdb", 10_000_000_000, &[("sdb1", 2_000_000_000)]);
+        let operations = vec![
+            Request::CreatePartition {
+                disk: "sdb".into(),
+                start: None,
+                size: "1GB".into(),
+                fs_type: None,
+                alignment: Alignment::Mib,
+                part_type: Some("swap".into()),
+                name: Some("swap".into()),
+            },
+            Request::ModifyPartition {
+                partition: "sdb1".into(),
+                part_type: None,
+                name: Some("boot".into()),
+                flags: vec![FlagChange {
+                    flag: PartitionFlag::Esp,
+                    enabled: true,
+                }],
+            },
+        ];
+
+        let after = simulate(&device, &operations);
+
+        let boot = &after.partitions[0];
+        assert_eq!(boot.part_label.as_deref(), Some("boot"));
+        assert_eq!(boot.flags, vec![PartitionFlag::Esp]);
+        assert_eq!(
+            boot.part_type
+                .as_deref()
+                .and_then(parttype::lookup)
+                .map(|t| t.alias),
+            Some("esp")
+        );
+        let swap = &after.partitions[1];
+        assert_eq!(
+            swap.part_type.as_deref(),
+            Some("0657FD6D-A4AB-43C4-84E5-0933C84B4F4F")
+        );
+        assert_eq!(swap.part_label.as_deref(), Some("swap"));
+    }
+
+    #[test]
+    fn reorder_and_undo_keep_selection_in_range() {
+        let mut queue = OperationQueue::default();
+        for partition in ["sdb1", "sdb2", "sdb3"] {
+            queue.push(Request::DeletePartition {
+                partition: partition.into(),
+            });
+        }
+
+        queue.move_selected_up();
+        assert_eq!(queue.operations[1].description(), "Delete sdb3");
+        assert_eq!(queue.state.selected(), Some(1));
+
+        queue.finish(2);
+        assert_eq!(queue.operations.len(), 1);
+        assert_eq!(queue.state.selected(), Some(0));
+
+        queue.undo();
+        assert!(queue.is_empty());
+        assert_eq!(queue.state.selected(), None);
+    }
+}
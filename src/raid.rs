@@ -0,0 +1,457 @@
+use crate::runner::CommandRunner;
+use anyhow::{Context, Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+pub const MDSTAT: &str = "/proc/mdstat";
+
+/// RAID levels offered for new arrays, with the fewest members each needs.
+pub const LEVELS: [(&str, usize); 6] = [
+    ("raid0", 2),
+    ("raid1", 2),
+    ("raid4", 3),
+    ("raid5", 3),
+    ("raid6", 4),
+    ("raid10", 2),
+];
+
+/// A resync, recovery, reshape or check running on an array.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SyncProgress {
+    /// "recovery", "resync", "reshape", "check" or "repair".
+    pub action: String,
+    /// `None` while the action is pending or delayed.
+    pub percent: Option<f64>,
+    /// Time left as the kernel estimates it, such as "12.5min".
+    pub finish: Option<String>,
+    pub speed: Option<String>,
+}
+
+impl SyncProgress {
+    /// "recovery 27.5%, 12.5min left".
+    pub fn summary(&self) -> String {
+        match (self.percent, &self.finish) {
+            (Some(percent), Some(finish)) => {
+                format!("{} {:.1}%, {} left", self.action, percent, finish)
+            }
+            (Some(percent), None) => format!("{} {:.1}%", self.action, percent),
+            (None, _) => format!("{} pending", self.action),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RaidMember {
+    /// Device name, such as "sdb1".
+    pub name: String,
+    /// Role number in the array; spares have one too.
+    pub slot: Option<u32>,
+    pub faulty: bool,
+    pub spare: bool,
+    /// State from `mdadm --detail`, such as "active sync" or
+    /// "spare rebuilding", when it could be read.
+    pub state: Option<String>,
+}
+
+impl RaidMember {
+    pub fn state(&self) -> String {
+        match &self.state {
+            Some(state) => state.clone(),
+            None if self.faulty => "faulty".to_string(),
+            None if self.spare => "spare".to_string(),
+            None => "active".to_string(),
+        }
+    }
+
+    /// Whether the member holds data of the array, which mdadm will not
+    /// remove before it is marked failed.
+    pub fn is_active(&self) -> bool {
+        !self.faulty && !self.spare
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RaidArray {
+    /// Kernel name, such as "md0".
+    pub name: String,
+    pub active: bool,
+    pub read_only: bool,
+    /// "raid1" and so on; unknown for inactive arrays.
+    pub level: Option<String>,
+    pub size: u64,
+    pub members: Vec<RaidMember>,
+    /// Devices the array has and how many of them work, such as 2 of 2.
+    pub raid_devices: Option<(u32, u32)>,
+    pub progress: Option<SyncProgress>,
+    /// State line of `mdadm --detail`, such as "clean, degraded".
+    pub state: Option<String>,
+    pub uuid: Option<String>,
+}
+
+impl RaidArray {
+    pub fn is_degraded(&self) -> bool {
+        self.raid_devices
+            .is_some_and(|(devices, working)| working < devices)
+    }
+
+    pub fn member(&self, name: &str) -> Option<&RaidMember> {
+        self.members.iter().find(|member| member.name == name)
+    }
+
+    pub fn state(&self) -> String {
+        match &self.state {
+            Some(state) => state.clone(),
+            None if !self.active => "inactive".to_string(),
+            None if self.is_degraded() => "degraded".to_string(),
+            None => "active".to_string(),
+        }
+    }
+}
+
+/// An array found in member superblocks by `mdadm --examine --scan` that
+/// is not running.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoppedArray {
+    /// Name from the superblock, such as "/dev/md/0".
+    pub name: String,
+    pub uuid: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RaidReport {
+    pub arrays: Vec<RaidArray>,
+    pub stopped: Vec<StoppedArray>,
+}
+
+impl RaidReport {
+    /// Reads /proc/mdstat, the details of each array and the stopped
+    /// arrays. Details are left out for arrays mdadm cannot read.
+    pub async fn load(runner: &dyn CommandRunner) -> Result<Self> {
+        let output = runner
+            .output("cat", &[MDSTAT])
+            .await
+            .context("Failed to read /proc/mdstat")?;
+        // Without the md driver loaded there are no arrays to show.
+        let mut arrays = match output.status.success() {
+            true => parse_mdstat(&String::from_utf8_lossy(&output.stdout)),
+            false => Vec::new(),
+        };
+
+        for array in &mut arrays {
+            let path = format!("/dev/{}", array.name);
+            let Ok(output) = runner.output("mdadm", &["--detail", &path]).await else {
+                continue;
+            };
+            if output.status.success() {
+                apply_detail(array, &String::from_utf8_lossy(&output.stdout));
+            }
+        }
+
+        let output = runner
+            .output("mdadm", &["--examine", "--scan"])
+            .await
+            .context("Failed to execute mdadm. Install the mdadm package.")?;
+        let stopped = parse_examine_scan(&String::from_utf8_lossy(&output.stdout))
+            .into_iter()
+            .filter(|stopped| {
+                !arrays
+                    .iter()
+                    .any(|array| array.uuid.as_deref() == Some(stopped.uuid.as_str()))
+            })
+            .collect();
+        Ok(Self { arrays, stopped })
+    }
+
+    /// The first "mdN" name no array uses.
+    pub fn next_name(&self) -> String {
+        (0..)
+            .map(|n| format!("md{}", n))
+            .find(|name| self.array(name).is_none())
+            .unwrap_or_default()
+    }
+
+    pub fn array(&self, name: &str) -> Option<&RaidArray> {
+        self.arrays.iter().find(|array| array.name == name)
+    }
+
+    /// The array `device` is a member of.
+    pub fn array_of(&self, device: &str) -> Option<&RaidArray> {
+        self.arrays
+            .iter()
+            .find(|array| array.member(device).is_some())
+    }
+}
+
+/// Reads the arrays, their members and running syncs from /proc/mdstat.
+pub fn parse_mdstat(text: &str) -> Vec<RaidArray> {
+    let mut arrays: Vec<RaidArray> = Vec::new();
+    for line in text.lines() {
+        if let Some((name, rest)) = line.split_once(" : ")
+            && name.starts_with("md")
+            && !name.contains(' ')
+        {
+            arrays.push(parse_array_line(name, rest));
+            continue;
+        }
+        let Some(array) = arrays.last_mut() else {
+            continue;
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some((blocks, _)) = line.split_once(" blocks")
+            && let Ok(blocks) = blocks.trim().parse::<u64>()
+        {
+            // Blocks are KiB.
+            array.size = blocks * 1024;
+            array.raid_devices = line.split_whitespace().find_map(|field| {
+                let (devices, working) = field
+                    .strip_prefix('[')?
+                    .strip_suffix(']')?
+                    .split_once('/')?;
+                Some((devices.parse().ok()?, working.parse().ok()?))
+            });
+        } else if let Some(progress) = parse_progress(line) {
+            array.progress = Some(progress);
+        }
+    }
+    arrays
+}
+
+/// "active raid1 sdc1[1] sdb1[0](F)" or "inactive sdd1[0](S)".
+fn parse_array_line(name: &str, rest: &str) -> RaidArray {
+    let mut array = RaidArray {
+        name: name.to_string(),
+        ..RaidArray::default()
+    };
+    for field in rest.split_whitespace() {
+        match field {
+            "active" => array.active = true,
+            "inactive" => array.active = false,
+            "(read-only)" | "(auto-read-only)" => array.read_only = true,
+            _ if field.starts_with("raid") || field == "linear" => {
+                array.level = Some(field.to_string());
+            }
+            _ => {
+                if let Some(member) = parse_member(field) {
+                    array.members.push(member);
+                }
+            }
+        }
+    }
+    // mdstat lists members newest first.
+    array.members.sort_by_key(|member| member.slot);
+    array
+}
+
+/// "sdb1[0]", "sdc1[2](S)" or "sdd1[1](F)".
+fn parse_member(field: &str) -> Option<RaidMember> {
+    let (name, rest) = field.split_once('[')?;
+    let (slot, flags) = rest.split_once(']')?;
+    Some(RaidMember {
+        name: name.to_string(),
+        slot: slot.parse().ok(),
+        faulty: flags.contains("(F)"),
+        spare: flags.contains("(S)"),
+        state: None,
+    })
+}
+
+/// "[===>.....]  recovery = 27.5% (288000/1046528) finish=0.1min
+/// speed=144000K/sec", or "resync=DELAYED".
+fn parse_progress(line: &str) -> Option<SyncProgress> {
+    let line = match line.strip_prefix('[') {
+        Some(bar) => bar.split_once(']')?.1,
+        None => line,
+    };
+    let (action, rest) = line.split_once('=')?;
+    let action = action.trim();
+    if !matches!(
+        action,
+        "recovery" | "resync" | "reshape" | "check" | "repair"
+    ) {
+        return None;
+    }
+    let rest = rest.trim();
+    let percent = rest
+        .split_whitespace()
+        .next()
+        .and_then(|percent| percent.strip_suffix('%'))
+        .and_then(|percent| percent.parse().ok());
+    let field = |key: &str| {
+        rest.split_whitespace()
+            .find_map(|field| field.strip_prefix(key))
+            .map(str::to_string)
+    };
+    Some(SyncProgress {
+        action: action.to_string(),
+        percent,
+        finish: field("finish="),
+        speed: field("speed="),
+    })
+}
+
+/// Fills in the state, UUID and member states from `mdadm --detail`.
+pub fn apply_detail(array: &mut RaidArray, text: &str) {
+    let mut in_devices = false;
+    for line in text.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("Number") && trimmed.contains("RaidDevice") {
+            in_devices = true;
+            continue;
+        }
+        if in_devices {
+            // "0  8  17  0  active sync  /dev/sdb1"; removed slots have
+            // no device.
+            let fields: Vec<&str> = trimmed.split_whitespace().collect();
+            let Some(device) = fields.last().and_then(|path| path.strip_prefix("/dev/")) else {
+                continue;
+            };
+            let state = fields[..fields.len() - 1]
+                .iter()
+                .skip_while(|field| field.parse::<i64>().is_ok() || **field == "-")
+                .copied()
+                .collect::<Vec<_>>()
+                .join(" ");
+            if let Some(member) = array.members.iter_mut().find(|m| m.name == device) {
+                member.state = Some(state);
+            }
+            continue;
+        }
+        let Some((key, value)) = trimmed.split_once(" : ") else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match key.trim() {
+            "State" => array.state = Some(value),
+            "UUID" => array.uuid = Some(value),
+            "Raid Level" if array.level.is_none() => array.level = Some(value),
+            _ => {}
+        }
+    }
+}
+
+/// The `ARRAY` lines of `mdadm --examine --scan`.
+pub fn parse_examine_scan(text: &str) -> Vec<StoppedArray> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            if fields.next() != Some("ARRAY") {
+                return None;
+            }
+            let name = fields.next()?.to_string();
+            let uuid = fields.find_map(|field| field.strip_prefix("UUID="))?;
+            Some(StoppedArray {
+                name,
+                uuid: uuid.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Checks the name of a new array: "md" followed by a number.
+pub fn validate_array_name(name: &str) -> Result<()> {
+    match name.strip_prefix("md") {
+        Some(number) if !number.is_empty() && number.len() <= 4 => {
+            number
+                .parse::<u32>()
+                .map_err(|_| anyhow!("Array names are md followed by a number, such as md0"))?;
+            Ok(())
+        }
+        _ => Err(anyhow!(
+            "Array names are md followed by a number, such as md0"
+        )),
+    }
+}
+
+/// Checks an array UUID as mdadm prints it, such as
+/// "3b1f8a2c:1d2e3f4a:5b6c7d8e:9f0a1b2c".
+pub fn validate_uuid(uuid: &str) -> Result<()> {
+    if uuid.len() != 35 || !uuid.chars().all(|c| c.is_ascii_hexdigit() || c == ':') {
+        return Err(anyhow!("Invalid array UUID: {}", uuid));
+    }
+    Ok(())
+}
+
+/// The fewest members an array of `level` needs.
+pub fn min_devices(level: &str) -> Result<usize> {
+    LEVELS
+        .iter()
+        .find(|(name, _)| *name == level)
+        .map(|(_, min)| *min)
+        .ok_or_else(|| anyhow!("Unsupported RAID level: {}", level))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MDSTAT_TEXT: &str = "\
+Personalities : [raid1] [raid6] [raid5] [raid4]
+md1 : active raid5 sde1[3] sdd1[1](F) sdc1[0]
+      2093056 blocks super 1.2 level 5, 512k chunk, algorithm 2 [3/2] [U_U]
+      [=====>...............]  recovery = 27.5% (288000/1046528) finish=0.1min speed=144000K/sec
+
+md0 : active raid1 sdb2[1] sdb1[0]
+      1046528 blocks super 1.2 [2/2] [UU]
+
+md127 : inactive sdf1[0](S)
+      1046528 blocks super 1.2
+
+unused devices: <none>
+";
+
+    #[test]
+    fn mdstat_lists_members_and_recovery() {
+        let arrays = parse_mdstat(MDSTAT_TEXT);
+        assert_eq!(arrays.len(), 3);
+
+        let md1 = &arrays[0];
+        assert_eq!(md1.level.as_deref(), Some("raid5"));
+        assert_eq!(md1.raid_devices, Some((3, 2)));
+        assert!(md1.is_degraded());
+        assert_eq!(md1.members[1].name, "sdd1");
+        assert!(md1.members[1].faulty);
+        let progress = md1.progress.as_ref().unwrap();
+        assert_eq!(progress.percent, Some(27.5));
+        assert_eq!(progress.summary(), "recovery 27.5%, 0.1min left");
+
+        assert_eq!(arrays[1].size, 1046528 * 1024);
+        assert!(arrays[1].progress.is_none());
+        assert!(!arrays[2].active);
+        assert!(arrays[2].members[0].spare);
+    }
+
+    #[test]
+    fn detail_adds_state_and_member_roles() {
+        let mut array = parse_mdstat(MDSTAT_TEXT).remove(0);
+        let detail = "\
+/dev/md1:
+           Version : 1.2
+        Raid Level : raid5
+             State : clean, degraded, recovering
+              UUID : 3b1f8a2c:1d2e3f4a:5b6c7d8e:9f0a1b2c
+
+    Number   Major   Minor   RaidDevice State
+       0       8       33        0      active sync   /dev/sdc1
+       -       0        0        1      removed
+       3       8       65        2      spare rebuilding   /dev/sde1
+
+       1       8       49        -      faulty   /dev/sdd1
+";
+        apply_detail(&mut array, detail);
+
+        assert_eq!(array.state(), "clean, degraded, recovering");
+        assert_eq!(array.member("sde1").unwrap().state(), "spare rebuilding");
+        assert_eq!(array.member("sdd1").unwrap().state(), "faulty");
+
+        let stopped = parse_examine_scan(
+            "ARRAY /dev/md/0  metadata=1.2 UUID=3b1f8a2c:1d2e3f4a:5b6c7d8e:9f0a1b2c name=host:0\n",
+        );
+        assert_eq!(stopped[0].name, "/dev/md/0");
+        assert!(validate_uuid(&stopped[0].uuid).is_ok());
+        assert!(validate_array_name("md12").is_ok());
+        assert!(validate_array_name("md").is_err());
+        assert!(validate_array_name("sda").is_err());
+    }
+}
@@ -0,0 +1,580 @@
+use futures::future::BoxFuture;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output, Stdio};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::process::Command;
+
+/// Abstraction over everything the disk operations need from the host:
+/// running external programs, probing device nodes and waiting for udev.
+pub trait CommandRunner: Send + Sync {
+    fn output<'a>(
+        &'a self,
+        program: &'a str,
+        args: &'a [&'a str],
+    ) -> BoxFuture<'a, io::Result<Output>>;
+
+    fn output_with_stdin<'a>(
+        &'a self,
+        program: &'a str,
+        args: &'a [&'a str],
+        stdin: &'a [u8],
+    ) -> BoxFuture<'a, io::Result<Output>>;
+
+    /// Like `output`, but passes every line the command prints on stdout
+    /// or stderr to `on_line` as soon as it is printed.
+    fn output_streaming<'a>(
+        &'a self,
+        program: &'a str,
+        args: &'a [&'a str],
+        on_line: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxFuture<'a, io::Result<Output>> {
+        Box::pin(async move {
+            let output = self.output(program, args).await?;
+            for line in String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .chain(String::from_utf8_lossy(&output.stderr).lines())
+            {
+                on_line(line);
+            }
+            Ok(output)
+        })
+    }
+
+    fn path_exists(&self, path: &str) -> bool {
+        std::path::Path::new(path).exists()
+    }
+
+    fn read_dir_names(&self, path: &str) -> io::Result<Vec<String>> {
+        Ok(std::fs::read_dir(path)?
+            .flatten()
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect())
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'_, ()> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+
+    /// True when commands that change the system are only recorded, so that
+    /// callers can predict results they would otherwise read back.
+    fn is_dry_run(&self) -> bool {
+        false
+    }
+}
+
+/// Runs commands on the real system.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn output<'a>(
+        &'a self,
+        program: &'a str,
+        args: &'a [&'a str],
+    ) -> BoxFuture<'a, io::Result<Output>> {
+        Box::pin(async move { Command::new(program).args(args).output().await })
+    }
+
+    fn output_with_stdin<'a>(
+        &'a self,
+        program: &'a str,
+        args: &'a [&'a str],
+        stdin: &'a [u8],
+    ) -> BoxFuture<'a, io::Result<Output>> {
+        Box::pin(async move {
+            let mut child = Command::new(program)
+                .args(args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?;
+
+            if let Some(mut child_stdin) = child.stdin.take() {
+                use tokio::io::AsyncWriteExt;
+                child_stdin.write_all(stdin).await?;
+                child_stdin.flush().await?;
+                drop(child_stdin);
+            }
+
+            child.wait_with_output().await
+        })
+    }
+
+    fn output_streaming<'a>(
+        &'a self,
+        program: &'a str,
+        args: &'a [&'a str],
+        on_line: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxFuture<'a, io::Result<Output>> {
+        Box::pin(async move {
+            use tokio::io::{AsyncBufReadExt, BufReader};
+
+            let mut child = Command::new(program)
+                .args(args)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()?;
+            let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+            let mut stderr = BufReader::new(child.stderr.take().expect("stderr is piped"));
+
+            // Partial lines stay in the buffers when the other stream wins
+            // the select, so no output is lost. A read cut short that way
+            // can leave the last, unterminated line there at end of file.
+            let (mut out_line, mut err_line) = (Vec::new(), Vec::new());
+            let (mut out, mut err) = (Vec::new(), Vec::new());
+            let (mut out_done, mut err_done) = (false, false);
+            loop {
+                tokio::select! {
+                    read = stdout.read_until(b'\n', &mut out_line), if !out_done => {
+                        out_done = read? == 0;
+                        if !out_line.is_empty() {
+                            emit_line(&mut out_line, &mut out, on_line);
+                        }
+                    }
+                    read = stderr.read_until(b'\n', &mut err_line), if !err_done => {
+                        err_done = read? == 0;
+                        if !err_line.is_empty() {
+                            emit_line(&mut err_line, &mut err, on_line);
+                        }
+                    }
+                    else => break,
+                }
+            }
+
+            Ok(Output {
+                status: child.wait().await?,
+                stdout: out,
+                stderr: err,
+            })
+        })
+    }
+}
+
+fn emit_line(line: &mut Vec<u8>, collected: &mut Vec<u8>, on_line: &mut (dyn FnMut(&str) + Send)) {
+    on_line(String::from_utf8_lossy(line).trim_end_matches(['\n', '\r']));
+    collected.append(line);
+}
+
+/// Wraps another runner and records every command that would change the
+/// system instead of running it. Read-only probes (lsblk, findmnt, parted
+/// print, ...) still go to the inner runner so that validation and device
+/// resolution see the real system.
+pub struct DryRunRunner<'a> {
+    inner: &'a dyn CommandRunner,
+    planned: Mutex<Vec<String>>,
+}
+
+impl<'a> DryRunRunner<'a> {
+    pub fn new(inner: &'a dyn CommandRunner) -> Self {
+        Self {
+            inner,
+            planned: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Command lines that would have been run, in order.
+    pub fn planned(&self) -> Vec<String> {
+        self.planned.lock().unwrap().clone()
+    }
+
+    fn plan(&self, program: &str, args: &[&str], stdin: bool) -> Output {
+        let mut line = std::iter::once(program)
+            .chain(args.iter().copied())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if stdin {
+            line.push_str(" < stdin");
+        }
+        self.planned.lock().unwrap().push(line);
+        FakeResponse::ok("").to_output()
+    }
+}
+
+/// The mapper name of the volume a planned `lvcreate -n <lv> ... <vg>`
+/// line creates.
+fn planned_logical_volume(line: &str) -> Option<String> {
+    let args: Vec<&str> = line.strip_prefix("lvcreate ")?.split(' ').collect();
+    let name = args.iter().position(|arg| *arg == "-n")?;
+    Some(crate::lvm::mapper_name(args.last()?, args.get(name + 1)?))
+}
+
+/// The disk whose partition table a planned parted or sfdisk line changes.
+fn planned_table_disk(line: &str) -> Option<&str> {
+    let args = line
+        .strip_prefix("parted ")
+        .or_else(|| line.strip_prefix("sfdisk "))?;
+    args.split(' ').find_map(|arg| arg.strip_prefix("/dev/"))
+}
+
+/// Commands that only inspect the system and are safe to run during a dry run.
+fn is_read_only(program: &str, args: &[&str]) -> bool {
+    match program {
+        "findmnt" | "lsblk" | "which" | "blkid" | "df" => true,
+        "smartctl" => !args
+            .iter()
+            .any(|arg| matches!(*arg, "-t" | "--test" | "-X" | "--abort")),
+        "cryptsetup" => matches!(args.first(), Some(&"isLuks") | Some(&"luksDump")),
+        "parted" => args.last() == Some(&"print"),
+        "sfdisk" => args.first() == Some(&"-d"),
+        "swapon" => args.first().is_some_and(|arg| arg.starts_with("--show")),
+        "readlink" | "ls" | "cat" => true,
+        "pvs" | "vgs" | "lvs" => true,
+        "mdadm" => args
+            .iter()
+            .any(|arg| matches!(*arg, "--detail" | "--examine" | "-D" | "-E")),
+        "btrfs" => matches!(
+            args,
+            ["subvolume", "list" | "get-default" | "show", ..]
+                | ["filesystem", "usage" | "show" | "df", ..]
+        ),
+        _ => false,
+    }
+}
+
+impl CommandRunner for DryRunRunner<'_> {
+    fn output<'a>(
+        &'a self,
+        program: &'a str,
+        args: &'a [&'a str],
+    ) -> BoxFuture<'a, io::Result<Output>> {
+        if is_read_only(program, args) {
+            return self.inner.output(program, args);
+        }
+        Box::pin(async move { Ok(self.plan(program, args, false)) })
+    }
+
+    fn output_with_stdin<'a>(
+        &'a self,
+        program: &'a str,
+        args: &'a [&'a str],
+        stdin: &'a [u8],
+    ) -> BoxFuture<'a, io::Result<Output>> {
+        if is_read_only(program, args) {
+            return self.inner.output_with_stdin(program, args, stdin);
+        }
+        Box::pin(async move { Ok(self.plan(program, args, true)) })
+    }
+
+    /// Devices a planned command would create cannot be checked: partition
+    /// nodes are assumed to exist once a planned parted or sfdisk command
+    /// names their disk, mappers once a planned `cryptsetup open` or
+    /// `lvcreate` names them.
+    fn path_exists(&self, path: &str) -> bool {
+        if self.inner.path_exists(path) {
+            return true;
+        }
+        let planned = self.planned.lock().unwrap();
+        if let Some(name) = path.strip_prefix("/dev/mapper/") {
+            return planned.iter().any(|line| {
+                (line.starts_with("cryptsetup open ")
+                    && line
+                        .trim_end_matches(" < stdin")
+                        .ends_with(&format!(" {}", name)))
+                    || planned_logical_volume(line).as_deref() == Some(name)
+            });
+        }
+        let Some((disk, _)) = path
+            .strip_prefix("/dev/")
+            .and_then(|name| crate::protocol::split_partition(name, None))
+        else {
+            return false;
+        };
+        planned
+            .iter()
+            .any(|line| planned_table_disk(line) == Some(disk))
+    }
+
+    fn read_dir_names(&self, path: &str) -> io::Result<Vec<String>> {
+        self.inner.read_dir_names(path)
+    }
+
+    fn sleep(&self, _duration: Duration) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    fn is_dry_run(&self) -> bool {
+        true
+    }
+}
+
+/// A single command executed through a [`FakeRunner`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedCall {
+    pub program: String,
+    pub args: Vec<String>,
+    pub stdin: Option<Vec<u8>>,
+}
+
+impl RecordedCall {
+    pub fn command_line(&self) -> String {
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Canned result returned by a [`FakeRunner`].
+#[derive(Debug, Clone)]
+pub struct FakeResponse {
+    pub code: i32,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl FakeResponse {
+    pub fn ok(stdout: impl Into<Vec<u8>>) -> Self {
+        Self {
+            code: 0,
+            stdout: stdout.into(),
+            stderr: Vec::new(),
+        }
+    }
+
+    pub fn fail(code: i32, stderr: impl Into<Vec<u8>>) -> Self {
+        Self {
+            code,
+            stdout: Vec::new(),
+            stderr: stderr.into(),
+        }
+    }
+
+    fn to_output(&self) -> Output {
+        Output {
+            status: ExitStatus::from_raw(self.code << 8),
+            stdout: self.stdout.clone(),
+            stderr: self.stderr.clone(),
+        }
+    }
+}
+
+struct Rule {
+    prefix: String,
+    response: FakeResponse,
+    once: bool,
+}
+
+/// Records every command and answers with canned output instead of touching
+/// the system. Rules match on the command line prefix; one-shot rules are
+/// consumed in registration order before persistent ones are considered.
+/// Unmatched commands succeed with empty output.
+#[derive(Default)]
+pub struct FakeRunner {
+    rules: Mutex<Vec<Rule>>,
+    calls: Mutex<Vec<RecordedCall>>,
+    paths: Mutex<HashSet<String>>,
+    dirs: Mutex<HashMap<String, Vec<String>>>,
+}
+
+impl FakeRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn on(&self, prefix: &str, response: FakeResponse) -> &Self {
+        self.add_rule(prefix, response, false)
+    }
+
+    pub fn once(&self, prefix: &str, response: FakeResponse) -> &Self {
+        self.add_rule(prefix, response, true)
+    }
+
+    pub fn with_path(&self, path: &str) -> &Self {
+        self.paths.lock().unwrap().insert(path.to_string());
+        self
+    }
+
+    pub fn with_dir(&self, path: &str, entries: &[&str]) -> &Self {
+        self.dirs.lock().unwrap().insert(
+            path.to_string(),
+            entries.iter().map(|e| e.to_string()).collect(),
+        );
+        self
+    }
+
+    pub fn calls(&self) -> Vec<RecordedCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    pub fn command_lines(&self) -> Vec<String> {
+        self.calls()
+            .iter()
+            .map(RecordedCall::command_line)
+            .collect()
+    }
+
+    /// Index of the first recorded call whose command line starts with `prefix`.
+    pub fn position(&self, prefix: &str) -> Option<usize> {
+        self.command_lines()
+            .iter()
+            .position(|c| c.starts_with(prefix))
+    }
+
+    fn add_rule(&self, prefix: &str, response: FakeResponse, once: bool) -> &Self {
+        self.rules.lock().unwrap().push(Rule {
+            prefix: prefix.to_string(),
+            response,
+            once,
+        });
+        self
+    }
+
+    fn respond(&self, call: RecordedCall) -> Output {
+        let command_line = call.command_line();
+        self.calls.lock().unwrap().push(call);
+
+        let mut rules = self.rules.lock().unwrap();
+        if let Some(idx) = rules
+            .iter()
+            .position(|r| r.once && command_line.starts_with(&r.prefix))
+        {
+            return rules.remove(idx).response.to_output();
+        }
+        rules
+            .iter()
+            .find(|r| !r.once && command_line.starts_with(&r.prefix))
+            .map(|r| r.response.to_output())
+            .unwrap_or_else(|| FakeResponse::ok("").to_output())
+    }
+}
+
+impl CommandRunner for FakeRunner {
+    fn output<'a>(
+        &'a self,
+        program: &'a str,
+        args: &'a [&'a str],
+    ) -> BoxFuture<'a, io::Result<Output>> {
+        let call = RecordedCall {
+            program: program.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            stdin: None,
+        };
+        Box::pin(async move { Ok(self.respond(call)) })
+    }
+
+    fn output_with_stdin<'a>(
+        &'a self,
+        program: &'a str,
+        args: &'a [&'a str],
+        stdin: &'a [u8],
+    ) -> BoxFuture<'a, io::Result<Output>> {
+        let call = RecordedCall {
+            program: program.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+            stdin: Some(stdin.to_vec()),
+        };
+        Box::pin(async move { Ok(self.respond(call)) })
+    }
+
+    fn path_exists(&self, path: &str) -> bool {
+        self.paths.lock().unwrap().contains(path)
+    }
+
+    fn read_dir_names(&self, path: &str) -> io::Result<Vec<String>> {
+        self.dirs
+            .lock()
+            .unwrap()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.to_string()))
+    }
+
+    fn sleep(&self, _duration: Duration) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn one_shot_rules_take_precedence_and_are_consumed() {
+        let runner = FakeRunner::new();
+        runner
+            .on("lsblk", FakeResponse::ok("persistent"))
+            .once("lsblk -J", FakeResponse::ok("first"));
+
+        let first = runner.output("lsblk", &["-J"]).await.unwrap();
+        let second = runner.output("lsblk", &["-J"]).await.unwrap();
+
+        assert_eq!(first.stdout, b"first");
+        assert_eq!(second.stdout, b"persistent");
+        assert_eq!(runner.command_lines(), vec!["lsblk -J", "lsblk -J"]);
+    }
+
+    #[tokio::test]
+    async fn unmatched_commands_succeed_and_failures_carry_exit_code() {
+        let runner = FakeRunner::new();
+        runner.on("cryptsetup isLuks", FakeResponse::fail(1, "not luks"));
+
+        let unmatched = runner.output("partprobe", &["/dev/sdb"]).await.unwrap();
+        let failed = runner
+            .output_with_stdin("cryptsetup", &["isLuks", "/dev/sdb1"], b"secret")
+            .await
+            .unwrap();
+
+        assert!(unmatched.status.success());
+        assert_eq!(failed.status.code(), Some(1));
+        assert_eq!(failed.stderr, b"not luks");
+        assert_eq!(runner.calls()[1].stdin.as_deref(), Some(&b"secret"[..]));
+    }
+
+    #[tokio::test]
+    async fn dry_run_passes_probes_through_and_records_changes() {
+        let inner = FakeRunner::new();
+        inner.on("lsblk", FakeResponse::ok("{}"));
+        let dry_run = DryRunRunner::new(&inner);
+
+        let probe = dry_run.output("lsblk", &["-J"]).await.unwrap();
+        dry_run
+            .output("parted", &["-s", "/dev/sdb", "mklabel", "gpt"])
+            .await
+            .unwrap();
+        dry_run
+            .output_with_stdin(
+                "cryptsetup",
+                &["open", "/dev/sdb1", "luks-sdb1"],
+                b"secret\n",
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(probe.stdout, b"{}");
+        assert_eq!(inner.command_lines(), vec!["lsblk -J"]);
+        assert_eq!(
+            dry_run.planned(),
+            vec![
+                "parted -s /dev/sdb mklabel gpt",
+                "cryptsetup open /dev/sdb1 luks-sdb1 < stdin"
+            ]
+        );
+        assert!(dry_run.path_exists("/dev/mapper/luks-sdb1"));
+        assert!(!dry_run.path_exists("/dev/mapper/sdb2"));
+        assert!(dry_run.path_exists("/dev/sdb2"));
+        assert!(!dry_run.path_exists("/dev/sdc1"));
+        assert!(!dry_run.path_exists("/dev/sdb"));
+    }
+
+    #[tokio::test]
+    async fn streaming_passes_lines_from_both_streams() {
+        let mut lines = Vec::new();
+        let output = SystemRunner
+            .output_streaming(
+                "sh",
+                &["-c", "echo one; echo two >&2; printf three"],
+                &mut |line: &str| lines.push(line.to_string()),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(output.stdout, b"one\nthree");
+        assert_eq!(output.stderr, b"two\n");
+        lines.sort();
+        assert_eq!(lines, vec!["one", "three", "two"]);
+    }
+}
@@ -0,0 +1,269 @@
+use crate::audit::{AuditEntry, AuditLog, AuditRunner, DEFAULT_AUDIT_LOG};
+use crate::backup::{BackupStore, DEFAULT_BACKUP_DIR};
+use crate::helper::{ResponseWriter, handle_request};
+use crate::protocol::Request;
+use crate::runner::{CommandRunner, DryRunRunner};
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use std::path::Path;
+
+pub const HELPER_CONFIG_PATH: &str = "/etc/disktui/helper.toml";
+
+/// Helper settings, read from a root-owned file so that the user driving the
+/// TUI cannot redirect or disable the audit log and table backups. An empty
+/// path turns the corresponding feature off.
+#[derive(Debug, Deserialize)]
+struct HelperConfig {
+	#[serde(default = "default_audit_log")]
+	audit_log: String,
+
+	#[serde(default = "default_backup_dir")]
+	backup_dir: String,
+}
+
+impl Default for HelperConfig {
+	fn default() -> Self {
+		Self {
+			audit_log: default_audit_log(),
+			backup_dir: default_backup_dir(),
+		}
+	}
+}
+
+fn default_audit_log() -> String {
+	DEFAULT_AUDIT_LOG.to_string()
+}
+
+fn default_backup_dir() -> String {
+	DEFAULT_BACKUP_DIR.to_string()
+}
+
+/// State that lives for the whole helper process: where requests are
+/// audited and where partition tables are backed up.
+#[derive(Debug, Default)]
+pub struct HelperSession {
+	pub audit_log: Option<AuditLog>,
+	pub backups: Option<BackupStore>,
+}
+
+impl HelperSession {
+	pub fn from_config(config_path: &Path) -> Result<Self> {
+		let config = match std::fs::read_to_string(config_path) {
+			Ok(contents) => {
+				toml::from_str(&contents).with_context(|| format!("Invalid helper config {}", config_path.display()))?
+			}
+			Err(e) if e.kind() == std::io::ErrorKind::NotFound => HelperConfig::default(),
+			Err(e) => return Err(e).with_context(|| format!("Failed to read {}", config_path.display())),
+		};
+		Ok(Self {
+			audit_log: (!config.audit_log.is_empty()).then(|| AuditLog::new(config.audit_log)),
+			backups: (!config.backup_dir.is_empty()).then(|| BackupStore::new(config.backup_dir)),
+		})
+	}
+
+	/// Handles a request like [`handle_request`], adding the session
+	/// features: the request and every command it ran are recorded in the
+	/// audit log, and partition tables are backed up before they change.
+	/// Queries for the log, the backups, the LVM volumes and the RAID
+	/// arrays are answered here and not audited.
+	pub async fn handle(&self, request: Request, writer: &mut ResponseWriter, runner: &dyn CommandRunner) -> Result<Option<String>> {
+		match request {
+			Request::AuditLog { disk, limit } => {
+				let log = self.audit_log.as_ref().ok_or_else(|| anyhow!("Audit logging is disabled"))?;
+				let entries = log.read(disk.as_deref(), limit)?;
+				return Ok(Some(serde_json::to_string(&entries)?));
+			}
+			Request::ListBackups { disk } => {
+				crate::helper::validate_device_name(&disk)?;
+				let backups = self.backups()?.list(runner, &disk).await?;
+				return Ok(Some(serde_json::to_string(&backups)?));
+			}
+			Request::ListLvm | Request::ListRaid | Request::ListSubvolumes { .. } => return handle_request(request, writer, runner).await,
+			_ => {}
+		}
+
+		let Some(log) = &self.audit_log else {
+			return self.dispatch(request, writer, runner).await;
+		};
+
+		let audit_runner = AuditRunner::new(runner);
+		let result = self.dispatch(request.clone(), writer, &audit_runner).await;
+		let entry = AuditEntry::new(&request, &result, audit_runner.into_commands());
+		if let Err(e) = log.append(&entry) {
+			writer.notify("warning", format!("Failed to write audit log: {}", e))?;
+		}
+		result
+	}
+
+	async fn dispatch(&self, request: Request, writer: &mut ResponseWriter, runner: &dyn CommandRunner) -> Result<Option<String>> {
+		let Request::DryRun { request } = request else {
+			return self.execute(request, writer, runner).await;
+		};
+		if matches!(
+			*request,
+			Request::DryRun { .. } | Request::AuditLog { .. } | Request::ListBackups { .. } | Request::ListLvm | Request::ListRaid | Request::ListSubvolumes { .. } | Request::Shutdown
+		) {
+			return Err(anyhow!("Cannot dry run this request"));
+		}
+
+		let dry_run = DryRunRunner::new(runner);
+		let mut sink = ResponseWriter::new(Box::new(std::io::sink()));
+		self.execute(*request, &mut sink, &dry_run).await?;
+		Ok(Some(dry_run.planned().join("\n")))
+	}
+
+	async fn execute(&self, request: Request, writer: &mut ResponseWriter, runner: &dyn CommandRunner) -> Result<Option<String>> {
+		if let Some(store) = &self.backups
+			&& changes_partition_table(&request)
+		{
+			store
+				.backup(runner, &request.disk(), writer)
+				.await
+				.context("Failed to back up partition table")?;
+		}
+
+		match request {
+			Request::RestoreTable { disk, backup } => {
+				crate::helper::validate_device_name(&disk)?;
+				self.backups()?.restore(runner, &disk, &backup, writer).await?;
+				Ok(None)
+			}
+			request => handle_request(request, writer, runner).await,
+		}
+	}
+
+	fn backups(&self) -> Result<&BackupStore> {
+		self.backups.as_ref().ok_or_else(|| anyhow!("Partition table backups are disabled"))
+	}
+}
+
+fn changes_partition_table(request: &Request) -> bool {
+	matches!(
+		request,
+		Request::CreatePartitionTable { .. }
+			| Request::FormatWholeDisk { .. }
+			| Request::DeletePartition { .. }
+			| Request::ResizePartition { .. }
+			| Request::ModifyPartition { .. }
+			| Request::RestoreTable { .. }
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::audit::AuditEntry;
+	use crate::runner::{FakeResponse, FakeRunner};
+	use std::path::PathBuf;
+
+	fn temp_path(name: &str) -> PathBuf {
+		let path = std::env::temp_dir().join(format!("disktui-{}-{}", name, std::process::id()));
+		let _ = std::fs::remove_dir_all(&path);
+		let _ = std::fs::remove_file(&path);
+		path
+	}
+
+	#[tokio::test]
+	async fn audited_requests_are_logged_without_passphrases() {
+		let path = temp_path("audit.jsonl");
+		let session = HelperSession {
+			audit_log: Some(AuditLog::new(&path)),
+			backups: None,
+		};
+		let runner = FakeRunner::new();
+		runner.on("cryptsetup open", FakeResponse::fail(2, "No key available with this passphrase."));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let unlock = Request::UnlockLuks {
+			device: "sdb1".into(),
+			passphrase: "hunter2".into(),
+			mapper_name: "luks-sdb1".into(),
+		};
+		assert!(session.handle(unlock, &mut writer, &runner).await.is_err());
+		let delete = Request::DeletePartition { partition: "sdc1".into() };
+		session.handle(delete, &mut writer, &runner).await.unwrap();
+
+		let contents = std::fs::read_to_string(&path).unwrap();
+		assert!(!contents.contains("hunter2"));
+
+		let log = session.audit_log.as_ref().unwrap();
+		let entries = log.read(Some("sdb"), 10).unwrap();
+		assert_eq!(entries.len(), 1);
+		assert!(!entries[0].success);
+		assert_eq!(entries[0].error.as_deref(), Some("Failed to unlock: No key available with this passphrase."));
+		assert_eq!(entries[0].commands[0].command, "cryptsetup open /dev/sdb1 luks-sdb1");
+		assert_eq!(entries[0].commands[0].exit_code, Some(2));
+
+		let query = Request::AuditLog { disk: None, limit: 1 };
+		let data = session.handle(query, &mut writer, &runner).await.unwrap().unwrap();
+		let latest: Vec<AuditEntry> = serde_json::from_str(&data).unwrap();
+		assert_eq!(latest.len(), 1);
+		assert_eq!(latest[0].request.description(), "Delete sdc1");
+		assert_eq!(log.read(None, 10).unwrap().len(), 2);
+
+		let _ = std::fs::remove_file(&path);
+	}
+
+	#[tokio::test]
+	async fn table_is_backed_up_before_delete_but_not_in_dry_run() {
+		let dir = temp_path("session-backups");
+		let session = HelperSession {
+			audit_log: None,
+			backups: Some(BackupStore::new(&dir)),
+		};
+		let runner = FakeRunner::new();
+		runner.on("sfdisk -d /dev/sdb", FakeResponse::ok("label: gpt\n/dev/sdb1 : start=2048, size=1000\n"));
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+
+		let delete = Request::DeletePartition { partition: "sdb1".into() };
+		let dry_run = Request::DryRun { request: Box::new(delete.clone()) };
+		session.handle(dry_run, &mut writer, &runner).await.unwrap();
+		assert!(!dir.exists());
+
+		session.handle(delete, &mut writer, &runner).await.unwrap();
+		let dump = runner.position("sfdisk -d /dev/sdb").unwrap();
+		let rm = runner.position("parted -s /dev/sdb rm 1").unwrap();
+		assert!(dump < rm);
+		assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+		let _ = std::fs::remove_dir_all(&dir);
+	}
+
+	#[tokio::test]
+	async fn dry_run_reports_commands_without_running_them() {
+		let runner = FakeRunner::new();
+		runner
+			.on("parted -s -m /dev/sdb unit B print", FakeResponse::ok("BYT;\n1:1048576B:1000000000B:998951425B:ext4::;\n"))
+			.on("lsblk -b -d -n -o SIZE,LOG-SEC,PHY-SEC,OPT-IO /dev/sdb", FakeResponse::ok("8000000000 512 4096 0\n"))
+			.on("lsblk -J -o NAME /dev/sdb", FakeResponse::ok(r#"{"blockdevices":[{"name":"sdb","children":[{"name":"sdb1"}]}]}"#));
+
+		let request = Request::DryRun {
+			request: Box::new(Request::CreatePartition {
+				disk: "sdb".into(),
+				start: None,
+				size: "1GB".into(),
+				fs_type: Some("ext4".into()),
+				alignment: crate::layout::Alignment::Mib,
+				part_type: None,
+				name: None,
+			}),
+		};
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+		let data = HelperSession::default().handle(request, &mut writer, &runner).await.unwrap();
+
+		assert_eq!(data.as_deref(), Some("parted -s /dev/sdb mkpart primary 1953792s 3906916s\nmkfs.ext4 /dev/sdb2"));
+		assert!(runner.command_lines().iter().all(|c| !c.starts_with("parted -s /dev/sdb mkpart") && !c.starts_with("mkfs")));
+	}
+
+	#[tokio::test]
+	async fn queries_and_nested_dry_runs_are_not_dry_run() {
+		let runner = FakeRunner::new();
+		let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+		for request in [Request::ListLvm, Request::ListBackups { disk: "sdb".into() }, Request::DryRun { request: Box::new(Request::Shutdown) }] {
+			let dry_run = Request::DryRun { request: Box::new(request) };
+			let err = HelperSession::default().handle(dry_run, &mut writer, &runner).await.unwrap_err();
+			assert_eq!(err.to_string(), "Cannot dry run this request");
+		}
+		assert!(runner.command_lines().is_empty());
+	}
+}
@@ -0,0 +1,423 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+
+/// ATA attributes whose raw value counts damaged sectors or failed commands.
+/// Any count above zero is worth a look even while the normalized value is
+/// still far from its threshold.
+const WATCHED_ATTRIBUTES: &[u8] = &[5, 10, 184, 187, 188, 196, 197, 198];
+
+/// Bytes in one NVMe "data unit" of the health log.
+const NVME_DATA_UNIT: u64 = 512_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    Ok,
+    Warning,
+    Failing,
+}
+
+/// One row of the ATA attribute table.
+#[derive(Debug, Clone, Serialize)]
+pub struct Attribute {
+    pub id: u8,
+    pub name: String,
+    pub value: u16,
+    pub worst: u16,
+    pub thresh: u16,
+    pub raw: String,
+    pub raw_value: u64,
+    pub prefailure: bool,
+    /// "now", "past" or empty, as smartctl reports it.
+    pub when_failed: String,
+}
+
+impl Attribute {
+    pub fn level(&self) -> Level {
+        if self.when_failed == "now" || (self.thresh > 0 && self.value <= self.thresh) {
+            Level::Failing
+        } else if self.when_failed == "past"
+            || (WATCHED_ATTRIBUTES.contains(&self.id) && self.raw_value > 0)
+        {
+            Level::Warning
+        } else {
+            Level::Ok
+        }
+    }
+}
+
+/// The SMART / health information log of an NVMe device.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct NvmeHealth {
+    pub critical_warning: u64,
+    pub available_spare: u64,
+    pub available_spare_threshold: u64,
+    pub percentage_used: u64,
+    pub data_units_read: u64,
+    pub data_units_written: u64,
+    pub unsafe_shutdowns: u64,
+    pub media_errors: u64,
+    pub error_log_entries: u64,
+}
+
+/// A named value of the NVMe health log, ready to be shown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reading {
+    pub name: &'static str,
+    pub value: String,
+    pub level: Level,
+}
+
+impl NvmeHealth {
+    pub fn readings(&self) -> Vec<Reading> {
+        let reading = |name, value: String, level| Reading { name, value, level };
+        let spare_level = if self.available_spare <= self.available_spare_threshold {
+            Level::Failing
+        } else {
+            Level::Ok
+        };
+        let used_level = match self.percentage_used {
+            100.. => Level::Failing,
+            90..=99 => Level::Warning,
+            _ => Level::Ok,
+        };
+        vec![
+            reading(
+                "Critical Warning",
+                format!("0x{:02x}", self.critical_warning),
+                if self.critical_warning == 0 {
+                    Level::Ok
+                } else {
+                    Level::Failing
+                },
+            ),
+            reading(
+                "Available Spare",
+                format!(
+                    "{}% (threshold {}%)",
+                    self.available_spare, self.available_spare_threshold
+                ),
+                spare_level,
+            ),
+            reading(
+                "Percentage Used",
+                format!("{}%", self.percentage_used),
+                used_level,
+            ),
+            reading(
+                "Data Read",
+                crate::utils::format_bytes(self.data_units_read * NVME_DATA_UNIT),
+                Level::Ok,
+            ),
+            reading(
+                "Data Written",
+                crate::utils::format_bytes(self.data_units_written * NVME_DATA_UNIT),
+                Level::Ok,
+            ),
+            reading(
+                "Unsafe Shutdowns",
+                self.unsafe_shutdowns.to_string(),
+                Level::Ok,
+            ),
+            reading(
+                "Media Errors",
+                self.media_errors.to_string(),
+                if self.media_errors == 0 {
+                    Level::Ok
+                } else {
+                    Level::Warning
+                },
+            ),
+            reading(
+                "Error Log Entries",
+                self.error_log_entries.to_string(),
+                Level::Ok,
+            ),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfTestKind {
+    Short,
+    /// "Extended" in ATA and NVMe terms.
+    Long,
+}
+
+impl SelfTestKind {
+    /// The value for `smartctl -t`.
+    pub fn arg(self) -> &'static str {
+        match self {
+            SelfTestKind::Short => "short",
+            SelfTestKind::Long => "long",
+        }
+    }
+}
+
+impl fmt::Display for SelfTestKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.arg())
+    }
+}
+
+/// The self-test the device is running, if any.
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestProgress {
+    pub description: String,
+    pub remaining_percent: Option<u8>,
+}
+
+/// One entry of the self-test log, newest first.
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestEntry {
+    pub kind: String,
+    pub status: String,
+    pub passed: bool,
+    pub power_on_hours: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SmartData {
+    /// The overall assessment; `None` when the device did not report one.
+    pub passed: Option<bool>,
+    pub temperature: Option<i32>,
+    pub power_on_hours: Option<u64>,
+    pub attributes: Vec<Attribute>,
+    pub nvme: Option<NvmeHealth>,
+    pub self_test: Option<SelfTestProgress>,
+    pub self_test_log: Vec<SelfTestEntry>,
+    /// Errors in the ATA error log or the NVMe error information log.
+    pub error_count: Option<u64>,
+    /// Minutes the device expects a short and a long self-test to take.
+    pub short_test_minutes: Option<u64>,
+    pub long_test_minutes: Option<u64>,
+}
+
+impl SmartData {
+    pub fn health(&self) -> &'static str {
+        match self.passed {
+            Some(true) => "PASSED",
+            Some(false) => "FAILED",
+            None => "N/A",
+        }
+    }
+
+    /// The worst level of the overall assessment and every attribute or
+    /// health log value.
+    pub fn level(&self) -> Level {
+        let overall = match self.passed {
+            Some(false) => Level::Failing,
+            _ => Level::Ok,
+        };
+        let attributes = self.attributes.iter().map(Attribute::level);
+        let nvme = self
+            .nvme
+            .iter()
+            .flat_map(|health| health.readings())
+            .map(|reading| reading.level);
+        attributes.chain(nvme).fold(overall, Level::max)
+    }
+
+    pub fn test_in_progress(&self) -> bool {
+        self.self_test.is_some()
+    }
+
+    pub fn attribute(&self, id: u8) -> Option<&Attribute> {
+        self.attributes.iter().find(|attribute| attribute.id == id)
+    }
+}
+
+fn text(value: &Value) -> String {
+    value.as_str().unwrap_or_default().to_string()
+}
+
+fn number(value: &Value) -> u64 {
+    value.as_u64().unwrap_or(0)
+}
+
+/// Parses the output of `smartctl --json -a`.
+pub fn parse(json: &str) -> Result<SmartData> {
+    let root: Value =
+        serde_json::from_str(json).map_err(|e| anyhow!("Unexpected smartctl output: {}", e))?;
+
+    // Bits 0 and 1 of the exit status mean smartctl could not read the
+    // device at all; the higher bits describe the disk.
+    let exit_status = number(&root["smartctl"]["exit_status"]);
+    if exit_status & 0b11 != 0 {
+        let message = root["smartctl"]["messages"]
+            .as_array()
+            .and_then(|messages| messages.first())
+            .map(|message| text(&message["string"]))
+            .unwrap_or_else(|| format!("smartctl exited with {}", exit_status));
+        return Err(anyhow!(message));
+    }
+
+    let attributes = root["ata_smart_attributes"]["table"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|row| Attribute {
+            id: number(&row["id"]) as u8,
+            name: text(&row["name"]),
+            value: number(&row["value"]) as u16,
+            worst: number(&row["worst"]) as u16,
+            thresh: number(&row["thresh"]) as u16,
+            raw: text(&row["raw"]["string"]),
+            raw_value: number(&row["raw"]["value"]),
+            prefailure: row["flags"]["prefailure"].as_bool().unwrap_or(false),
+            when_failed: text(&row["when_failed"]),
+        })
+        .collect();
+
+    let log = &root["nvme_smart_health_information_log"];
+    let nvme = log.is_object().then(|| NvmeHealth {
+        critical_warning: number(&log["critical_warning"]),
+        available_spare: number(&log["available_spare"]),
+        available_spare_threshold: number(&log["available_spare_threshold"]),
+        percentage_used: number(&log["percentage_used"]),
+        data_units_read: number(&log["data_units_read"]),
+        data_units_written: number(&log["data_units_written"]),
+        unsafe_shutdowns: number(&log["unsafe_shutdowns"]),
+        media_errors: number(&log["media_errors"]),
+        error_log_entries: number(&log["num_err_log_entries"]),
+    });
+
+    let ata_test = &root["ata_smart_data"]["self_test"];
+    let nvme_log = &root["nvme_self_test_log"];
+    // ATA reports a running test with 0xF in the upper nibble of the status.
+    let self_test = if number(&ata_test["status"]["value"]) >> 4 == 0xf {
+        Some(SelfTestProgress {
+            description: text(&ata_test["status"]["string"]),
+            remaining_percent: ata_test["status"]["remaining_percent"]
+                .as_u64()
+                .map(|percent| percent as u8),
+        })
+    } else if number(&nvme_log["current_self_test_operation"]["value"]) != 0 {
+        Some(SelfTestProgress {
+            description: text(&nvme_log["current_self_test_operation"]["string"]),
+            remaining_percent: nvme_log["current_self_test_completion_percent"]
+                .as_u64()
+                .map(|done| 100u64.saturating_sub(done) as u8),
+        })
+    } else {
+        None
+    };
+
+    let ata_log = root["ata_smart_self_test_log"]["standard"]["table"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|row| SelfTestEntry {
+            kind: text(&row["type"]["string"]),
+            status: text(&row["status"]["string"]),
+            passed: row["status"]["passed"].as_bool().unwrap_or(false),
+            power_on_hours: row["lifetime_hours"].as_u64(),
+        });
+    let nvme_entries = nvme_log["table"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|row| SelfTestEntry {
+            kind: text(&row["self_test_code"]["string"]),
+            status: text(&row["self_test_result"]["string"]),
+            passed: row["self_test_result"]["value"].as_u64() == Some(0),
+            power_on_hours: row["power_on_hours"].as_u64(),
+        });
+
+    Ok(SmartData {
+        passed: root["smart_status"]["passed"].as_bool(),
+        temperature: root["temperature"]["current"]
+            .as_i64()
+            .map(|temperature| temperature as i32),
+        power_on_hours: root["power_on_time"]["hours"].as_u64(),
+        attributes,
+        nvme,
+        self_test,
+        self_test_log: ata_log.chain(nvme_entries).collect(),
+        error_count: root["ata_smart_error_log"]["summary"]["count"]
+            .as_u64()
+            .or(log["num_err_log_entries"].as_u64()),
+        short_test_minutes: ata_test["polling_minutes"]["short"].as_u64(),
+        long_test_minutes: ata_test["polling_minutes"]["extended"].as_u64(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ata_attributes_and_running_tests_are_parsed() {
+        let json = r#"{
+            "smartctl": {"exit_status": 64},
+            "smart_status": {"passed": true},
+            "temperature": {"current": 34},
+            "power_on_time": {"hours": 18231},
+            "ata_smart_data": {"self_test": {
+                "status": {"value": 249, "string": "in progress, 90% of test remaining", "remaining_percent": 90},
+                "polling_minutes": {"short": 2, "extended": 184}
+            }},
+            "ata_smart_attributes": {"table": [
+                {"id": 5, "name": "Reallocated_Sector_Ct", "value": 100, "worst": 100, "thresh": 10,
+                 "when_failed": "", "flags": {"prefailure": true}, "raw": {"value": 8, "string": "8"}},
+                {"id": 1, "name": "Raw_Read_Error_Rate", "value": 5, "worst": 5, "thresh": 6,
+                 "when_failed": "now", "flags": {"prefailure": true}, "raw": {"value": 0, "string": "0"}},
+                {"id": 194, "name": "Temperature_Celsius", "value": 66, "worst": 50, "thresh": 0,
+                 "when_failed": "", "flags": {"prefailure": false}, "raw": {"value": 34, "string": "34 (Min/Max 18/50)"}}
+            ]},
+            "ata_smart_self_test_log": {"standard": {"table": [
+                {"type": {"string": "Short offline"}, "status": {"string": "Completed without error", "passed": true}, "lifetime_hours": 18200}
+            ]}}
+        }"#;
+        let data = parse(json).unwrap();
+
+        assert_eq!(data.health(), "PASSED");
+        assert_eq!(data.temperature, Some(34));
+        assert_eq!(data.attribute(5).unwrap().level(), Level::Warning);
+        assert_eq!(data.attribute(1).unwrap().level(), Level::Failing);
+        assert_eq!(data.attribute(194).unwrap().level(), Level::Ok);
+        assert_eq!(data.level(), Level::Failing);
+        assert_eq!(data.self_test.unwrap().remaining_percent, Some(90));
+        assert_eq!(data.long_test_minutes, Some(184));
+        assert!(data.self_test_log[0].passed);
+    }
+
+    #[test]
+    fn nvme_health_log_is_parsed() {
+        let json = r#"{
+            "smartctl": {"exit_status": 0},
+            "smart_status": {"passed": true},
+            "nvme_smart_health_information_log": {
+                "critical_warning": 0, "temperature": 41, "available_spare": 100,
+                "available_spare_threshold": 10, "percentage_used": 93,
+                "data_units_read": 2000, "data_units_written": 1000,
+                "unsafe_shutdowns": 12, "media_errors": 0, "num_err_log_entries": 3
+            },
+            "nvme_self_test_log": {
+                "current_self_test_operation": {"value": 2, "string": "Extended self-test in progress"},
+                "current_self_test_completion_percent": 40,
+                "table": [{"self_test_code": {"string": "Short"}, "self_test_result": {"value": 7, "string": "Completed: failed segments"}, "power_on_hours": 900}]
+            }
+        }"#;
+        let data = parse(json).unwrap();
+
+        let readings = data.nvme.as_ref().unwrap().readings();
+        let used = readings
+            .iter()
+            .find(|r| r.name == "Percentage Used")
+            .unwrap();
+        assert_eq!((used.value.as_str(), used.level), ("93%", Level::Warning));
+        assert_eq!(data.level(), Level::Warning);
+        assert_eq!(data.self_test.unwrap().remaining_percent, Some(60));
+        assert!(!data.self_test_log[0].passed);
+    }
+
+    #[test]
+    fn unreadable_devices_are_errors() {
+        let json = r#"{"smartctl": {"exit_status": 2, "messages": [{"string": "Permission denied", "severity": "error"}]}}"#;
+        assert_eq!(parse(json).unwrap_err().to_string(), "Permission denied");
+    }
+}
@@ -0,0 +1,132 @@
+use crate::operations::BlockDevice;
+use crate::sysfs::{unescape_octal, unescape_udev};
+use serde::{Deserialize, Serialize};
+
+/// An active swap area, as listed in /proc/swaps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SwapArea {
+    /// Device node or file path.
+    pub path: String,
+    pub is_file: bool,
+    pub size: u64,
+    pub used: u64,
+    pub priority: i32,
+}
+
+/// Parses /proc/swaps. Sizes there are in KiB, and paths have spaces and
+/// other special characters escaped as octal like in mountinfo.
+pub fn parse_proc_swaps(contents: &str) -> Vec<SwapArea> {
+    contents
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [path, kind, size, used, priority] = fields[..] else {
+                return None;
+            };
+            Some(SwapArea {
+                path: unescape_octal(path),
+                is_file: kind == "file",
+                size: size.parse::<u64>().ok()? * 1024,
+                used: used.parse::<u64>().ok()? * 1024,
+                priority: priority.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Parses `swapon --show=NAME,TYPE,SIZE,USED,PRIO --bytes --noheadings
+/// --raw`, which escapes special characters in paths as `\xNN`.
+pub fn parse_swapon(output: &str) -> Vec<SwapArea> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [path, kind, size, used, priority] = fields[..] else {
+                return None;
+            };
+            Some(SwapArea {
+                path: unescape_udev(path),
+                is_file: kind == "file",
+                size: size.parse().ok()?,
+                used: used.parse().ok()?,
+                priority: priority.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Attaches active swap areas to the partitions they live on. Swap
+/// partitions are matched by their device or mapper path, swap files by the
+/// deepest mount point that contains them.
+pub fn attach_swap_areas(devices: &mut [BlockDevice], swaps: &[SwapArea]) {
+    for swap in swaps {
+        let mut partitions = devices.iter_mut().flat_map(|d| d.partitions.iter_mut());
+        if swap.is_file {
+            let owner = partitions
+                .filter(|p| {
+                    p.mount_point
+                        .as_deref()
+                        .is_some_and(|mount| is_under(&swap.path, mount))
+                })
+                .max_by_key(|p| p.mount_point.as_ref().map(|m| m.len()));
+            if let Some(part) = owner {
+                part.swap_files.push(swap.clone());
+            }
+        } else {
+            let owner = partitions.find(|p| {
+                swap.path == format!("/dev/{}", p.name)
+                    || p.mapper_device
+                        .as_ref()
+                        .is_some_and(|m| swap.path == format!("/dev/mapper/{}", m))
+            });
+            if let Some(part) = owner {
+                part.used_bytes = Some(swap.used);
+                part.available_bytes = Some(swap.size.saturating_sub(swap.used));
+                part.swap = Some(swap.clone());
+            }
+        }
+    }
+}
+
+fn is_under(path: &str, mount: &str) -> bool {
+    mount == "/"
+        || path
+            .strip_prefix(mount)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_proc_swaps_and_swapon_output() {
+        let proc_swaps = "Filename\t\t\t\tType\t\tSize\t\tUsed\t\tPriority\n\
+                          /dev/sdb2                               partition\t2097148\t\t1024\t\t-2\n\
+                          /mnt/my\\040data/swapfile                file\t\t1048572\t\t0\t\t10\n";
+        let swaps = parse_proc_swaps(proc_swaps);
+        assert_eq!(swaps.len(), 2);
+        assert_eq!(swaps[0].path, "/dev/sdb2");
+        assert_eq!(swaps[0].size, 2_097_148 * 1024);
+        assert_eq!(swaps[0].used, 1024 * 1024);
+        assert_eq!(swaps[0].priority, -2);
+        assert!(!swaps[0].is_file);
+        assert_eq!(swaps[1].path, "/mnt/my data/swapfile");
+        assert!(swaps[1].is_file);
+
+        let swapon =
+            "/dev/dm-1 partition 4294963200 0 -2\n/mnt/my\\x20data/swapfile file 1073737728 0 10\n";
+        let swaps = parse_swapon(swapon);
+        assert_eq!(swaps[0].size, 4_294_963_200);
+        assert_eq!(swaps[1].path, "/mnt/my data/swapfile");
+        assert_eq!(swaps[1].priority, 10);
+    }
+
+    #[test]
+    fn swap_files_belong_to_the_deepest_mount() {
+        assert!(is_under("/home/swapfile", "/home"));
+        assert!(!is_under("/homework/swapfile", "/home"));
+        assert!(is_under("/swapfile", "/"));
+    }
+}
@@ -0,0 +1,553 @@
+use crate::holder::{Holder, attach_btrfs_holders};
+use crate::layout::DiskGeometry;
+use crate::operations::BlockDevice;
+use crate::partition::{Partition, PartitionGeometry};
+use crate::parttype::PartitionFlag;
+use crate::swap::{SwapArea, attach_swap_areas, parse_proc_swaps};
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// LUKS1 and LUKS2 headers both start with this magic, followed by a
+/// big-endian version number.
+const LUKS_MAGIC: &[u8] = b"LUKS\xba\xbe";
+
+/// Offset and length of the UUID field, the same in both header versions.
+const LUKS_UUID_OFFSET: usize = 168;
+const LUKS_UUID_LEN: usize = 40;
+
+/// Reads the block device layout straight from sysfs, the udev database and
+/// mountinfo. A refresh then costs a few file reads instead of one process
+/// per probe.
+#[derive(Debug, Clone)]
+pub struct SysfsReader {
+    sys_block: PathBuf,
+    udev_data: PathBuf,
+    mountinfo: PathBuf,
+    swaps: PathBuf,
+    dev: PathBuf,
+}
+
+impl Default for SysfsReader {
+    fn default() -> Self {
+        Self::with_root(Path::new("/"))
+    }
+}
+
+/// One line of mountinfo: which device is mounted where.
+#[derive(Debug, Clone, PartialEq)]
+struct Mount {
+    devno: String,
+    source: String,
+    target: String,
+}
+
+/// What the LUKS header says about a device.
+#[derive(Debug, Clone, PartialEq)]
+struct LuksHeader {
+    version: String,
+    uuid: String,
+}
+
+impl SysfsReader {
+    /// Reader for a system tree under `root`, so tests can use a fake one.
+    pub fn with_root(root: &Path) -> Self {
+        Self {
+            sys_block: root.join("sys/block"),
+            udev_data: root.join("run/udev/data"),
+            mountinfo: root.join("proc/self/mountinfo"),
+            swaps: root.join("proc/swaps"),
+            dev: root.join("dev"),
+        }
+    }
+
+    /// Disks and their partitions, like `operations::list_block_devices`.
+    /// Fails when sysfs or the udev database are missing (containers, some
+    /// minimal systems), since filesystem types and labels come from udev.
+    pub fn list_block_devices(&self) -> io::Result<Vec<BlockDevice>> {
+        if !self.udev_data.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "udev database not available",
+            ));
+        }
+        let mounts = parse_mountinfo(&fs::read_to_string(&self.mountinfo)?);
+
+        let mut names = dir_names(&self.sys_block)?;
+        names.sort();
+
+        let mut devices = Vec::new();
+        for name in names {
+            let dir = self.sys_block.join(&name);
+            if is_disk(&dir) {
+                devices.push(self.read_disk(name, &dir, &mounts));
+            }
+        }
+
+        let swaps = self.active_swaps();
+        attach_swap_areas(&mut devices, &swaps);
+        attach_btrfs_holders(&mut devices);
+        Ok(devices)
+    }
+
+    /// Active swap areas, with device mapper nodes given by their
+    /// /dev/mapper name like the partitions refer to them.
+    fn active_swaps(&self) -> Vec<SwapArea> {
+        let mut swaps = parse_proc_swaps(&fs::read_to_string(&self.swaps).unwrap_or_default());
+        for swap in &mut swaps {
+            if let Some(dm) = swap.path.strip_prefix("/dev/")
+                && dm.starts_with("dm-")
+                && let Some(name) = read_trimmed(&self.sys_block.join(dm).join("dm/name"))
+            {
+                swap.path = format!("/dev/mapper/{}", name);
+            }
+        }
+        swaps
+    }
+
+    fn read_disk(&self, name: String, dir: &Path, mounts: &[Mount]) -> BlockDevice {
+        let udev = self.udev_properties(dir);
+        let size = read_sectors(dir);
+        let model = read_trimmed(&dir.join("device/model"))
+            .or_else(|| udev.get("ID_MODEL").map(|model| model.replace('_', " ")));
+        let serial = udev
+            .get("ID_SERIAL_SHORT")
+            .cloned()
+            .or_else(|| read_trimmed(&dir.join("device/serial")));
+        let partition_table = udev.get("ID_PART_TABLE_TYPE").cloned();
+        let defaults = DiskGeometry::default();
+        let geometry = DiskGeometry {
+            logical_sector_size: read_number(&dir.join("queue/logical_block_size"))
+                .unwrap_or(defaults.logical_sector_size),
+            physical_sector_size: read_number(&dir.join("queue/physical_block_size"))
+                .unwrap_or(defaults.physical_sector_size),
+            optimal_io_size: read_number(&dir.join("queue/optimal_io_size")).unwrap_or(0),
+        };
+
+        let mut parts: Vec<(u32, String)> = dir_names(dir)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|entry| {
+                let number = read_trimmed(&dir.join(&entry).join("partition"))?
+                    .parse()
+                    .ok()?;
+                Some((number, entry))
+            })
+            .collect();
+        parts.sort();
+
+        let mut partitions: Vec<Partition> = parts
+            .into_iter()
+            .map(|(_, part)| self.read_partition(part.clone(), &dir.join(part), &geometry, mounts))
+            .collect();
+
+        // A filesystem directly on the disk is shown as its only partition.
+        if partitions.is_empty() {
+            let mut whole = self.read_partition(name.clone(), dir, &geometry, mounts);
+            whole.geometry = None;
+            if whole.filesystem.is_some() || whole.mount_point.is_some() {
+                partitions.push(whole);
+            }
+        }
+
+        BlockDevice {
+            name,
+            size,
+            model,
+            serial,
+            partition_table,
+            geometry,
+            partitions,
+        }
+    }
+
+    fn read_partition(
+        &self,
+        name: String,
+        dir: &Path,
+        disk: &DiskGeometry,
+        mounts: &[Mount],
+    ) -> Partition {
+        let udev = self.udev_properties(dir);
+        let filesystem = udev.get("ID_FS_TYPE").filter(|fs| !fs.is_empty()).cloned();
+        let label = udev
+            .get("ID_FS_LABEL_ENC")
+            .map(|label| unescape_udev(label))
+            .or_else(|| udev.get("ID_FS_LABEL").cloned())
+            .filter(|label| !label.is_empty());
+
+        // The header is only read when udev did not identify the device as
+        // something else, so idle disks are not woken up. Reading it needs
+        // access to the device node; without that udev's result is used.
+        let probe_header = matches!(filesystem.as_deref(), None | Some("crypto_LUKS"));
+        let luks = probe_header
+            .then(|| self.read_luks_header(&name))
+            .flatten()
+            .or_else(|| {
+                (filesystem.as_deref() == Some("crypto_LUKS")).then(|| LuksHeader {
+                    version: format!(
+                        "LUKS{}",
+                        udev.get("ID_FS_VERSION").map(|v| v.as_str()).unwrap_or("2")
+                    ),
+                    uuid: udev.get("ID_FS_UUID").cloned().unwrap_or_default(),
+                })
+            });
+        let mapper = luks.as_ref().and_then(|_| self.crypt_holder(dir));
+
+        let own_mount = find_mount(
+            mounts,
+            read_trimmed(&dir.join("dev")),
+            &format!("/dev/{}", name),
+        );
+        let mount_point = match &mapper {
+            Some((mapper_name, mapper_dir)) => find_mount(
+                mounts,
+                read_trimmed(&mapper_dir.join("dev")),
+                &format!("/dev/mapper/{}", mapper_name),
+            )
+            .or(own_mount),
+            None => own_mount,
+        };
+        let usage = mount_point.as_deref().and_then(filesystem_usage);
+
+        let size = read_sectors(dir);
+        // Like the size, the start is in 512-byte units.
+        let geometry = read_number(&dir.join("start")).map(|start| PartitionGeometry {
+            start: start * 512 / disk.logical_sector_size,
+            sectors: size / disk.logical_sector_size,
+            logical_sector_size: disk.logical_sector_size,
+            physical_sector_size: disk.physical_sector_size,
+        });
+
+        let part_type = udev.get("ID_PART_ENTRY_TYPE").cloned();
+        let attributes = udev
+            .get("ID_PART_ENTRY_FLAGS")
+            .and_then(|flags| u64::from_str_radix(flags.trim_start_matches("0x"), 16).ok())
+            .unwrap_or(0);
+        let flags = PartitionFlag::from_entry(part_type.as_deref(), attributes);
+        let holder = Holder::detect(filesystem.as_deref(), &self.stacked(dir));
+
+        Partition {
+            name,
+            geometry,
+            size,
+            filesystem,
+            mount_point: mount_point.clone(),
+            is_mounted: mount_point.is_some(),
+            label,
+            uuid: udev.get("ID_FS_UUID").filter(|uuid| !uuid.is_empty()).cloned(),
+            used_bytes: usage.map(|(used, _)| used),
+            available_bytes: usage.map(|(_, available)| available),
+            is_encrypted: luks.is_some(),
+            encryption_type: luks.as_ref().map(|luks| luks.version.clone()),
+            luks_uuid: luks.map(|luks| luks.uuid),
+            mapper_device: mapper.map(|(mapper_name, _)| mapper_name),
+            part_type,
+            part_uuid: udev.get("ID_PART_ENTRY_UUID").cloned(),
+            part_label: udev
+                .get("ID_PART_ENTRY_NAME")
+                .map(|name| unescape_udev(name))
+                .filter(|name| !name.is_empty()),
+            flags,
+            swap: None,
+            swap_files: Vec::new(),
+            holder,
+        }
+    }
+
+    /// Properties udev recorded for the device in `dir`, from the
+    /// `E:KEY=value` lines of its database entry.
+    fn udev_properties(&self, dir: &Path) -> HashMap<String, String> {
+        let Some(devno) = read_trimmed(&dir.join("dev")) else {
+            return HashMap::new();
+        };
+        let Ok(contents) = fs::read_to_string(self.udev_data.join(format!("b{}", devno))) else {
+            return HashMap::new();
+        };
+        contents
+            .lines()
+            .filter_map(|line| line.strip_prefix("E:")?.split_once('='))
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn read_luks_header(&self, name: &str) -> Option<LuksHeader> {
+        let mut header = [0u8; LUKS_UUID_OFFSET + LUKS_UUID_LEN];
+        fs::File::open(self.dev.join(name))
+            .ok()?
+            .read_exact(&mut header)
+            .ok()?;
+        parse_luks_header(&header)
+    }
+
+    /// Names of the devices stacked on the device in `dir`: md arrays by
+    /// their kernel name, device-mapper nodes by their mapper name.
+    fn stacked(&self, dir: &Path) -> Vec<String> {
+        dir_names(&dir.join("holders"))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|holder| {
+                read_trimmed(&self.sys_block.join(&holder).join("dm/name")).unwrap_or(holder)
+            })
+            .collect()
+    }
+
+    /// The dm-crypt mapping holding the device in `dir`, if it is unlocked:
+    /// its mapper name and sysfs directory.
+    fn crypt_holder(&self, dir: &Path) -> Option<(String, PathBuf)> {
+        dir_names(&dir.join("holders"))
+            .ok()?
+            .into_iter()
+            .map(|holder| self.sys_block.join(holder))
+            .find(|holder| {
+                read_trimmed(&holder.join("dm/uuid")).is_some_and(|uuid| uuid.starts_with("CRYPT-"))
+            })
+            .and_then(|holder| Some((read_trimmed(&holder.join("dm/name"))?, holder)))
+    }
+}
+
+/// Whether a /sys/block entry is what lsblk reports as a disk, rather than
+/// a device-mapper, RAID, loop or optical device.
+fn is_disk(dir: &Path) -> bool {
+    let virtual_device = ["dm", "md", "loop"]
+        .iter()
+        .any(|sub| dir.join(sub).exists());
+    let optical = read_trimmed(&dir.join("device/type")).as_deref() == Some("5");
+    !virtual_device && !optical
+}
+
+fn dir_names(path: &Path) -> io::Result<Vec<String>> {
+    Ok(fs::read_dir(path)?
+        .flatten()
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect())
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    let value = fs::read_to_string(path).ok()?.trim().to_string();
+    (!value.is_empty()).then_some(value)
+}
+
+fn read_number(path: &Path) -> Option<u64> {
+    read_trimmed(path)?.parse().ok()
+}
+
+/// Size in bytes; sysfs counts 512-byte sectors regardless of the device's
+/// logical block size.
+fn read_sectors(dir: &Path) -> u64 {
+    read_number(&dir.join("size")).unwrap_or(0) * 512
+}
+
+fn parse_luks_header(header: &[u8]) -> Option<LuksHeader> {
+    if !header.starts_with(LUKS_MAGIC) || header.len() < LUKS_UUID_OFFSET + LUKS_UUID_LEN {
+        return None;
+    }
+    let version = u16::from_be_bytes([header[6], header[7]]);
+    let uuid = &header[LUKS_UUID_OFFSET..LUKS_UUID_OFFSET + LUKS_UUID_LEN];
+    let uuid = String::from_utf8_lossy(uuid)
+        .trim_end_matches('\0')
+        .to_string();
+    Some(LuksHeader {
+        version: format!("LUKS{}", version),
+        uuid,
+    })
+}
+
+/// Mount entries from mountinfo, in mount order. Mount points have spaces
+/// and other special characters escaped as octal.
+fn parse_mountinfo(contents: &str) -> Vec<Mount> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let (fields, rest) = line.split_once(" - ")?;
+            let fields: Vec<&str> = fields.split(' ').collect();
+            let source = rest.split(' ').nth(1)?;
+            Some(Mount {
+                devno: fields.get(2)?.to_string(),
+                source: unescape_octal(source),
+                target: unescape_octal(fields.get(4)?),
+            })
+        })
+        .collect()
+}
+
+/// The first mount of a device, matched by device number or by the source
+/// path (btrfs mounts report an anonymous device number).
+fn find_mount(mounts: &[Mount], devno: Option<String>, source: &str) -> Option<String> {
+    mounts
+        .iter()
+        .find(|mount| devno.as_deref() == Some(mount.devno.as_str()) || mount.source == source)
+        .map(|mount| mount.target.clone())
+}
+
+pub(crate) fn unescape_octal(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let raw = value.as_bytes();
+    let mut i = 0;
+    while i < raw.len() {
+        if raw[i] == b'\\'
+            && let Some(code) = value
+                .get(i + 1..i + 4)
+                .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+        {
+            bytes.push(code);
+            i += 4;
+        } else {
+            bytes.push(raw[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+/// Undoes udev's `\xNN` escaping in `*_ENC` properties.
+pub(crate) fn unescape_udev(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let raw = value.as_bytes();
+    let mut i = 0;
+    while i < raw.len() {
+        if value.get(i..i + 2) == Some("\\x")
+            && let Some(code) = value
+                .get(i + 2..i + 4)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            bytes.push(code);
+            i += 4;
+        } else {
+            bytes.push(raw[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+/// Used and available bytes of the filesystem mounted at `path`, as `df`
+/// reports them.
+fn filesystem_usage(path: &str) -> Option<(u64, u64)> {
+    let path = CString::new(path).ok()?;
+    // SAFETY: statvfs is plain data, all zeroes is a valid value.
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: path is a valid C string and stat a valid out pointer.
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    let block = stat.f_frsize as u64;
+    let used = (stat.f_blocks as u64).saturating_sub(stat.f_bfree as u64) * block;
+    Some((used, stat.f_bavail as u64 * block))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(root: &Path, path: &str, contents: &[u8]) {
+        let path = root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn reads_disks_partitions_luks_and_mounts_from_sysfs() {
+        let root = std::env::temp_dir().join(format!("disktui-sysfs-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let mnt = root.join("mnt");
+        fs::create_dir_all(&mnt).unwrap();
+
+        write(&root, "sys/block/sdb/size", b"31260672\n");
+        write(&root, "sys/block/sdb/dev", b"8:16\n");
+        write(&root, "sys/block/sdb/device/model", b"Cruzer Blade    \n");
+        write(&root, "sys/block/sdb/queue/logical_block_size", b"4096\n");
+        write(&root, "sys/block/sdb/queue/physical_block_size", b"4096\n");
+        write(&root, "sys/block/sdb/sdb1/partition", b"1\n");
+        write(&root, "sys/block/sdb/sdb1/start", b"2048\n");
+        write(&root, "sys/block/sdb/sdb1/size", b"2048\n");
+        write(&root, "sys/block/sdb/sdb1/dev", b"8:17\n");
+        write(&root, "sys/block/sdb/sdb2/partition", b"2\n");
+        write(&root, "sys/block/sdb/sdb2/size", b"4096\n");
+        write(&root, "sys/block/sdb/sdb2/dev", b"8:18\n");
+        write(&root, "sys/block/sdb/sdb2/holders/dm-0", b"");
+        write(&root, "sys/block/dm-0/dev", b"254:0\n");
+        write(&root, "sys/block/dm-0/dm/name", b"luks-sdb2\n");
+        write(
+            &root,
+            "sys/block/dm-0/dm/uuid",
+            b"CRYPT-LUKS2-0123-luks-sdb2\n",
+        );
+        write(&root, "sys/block/loop0/size", b"0\n");
+        write(&root, "sys/block/loop0/loop/backing_file", b"/tmp/img\n");
+
+        write(
+            &root,
+            "run/udev/data/b8:16",
+            b"E:ID_SERIAL_SHORT=4C530001\nE:ID_PART_TABLE_TYPE=gpt\n",
+        );
+        write(
+            &root,
+            "run/udev/data/b8:17",
+            b"E:ID_FS_TYPE=vfat\nE:ID_FS_LABEL=MY_USB\nE:ID_FS_LABEL_ENC=MY\\x20USB\n\
+              E:ID_PART_ENTRY_TYPE=c12a7328-f81f-11d2-ba4b-00a0c93ec93b\n\
+              E:ID_PART_ENTRY_UUID=5f1c2f0e-01\nE:ID_PART_ENTRY_NAME=EFI\\x20system\n\
+              E:ID_PART_ENTRY_FLAGS=0x4\n",
+        );
+        write(&root, "run/udev/data/b8:18", b"E:ID_FS_TYPE=crypto_LUKS\n");
+
+        let mut header = vec![0u8; 512];
+        header[..6].copy_from_slice(LUKS_MAGIC);
+        header[7] = 2;
+        header[LUKS_UUID_OFFSET..LUKS_UUID_OFFSET + 4].copy_from_slice(b"c0de");
+        write(&root, "dev/sdb2", &header);
+
+        let mountinfo = format!(
+            "36 25 8:17 / /media/my\\040usb rw,relatime shared:1 - vfat /dev/sdb1 rw\n\
+             40 25 0:52 / {} rw,relatime shared:2 - btrfs /dev/mapper/luks-sdb2 rw\n",
+            mnt.display()
+        );
+        write(&root, "proc/self/mountinfo", mountinfo.as_bytes());
+        write(
+            &root,
+            "proc/swaps",
+            b"Filename Type Size Used Priority\n/media/my\\040usb/swapfile file 1020 0 -2\n",
+        );
+
+        let devices = SysfsReader::with_root(&root).list_block_devices().unwrap();
+
+        assert_eq!(devices.len(), 1);
+        let disk = &devices[0];
+        assert_eq!(disk.name, "sdb");
+        assert_eq!(disk.size, 31_260_672 * 512);
+        assert_eq!(disk.model.as_deref(), Some("Cruzer Blade"));
+        assert_eq!(disk.serial.as_deref(), Some("4C530001"));
+        assert_eq!(disk.partition_table.as_deref(), Some("gpt"));
+        assert_eq!(disk.geometry.logical_sector_size, 4096);
+        assert_eq!(disk.geometry.optimal_io_size, 0);
+
+        let data = &disk.partitions[0];
+        assert_eq!(data.start(), Some(1_048_576));
+        assert_eq!(data.geometry.map(|g| g.logical_sector_size), Some(4096));
+        assert_eq!(data.geometry.map(|g| g.sectors), Some(256));
+        assert_eq!(data.size, 1_048_576);
+        assert_eq!(data.filesystem.as_deref(), Some("vfat"));
+        assert_eq!(data.label.as_deref(), Some("MY USB"));
+        assert_eq!(data.mount_point.as_deref(), Some("/media/my usb"));
+        assert!(!data.is_encrypted);
+        assert_eq!(data.part_uuid.as_deref(), Some("5f1c2f0e-01"));
+        assert_eq!(data.part_label.as_deref(), Some("EFI system"));
+        assert_eq!(
+            data.flags,
+            vec![PartitionFlag::Esp, PartitionFlag::LegacyBoot]
+        );
+
+        let luks = &disk.partitions[1];
+        assert!(luks.is_encrypted);
+        assert_eq!(luks.encryption_type.as_deref(), Some("LUKS2"));
+        assert_eq!(luks.luks_uuid.as_deref(), Some("c0de"));
+        assert_eq!(luks.mapper_device.as_deref(), Some("luks-sdb2"));
+        assert_eq!(luks.mount_point.as_deref(), mnt.to_str());
+        assert!(luks.used_bytes.is_some() && luks.available_bytes.is_some());
+
+        fs::remove_dir_all(&root).unwrap();
+        assert!(SysfsReader::with_root(&root).list_block_devices().is_err());
+    }
+}
@@ -16,10 +16,12 @@ pub struct Theme {
     pub disk_type_width: u16,
     pub disk_model_width: u16,
     pub disk_serial_width: u16,
+    pub disk_health_width: u16,
 
     pub partition_name_width: u16,
     pub partition_size_width: u16,
     pub partition_fs_width: u16,
+    pub partition_type_width: u16,
     pub partition_mount_width: u16,
     pub partition_label_width: u16,
     pub partition_usage_min_width: u16,
@@ -46,10 +48,12 @@ impl Default for Theme {
             disk_type_width: 10,
             disk_model_width: 25,
             disk_serial_width: 20,
+            disk_health_width: 6,
 
             partition_name_width: 15,
             partition_size_width: 10,
             partition_fs_width: 12,
+            partition_type_width: 20,
             partition_mount_width: 20,
             partition_label_width: 15,
             partition_usage_min_width: 40,
@@ -3,11 +3,19 @@ use ratatui::{
     layout::{Alignment, Constraint, Direction, Flex, Layout, Rect},
     style::{Color, Modifier, Style, Stylize},
     text::{Line, Span},
-    widgets::{Block, BorderType, Borders, Cell, Clear, List, ListItem, Paragraph, Row, Table},
+    widgets::{Block, BorderType, Borders, Cell, Clear, Gauge, List, ListItem, Paragraph, Row, Table},
 };
 
-use crate::app::{App, FocusedBlock, PartitionDialogMode};
-use crate::utils::format_bytes;
+use crate::app::{App, CommandPreview, FocusedBlock, PartitionDialogMode};
+use crate::backup::{DiffLine, diff_tables};
+use crate::fstab::canonical_path;
+use crate::layout::{LayoutRow, device_gaps, layout_rows, misaligned_partitions};
+use crate::operations::BlockDevice;
+use crate::partition::Partition;
+use crate::parttype::type_name;
+use crate::queue::simulate;
+use crate::smart::Level;
+use crate::utils::{SizeUnits, format_size, format_timestamp};
 use ratatui::widgets::Wrap;
 
 pub fn render(app: &mut App, frame: &mut Frame) {
@@ -15,6 +23,9 @@ pub fn render(app: &mut App, frame: &mut Frame) {
         render_help_dialog(frame);
     } else if app.progress.show_dialog {
         render_main(app, frame);
+        if app.raid.show_dialog {
+            render_raid_view(app, frame);
+        }
         render_progress_dialog(app, frame);
     } else if app.passphrase_dialog.show_dialog {
         render_main(app, frame);
@@ -31,6 +42,39 @@ pub fn render(app: &mut App, frame: &mut Frame) {
     } else if app.resize_dialog.show_dialog {
         render_main(app, frame);
         render_resize_dialog(app, frame);
+    } else if app.part_type_dialog.show_dialog {
+        render_main(app, frame);
+        render_part_type_dialog(app, frame);
+    } else if app.swap_file_dialog.show_dialog {
+        render_main(app, frame);
+        render_swap_file_dialog(app, frame);
+    } else if app.mount_dialog.show_dialog {
+        render_main(app, frame);
+        render_mount_dialog(app, frame);
+    } else if app.label_dialog.show_dialog {
+        render_main(app, frame);
+        render_label_dialog(app, frame);
+    } else if app.boot_table_dialog.show_dialog {
+        render_main(app, frame);
+        render_boot_table_dialog(app, frame);
+    } else if app.check_log.show_dialog {
+        render_main(app, frame);
+        render_check_log(app, frame);
+    } else if app.audit_log.show_dialog {
+        render_main(app, frame);
+        render_audit_log(app, frame);
+    } else if app.restore_dialog.show_dialog {
+        render_main(app, frame);
+        render_restore_dialog(app, frame);
+    } else if app.lvm.show_dialog {
+        render_main(app, frame);
+        render_lvm_view(app, frame);
+    } else if app.raid.show_dialog {
+        render_main(app, frame);
+        render_raid_view(app, frame);
+    } else if app.subvolumes.show_dialog {
+        render_main(app, frame);
+        render_subvolume_view(app, frame);
     } else if app.focused_block == FocusedBlock::DiskInfo {
         render_main(app, frame);
         render_disk_info(app, frame);
@@ -44,11 +88,18 @@ pub fn render(app: &mut App, frame: &mut Frame) {
 }
 
 fn render_main(app: &mut App, frame: &mut Frame) {
+    let queue_height = if app.queue.enabled {
+        (app.queue.operations.len() as u16).clamp(1, 6) + 2
+    } else {
+        0
+    };
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Min(8),
             Constraint::Min(8),
+            Constraint::Length(queue_height),
             Constraint::Length(6),
             Constraint::Length(1),
         ])
@@ -56,8 +107,52 @@ fn render_main(app: &mut App, frame: &mut Frame) {
 
     render_disks_table(app, frame, chunks[0]);
     render_partitions_table(app, frame, chunks[1]);
-    render_disk_summary(app, frame, chunks[2]);
-    render_context_help(app, frame, chunks[3]);
+    if app.queue.enabled {
+        render_queue(app, frame, chunks[2]);
+    }
+    render_disk_summary(app, frame, chunks[3]);
+    render_context_help(app, frame, chunks[4]);
+}
+
+fn render_queue(app: &mut App, frame: &mut Frame, area: Rect) {
+    let focused = app.focused_block == FocusedBlock::Queue;
+
+    let items: Vec<ListItem> = if app.queue.is_empty() {
+        vec![ListItem::new("No pending operations").style(Style::default().fg(Color::DarkGray))]
+    } else {
+        app.queue
+            .operations
+            .iter()
+            .enumerate()
+            .map(|(i, request)| ListItem::new(format!("{}. {}", i + 1, request.description())))
+            .collect()
+    };
+
+    let list = List::new(items)
+        .block(
+            Block::default()
+                .title(format!(" Pending Operations ({}) ", app.queue.operations.len()))
+                .borders(Borders::ALL)
+                .border_style(if focused {
+                    Style::default().fg(app.theme.focus_border)
+                } else {
+                    Style::default().fg(app.theme.normal_border)
+                })
+                .border_type(if focused {
+                    BorderType::Thick
+                } else {
+                    BorderType::default()
+                }),
+        )
+        .highlight_style(if focused {
+            Style::default()
+                .bg(app.theme.highlight_bg)
+                .fg(app.theme.highlight_fg)
+        } else {
+            Style::default()
+        });
+
+    frame.render_stateful_widget(list, area, &mut app.queue.state);
 }
 
 fn render_disks_table(app: &mut App, frame: &mut Frame, area: Rect) {
@@ -92,6 +187,11 @@ fn render_disks_table(app: &mut App, frame: &mut Frame, area: Rect) {
                 .add_modifier(Modifier::BOLD)
                 .fg(header_color),
         ),
+        Cell::from("Health").style(
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .fg(header_color),
+        ),
     ])
     .bottom_margin(1);
 
@@ -99,9 +199,17 @@ fn render_disks_table(app: &mut App, frame: &mut Frame, area: Rect) {
         .disks
         .iter()
         .map(|disk| {
+            let health = match &disk.smart_data {
+                None => Cell::from("-").style(Style::default().fg(Color::DarkGray)),
+                Some(smart) => match app.monitor.thresholds.level(smart) {
+                    Level::Ok => Cell::from("● OK").style(Style::default().fg(app.theme.success)),
+                    Level::Warning => Cell::from("● WARN").style(Style::default().fg(app.theme.warning)),
+                    Level::Failing => Cell::from("● FAIL").style(Style::default().fg(app.theme.error).bold()),
+                },
+            };
             Row::new(vec![
                 Cell::from(disk.device.name.clone()),
-                Cell::from(disk.size_str()),
+                Cell::from(disk.size_str(app.size_units)),
                 Cell::from(disk.device_type()),
                 Cell::from(
                     disk.device
@@ -115,6 +223,7 @@ fn render_disks_table(app: &mut App, frame: &mut Frame, area: Rect) {
                         .clone()
                         .unwrap_or_else(|| "N/A".to_string()),
                 ),
+                health,
             ])
         })
         .collect();
@@ -125,6 +234,7 @@ fn render_disks_table(app: &mut App, frame: &mut Frame, area: Rect) {
         Constraint::Length(app.theme.disk_type_width),
         Constraint::Length(app.theme.disk_model_width),
         Constraint::Length(app.theme.disk_serial_width),
+        Constraint::Length(app.theme.disk_health_width),
     ];
 
     let table = Table::new(rows, widths)
@@ -179,6 +289,11 @@ fn render_partitions_table(app: &mut App, frame: &mut Frame, area: Rect) {
                 .add_modifier(Modifier::BOLD)
                 .fg(header_color),
         ),
+        Cell::from("Type").style(
+            Style::default()
+                .add_modifier(Modifier::BOLD)
+                .fg(header_color),
+        ),
         Cell::from("Mount Point").style(
             Style::default()
                 .add_modifier(Modifier::BOLD)
@@ -197,11 +312,35 @@ fn render_partitions_table(app: &mut App, frame: &mut Frame, area: Rect) {
     ])
     .bottom_margin(1);
 
+    let misaligned: Vec<String> = app
+        .selected_disk()
+        .map(|disk| {
+            misaligned_partitions(&disk.device, app.alignment)
+                .into_iter()
+                .map(|part| part.name.clone())
+                .collect()
+        })
+        .unwrap_or_default();
+
     let rows: Vec<Row> = if let Some(disk) = app.selected_disk() {
-        disk.device
-            .partitions
-            .iter()
-            .map(|part| {
+        layout_rows(&disk.device)
+            .into_iter()
+            .map(|row| {
+                let part = match row {
+                    LayoutRow::Partition(part) => part,
+                    LayoutRow::Free(gap) => {
+                        return Row::new(vec![
+                            Cell::from("Free space"),
+                            Cell::from(format_size(gap.size(), app.size_units)),
+                            Cell::from("-"),
+                            Cell::from("-"),
+                            Cell::from("-"),
+                            Cell::from("-"),
+                            Cell::from(""),
+                        ])
+                        .style(Style::default().fg(Color::DarkGray));
+                    }
+                };
                 let name_display = if part.is_encrypted {
                     if part.mapper_device.is_some() {
                         format!("🔓 {}", part.name)
@@ -211,6 +350,13 @@ fn render_partitions_table(app: &mut App, frame: &mut Frame, area: Rect) {
                 } else {
                     part.name.clone()
                 };
+                let is_misaligned = misaligned.contains(&part.name);
+                let name_cell = if is_misaligned {
+                    Cell::from(format!("{} ⚠", name_display))
+                        .style(Style::default().fg(app.theme.warning))
+                } else {
+                    Cell::from(name_display)
+                };
 
                 let filesystem_display = if part.is_encrypted && part.mapper_device.is_none() {
                     part.encryption_type
@@ -221,15 +367,32 @@ fn render_partitions_table(app: &mut App, frame: &mut Frame, area: Rect) {
                 };
 
                 Row::new(vec![
-                    Cell::from(name_display),
-                    Cell::from(part.size_str()),
+                    name_cell,
+                    Cell::from(part.size_str(app.size_units)),
                     Cell::from(filesystem_display),
-                    Cell::from(part.mount_point.clone().unwrap_or_else(|| "-".to_string())),
+                    Cell::from(part.part_type.as_deref().map(type_name).unwrap_or_else(|| "-".to_string())),
+                    match app.boot_tables.stale_entry_for(part.mount_point.as_deref(), part.mapper_device.as_deref()) {
+                        Some(_) if part.swap.is_none() => Cell::from(format!(
+                            "{} ⚠",
+                            part.mount_point.as_deref().unwrap_or("-")
+                        ))
+                        .style(Style::default().fg(app.theme.warning)),
+                        // An unmounted member shows what holds it instead.
+                        _ if !part.is_mounted && part.swap.is_none() && part.holder.is_some() => {
+                            Cell::from(part.holder.as_ref().map(|holder| holder.label()).unwrap_or_default())
+                                .style(Style::default().fg(app.theme.header))
+                        }
+                        _ => Cell::from(match &part.swap {
+                            Some(swap) => format!("[SWAP] pri {}", swap.priority),
+                            None => part.mount_point.clone().unwrap_or_else(|| "-".to_string()),
+                        }),
+                    },
                     Cell::from(part.label.clone().unwrap_or_else(|| "-".to_string())),
                     Cell::from(part.usage_str(
                         app.theme.usage_bar_filled,
                         app.theme.usage_bar_empty,
                         app.theme.usage_bar_length,
+                        app.size_units,
                     )),
                 ])
             })
@@ -241,6 +404,13 @@ fn render_partitions_table(app: &mut App, frame: &mut Frame, area: Rect) {
     let title = if let Some(disk) = app.selected_disk() {
         if disk.device.partitions.len() == 1 && disk.device.partitions[0].name == disk.device.name {
             format!(" {} (whole disk - no partition table) ", disk.device.name)
+        } else if !misaligned.is_empty() {
+            format!(
+                " Partitions of {} (⚠ {} not aligned to {}) ",
+                disk.device.name,
+                misaligned.len(),
+                app.alignment
+            )
         } else {
             format!(" Partitions of {} ", disk.device.name)
         }
@@ -252,6 +422,7 @@ fn render_partitions_table(app: &mut App, frame: &mut Frame, area: Rect) {
         Constraint::Length(app.theme.partition_name_width),
         Constraint::Length(app.theme.partition_size_width),
         Constraint::Length(app.theme.partition_fs_width),
+        Constraint::Length(app.theme.partition_type_width),
         Constraint::Length(app.theme.partition_mount_width),
         Constraint::Length(app.theme.partition_label_width),
         Constraint::Min(app.theme.partition_usage_min_width),
@@ -294,12 +465,12 @@ fn render_disk_summary(app: &App, frame: &mut Frame, area: Rect) {
             .model
             .clone()
             .unwrap_or_else(|| "N/A".to_string());
-        let size = disk.size_str();
+        let size = disk.size_str(app.size_units);
         let dtype = disk.device_type();
         let smart = disk
             .smart_data
             .as_ref()
-            .map(|s| s.health.clone())
+            .map(|s| s.health().to_string())
             .unwrap_or_else(|| "N/A".to_string());
         let temp = disk
             .smart_data
@@ -308,11 +479,62 @@ fn render_disk_summary(app: &App, frame: &mut Frame, area: Rect) {
             .map(|t| format!("{}°C", t))
             .unwrap_or_else(|| "N/A".to_string());
 
-        let layout_bar = generate_layout_bar(disk);
+        let layout_bar = generate_layout_bar(&disk.device, app.size_units);
+
+        let pending: Vec<_> = app.queue.for_disk(&disk.device.name).cloned().collect();
+        let after = if pending.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "\nAfter: {}",
+                generate_layout_bar(&simulate(&disk.device, &pending), app.size_units)
+            )
+        };
+
+        // The partition entry of the selected partition, when there is one.
+        let entry = match app.selected_partition() {
+            Some(part) if app.focused_block == FocusedBlock::Partitions && part.geometry.is_some() => {
+                let flags: Vec<&str> = part.flags.iter().map(|flag| flag.as_str()).collect();
+                format!(
+                    "\n{}: Type {} | PARTUUID {} | Name {} | Flags {}",
+                    part.name,
+                    part.part_type.as_deref().unwrap_or("-"),
+                    part.part_uuid.as_deref().unwrap_or("-"),
+                    part.part_label.as_deref().unwrap_or("-"),
+                    if flags.is_empty() { "-".to_string() } else { flags.join(",") }
+                )
+            }
+            _ => String::new(),
+        };
+
+        let swap_files = match app.selected_partition() {
+            Some(part) if app.focused_block == FocusedBlock::Partitions && !part.swap_files.is_empty() => {
+                let files: Vec<String> = part
+                    .swap_files
+                    .iter()
+                    .map(|swap| {
+                        format!(
+                            "{} ({} of {} used, pri {})",
+                            swap.path,
+                            format_size(swap.used, app.size_units),
+                            format_size(swap.size, app.size_units),
+                            swap.priority
+                        )
+                    })
+                    .collect();
+                format!("\nSwap files: {}", files.join(", "))
+            }
+            _ => String::new(),
+        };
+
+        let boot = match app.selected_partition() {
+            Some(part) if app.focused_block == FocusedBlock::Partitions => boot_entries(app, part),
+            _ => String::new(),
+        };
 
         format!(
-            "Model: {} | Size: {} | Type: {} | SMART: {} | Temp: {}\nLayout: {}",
-            model, size, dtype, smart, temp, layout_bar
+            "Model: {} | Size: {} | Type: {} | SMART: {} | Temp: {}\nLayout: {}{}{}{}{}",
+            model, size, dtype, smart, temp, layout_bar, after, entry, swap_files, boot
         )
     } else {
         "No disk selected".to_string()
@@ -327,35 +549,57 @@ fn render_disk_summary(app: &App, frame: &mut Frame, area: Rect) {
     frame.render_widget(paragraph, area);
 }
 
-fn generate_layout_bar(disk: &crate::disk::Disk) -> String {
-    let total_size = disk.device.size;
+/// The fstab and crypttab entries of a partition for the disk summary,
+/// and entries meant for it whose device is gone.
+fn boot_entries(app: &App, part: &Partition) -> String {
+    let mut entries = Vec::new();
+    if part.is_encrypted
+        && let Some(entry) = app.boot_tables.crypttab_entry(&format!("/dev/{}", part.name))
+    {
+        entries.push(format!("crypttab {} ({})", entry.name, entry.options));
+    }
+    let path = match &part.mapper_device {
+        Some(mapper) => format!("/dev/mapper/{}", mapper),
+        None => format!("/dev/{}", part.name),
+    };
+    if let Some(entry) = app.boot_tables.fstab_entry(&canonical_path(&path).unwrap_or(path)) {
+        entries.push(format!("fstab {} ({}, pass {})", entry.target, entry.options, entry.pass));
+    }
+    if let Some(stale) = app.boot_tables.stale_entry_for(part.mount_point.as_deref(), part.mapper_device.as_deref()) {
+        entries.push(format!("⚠ {} no longer exists", stale));
+    }
+    if entries.is_empty() {
+        String::new()
+    } else {
+        format!("\nBoot: {}", entries.join(" | "))
+    }
+}
+
+fn generate_layout_bar(device: &BlockDevice, units: SizeUnits) -> String {
+    let total_size = device.size;
     if total_size == 0 {
         return "[ EMPTY ]".to_string();
     }
 
-    let mut parts = Vec::new();
+    let mut parts: Vec<(String, u64)> = layout_rows(device)
+        .into_iter()
+        .map(|row| match row {
+            LayoutRow::Partition(partition) => (partition.name.clone(), partition.size),
+            LayoutRow::Free(gap) => ("FREE".to_string(), gap.size()),
+        })
+        .collect();
 
-    for partition in &disk.device.partitions {
-        parts.push((partition.name.clone(), partition.size));
+    if parts.is_empty() {
+        parts.push(("FREE".to_string(), total_size));
     }
 
-    let used_space: u64 = parts.iter().map(|(_, size)| size).sum();
-    let free_space = total_size.saturating_sub(used_space);
-
     let mut layout = String::from("[ ");
 
     for (i, (name, size)) in parts.iter().enumerate() {
         if i > 0 {
             layout.push_str(" | ");
         }
-        layout.push_str(&format!("{} ({})", name, format_bytes(*size)));
-    }
-
-    if free_space > 0 {
-        if !parts.is_empty() {
-            layout.push_str(" | ");
-        }
-        layout.push_str(&format!("FREE ({})", format_bytes(free_space)));
+        layout.push_str(&format!("{} ({})", name, format_size(*size, units)));
     }
 
     layout.push_str(" ]");
@@ -369,10 +613,7 @@ fn render_context_help(app: &App, frame: &mut Frame, area: Rect) {
             let has_selection = disk_opt.is_some();
 
             let has_free_space = disk_opt
-                .map(|d| {
-                    let used_space: u64 = d.device.partitions.iter().map(|p| p.size).sum();
-                    d.device.size > used_space
-                })
+                .map(|d| !device_gaps(&d.device).is_empty())
                 .unwrap_or(false);
 
             // Check if disk has a partition table (not showing whole disk as single partition)
@@ -405,6 +646,14 @@ fn render_context_help(app: &App, frame: &mut Frame, area: Rect) {
                     Span::from("Partition Table | "),
                     Span::from("i ").bold().yellow(),
                     Span::from("Info | "),
+                    Span::from("h ").bold().yellow(),
+                    Span::from("History | "),
+                    Span::from("b ").bold().yellow(),
+                    Span::from("Restore Table | "),
+                    Span::from("V ").bold().yellow(),
+                    Span::from("LVM | "),
+                    Span::from("R ").bold().yellow(),
+                    Span::from("RAID | "),
                     Span::from("? ").bold().yellow(),
                     Span::from("Help | "),
                     Span::from("q ").bold().yellow(),
@@ -436,7 +685,14 @@ fn render_context_help(app: &App, frame: &mut Frame, area: Rect) {
                     .and_then(|p| p.mapper_device.as_ref())
                     .is_some();
 
-                let mount_text = if is_mounted { "Unmount" } else { "Mount" };
+                let is_swap = partition.as_ref().and_then(|p| p.filesystem.as_deref()) == Some("swap");
+                let is_active_swap = partition.as_ref().is_some_and(|p| p.swap.is_some());
+                let mount_text = match (is_swap, is_active_swap) {
+                    (true, true) => "Swap Off",
+                    (true, false) => "Swap On",
+                    (false, _) if is_mounted => "Unmount",
+                    (false, _) => "Mount",
+                };
 
                 let mut spans = vec![
                     Span::from("Tab ").bold().yellow(),
@@ -472,14 +728,53 @@ fn render_context_help(app: &App, frame: &mut Frame, area: Rect) {
                     ]);
                 }
 
-                if !is_mounted && !is_encrypted {
+                if is_mounted && !is_swap {
+                    spans.extend_from_slice(&[
+                        Span::from("w ").bold().yellow(),
+                        Span::from("Swap File | "),
+                    ]);
+                }
+
+                if !is_mounted && !is_encrypted && !is_active_swap {
                     spans.extend_from_slice(&[
                         Span::from("r ").bold().yellow(),
                         Span::from("Resize | "),
                     ]);
                 }
 
+                if !is_mounted && !is_swap && (!is_encrypted || is_unlocked) {
+                    spans.extend_from_slice(&[
+                        Span::from("c ").bold().yellow(),
+                        Span::from("Check | "),
+                    ]);
+                }
+
+                let has_filesystem = partition.as_ref().is_some_and(|p| p.filesystem.is_some());
+                if has_filesystem && (!is_encrypted || is_unlocked) {
+                    spans.extend_from_slice(&[
+                        Span::from("L ").bold().yellow(),
+                        Span::from("Label | "),
+                    ]);
+                }
+
+                if has_filesystem || is_encrypted {
+                    spans.extend_from_slice(&[
+                        Span::from("F ").bold().yellow(),
+                        Span::from("fstab | "),
+                    ]);
+                }
+
+                let is_btrfs = partition.as_ref().and_then(|p| p.filesystem.as_deref()) == Some("btrfs");
+                if is_mounted && (is_btrfs || is_unlocked) {
+                    spans.extend_from_slice(&[
+                        Span::from("S ").bold().yellow(),
+                        Span::from("Subvolumes | "),
+                    ]);
+                }
+
                 spans.extend_from_slice(&[
+                    Span::from("t ").bold().yellow(),
+                    Span::from("Type/Flags | "),
                     Span::from("d ").bold().yellow(),
                     Span::from("Delete | "),
                     Span::from("? ").bold().yellow(),
@@ -489,6 +784,19 @@ fn render_context_help(app: &App, frame: &mut Frame, area: Rect) {
                 ]);
 
                 Line::from(spans)
+            } else if app.selected_free_space().is_some() {
+                Line::from(vec![
+                    Span::from("Tab ").bold().yellow(),
+                    Span::from("Switch | "),
+                    Span::from("j/k ").bold().yellow(),
+                    Span::from("Scroll | "),
+                    Span::from("n ").bold().yellow(),
+                    Span::from("New Partition | "),
+                    Span::from("? ").bold().yellow(),
+                    Span::from("Help | "),
+                    Span::from("q ").bold().yellow(),
+                    Span::from("Quit"),
+                ])
             } else {
                 Line::from(vec![
                     Span::from("Tab ").bold().yellow(),
@@ -502,6 +810,22 @@ fn render_context_help(app: &App, frame: &mut Frame, area: Rect) {
                 ])
             }
         }
+        FocusedBlock::Queue => Line::from(vec![
+            Span::from("Tab ").bold().yellow(),
+            Span::from("Switch | "),
+            Span::from("J/K ").bold().yellow(),
+            Span::from("Reorder | "),
+            Span::from("d ").bold().yellow(),
+            Span::from("Remove | "),
+            Span::from("u ").bold().yellow(),
+            Span::from("Undo | "),
+            Span::from("c ").bold().yellow(),
+            Span::from("Clear | "),
+            Span::from("a ").bold().yellow(),
+            Span::from("Apply All | "),
+            Span::from("o ").bold().yellow(),
+            Span::from("Queue Mode"),
+        ]),
         _ => Line::from(vec![
             Span::from("? ").bold().yellow(),
             Span::from("Help | "),
@@ -518,7 +842,7 @@ fn render_help_dialog(frame: &mut Frame) {
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Fill(1),
-            Constraint::Length(35),
+            Constraint::Length(50),
             Constraint::Fill(1),
         ])
         .flex(Flex::SpaceBetween)
@@ -544,15 +868,28 @@ fn render_help_dialog(frame: &mut Frame) {
             .bold()
             .yellow(),
         Line::from("  f  - Format partition/disk"),
-        Line::from("  m  - Mount/unmount"),
+        Line::from("  m  - Mount (choosing where and how) or unmount, or toggle swap"),
+        Line::from("  w  - Create or disable a swap file on a mounted partition"),
+        Line::from("  c  - Check or repair the filesystem (unmounted only)"),
+        Line::from("  L  - Change or clear the filesystem label"),
+        Line::from("  U  - Give the filesystem a new UUID (unmounted only)"),
+        Line::from("  F  - Add or edit the /etc/fstab or /etc/crypttab entry"),
         Line::from("  r  - Resize partition (unmounted only)"),
         Line::from("  d  - Delete partition"),
+        Line::from("  t  - Change partition type, GPT name and flags"),
+        Line::from("  S  - Btrfs subvolumes and snapshots (mounted only)"),
+        Line::from("  n  - New partition in the selected free space"),
         Line::from(""),
         Line::from("Disk Operations (focus on Disks):")
             .bold()
             .yellow(),
         Line::from("  p  - Partition (create table/partition)"),
-        Line::from("  i  - Show disk SMART info"),
+        Line::from("  n  - New partition in the largest free space"),
+        Line::from("  i  - Show disk SMART info (s/l: short/long self-test)"),
+        Line::from("  h  - Operation history (audit log)"),
+        Line::from("  b  - Restore a saved partition table"),
+        Line::from("  V  - LVM volumes (the selected partition can become a PV)"),
+        Line::from("  R  - RAID arrays (create, add/fail/remove members, stop)"),
         Line::from(""),
         Line::from("Workflow for USB with ISO:").bold().yellow(),
         Line::from("  1. Tab to Partitions, press 'm' to unmount"),
@@ -561,6 +898,10 @@ fn render_help_dialog(frame: &mut Frame) {
         Line::from("  4. Tab to create partition, adjust size, Enter"),
         Line::from("  5. Tab to Partitions, select partition, press 'f'"),
         Line::from(""),
+        Line::from("Pending Operations:").bold().yellow(),
+        Line::from("  o  - Toggle queue mode (changes wait until applied)"),
+        Line::from("  Tab to the queue: J/K reorder, d remove, u undo, a apply"),
+        Line::from(""),
         Line::from("Other:").bold().yellow(),
         Line::from("  ?  - Toggle this help | q  - Quit"),
         Line::from(""),
@@ -583,11 +924,12 @@ fn render_help_dialog(frame: &mut Frame) {
 }
 
 fn render_format_dialog(app: &mut App, frame: &mut Frame) {
+    let with_label = !app.format_dialog.encrypt_mode;
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Percentage(30),
-            Constraint::Length(15),
+            Constraint::Length(if with_label { 19 } else { 15 }),
             Constraint::Percentage(30),
         ])
         .split(frame.area());
@@ -629,18 +971,53 @@ fn render_format_dialog(app: &mut App, frame: &mut Frame) {
         )
         .highlight_style(Style::default().bg(Color::DarkGray).fg(Color::White));
 
-    let warning = Paragraph::new("WARNING: All data will be lost!\n\nEnter: Confirm | Esc: Cancel")
+    let keys = if with_label {
+        "Tab: Label | Enter: Confirm | Esc: Cancel"
+    } else {
+        "Enter: Confirm | Esc: Cancel"
+    };
+    let warning = Paragraph::new(format!("WARNING: All data will be lost!\n\n{}", keys))
         .alignment(Alignment::Center)
         .style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD));
 
+    let label_height = if with_label { 4 } else { 0 };
     let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Fill(1), Constraint::Length(3)])
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(label_height),
+            Constraint::Length(3),
+        ])
         .split(area);
 
     frame.render_widget(Clear, area);
     frame.render_stateful_widget(list, chunks[0], &mut app.format_dialog.type_state);
-    frame.render_widget(warning, chunks[1]);
+
+    if with_label {
+        let label_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Length(1)])
+            .split(chunks[1]);
+        let mut label_block = Block::default()
+            .title(" Label (optional) ")
+            .borders(Borders::ALL);
+        if app.format_dialog.label_focused {
+            label_block = label_block.border_style(Style::default().fg(app.theme.focus_border));
+        }
+        let label = Paragraph::new(app.format_dialog.label_input.value()).block(label_block);
+        frame.render_widget(label, label_chunks[0]);
+
+        let selected = app
+            .format_dialog
+            .type_state
+            .selected()
+            .and_then(|i| app.filesystem_types.get(i));
+        if let Some(error) = selected.and_then(|fs| app.format_dialog.label_error(fs)) {
+            let error = Paragraph::new(error).style(Style::default().fg(app.theme.error));
+            frame.render_widget(error, label_chunks[1]);
+        }
+    }
+    frame.render_widget(warning, chunks[2]);
 }
 
 fn render_partition_dialog(app: &mut App, frame: &mut Frame) {
@@ -704,14 +1081,9 @@ fn render_partition_dialog(app: &mut App, frame: &mut Frame) {
     } else {
         use crate::app::CreatePartitionStep;
 
-        let free_space = if let Some(disk) = app.selected_disk() {
-            let used_space: u64 = disk.device.partitions.iter().map(|p| p.size).sum();
-            disk.device.size.saturating_sub(used_space)
-        } else {
-            0
-        };
+        let free_space = app.partition_dialog.gap.map(|gap| gap.size()).unwrap_or(0);
 
-        let free_space_str = format_bytes(free_space);
+        let free_space_str = format_size(free_space, app.size_units);
 
         if app.partition_dialog.create_step == CreatePartitionStep::EnterSize {
             let border_block = Block::default()
@@ -728,7 +1100,7 @@ fn render_partition_dialog(app: &mut App, frame: &mut Frame) {
                 .constraints([
                     Constraint::Length(1), // Label line
                     Constraint::Length(3), // Input box with border
-                    Constraint::Length(1), // Spacing
+                    Constraint::Length(1), // Parsed size
                     Constraint::Length(2), // Info text (2 lines)
                     Constraint::Fill(1),   // Remaining space
                 ])
@@ -743,15 +1115,61 @@ fn render_partition_dialog(app: &mut App, frame: &mut Frame) {
             let size_input = Paragraph::new(app.partition_dialog.size_input.value())
                 .block(Block::default().borders(Borders::ALL));
 
+            let preview = match app.create_preview() {
+                Some(Ok(placement)) => Paragraph::new(format!(
+                    "= {} at {}",
+                    format_size(placement.range.size(), app.size_units),
+                    format_size(placement.range.start, app.size_units)
+                ))
+                .style(Style::default().fg(app.theme.success)),
+                Some(Err(e)) => {
+                    Paragraph::new(e.to_string()).style(Style::default().fg(app.theme.error))
+                }
+                None => Paragraph::new(""),
+            };
+
             let info = Paragraph::new(
-                "Examples: 100M, 2.5G, 1T (leave empty for max)\n\
+                "Examples: 512M, 2.5GiB, 10GB, 2048s, 50%, -1G (empty for all)\n\
                  Enter: Next | Esc: Cancel",
             )
             .alignment(Alignment::Center);
 
             frame.render_widget(size_label, chunks[0]);
             frame.render_widget(size_input, chunks[1]);
+            frame.render_widget(preview, chunks[2]);
             frame.render_widget(info, chunks[3]);
+        } else if app.partition_dialog.create_step == CreatePartitionStep::SelectType {
+            let items: Vec<ListItem> = app
+                .partition_dialog
+                .part_types
+                .iter()
+                .map(|t| ListItem::new(t.name))
+                .collect();
+
+            let list = List::new(items)
+                .block(
+                    Block::default()
+                        .title(" Select Partition Type ")
+                        .title_alignment(Alignment::Center)
+                        .borders(Borders::ALL)
+                        .border_type(BorderType::Thick)
+                        .border_style(Style::default().fg(Color::Green)),
+                )
+                .highlight_style(Style::default().bg(Color::DarkGray).fg(Color::White));
+
+            let info = Paragraph::new(
+                "j/k: Navigate | Enter: Next | Backspace: Go Back | Esc: Cancel",
+            )
+            .alignment(Alignment::Center)
+            .style(Style::default().fg(Color::Yellow));
+
+            let chunks = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Fill(1), Constraint::Length(3)])
+                .split(area);
+
+            frame.render_stateful_widget(list, chunks[0], &mut app.partition_dialog.part_type_state);
+            frame.render_widget(info, chunks[1]);
         } else {
             let items: Vec<ListItem> = app
                 .filesystem_types
@@ -794,117 +1212,1139 @@ fn render_partition_dialog(app: &mut App, frame: &mut Frame) {
     }
 }
 
-fn render_disk_info(app: &App, frame: &mut Frame) {
-    let popup_layout = Layout::default()
+fn render_disk_info(app: &mut App, frame: &mut Frame) {
+    let Some(disk) = app.selected_disk() else {
+        return;
+    };
+
+    let area = frame.area().inner(ratatui::layout::Margin {
+        horizontal: frame.area().width / 8,
+        vertical: frame.area().height / 10,
+    });
+    let block = Block::default()
+        .title(format!(" Disk Information - {} ", disk.device.name))
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Green))
+        .border_type(BorderType::Thick);
+    let inner = block.inner(area);
+    let chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Fill(1),
-            Constraint::Length(12),
-            Constraint::Fill(1),
+        .constraints([Constraint::Fill(1), Constraint::Length(1)])
+        .split(inner);
+
+    let theme = &app.theme;
+    let level_style = |level: Level| match level {
+        Level::Ok => Style::default(),
+        Level::Warning => Style::default().fg(theme.warning),
+        Level::Failing => Style::default().fg(theme.error).bold(),
+    };
+    let field = |name: &str, value: String, style: Style| {
+        Line::from(vec![
+            Span::styled(format!("{:<20}", name), Style::default().bold().yellow()),
+            Span::styled(value, style),
         ])
-        .flex(Flex::Start)
-        .split(frame.area());
+    };
+    let heading = |title: &str| Line::from(title.to_string()).style(Style::default().bold().fg(theme.header));
+
+    let mut lines = vec![
+        field("Size", disk.size_str(app.size_units), Style::default()),
+        field("Type", disk.device_type().to_string(), Style::default()),
+        field(
+            "Sector Size",
+            format!(
+                "{} B logical / {} B physical",
+                disk.device.geometry.logical_sector_size, disk.device.geometry.physical_sector_size
+            ),
+            Style::default(),
+        ),
+        field(
+            "Model",
+            disk.device.model.clone().unwrap_or_else(|| "N/A".to_string()),
+            Style::default(),
+        ),
+        field(
+            "Serial",
+            disk.device.serial.clone().unwrap_or_else(|| "N/A".to_string()),
+            Style::default(),
+        ),
+    ];
 
-    let area = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Fill(1),
-            Constraint::Min(70),
-            Constraint::Fill(1),
-        ])
-        .split(popup_layout[1])[1];
+    match &disk.smart_data {
+        None => lines.push(field("SMART", "N/A".to_string(), Style::default().fg(Color::DarkGray))),
+        Some(smart) => {
+            let health_style = match smart.passed {
+                Some(true) => Style::default().fg(theme.success),
+                Some(false) => Style::default().fg(theme.error).bold(),
+                None => Style::default().fg(Color::DarkGray),
+            };
+            lines.push(field("SMART Health", smart.health().to_string(), health_style));
+            lines.push(field(
+                "Temperature",
+                smart.temperature.map(|t| format!("{}°C", t)).unwrap_or_else(|| "N/A".to_string()),
+                Style::default(),
+            ));
+            lines.push(field(
+                "Power On Hours",
+                smart.power_on_hours.map(|h| h.to_string()).unwrap_or_else(|| "N/A".to_string()),
+                Style::default(),
+            ));
+            let running = match &smart.self_test {
+                Some(test) => match test.remaining_percent {
+                    Some(remaining) => format!("{} ({}% remaining)", test.description, remaining),
+                    None => test.description.clone(),
+                },
+                None => "None running".to_string(),
+            };
+            lines.push(field("Self-test", running, Style::default()));
+            if let (Some(short), Some(long)) = (smart.short_test_minutes, smart.long_test_minutes) {
+                lines.push(field(
+                    "Test Duration",
+                    format!("short about {} min, long about {} min", short, long),
+                    Style::default(),
+                ));
+            }
 
-    if let Some(disk) = app.selected_disk() {
-        let rows = vec![
-            Row::new(vec![
-                Cell::from("Name").style(Style::default().bold().yellow()),
-                Cell::from(disk.device.name.clone()),
-            ]),
-            Row::new(vec![
-                Cell::from("Size").style(Style::default().bold().yellow()),
-                Cell::from(disk.size_str()),
-            ]),
-            Row::new(vec![
-                Cell::from("Type").style(Style::default().bold().yellow()),
-                Cell::from(disk.device_type()),
-            ]),
-            Row::new(vec![
-                Cell::from("Model").style(Style::default().bold().yellow()),
-                Cell::from(
-                    disk.device
-                        .model
-                        .clone()
-                        .unwrap_or_else(|| "N/A".to_string()),
-                ),
-            ]),
-            Row::new(vec![
-                Cell::from("Serial").style(Style::default().bold().yellow()),
-                Cell::from(
-                    disk.device
-                        .serial
-                        .clone()
-                        .unwrap_or_else(|| "N/A".to_string()),
-                ),
-            ]),
-            Row::new(vec![
-                Cell::from("SMART Health").style(Style::default().bold().yellow()),
-                Cell::from(
-                    disk.smart_data
-                        .as_ref()
-                        .map(|s| s.health.clone())
-                        .unwrap_or_else(|| "N/A".to_string()),
-                ),
-            ]),
-            Row::new(vec![
-                Cell::from("Temperature").style(Style::default().bold().yellow()),
-                Cell::from(
-                    disk.smart_data
-                        .as_ref()
-                        .and_then(|s| s.temperature)
-                        .map(|t| format!("{}°C", t))
-                        .unwrap_or_else(|| "N/A".to_string()),
-                ),
-            ]),
-            Row::new(vec![
-                Cell::from("Power On Hours").style(Style::default().bold().yellow()),
-                Cell::from(
-                    disk.smart_data
-                        .as_ref()
-                        .and_then(|s| s.power_on_hours)
-                        .map(|h| format!("{}", h))
-                        .unwrap_or_else(|| "N/A".to_string()),
-                ),
-            ]),
-        ];
+            if let Some(nvme) = &smart.nvme {
+                lines.push(Line::from(""));
+                lines.push(heading("NVMe Health Log"));
+                for reading in nvme.readings() {
+                    lines.push(field(reading.name, reading.value, level_style(reading.level)));
+                }
+            }
 
-        let table = Table::new(rows, [Constraint::Length(20), Constraint::Fill(1)]).block(
-            Block::default()
-                .title(" Disk Information ")
-                .title_alignment(Alignment::Center)
-                .borders(Borders::ALL)
-                .border_style(Style::default().fg(Color::Green))
-                .border_type(BorderType::Thick),
-        );
+            if !smart.attributes.is_empty() {
+                lines.push(Line::from(""));
+                lines.push(heading("  ID Attribute                  Value Worst Thresh Type      Raw"));
+                for attribute in &smart.attributes {
+                    lines.push(
+                        Line::from(format!(
+                            "{:>4} {:<26} {:>5} {:>5} {:>6} {:<9} {}{}",
+                            attribute.id,
+                            attribute.name,
+                            attribute.value,
+                            attribute.worst,
+                            attribute.thresh,
+                            if attribute.prefailure { "Pre-fail" } else { "Old_age" },
+                            attribute.raw,
+                            match attribute.when_failed.as_str() {
+                                "" => String::new(),
+                                when => format!("  (failed {})", when),
+                            }
+                        ))
+                        .style(level_style(attribute.level())),
+                    );
+                }
+            }
 
-        frame.render_widget(Clear, area);
-        frame.render_widget(table, area);
+            if let Some(history) = app.monitor.history(&disk.device.name).filter(|history| history.len() > 1) {
+                lines.push(Line::from(""));
+                lines.push(heading(&format!("History ({} readings since {})", history.len(), format_system_time(history[0].at))));
+                let tracked: [(&str, Vec<Option<u64>>); 4] = [
+                    ("Temperature", history.iter().map(|s| s.temperature.map(|t| t.max(0) as u64)).collect()),
+                    ("Reallocated", history.iter().map(|s| s.reallocated).collect()),
+                    ("Pending", history.iter().map(|s| s.pending).collect()),
+                    ("Percentage Used", history.iter().map(|s| s.percentage_used).collect()),
+                ];
+                let width = chunks[0].width.saturating_sub(40) as usize;
+                for (name, values) in tracked {
+                    let values: Vec<u64> = values.into_iter().flatten().collect();
+                    let (Some(first), Some(last)) = (values.first(), values.last()) else {
+                        continue;
+                    };
+                    let range = format!("{} -> {}", first, last);
+                    lines.push(field(name, format!("{:<16} {}", range, sparkline(&values, width)), Style::default()));
+                }
+            }
+
+            lines.push(Line::from(""));
+            lines.push(heading("Self-test Log"));
+            if smart.self_test_log.is_empty() {
+                lines.push(Line::from("No self-tests recorded").style(Style::default().fg(Color::DarkGray)));
+            }
+            for entry in &smart.self_test_log {
+                let hours = entry.power_on_hours.map(|h| format!("{} h", h)).unwrap_or_default();
+                let style = if entry.passed { Style::default() } else { Style::default().fg(theme.warning) };
+                lines.push(Line::from(format!("{:<20} {:<40} {:>10}", entry.kind, entry.status, hours)).style(style));
+            }
+        }
     }
-}
 
-fn render_progress_dialog(app: &App, frame: &mut Frame) {
-    let popup_layout = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints([
-            Constraint::Fill(1),
-            Constraint::Length(10),
-            Constraint::Fill(1),
-        ])
-        .split(frame.area());
+    // Keep the last line at the bottom of the view instead of scrolling
+    // past it.
+    let max_scroll = lines.len().saturating_sub(chunks[0].height as usize);
+    app.disk_info_scroll = app.disk_info_scroll.min(max_scroll);
+    let paragraph = Paragraph::new(lines).scroll((app.disk_info_scroll as u16, 0));
 
-    let area = Layout::default()
-        .direction(Direction::Horizontal)
-        .constraints([
-            Constraint::Fill(1),
+    let help = Paragraph::new("j/k: Scroll | s: Short test | l: Long test | r: Reload | Esc: Close")
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(Color::Yellow));
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+    frame.render_widget(paragraph, chunks[0]);
+    frame.render_widget(help, chunks[1]);
+}
+
+/// The last `width` values as block characters scaled between their
+/// minimum and maximum.
+fn sparkline(values: &[u64], width: usize) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let values = &values[values.len().saturating_sub(width)..];
+    let min = values.iter().copied().min().unwrap_or(0);
+    let max = values.iter().copied().max().unwrap_or(0);
+    values
+        .iter()
+        .map(|&value| match max - min {
+            0 => BARS[0],
+            span => BARS[((value - min) * 7 / span) as usize],
+        })
+        .collect()
+}
+
+fn format_system_time(time: std::time::SystemTime) -> String {
+    let secs = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0);
+    format_timestamp(secs)
+}
+
+fn render_check_log(app: &App, frame: &mut Frame) {
+    use crate::fsck::CheckStatus;
+
+    let log = &app.check_log;
+    if !log.started {
+        let area = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Fill(1),
+                Constraint::Length(7),
+                Constraint::Fill(1),
+            ])
+            .split(frame.area())[1];
+        let area = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Fill(1),
+                Constraint::Length(56),
+                Constraint::Fill(1),
+            ])
+            .split(area)[1];
+
+        let repair = if log.can_repair() {
+            "Check and repair".to_string()
+        } else {
+            format!("Check and repair (not available for {})", log.fs_type)
+        };
+        let items = [("Check only (read-only)".to_string(), !log.repair), (repair, log.repair)];
+        let mut lines: Vec<Line> = items
+            .into_iter()
+            .map(|(label, selected)| {
+                let line = Line::from(format!(" {} {}", if selected { ">" } else { " " }, label));
+                if selected {
+                    line.style(Style::default().bg(Color::DarkGray).fg(Color::White))
+                } else {
+                    line
+                }
+            })
+            .collect();
+        lines.push(Line::from(""));
+        lines.push(
+            Line::from("j/k: Select | Enter: Start | Esc: Cancel")
+                .centered()
+                .style(Style::default().fg(Color::Yellow)),
+        );
+
+        let dialog = Paragraph::new(lines).block(
+            Block::default()
+                .title(format!(" Check {} ({}) ", log.device, log.fs_type))
+                .title_alignment(Alignment::Center)
+                .borders(Borders::ALL)
+                .border_type(BorderType::Thick)
+                .border_style(Style::default().fg(Color::Green)),
+        );
+        frame.render_widget(Clear, area);
+        frame.render_widget(dialog, area);
+        return;
+    }
+
+    let area = frame.area().inner(ratatui::layout::Margin {
+        horizontal: frame.area().width / 10,
+        vertical: frame.area().height / 10,
+    });
+    let mode = if log.repair { "repair" } else { "read-only" };
+    let block = Block::default()
+        .title(format!(" Check {} ({}, {}) ", log.device, log.fs_type, mode))
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Thick)
+        .border_style(Style::default().fg(Color::Green));
+    let inner = block.inner(area);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .split(inner);
+
+    // The view ends `scroll` lines before the last one.
+    let height = chunks[0].height as usize;
+    let end = log.lines.len().saturating_sub(log.scroll);
+    let start = end.saturating_sub(height);
+    let output: Vec<Line> = log.lines[start..end]
+        .iter()
+        .map(|line| Line::from(line.as_str()))
+        .collect();
+    frame.render_widget(Paragraph::new(output), chunks[0]);
+
+    let status = if log.running {
+        Line::from("Running...").style(Style::default().fg(Color::DarkGray))
+    } else if let Some(error) = &log.error {
+        Line::from(error.clone()).style(Style::default().fg(app.theme.error))
+    } else if let Some(report) = &log.report {
+        let color = match report.status {
+            CheckStatus::Clean | CheckStatus::Corrected => app.theme.success,
+            CheckStatus::Errors => app.theme.warning,
+            CheckStatus::Failed => app.theme.error,
+        };
+        Line::from(report.meaning.clone()).style(Style::default().fg(color).bold())
+    } else {
+        Line::from("")
+    };
+    frame.render_widget(Paragraph::new(status.centered()), chunks[1]);
+
+    let position = if log.scroll > 0 {
+        format!(" | {} more below", log.scroll)
+    } else {
+        String::new()
+    };
+    let help = Paragraph::new(format!("j/k: Scroll | g/G: Top/Bottom | Esc: Close{}", position))
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(Color::Yellow));
+    frame.render_widget(help, chunks[2]);
+}
+
+fn render_audit_log(app: &mut App, frame: &mut Frame) {
+    let area = frame.area().inner(ratatui::layout::Margin {
+        horizontal: frame.area().width / 10,
+        vertical: frame.area().height / 10,
+    });
+
+    let block = Block::default()
+        .title(format!(" Operation History - {} ", app.audit_log.disk))
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Thick)
+        .border_style(Style::default().fg(Color::Green));
+    let inner = block.inner(area);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+
+    let log = &mut app.audit_log;
+    let status = if log.loading {
+        Some(Line::from("Loading...").style(Style::default().fg(Color::DarkGray)))
+    } else if let Some(error) = &log.error {
+        Some(Line::from(error.clone()).style(Style::default().fg(Color::Red)))
+    } else if log.entries.is_empty() {
+        Some(Line::from("No recorded operations").style(Style::default().fg(Color::DarkGray)))
+    } else {
+        None
+    };
+    if let Some(status) = status {
+        frame.render_widget(Paragraph::new(status.centered()), inner);
+        return;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage(40),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .split(inner);
+
+    let items: Vec<ListItem> = log
+        .entries
+        .iter()
+        .map(|entry| {
+            let (mark, color) = if entry.success {
+                ("✓", Color::Green)
+            } else {
+                ("✗", Color::Red)
+            };
+            ListItem::new(Line::from(vec![
+                Span::styled(
+                    format!("{} ", format_timestamp(entry.timestamp)),
+                    Style::default().fg(Color::DarkGray),
+                ),
+                Span::styled(format!("{} ", mark), Style::default().fg(color)),
+                Span::raw(entry.request.description()),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::BOTTOM))
+        .highlight_style(
+            Style::default()
+                .bg(app.theme.highlight_bg)
+                .fg(app.theme.highlight_fg),
+        );
+    frame.render_stateful_widget(list, chunks[0], &mut log.state);
+
+    if let Some(entry) = log.state.selected().and_then(|i| log.entries.get(i)) {
+        let label = Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD);
+        let mut lines = vec![Line::from(vec![
+            Span::styled("User: ", label),
+            Span::raw(
+                entry
+                    .uid
+                    .map(|uid| format!("uid {}", uid))
+                    .unwrap_or_else(|| "unknown".to_string()),
+            ),
+        ])];
+        if let Some(error) = &entry.error {
+            lines.push(Line::from(vec![
+                Span::styled("Error: ", label),
+                Span::styled(error.clone(), Style::default().fg(Color::Red)),
+            ]));
+        }
+        lines.push(Line::from(Span::styled("Commands:", label)));
+        for command in &entry.commands {
+            let exit = command
+                .exit_code
+                .map(|code| format!("exit {}", code))
+                .unwrap_or_else(|| "not run".to_string());
+            lines.push(Line::from(vec![
+                Span::styled("  $ ", Style::default().fg(Color::DarkGray)),
+                Span::raw(command.command.clone()),
+                Span::styled(
+                    format!("  ({})", exit),
+                    Style::default().fg(if command.exit_code == Some(0) {
+                        Color::DarkGray
+                    } else {
+                        Color::Red
+                    }),
+                ),
+            ]));
+            for line in command.stderr.lines().take(3) {
+                lines.push(
+                    Line::from(format!("      {}", line)).style(Style::default().fg(Color::Red)),
+                );
+            }
+        }
+        frame.render_widget(
+            Paragraph::new(lines).wrap(Wrap { trim: false }),
+            chunks[1],
+        );
+    }
+
+    frame.render_widget(
+        Line::from("j/k to browse  |  Esc to close")
+            .style(Style::default().fg(Color::DarkGray))
+            .centered(),
+        chunks[2],
+    );
+}
+
+fn render_restore_dialog(app: &mut App, frame: &mut Frame) {
+    let area = frame.area().inner(ratatui::layout::Margin {
+        horizontal: frame.area().width / 10,
+        vertical: frame.area().height / 10,
+    });
+
+    let block = Block::default()
+        .title(format!(" Restore Partition Table - {} ", app.restore_dialog.disk))
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Thick)
+        .border_style(Style::default().fg(Color::Green));
+    let inner = block.inner(area);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+
+    let dialog = &mut app.restore_dialog;
+    let status = if dialog.loading {
+        Some(Line::from("Loading...").style(Style::default().fg(Color::DarkGray)))
+    } else if let Some(error) = &dialog.error {
+        Some(Line::from(error.clone()).style(Style::default().fg(Color::Red)))
+    } else if dialog.backups.is_empty() {
+        Some(
+            Line::from("No saved partition tables for this disk")
+                .style(Style::default().fg(Color::DarkGray)),
+        )
+    } else {
+        None
+    };
+    if let Some(status) = status {
+        frame.render_widget(Paragraph::new(status.centered()), inner);
+        return;
+    }
+
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Fill(1), Constraint::Length(1)])
+        .split(inner);
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Length(24), Constraint::Fill(1)])
+        .split(rows[0]);
+
+    let items: Vec<ListItem> = dialog
+        .backups
+        .iter()
+        .map(|backup| ListItem::new(format_timestamp(backup.timestamp)))
+        .collect();
+    let list = List::new(items)
+        .block(Block::default().title(" Saved ").borders(Borders::RIGHT))
+        .highlight_style(
+            Style::default()
+                .bg(app.theme.highlight_bg)
+                .fg(app.theme.highlight_fg),
+        );
+    frame.render_stateful_widget(list, chunks[0], &mut dialog.state);
+
+    // Diff from the current table to the backup: what restoring would change.
+    if let Some(backup) = dialog.selected_backup() {
+        let current = dialog.current.as_deref().unwrap_or_default();
+        let lines: Vec<Line> = diff_tables(current, &backup.table)
+            .into_iter()
+            .map(|line| match line {
+                DiffLine::Same(text) => Line::from(format!("  {}", text)),
+                DiffLine::Removed(text) => {
+                    Line::from(format!("- {}", text)).style(Style::default().fg(Color::Red))
+                }
+                DiffLine::Added(text) => {
+                    Line::from(format!("+ {}", text)).style(Style::default().fg(Color::Green))
+                }
+            })
+            .collect();
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(Block::default().title(format!(" Current -> {} ", backup.id)))
+                .wrap(Wrap { trim: false }),
+            chunks[1].inner(ratatui::layout::Margin {
+                horizontal: 1,
+                vertical: 0,
+            }),
+        );
+    }
+
+    frame.render_widget(
+        Line::from("j/k to browse  |  Enter to restore  |  Esc to close")
+            .style(Style::default().fg(Color::DarkGray))
+            .centered(),
+        rows[1],
+    );
+}
+
+fn render_lvm_view(app: &mut App, frame: &mut Frame) {
+    use crate::app::{LvmAction, LvmSection};
+
+    let area = frame.area().inner(ratatui::layout::Margin {
+        horizontal: frame.area().width / 10,
+        vertical: frame.area().height / 10,
+    });
+
+    let block = Block::default()
+        .title(" LVM ")
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Thick)
+        .border_style(Style::default().fg(Color::Green));
+    let inner = block.inner(area);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+
+    let units = app.size_units;
+    let view = &mut app.lvm;
+    let status = if view.loading && view.report == Default::default() {
+        Some(Line::from("Loading...").style(Style::default().fg(Color::DarkGray)))
+    } else {
+        view.error
+            .as_ref()
+            .map(|error| Line::from(error.clone()).style(Style::default().fg(Color::Red)))
+    };
+    if let Some(status) = status {
+        frame.render_widget(Paragraph::new(status.centered()), inner);
+        return;
+    }
+
+    let form_height = view.form.as_ref().map_or(0, |form| form.fields.len() as u16 * 3 + 2);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Fill(1),
+            Constraint::Length(form_height),
+            Constraint::Length(1),
+        ])
+        .split(inner);
+
+    let tabs: Vec<Span> = LvmSection::ALL
+        .iter()
+        .flat_map(|section| {
+            let style = if *section == view.section {
+                Style::default()
+                    .bg(app.theme.highlight_bg)
+                    .fg(app.theme.highlight_fg)
+            } else {
+                Style::default().fg(Color::DarkGray)
+            };
+            [Span::styled(format!(" {} ", section.title()), style), Span::raw(" ")]
+        })
+        .collect();
+    frame.render_widget(Line::from(tabs).centered(), chunks[0]);
+
+    let size = |bytes: u64| format_size(bytes, units);
+    let (header, rows, widths): (Vec<&str>, Vec<Row>, Vec<Constraint>) = match view.section {
+        LvmSection::PhysicalVolumes => (
+            vec!["Device", "Volume Group", "Size", "Free"],
+            view.report
+                .pvs
+                .iter()
+                .map(|pv| {
+                    Row::new(vec![
+                        Cell::from(pv.name.clone()),
+                        match &pv.vg_name {
+                            Some(vg) => Cell::from(vg.clone()),
+                            None => Cell::from("unused").style(Style::default().fg(Color::DarkGray)),
+                        },
+                        Cell::from(size(pv.size)),
+                        Cell::from(size(pv.free)),
+                    ])
+                })
+                .collect(),
+            vec![
+                Constraint::Fill(2),
+                Constraint::Fill(2),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+            ],
+        ),
+        LvmSection::VolumeGroups => (
+            vec!["Name", "Size", "Free", "Free Extents", "PVs", "LVs"],
+            view.report
+                .vgs
+                .iter()
+                .map(|vg| {
+                    Row::new(vec![
+                        vg.name.clone(),
+                        size(vg.size),
+                        size(vg.free),
+                        format!("{} × {}", vg.free_extents, size(vg.extent_size)),
+                        vg.pv_count.to_string(),
+                        vg.lv_count.to_string(),
+                    ])
+                })
+                .collect(),
+            vec![
+                Constraint::Fill(2),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+                Constraint::Fill(2),
+                Constraint::Length(5),
+                Constraint::Length(5),
+            ],
+        ),
+        LvmSection::LogicalVolumes => (
+            vec!["Name", "Volume Group", "Size", "Path", "State"],
+            view.report
+                .lvs
+                .iter()
+                .map(|lv| {
+                    Row::new(vec![
+                        Cell::from(lv.name.clone()),
+                        Cell::from(lv.vg_name.clone()),
+                        Cell::from(size(lv.size)),
+                        Cell::from(lv.path.clone()),
+                        if lv.active {
+                            Cell::from("active").style(Style::default().fg(Color::Green))
+                        } else {
+                            Cell::from("inactive").style(Style::default().fg(Color::DarkGray))
+                        },
+                    ])
+                })
+                .collect(),
+            vec![
+                Constraint::Fill(2),
+                Constraint::Fill(2),
+                Constraint::Fill(1),
+                Constraint::Fill(3),
+                Constraint::Length(8),
+            ],
+        ),
+    };
+
+    if rows.is_empty() {
+        let empty = match view.section {
+            LvmSection::PhysicalVolumes => "No physical volumes",
+            LvmSection::VolumeGroups => "No volume groups",
+            LvmSection::LogicalVolumes => "No logical volumes",
+        };
+        frame.render_widget(
+            Paragraph::new(Line::from(empty).style(Style::default().fg(Color::DarkGray)).centered())
+                .block(Block::default().borders(Borders::TOP)),
+            chunks[1],
+        );
+    } else {
+        let table = Table::new(rows, widths)
+            .header(Row::new(header).style(Style::default().fg(Color::Yellow).bold()))
+            .block(Block::default().borders(Borders::TOP))
+            .row_highlight_style(
+                Style::default()
+                    .bg(app.theme.highlight_bg)
+                    .fg(app.theme.highlight_fg),
+            );
+        frame.render_stateful_widget(table, chunks[1], &mut view.state);
+    }
+
+    if let Some(form) = &view.form {
+        let block = Block::default()
+            .title(format!(" {} ", form.title()))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(app.theme.focus_border));
+        let form_area = block.inner(chunks[2]);
+        frame.render_widget(block, chunks[2]);
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![Constraint::Length(3); form.fields.len()])
+            .split(form_area);
+        for (index, (label, input)) in form.fields.iter().enumerate() {
+            let border = if index == form.focus {
+                app.theme.focus_border
+            } else {
+                app.theme.normal_border
+            };
+            frame.render_widget(
+                Paragraph::new(input.value()).block(
+                    Block::default()
+                        .title(format!(" {} ", label))
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(border)),
+                ),
+                rows[index],
+            );
+        }
+    }
+
+    let help = match (&view.form, view.section) {
+        (Some(form), _) => match &form.error {
+            Some(error) => Line::from(error.clone()).style(Style::default().fg(app.theme.error)),
+            None if matches!(form.action, LvmAction::CreateLogicalVolume { .. }) => {
+                Line::from("Size: 10G, 50%, -1G (leave free) | Tab next field | Enter create | Esc cancel")
+                    .style(Style::default().fg(Color::DarkGray))
+            }
+            None => Line::from("Enter to confirm  |  Esc to cancel")
+                .style(Style::default().fg(Color::DarkGray)),
+        },
+        (None, LvmSection::PhysicalVolumes) => Line::from(match &view.candidate {
+            Some(device) => format!(
+                "n make {} a PV  |  v new VG  |  a add to VG  |  Tab section  |  Esc close",
+                device
+            ),
+            None => "v new VG from unused PV  |  a add to VG  |  Tab section  |  Esc close".to_string(),
+        })
+        .style(Style::default().fg(Color::DarkGray)),
+        (None, LvmSection::VolumeGroups) => {
+            Line::from("n new LV  |  Tab section  |  Esc close")
+                .style(Style::default().fg(Color::DarkGray))
+        }
+        (None, LvmSection::LogicalVolumes) => {
+            Line::from("n new LV  |  r resize  |  d remove  |  Tab section  |  Esc close")
+                .style(Style::default().fg(Color::DarkGray))
+        }
+    };
+    frame.render_widget(help.centered(), chunks[3]);
+}
+
+fn render_subvolume_view(app: &mut App, frame: &mut Frame) {
+    use crate::app::SubvolumeAction;
+
+    let area = frame.area().inner(ratatui::layout::Margin {
+        horizontal: frame.area().width / 10,
+        vertical: frame.area().height / 10,
+    });
+
+    let view = &mut app.subvolumes;
+    let title = match view.list.mount_point.as_str() {
+        "" => format!(" Subvolumes of {} ", view.device),
+        mount_point => format!(" Subvolumes of {} ({}) ", view.device, mount_point),
+    };
+    let block = Block::default()
+        .title(title)
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Thick)
+        .border_style(Style::default().fg(Color::Green));
+    let inner = block.inner(area);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+
+    let status = if view.loading && view.list == Default::default() {
+        Some(Line::from("Loading...").style(Style::default().fg(Color::DarkGray)))
+    } else {
+        view.error
+            .as_ref()
+            .map(|error| Line::from(error.clone()).style(Style::default().fg(Color::Red)))
+    };
+    if let Some(status) = status {
+        frame.render_widget(Paragraph::new(status.centered()), inner);
+        return;
+    }
+
+    let form_height = if view.form.is_some() { 5 } else { 0 };
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(form_height),
+            Constraint::Length(1),
+        ])
+        .split(inner);
+
+    let list = &view.list;
+    let rows: Vec<Row> = list
+        .subvolumes
+        .iter()
+        .map(|subvolume| {
+            let mut flags = Vec::new();
+            if subvolume.id == list.default_id {
+                flags.push("default");
+            }
+            if list.local_path(subvolume).as_deref() == Some("") {
+                flags.push("mounted");
+            }
+            if subvolume.read_only {
+                flags.push("read-only");
+            }
+            Row::new(vec![
+                Cell::from(subvolume.id.to_string()),
+                Cell::from(subvolume.display_path().to_string()),
+                match list.absolute_path(subvolume) {
+                    Some(path) => Cell::from(path),
+                    None => Cell::from("not below the mount").style(Style::default().fg(Color::DarkGray)),
+                },
+                Cell::from(flags.join(", ")).style(Style::default().fg(Color::Cyan)),
+            ])
+        })
+        .collect();
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(6),
+            Constraint::Fill(2),
+            Constraint::Fill(3),
+            Constraint::Length(26),
+        ],
+    )
+    .header(
+        Row::new(vec!["ID", "Subvolume", "Path", "Flags"])
+            .style(Style::default().fg(Color::Yellow).bold()),
+    )
+    .row_highlight_style(
+        Style::default()
+            .bg(app.theme.highlight_bg)
+            .fg(app.theme.highlight_fg),
+    );
+    frame.render_stateful_widget(table, chunks[0], &mut view.state);
+
+    if let Some(form) = &view.form {
+        let block = Block::default()
+            .title(format!(" {} ", form.title()))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(app.theme.focus_border));
+        let form_area = block.inner(chunks[1]);
+        frame.render_widget(block, chunks[1]);
+        frame.render_widget(
+            Paragraph::new(form.input.value()).block(
+                Block::default()
+                    .title(" Path below the mount point ")
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(app.theme.focus_border)),
+            ),
+            form_area,
+        );
+    }
+
+    let help = match &view.form {
+        Some(form) => match &form.error {
+            Some(error) => Line::from(error.clone()).style(Style::default().fg(app.theme.error)),
+            None => Line::from(match form.action {
+                SubvolumeAction::Create => "Enter create  |  Esc cancel",
+                SubvolumeAction::Snapshot { .. } => "Enter take snapshot  |  Esc cancel",
+            })
+            .style(Style::default().fg(Color::DarkGray)),
+        },
+        None => Line::from("n new  |  s snapshot  |  d delete  |  * set default  |  Esc close")
+            .style(Style::default().fg(Color::DarkGray)),
+    };
+    frame.render_widget(help.centered(), chunks[2]);
+}
+
+fn render_raid_view(app: &mut App, frame: &mut Frame) {
+    use crate::app::RaidFormField;
+    use crate::raid::{self, RaidArray};
+
+    let area = frame.area().inner(ratatui::layout::Margin {
+        horizontal: frame.area().width / 10,
+        vertical: frame.area().height / 10,
+    });
+
+    let block = Block::default()
+        .title(" RAID ")
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Thick)
+        .border_style(Style::default().fg(Color::Green));
+    let inner = block.inner(area);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(block, area);
+
+    let units = app.size_units;
+    let theme = &app.theme;
+    let view = &mut app.raid;
+    let status = if view.loading && view.report == Default::default() {
+        Some(Line::from("Loading...").style(Style::default().fg(Color::DarkGray)))
+    } else {
+        view.error
+            .as_ref()
+            .map(|error| Line::from(error.clone()).style(Style::default().fg(Color::Red)))
+    };
+    if let Some(status) = status {
+        frame.render_widget(Paragraph::new(status.centered()), inner);
+        return;
+    }
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+        ])
+        .split(inner);
+
+    let state_style = |array: &RaidArray| match () {
+        _ if !array.active => Style::default().fg(Color::DarkGray),
+        _ if array.is_degraded() => Style::default().fg(theme.error),
+        _ if array.progress.is_some() => Style::default().fg(Color::Yellow),
+        _ => Style::default().fg(Color::Green),
+    };
+    let mut rows: Vec<Row> = view
+        .report
+        .arrays
+        .iter()
+        .map(|array| {
+            Row::new(vec![
+                Cell::from(array.name.clone()),
+                Cell::from(array.level.clone().unwrap_or_default()),
+                Cell::from(format_size(array.size, units)),
+                Cell::from(array.state()).style(state_style(array)),
+                Cell::from(
+                    array
+                        .raid_devices
+                        .map(|(devices, working)| format!("{}/{}", working, devices))
+                        .unwrap_or_default(),
+                ),
+                Cell::from(array.progress.as_ref().map(|p| p.summary()).unwrap_or_default()),
+            ])
+        })
+        .collect();
+    rows.extend(view.report.stopped.iter().map(|stopped| {
+        Row::new(vec![
+            Cell::from(stopped.name.clone()),
+            Cell::from(""),
+            Cell::from(""),
+            Cell::from("stopped").style(Style::default().fg(Color::DarkGray)),
+            Cell::from(""),
+            Cell::from(stopped.uuid.clone()).style(Style::default().fg(Color::DarkGray)),
+        ])
+    }));
+
+    let border = |focused: bool| match focused {
+        true => Style::default().fg(theme.focus_border),
+        false => Style::default().fg(theme.normal_border),
+    };
+    let highlight = Style::default().bg(theme.highlight_bg).fg(theme.highlight_fg);
+    let arrays_block = Block::default()
+        .title(" Arrays ")
+        .borders(Borders::ALL)
+        .border_style(border(!view.members_focused && view.form.is_none()));
+    if rows.is_empty() {
+        frame.render_widget(
+            Paragraph::new(Line::from("No md arrays").style(Style::default().fg(Color::DarkGray)).centered())
+                .block(arrays_block),
+            chunks[0],
+        );
+    } else {
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(1),
+                Constraint::Length(7),
+                Constraint::Length(10),
+                Constraint::Fill(2),
+                Constraint::Length(7),
+                Constraint::Fill(3),
+            ],
+        )
+        .header(
+            Row::new(vec!["Name", "Level", "Size", "State", "Devices", "Sync"])
+                .style(Style::default().fg(Color::Yellow).bold()),
+        )
+        .block(arrays_block)
+        .row_highlight_style(highlight);
+        frame.render_stateful_widget(table, chunks[0], &mut view.state);
+    }
+
+    if let Some(form) = &mut view.form {
+        let block = Block::default()
+            .title(format!(" {} ", form.title()))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(theme.focus_border));
+        let form_area = block.inner(chunks[1]);
+        frame.render_widget(block, chunks[1]);
+        let parts = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(match form.array {
+                Some(_) => vec![Constraint::Length(0), Constraint::Length(0), Constraint::Fill(1)],
+                None => vec![Constraint::Length(3), Constraint::Length(1), Constraint::Fill(1)],
+            })
+            .split(form_area);
+        let field_border = |field: RaidFormField| border(form.focus == field);
+
+        if form.array.is_none() {
+            frame.render_widget(
+                Paragraph::new(form.name.value()).block(
+                    Block::default()
+                        .title(" Name ")
+                        .borders(Borders::ALL)
+                        .border_style(field_border(RaidFormField::Name)),
+                ),
+                parts[0],
+            );
+            let (level, min) = raid::LEVELS[form.level];
+            let level_style = match form.focus {
+                RaidFormField::Level => highlight,
+                _ => Style::default(),
+            };
+            frame.render_widget(
+                Line::from(vec![
+                    Span::raw(" Level: "),
+                    Span::styled(format!(" ◀ {} ▶ ", level), level_style),
+                    Span::styled(
+                        format!("  at least {} devices", min),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                ]),
+                parts[1],
+            );
+        }
+
+        let picking = form.array.is_none();
+        let items: Vec<ListItem> = form
+            .devices
+            .iter()
+            .map(|(device, size, picked)| {
+                let mark = match (picking, picked) {
+                    (false, _) => "",
+                    (true, true) => "[x] ",
+                    (true, false) => "[ ] ",
+                };
+                ListItem::new(format!("{}{}  {}", mark, device, format_size(*size, units)))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .title(" Unused partitions ")
+                    .borders(Borders::ALL)
+                    .border_style(field_border(RaidFormField::Devices)),
+            )
+            .highlight_style(highlight);
+        frame.render_stateful_widget(list, parts[2], &mut form.state);
+    } else {
+        let members = view.selected_array().map(|array| &array.members);
+        let rows: Vec<Row> = members
+            .into_iter()
+            .flatten()
+            .map(|member| {
+                let style = match () {
+                    _ if member.faulty => Style::default().fg(theme.error),
+                    _ if member.spare => Style::default().fg(Color::Yellow),
+                    _ => Style::default(),
+                };
+                Row::new(vec![
+                    Cell::from(member.name.clone()),
+                    Cell::from(member.slot.map(|slot| slot.to_string()).unwrap_or_default()),
+                    Cell::from(member.state()).style(style),
+                ])
+            })
+            .collect();
+        let table = Table::new(rows, [Constraint::Fill(1), Constraint::Length(6), Constraint::Fill(2)])
+            .header(Row::new(vec!["Device", "Slot", "State"]).style(Style::default().fg(Color::Yellow).bold()))
+            .block(
+                Block::default()
+                    .title(" Members ")
+                    .borders(Borders::ALL)
+                    .border_style(border(view.members_focused)),
+            )
+            .row_highlight_style(highlight);
+        frame.render_stateful_widget(table, chunks[1], &mut view.member_state);
+    }
+
+    let help = match &view.form {
+        Some(form) => match &form.error {
+            Some(error) => Line::from(error.clone()).style(Style::default().fg(theme.error)),
+            None if form.array.is_some() => Line::from("j/k select  |  Enter add  |  Esc cancel")
+                .style(Style::default().fg(Color::DarkGray)),
+            None => Line::from("Tab next field  |  ←/→ level  |  Space pick device  |  Enter create  |  Esc cancel")
+                .style(Style::default().fg(Color::DarkGray)),
+        },
+        None if view.members_focused => {
+            Line::from("f mark failed  |  d remove  |  Tab arrays  |  Esc close")
+                .style(Style::default().fg(Color::DarkGray))
+        }
+        None if view.selected_stopped().is_some() => {
+            Line::from("a assemble  |  n new array  |  Esc close")
+                .style(Style::default().fg(Color::DarkGray))
+        }
+        None => Line::from(
+            "n new array  |  a add device  |  s stop  |  Enter follow sync  |  Tab members  |  Esc close",
+        )
+        .style(Style::default().fg(Color::DarkGray)),
+    };
+    frame.render_widget(help.centered(), chunks[2]);
+}
+
+fn render_progress_dialog(app: &App, frame: &mut Frame) {
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(10),
+            Constraint::Fill(1),
+        ])
+        .split(frame.area());
+
+    let area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Fill(1),
             Constraint::Length(60),
             Constraint::Fill(1),
         ])
@@ -956,18 +2396,60 @@ fn render_progress_dialog(app: &App, frame: &mut Frame) {
         .alignment(Alignment::Center);
 
     // Status message
-    let status_text = Paragraph::new("Please wait while the operation completes...")
+    let status = match app.progress.raid {
+        Some(_) => "The array keeps syncing in the background. Esc to hide",
+        None => "Please wait while the operation completes...",
+    };
+    let status_text = Paragraph::new(status)
         .style(Style::default().fg(Color::DarkGray))
         .alignment(Alignment::Center);
 
-    frame.render_widget(spinner_text, chunks[1]);
+    match app.progress.percent {
+        Some(percent) => {
+            let gauge = Gauge::default()
+                .gauge_style(Style::default().fg(Color::Cyan))
+                .ratio((percent / 100.0).clamp(0.0, 1.0))
+                .label(format!("{:.1}%", percent));
+            let gauge_area = chunks[1].inner(ratatui::layout::Margin {
+                horizontal: 2,
+                vertical: 1,
+            });
+            frame.render_widget(gauge, gauge_area);
+        }
+        None => frame.render_widget(spinner_text, chunks[1]),
+    }
     frame.render_widget(status_text, chunks[2]);
 }
 
 fn render_confirmation_dialog(app: &mut App, frame: &mut Frame) {
+    let command_lines: Vec<Line> = match &app.confirmation_dialog.commands {
+        CommandPreview::None => Vec::new(),
+        CommandPreview::Loading => vec![Line::from("  Checking...")],
+        CommandPreview::Ready(commands) if commands.is_empty() => {
+            vec![Line::from("  (no commands)")]
+        }
+        CommandPreview::Ready(commands) => commands
+            .iter()
+            .map(|command| {
+                Line::from(vec![
+                    Span::styled("  $ ", Style::default().fg(Color::DarkGray)),
+                    Span::styled(command.clone(), Style::default().fg(Color::White)),
+                ])
+            })
+            .collect(),
+        CommandPreview::Failed(message) => {
+            vec![Line::from(format!("  {}", message)).style(Style::default().fg(Color::Red))]
+        }
+    };
+
     // Calculate dialog height based on content
     let details_count = app.confirmation_dialog.details.len();
-    let dialog_height = 10 + details_count as u16;
+    let commands_height = if command_lines.is_empty() {
+        0
+    } else {
+        command_lines.len() + 2
+    };
+    let dialog_height = 10 + (details_count + commands_height) as u16;
 
     let popup_layout = Layout::default()
         .direction(Direction::Vertical)
@@ -982,7 +2464,7 @@ fn render_confirmation_dialog(app: &mut App, frame: &mut Frame) {
         .direction(Direction::Horizontal)
         .constraints([
             Constraint::Fill(1),
-            Constraint::Length(60),
+            Constraint::Length(if command_lines.is_empty() { 60 } else { 76 }),
             Constraint::Fill(1),
         ])
         .split(popup_layout[1])[1];
@@ -1023,12 +2505,29 @@ fn render_confirmation_dialog(app: &mut App, frame: &mut Frame) {
         text_lines.push(Line::from(""));
     }
 
+    if !command_lines.is_empty() {
+        text_lines.push(
+            Line::from("Commands to be run:")
+                .style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)),
+        );
+        text_lines.extend(command_lines);
+        text_lines.push(Line::from(""));
+    }
+
     // Warning message
-    text_lines.push(
-        Line::from("⚠ WARNING: This operation cannot be undone! ⚠")
-            .style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
-            .centered(),
-    );
+    if app.queue.enabled && app.confirmation_dialog.operation.is_queueable() {
+        text_lines.push(
+            Line::from("Will be added to the pending operations queue")
+                .style(Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD))
+                .centered(),
+        );
+    } else {
+        text_lines.push(
+            Line::from("⚠ WARNING: This operation cannot be undone! ⚠")
+                .style(Style::default().fg(Color::Red).add_modifier(Modifier::BOLD))
+                .centered(),
+        );
+    }
     text_lines.push(Line::from(""));
 
     // Buttons
@@ -1094,7 +2593,7 @@ fn render_resize_dialog(app: &mut App, frame: &mut Frame) {
         .split(popup_layout[1])[1];
 
     if let Some(partition) = app.selected_partition() {
-        let current_size_str = format_bytes(partition.size);
+        let current_size_str = format_size(partition.size, app.size_units);
         let filesystem = partition
             .filesystem
             .clone()
@@ -1116,7 +2615,7 @@ fn render_resize_dialog(app: &mut App, frame: &mut Frame) {
                 Constraint::Length(1), // Spacing
                 Constraint::Length(1), // Label line
                 Constraint::Length(3), // Input box with border
-                Constraint::Length(1), // Spacing
+                Constraint::Length(1), // Parsed size
                 Constraint::Length(4), // Info text (4 lines)
                 Constraint::Fill(1),   // Remaining space
             ])
@@ -1136,8 +2635,17 @@ fn render_resize_dialog(app: &mut App, frame: &mut Frame) {
         let size_input = Paragraph::new(app.resize_dialog.size_input.value())
             .block(Block::default().borders(Borders::ALL));
 
+        let preview = match app.resize_preview() {
+            Some(Ok(size)) => Paragraph::new(format!("= {}", format_size(size, app.size_units)))
+                .style(Style::default().fg(app.theme.success)),
+            Some(Err(e)) => {
+                Paragraph::new(e.to_string()).style(Style::default().fg(app.theme.error))
+            }
+            None => Paragraph::new(""),
+        };
+
         let help_text = Paragraph::new(
-            "Examples: 100M, 2.5G, 1T\n\
+            "Examples: 512M, 2.5GiB, 10GB, 50%, -1G, rest\n\
              Supports both growing and shrinking.\n\
              \n\
              Enter: Confirm | Esc: Cancel",
@@ -1148,10 +2656,448 @@ fn render_resize_dialog(app: &mut App, frame: &mut Frame) {
         frame.render_widget(info_text, chunks[0]);
         frame.render_widget(size_label, chunks[2]);
         frame.render_widget(size_input, chunks[3]);
+        frame.render_widget(preview, chunks[4]);
         frame.render_widget(help_text, chunks[5]);
     }
 }
 
+fn render_part_type_dialog(app: &mut App, frame: &mut Frame) {
+    use crate::app::TypeDialogField;
+
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(21),
+            Constraint::Fill(1),
+        ])
+        .split(frame.area());
+
+    let area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(56),
+            Constraint::Fill(1),
+        ])
+        .split(popup_layout[1])[1];
+
+    let dialog = &mut app.part_type_dialog;
+    let border_block = Block::default()
+        .title(format!(" Type and Flags of {} ", dialog.partition))
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Thick)
+        .border_style(Style::default().fg(Color::Green));
+    let inner_area = border_block.inner(area);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(border_block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(dialog.types.len() as u16 + 2), // Type list
+            Constraint::Length(if dialog.gpt { 3 } else { 0 }), // Name input
+            Constraint::Length(dialog.flags.len() as u16 + 2),  // Flags
+            Constraint::Fill(1),
+            Constraint::Length(1), // Help text
+        ])
+        .split(inner_area);
+
+    let field_block = |title: &'static str, field: TypeDialogField| {
+        let block = Block::default().title(title).borders(Borders::ALL);
+        if dialog.focus == field {
+            block.border_style(Style::default().fg(app.theme.focus_border))
+        } else {
+            block
+        }
+    };
+
+    let types = List::new(dialog.types.iter().map(|t| ListItem::new(t.name)))
+        .block(field_block(" Type ", TypeDialogField::Type))
+        .highlight_style(Style::default().bg(Color::DarkGray).fg(Color::White));
+    frame.render_stateful_widget(types, chunks[0], &mut dialog.type_state);
+
+    if dialog.gpt {
+        let name = Paragraph::new(dialog.name_input.value())
+            .block(field_block(" Name ", TypeDialogField::Name));
+        frame.render_widget(name, chunks[1]);
+    }
+
+    let flags = List::new(dialog.flags.iter().map(|(flag, enabled)| {
+        ListItem::new(format!("[{}] {}", if *enabled { "x" } else { " " }, flag))
+    }))
+    .block(field_block(" Flags ", TypeDialogField::Flags))
+    .highlight_style(Style::default().bg(Color::DarkGray).fg(Color::White));
+    frame.render_stateful_widget(flags, chunks[2], &mut dialog.flag_state);
+
+    let info = Paragraph::new("Tab: Next field | Space: Toggle flag | Enter: Apply | Esc: Cancel")
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(Color::Yellow));
+    frame.render_widget(info, chunks[4]);
+}
+
+fn render_swap_file_dialog(app: &App, frame: &mut Frame) {
+    use crate::app::SwapFileField;
+    use crate::utils::Amount;
+
+    let dialog = &app.swap_file_dialog;
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(14 + dialog.active.len() as u16),
+            Constraint::Fill(1),
+        ])
+        .split(frame.area());
+
+    let area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(64),
+            Constraint::Fill(1),
+        ])
+        .split(popup_layout[1])[1];
+
+    let border_block = Block::default()
+        .title(format!(" Swap File on {} ", dialog.mount_point))
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Thick)
+        .border_style(Style::default().fg(Color::Green));
+    let inner_area = border_block.inner(area);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(border_block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1 + dialog.active.len() as u16), // Active swap files
+            Constraint::Length(3),                              // Path input
+            Constraint::Length(3),                              // Size input
+            Constraint::Length(1),                              // Parsed size
+            Constraint::Fill(1),
+            Constraint::Length(1), // Help text
+        ])
+        .split(inner_area);
+
+    let mut active = vec![Line::from("Active swap files:")];
+    if dialog.active.is_empty() {
+        active[0] = Line::from("No active swap files on this filesystem");
+    }
+    for swap in &dialog.active {
+        active.push(Line::from(format!(
+            "  {} ({}, {} used, pri {})",
+            swap.path,
+            format_size(swap.size, app.size_units),
+            format_size(swap.used, app.size_units),
+            swap.priority
+        )));
+    }
+    frame.render_widget(Paragraph::new(active), chunks[0]);
+
+    let field_block = |title: &'static str, field: SwapFileField| {
+        let block = Block::default().title(title).borders(Borders::ALL);
+        if dialog.focus == field {
+            block.border_style(Style::default().fg(app.theme.focus_border))
+        } else {
+            block
+        }
+    };
+    let path = Paragraph::new(dialog.path_input.value()).block(field_block(" Path ", SwapFileField::Path));
+    frame.render_widget(path, chunks[1]);
+
+    let disabling = dialog.selected_active().is_some();
+    if !disabling {
+        let size = Paragraph::new(dialog.size_input.value()).block(field_block(" Size ", SwapFileField::Size));
+        frame.render_widget(size, chunks[2]);
+
+        let preview = match dialog.size_input.value().trim() {
+            "" => Paragraph::new("Examples: 512M, 2G, 4GiB"),
+            input => match input.parse::<Amount>() {
+                Ok(Amount::Bytes(bytes)) => Paragraph::new(format!("= {}", format_size(bytes, app.size_units)))
+                    .style(Style::default().fg(app.theme.success)),
+                Ok(Amount::Sectors(_)) => Paragraph::new("Give the size in bytes, not sectors")
+                    .style(Style::default().fg(app.theme.error)),
+                Err(e) => Paragraph::new(e.to_string()).style(Style::default().fg(app.theme.error)),
+            },
+        };
+        frame.render_widget(preview, chunks[3]);
+    }
+
+    let action = if disabling { "Disable" } else { "Create" };
+    let info = Paragraph::new(format!("Tab: Next field | Enter: {} | Esc: Cancel", action))
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(Color::Yellow));
+    frame.render_widget(info, chunks[5]);
+}
+
+fn render_mount_dialog(app: &mut App, frame: &mut Frame) {
+    use crate::app::MountField;
+
+    let theme = &app.theme;
+    let dialog = &mut app.mount_dialog;
+    let offers_compress = dialog.fields().contains(&MountField::Compress);
+    let compress_height = if offers_compress { 7 } else { 0 };
+    let options_height = dialog.toggles.len() as u16 + 2;
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(3 + options_height + compress_height + 4),
+            Constraint::Fill(1),
+        ])
+        .split(frame.area());
+
+    let area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(60),
+            Constraint::Fill(1),
+        ])
+        .split(popup_layout[1])[1];
+
+    let fs_type = dialog.fs_type.as_deref().unwrap_or("encrypted");
+    let border_block = Block::default()
+        .title(format!(" Mount {} ({}) ", dialog.device, fs_type))
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Thick)
+        .border_style(Style::default().fg(Color::Green));
+    let inner_area = border_block.inner(area);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(border_block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),               // Mount point
+            Constraint::Length(options_height),  // Options
+            Constraint::Length(compress_height), // Compression and subvolume
+            Constraint::Fill(1),
+            Constraint::Length(1), // Help text
+        ])
+        .split(inner_area);
+
+    let field_block = |title: &'static str, field: MountField| {
+        let block = Block::default().title(title).borders(Borders::ALL);
+        if dialog.focus == field {
+            block.border_style(Style::default().fg(theme.focus_border))
+        } else {
+            block
+        }
+    };
+
+    let target = Paragraph::new(dialog.target_input.value())
+        .block(field_block(" Mount Point ", MountField::Target));
+    frame.render_widget(target, chunks[0]);
+
+    let options = List::new(dialog.toggles.iter().map(|(toggle, enabled)| {
+        ListItem::new(format!("[{}] {}", if *enabled { "x" } else { " " }, toggle.label()))
+    }))
+    .block(field_block(" Options ", MountField::Options))
+    .highlight_style(Style::default().bg(Color::DarkGray).fg(Color::White));
+    if dialog.focus == MountField::Options {
+        frame.render_stateful_widget(options, chunks[1], &mut dialog.toggle_state);
+    } else {
+        frame.render_widget(options, chunks[1]);
+    }
+
+    if offers_compress {
+        let compress_chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(1),
+            ])
+            .split(chunks[2]);
+        let compress = Paragraph::new(dialog.compress_input.value())
+            .block(field_block(" Compression (btrfs) ", MountField::Compress));
+        frame.render_widget(compress, compress_chunks[0]);
+        let subvol = Paragraph::new(dialog.subvol_input.value())
+            .block(field_block(" Subvolume (btrfs) ", MountField::Subvol));
+        frame.render_widget(subvol, compress_chunks[1]);
+
+        let value = dialog.compress_input.value().trim();
+        let subvol = dialog.subvol_input.value().trim();
+        let error = crate::mount::validate_compress(value)
+            .err()
+            .filter(|_| !value.is_empty())
+            .or_else(|| {
+                crate::mount::validate_subvol(subvol)
+                    .err()
+                    .filter(|_| !subvol.is_empty())
+            });
+        let status = match error {
+            Some(e) => Paragraph::new(e.to_string()).style(Style::default().fg(theme.error)),
+            None if dialog.focus == MountField::Subvol => {
+                Paragraph::new("Examples: @, @home; empty for the default subvolume")
+            }
+            None if value.is_empty() => Paragraph::new("Examples: zstd, zstd:3, lzo; empty for none"),
+            None => Paragraph::new(""),
+        };
+        frame.render_widget(status, compress_chunks[2]);
+    }
+
+    let info = Paragraph::new("Tab: Next field | Space: Toggle | Enter: Mount | Esc: Cancel")
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(Color::Yellow));
+    frame.render_widget(info, chunks[4]);
+}
+
+fn render_label_dialog(app: &App, frame: &mut Frame) {
+    let dialog = &app.label_dialog;
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(8),
+            Constraint::Fill(1),
+        ])
+        .split(frame.area());
+
+    let area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(54),
+            Constraint::Fill(1),
+        ])
+        .split(popup_layout[1])[1];
+
+    let border_block = Block::default()
+        .title(format!(" Label {} ({}) ", dialog.device, dialog.fs_type))
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Thick)
+        .border_style(Style::default().fg(Color::Green));
+    let inner_area = border_block.inner(area);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(border_block, area);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3), // Label input
+            Constraint::Length(1), // Validation
+            Constraint::Fill(1),
+            Constraint::Length(1), // Help text
+        ])
+        .split(inner_area);
+
+    let input = Paragraph::new(dialog.input.value()).block(
+        Block::default()
+            .title(" Label ")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(app.theme.focus_border)),
+    );
+    frame.render_widget(input, chunks[0]);
+
+    let status = match dialog.error() {
+        Some(error) => Paragraph::new(error).style(Style::default().fg(app.theme.error)),
+        None if dialog.input.value().is_empty() => Paragraph::new("An empty label clears it"),
+        None => Paragraph::new(""),
+    };
+    frame.render_widget(status, chunks[1]);
+
+    let info = Paragraph::new("Enter: Apply | Esc: Cancel")
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(Color::Yellow));
+    frame.render_widget(info, chunks[3]);
+}
+
+fn render_boot_table_dialog(app: &App, frame: &mut Frame) {
+    use crate::app::{BootTable, BootTableField};
+
+    let dialog = &app.boot_table_dialog;
+    let fields = dialog.fields();
+    let popup_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(fields.len() as u16 * 3 + 5),
+            Constraint::Fill(1),
+        ])
+        .split(frame.area());
+
+    let area = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Fill(1),
+            Constraint::Length(78),
+            Constraint::Fill(1),
+        ])
+        .split(popup_layout[1])[1];
+
+    let border_block = Block::default()
+        .title(format!(" {} entry for {} ", dialog.table.path(), dialog.device))
+        .title_alignment(Alignment::Center)
+        .borders(Borders::ALL)
+        .border_type(BorderType::Thick)
+        .border_style(Style::default().fg(Color::Green));
+    let inner_area = border_block.inner(area);
+
+    frame.render_widget(Clear, area);
+    frame.render_widget(border_block, area);
+
+    let mut constraints: Vec<Constraint> = fields.iter().map(|_| Constraint::Length(3)).collect();
+    constraints.extend([
+        Constraint::Length(1), // Status
+        Constraint::Fill(1),
+        Constraint::Length(1), // Help text
+    ]);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(constraints)
+        .split(inner_area);
+
+    for (chunk, field) in chunks.iter().zip(&fields) {
+        let (title, input) = match (field, dialog.table) {
+            (BootTableField::Target, BootTable::Fstab) => (" Mount Point ", &dialog.target_input),
+            (BootTableField::Target, BootTable::Crypttab) => (" Name under /dev/mapper ", &dialog.target_input),
+            (BootTableField::Options, _) => (" Options ", &dialog.options_input),
+            (BootTableField::Pass, _) => (" Pass (0 none, 1 root, 2 others) ", &dialog.pass_input),
+        };
+        let mut block = Block::default().title(title).borders(Borders::ALL);
+        if dialog.focus == *field {
+            block = block.border_style(Style::default().fg(app.theme.focus_border));
+        }
+        frame.render_widget(Paragraph::new(input.value()).block(block), *chunk);
+    }
+
+    let status = match (&dialog.existing, dialog.request()) {
+        (_, Err(e)) => Paragraph::new(e.to_string()).style(Style::default().fg(app.theme.error)),
+        (Some(existing), _) if dialog.stale => Paragraph::new(format!("Replaces {}, whose device is gone", existing))
+            .style(Style::default().fg(app.theme.warning)),
+        (Some(existing), _) => Paragraph::new(format!("Replaces the entry for {}", existing)),
+        (None, _) => Paragraph::new(format!("Adds an entry to {}", dialog.table.path())),
+    };
+    frame.render_widget(status, chunks[fields.len()]);
+
+    let mut help = vec!["Tab: Field", "Enter: Save"];
+    if dialog.existing.is_some() {
+        help.push("Ctrl+D: Remove");
+    }
+    if dialog.switchable {
+        help.push(match dialog.table {
+            BootTable::Fstab => "Ctrl+T: crypttab",
+            BootTable::Crypttab => "Ctrl+T: fstab",
+        });
+    }
+    help.push("Esc: Cancel");
+    let info = Paragraph::new(help.join(" | "))
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(Color::Yellow));
+    frame.render_widget(info, chunks[fields.len() + 2]);
+}
+
 fn render_passphrase_dialog(app: &App, frame: &mut Frame) {
     use crate::app::PassphraseOperation;
 
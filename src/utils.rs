@@ -1,33 +1,261 @@
+use anyhow::{Result, anyhow};
+use serde::Deserialize;
+use std::str::FromStr;
+
+const KIB: u64 = 1024;
+
+/// Unit system sizes are displayed in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SizeUnits {
+    /// Powers of 1000: KB, MB, GB, TB.
+    #[default]
+    Si,
+    /// Powers of 1024: KiB, MiB, GiB, TiB.
+    Iec,
+}
+
+/// Formats a size in SI units.
 pub fn format_bytes(bytes: u64) -> String {
-    if bytes >= 1_000_000_000_000 {
-        let val = bytes as f64 / 1_000_000_000_000.0;
-        if bytes.is_multiple_of(1_000_000_000_000) {
-            format!("{}TB", bytes / 1_000_000_000_000)
-        } else {
-            format!("{:.1}TB", val)
-        }
-    } else if bytes >= 1_000_000_000 {
-        let val = bytes as f64 / 1_000_000_000.0;
-        if bytes.is_multiple_of(1_000_000_000) {
-            format!("{}GB", bytes / 1_000_000_000)
-        } else {
-            format!("{:.1}GB", val)
-        }
-    } else if bytes >= 1_000_000 {
-        let val = bytes as f64 / 1_000_000.0;
-        if bytes.is_multiple_of(1_000_000) {
-            format!("{}MB", bytes / 1_000_000)
-        } else {
-            format!("{:.1}MB", val)
-        }
-    } else if bytes >= 1_000 {
-        let val = bytes as f64 / 1_000.0;
-        if bytes.is_multiple_of(1_000) {
-            format!("{}KB", bytes / 1_000)
-        } else {
-            format!("{:.1}KB", val)
-        }
-    } else {
-        format!("{}B", bytes)
+    format_size(bytes, SizeUnits::Si)
+}
+
+pub fn format_size(bytes: u64, units: SizeUnits) -> String {
+    let (base, suffixes) = match units {
+        SizeUnits::Si => (1000u64, ["KB", "MB", "GB", "TB"]),
+        SizeUnits::Iec => (KIB, ["KiB", "MiB", "GiB", "TiB"]),
+    };
+
+    let mut scale = base.pow(suffixes.len() as u32);
+    for suffix in suffixes.iter().rev() {
+        if bytes >= scale {
+            return if bytes.is_multiple_of(scale) {
+                format!("{}{}", bytes / scale, suffix)
+            } else {
+                format!("{:.1}{}", bytes as f64 / scale as f64, suffix)
+            };
+        }
+        scale /= base;
+    }
+    format!("{}B", bytes)
+}
+
+/// A fixed amount of space, in bytes or in sectors of the disk.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Amount {
+    Bytes(u64),
+    Sectors(u64),
+}
+
+impl Amount {
+    pub fn bytes(self, sector_size: u64) -> Result<u64> {
+        match self {
+            Amount::Bytes(bytes) => Ok(bytes),
+            Amount::Sectors(sectors) => sectors
+                .checked_mul(sector_size)
+                .ok_or_else(|| anyhow!("{} sectors is too large", sectors)),
+        }
+    }
+}
+
+impl FromStr for Amount {
+    type Err = anyhow::Error;
+
+    /// K, M, G and T (with or without "iB") are powers of 1024, KB, MB, GB
+    /// and TB powers of 1000, like in fdisk. A trailing "s" counts sectors.
+    fn from_str(input: &str) -> Result<Self> {
+        let lower = input.trim().to_lowercase();
+        if let Some(sectors) = lower.strip_suffix('s') {
+            return sectors
+                .trim()
+                .parse()
+                .map(Amount::Sectors)
+                .map_err(|_| anyhow!("Invalid sector count: {}", input.trim()));
+        }
+
+        const UNITS: [(&str, u64); 13] = [
+            ("kib", KIB),
+            ("mib", KIB.pow(2)),
+            ("gib", KIB.pow(3)),
+            ("tib", KIB.pow(4)),
+            ("kb", 1_000),
+            ("mb", 1_000_000),
+            ("gb", 1_000_000_000),
+            ("tb", 1_000_000_000_000),
+            ("k", KIB),
+            ("m", KIB.pow(2)),
+            ("g", KIB.pow(3)),
+            ("t", KIB.pow(4)),
+            ("b", 1),
+        ];
+        let (number, unit) = UNITS
+            .iter()
+            .find_map(|(suffix, unit)| Some((lower.strip_suffix(suffix)?, *unit)))
+            .unwrap_or((lower.as_str(), 1));
+
+        let number: f64 = number.trim().parse().map_err(|_| {
+            anyhow!("Invalid size format. Use a size like 512MiB, 2.5G, 1TB or 2048s")
+        })?;
+        if !number.is_finite() || number < 0.0 {
+            return Err(anyhow!("Invalid size: {}", input.trim()));
+        }
+        let bytes = (number * unit as f64).round();
+        // `as` saturates, so sizes past u64::MAX have to be caught here.
+        if bytes >= u64::MAX as f64 {
+            return Err(anyhow!("Size is too large: {}", input.trim()));
+        }
+        Ok(Amount::Bytes(bytes as u64))
+    }
+}
+
+/// A size typed by the user. Besides fixed amounts it can be relative to
+/// the space available: a percentage of it, all of it ("rest", or an empty
+/// input), or all but an amount ("-1G" leaves 1 GiB free).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SizeSpec {
+    Amount(Amount),
+    Percent(f64),
+    Rest,
+    LeaveFree(Amount),
+}
+
+impl SizeSpec {
+    /// Size in bytes, given the space available and the disk's sector size.
+    pub fn resolve(self, available: u64, sector_size: u64) -> Result<u64> {
+        match self {
+            SizeSpec::Amount(amount) => amount.bytes(sector_size),
+            SizeSpec::Percent(percent) => Ok((available as f64 * percent / 100.0) as u64),
+            SizeSpec::Rest => Ok(available),
+            SizeSpec::LeaveFree(amount) => {
+                let free = amount.bytes(sector_size)?;
+                available.checked_sub(free).ok_or_else(|| {
+                    anyhow!(
+                        "Cannot leave {} free, only {} is available",
+                        format_bytes(free),
+                        format_bytes(available)
+                    )
+                })
+            }
+        }
+    }
+}
+
+impl FromStr for SizeSpec {
+    type Err = anyhow::Error;
+
+    fn from_str(input: &str) -> Result<Self> {
+        let input = input.trim();
+        if input.is_empty() || input.eq_ignore_ascii_case("rest") {
+            return Ok(SizeSpec::Rest);
+        }
+        if let Some(percent) = input.strip_suffix('%') {
+            let percent: f64 = percent
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("Invalid percentage: {}", input))?;
+            if !(percent > 0.0 && percent <= 100.0) {
+                return Err(anyhow!("Percentage must be between 0 and 100"));
+            }
+            return Ok(SizeSpec::Percent(percent));
+        }
+        if let Some(free) = input.strip_prefix('-') {
+            return Ok(SizeSpec::LeaveFree(free.parse()?));
+        }
+        Ok(SizeSpec::Amount(input.parse()?))
+    }
+}
+
+/// Formats a Unix timestamp as `YYYY-MM-DD HH:MM:SS` in UTC.
+pub fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amounts_use_binary_units_unless_spelled_decimal() {
+        assert_eq!(
+            "512M".parse::<Amount>().unwrap(),
+            Amount::Bytes(512 * 1_048_576)
+        );
+        assert_eq!(
+            "512MiB".parse::<Amount>().unwrap(),
+            Amount::Bytes(512 * 1_048_576)
+        );
+        assert_eq!(
+            "512MB".parse::<Amount>().unwrap(),
+            Amount::Bytes(512_000_000)
+        );
+        assert_eq!(
+            "1.5g".parse::<Amount>().unwrap(),
+            Amount::Bytes(1_610_612_736)
+        );
+        assert_eq!("4096".parse::<Amount>().unwrap(), Amount::Bytes(4096));
+        assert_eq!("2048s".parse::<Amount>().unwrap(), Amount::Sectors(2048));
+        assert!("1.5s".parse::<Amount>().is_err());
+        assert!("lots".parse::<Amount>().is_err());
+    }
+
+    #[test]
+    fn relative_sizes_resolve_against_available_space() {
+        let resolve = |input: &str| input.parse::<SizeSpec>()?.resolve(10 * 1_048_576, 4096);
+
+        assert_eq!(resolve("50%").unwrap(), 5 * 1_048_576);
+        assert_eq!(resolve("rest").unwrap(), 10 * 1_048_576);
+        assert_eq!(resolve("").unwrap(), 10 * 1_048_576);
+        assert_eq!(resolve("-1M").unwrap(), 9 * 1_048_576);
+        assert_eq!(resolve("256s").unwrap(), 1_048_576);
+        assert!(resolve("-11M").is_err());
+        assert!(resolve("150%").is_err());
+    }
+
+    #[test]
+    fn sizes_past_u64_max_are_refused() {
+        let resolve = |input: &str| input.parse::<SizeSpec>()?.resolve(1_048_576, 512);
+
+        assert!(resolve("40000000000000000s").is_err());
+        assert!(resolve("-40000000000000000s").is_err());
+        assert!("20000000T".parse::<Amount>().is_err());
+        assert!("18446744073709551616".parse::<Amount>().is_err());
+    }
+
+    #[test]
+    fn format_size_in_si_and_iec() {
+        assert_eq!(format_size(1_000_000_000, SizeUnits::Si), "1GB");
+        assert_eq!(format_size(1_073_741_824, SizeUnits::Si), "1.1GB");
+        assert_eq!(format_size(1_073_741_824, SizeUnits::Iec), "1GiB");
+        assert_eq!(format_size(1_536, SizeUnits::Iec), "1.5KiB");
+        assert_eq!(format_size(999, SizeUnits::Iec), "999B");
+    }
+
+    #[test]
+    fn format_timestamp_handles_epoch_and_leap_days() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(format_timestamp(1_792_183_502), "2026-10-16 20:45:02");
     }
 }
@@ -0,0 +1,199 @@
+//! Integration tests that drive the helper's request handler against real
+//! loop devices. They need root plus parted, sfdisk, cryptsetup and
+//! mkfs.ext4, so they are ignored by default:
+//!
+//!     sudo -E cargo test --test loop_devices -- --ignored --test-threads=1
+
+use disktui::helper::{ResponseWriter, handle_request};
+use disktui::protocol::Request;
+use disktui::runner::SystemRunner;
+use serde_json::{Value, json};
+use std::fs::File;
+use std::path::PathBuf;
+use std::process::Command;
+
+const IMAGE_SIZE: u64 = 256 * 1024 * 1024;
+const REQUIRED_TOOLS: &[&str] = &["losetup", "parted", "sfdisk", "partprobe", "cryptsetup", "mkfs.ext4", "resize2fs", "lsblk"];
+
+/// A sparse image attached to a loop device, detached and removed on drop.
+struct LoopDevice {
+	image: PathBuf,
+	name: String,
+}
+
+impl LoopDevice {
+	fn attach(test: &str) -> Self {
+		assert_eq!(unsafe { libc::geteuid() }, 0, "loop device tests must run as root");
+		for tool in REQUIRED_TOOLS {
+			let found = Command::new("which").arg(tool).output().map(|o| o.status.success()).unwrap_or(false);
+			assert!(found, "{} is required for loop device tests", tool);
+		}
+
+		let image = std::env::temp_dir().join(format!("disktui-{}-{}.img", test, std::process::id()));
+		File::create(&image).and_then(|f| f.set_len(IMAGE_SIZE)).expect("create sparse image");
+
+		let output = Command::new("losetup")
+			.args(["-f", "--show", "-P"])
+			.arg(&image)
+			.output()
+			.expect("run losetup");
+		assert!(output.status.success(), "losetup failed: {}", String::from_utf8_lossy(&output.stderr));
+
+		let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+		let name = path.trim_start_matches("/dev/").to_string();
+		Self { image, name }
+	}
+
+	fn partition(&self, num: u32) -> String {
+		format!("{}p{}", self.name, num)
+	}
+
+	/// The device as reported by `lsblk -J`, including its children.
+	fn lsblk(&self) -> Value {
+		let _ = Command::new("udevadm").args(["settle", "--timeout=10"]).output();
+		let output = Command::new("lsblk")
+			.args(["-J", "-b", "-o", "NAME,SIZE,TYPE,FSTYPE,MOUNTPOINT"])
+			.arg(format!("/dev/{}", self.name))
+			.output()
+			.expect("run lsblk");
+		let json: Value = serde_json::from_slice(&output.stdout).expect("parse lsblk");
+		json["blockdevices"][0].clone()
+	}
+
+	fn children(&self) -> Vec<Value> {
+		self.lsblk()["children"].as_array().cloned().unwrap_or_default()
+	}
+
+	fn child(&self, name: &str) -> Value {
+		fn find(devices: &[Value], name: &str) -> Option<Value> {
+			devices.iter().find_map(|d| {
+				if d["name"] == name {
+					Some(d.clone())
+				} else {
+					find(d["children"].as_array().map(Vec::as_slice).unwrap_or_default(), name)
+				}
+			})
+		}
+		find(&self.children(), name).unwrap_or_else(|| panic!("{} not found in lsblk output", name))
+	}
+}
+
+impl Drop for LoopDevice {
+	fn drop(&mut self) {
+		for child in self.children() {
+			let name = child["name"].as_str().unwrap_or_default().to_string();
+			let _ = Command::new("umount").arg(format!("/dev/{}", name)).output();
+			let _ = Command::new("cryptsetup").args(["close", &format!("luks-{}", name)]).output();
+		}
+		let _ = Command::new("losetup").args(["-d", &format!("/dev/{}", self.name)]).output();
+		let _ = std::fs::remove_file(&self.image);
+	}
+}
+
+/// Sends a request to the helper handler exactly as the TUI would, as JSON.
+async fn send(request: Value) -> anyhow::Result<()> {
+	let request: Request = serde_json::from_value(request)?;
+	let mut writer = ResponseWriter::new(Box::new(std::io::sink()));
+	handle_request(request, &mut writer, &SystemRunner).await.map(|_| ())
+}
+
+async fn prepare(test: &str) -> LoopDevice {
+	let disk = LoopDevice::attach(test);
+	send(json!({"op": "create_partition_table", "disk": disk.name, "table_type": "gpt"}))
+		.await
+		.expect("create partition table");
+	disk
+}
+
+#[tokio::test]
+#[ignore = "requires root and loop devices"]
+async fn create_format_mount_and_delete() {
+	let disk = prepare("basic").await;
+	let part = disk.partition(1);
+
+	send(json!({"op": "create_partition", "disk": disk.name, "size": "64MB", "fs_type": "ext4"}))
+		.await
+		.expect("create partition");
+	let created = disk.child(&part);
+	assert_eq!(created["fstype"], "ext4");
+	assert!(created["size"].as_u64().unwrap() >= 60_000_000);
+
+	send(json!({"op": "mount", "device": part})).await.expect("mount");
+	assert_eq!(disk.child(&part)["mountpoint"], format!("/mnt/{}", part));
+
+	send(json!({"op": "unmount", "device": part})).await.expect("unmount");
+	assert!(disk.child(&part)["mountpoint"].is_null());
+
+	send(json!({"op": "format", "device": part, "fs_type": "ext4"})).await.expect("format");
+
+	send(json!({"op": "delete_partition", "partition": part})).await.expect("delete");
+	assert!(disk.children().is_empty());
+}
+
+#[tokio::test]
+#[ignore = "requires root and loop devices"]
+async fn resize_grows_and_shrinks_partition_and_filesystem() {
+	let disk = prepare("resize").await;
+	let part = disk.partition(1);
+
+	send(json!({"op": "create_partition", "disk": disk.name, "size": "64MB", "fs_type": "ext4"}))
+		.await
+		.expect("create partition");
+
+	send(json!({"op": "resize_partition", "partition": part, "new_size": "128M"}))
+		.await
+		.expect("grow");
+	assert_eq!(disk.child(&part)["size"].as_u64(), Some(128 * 1_048_576));
+
+	send(json!({"op": "resize_partition", "partition": part, "new_size": "96M"}))
+		.await
+		.expect("shrink");
+	assert_eq!(disk.child(&part)["size"].as_u64(), Some(96 * 1_048_576));
+	assert_eq!(disk.child(&part)["fstype"], "ext4");
+}
+
+#[tokio::test]
+#[ignore = "requires root and loop devices"]
+async fn encrypt_unlock_and_lock() {
+	let disk = prepare("luks").await;
+	let part = disk.partition(1);
+	let mapper = format!("luks-{}", part);
+
+	send(json!({"op": "create_partition", "disk": disk.name, "size": "64MB"}))
+		.await
+		.expect("create partition");
+
+	send(json!({"op": "encrypt_and_format", "partition": part, "passphrase": "correct horse", "fs_type": "ext4"}))
+		.await
+		.expect("encrypt and format");
+	assert_eq!(disk.child(&part)["fstype"], "crypto_LUKS");
+	assert_eq!(disk.child(&mapper)["fstype"], "ext4");
+
+	send(json!({"op": "lock_luks", "mapper_name": mapper})).await.expect("lock");
+	assert!(disk.child(&part)["children"].is_null());
+
+	let wrong = send(json!({"op": "unlock_luks", "device": part, "passphrase": "wrong", "mapper_name": mapper})).await;
+	assert!(wrong.is_err());
+
+	send(json!({"op": "unlock_luks", "device": part, "passphrase": "correct horse", "mapper_name": mapper}))
+		.await
+		.expect("unlock");
+	assert_eq!(disk.child(&mapper)["type"], "crypt");
+
+	send(json!({"op": "lock_luks", "mapper_name": mapper})).await.expect("lock again");
+}
+
+#[tokio::test]
+#[ignore = "requires root and loop devices"]
+async fn format_whole_disk_uses_loop_partition_naming() {
+	let disk = LoopDevice::attach("whole");
+
+	send(json!({"op": "format_whole_disk", "disk": disk.name, "fs_type": "ext4"}))
+		.await
+		.expect("format whole disk");
+
+	let children = disk.children();
+	assert_eq!(children.len(), 1);
+	assert_eq!(children[0]["name"], disk.partition(1));
+	assert_eq!(children[0]["fstype"], "ext4");
+}